@@ -3,13 +3,20 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll, Waker};
+use std::time::{Duration, SystemTime};
 
 use bytes::Bytes;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use tracing::{error, warn};
 
-use crate::exporter::Exporter;
+use crate::config::{EvictionPolicy, FlushThresholds};
+use crate::exporter::{ExportError, Exporter};
+use crate::processor::Pipeline;
+use crate::self_metrics::RelayMetrics;
+
+mod trim;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Signal {
@@ -19,6 +26,8 @@ pub enum Signal {
 }
 
 impl Signal {
+    pub const ALL: [Signal; 3] = [Signal::Traces, Signal::Metrics, Signal::Logs];
+
     const fn bit(self) -> u8 {
         1 << (self as u8)
     }
@@ -31,6 +40,14 @@ impl Signal {
             _ => None,
         }
     }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::Traces => "traces",
+            Signal::Metrics => "metrics",
+            Signal::Logs => "logs",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -60,12 +77,26 @@ impl Default for EnabledSignals {
 pub struct SignalBuffer {
     pub queue: VecDeque<Bytes>,
     pub size_bytes: usize,
+    /// Number of entries at the front of `queue` that have already been
+    /// through the processing pipeline (data prepended back after a failed
+    /// export).
+    pub processed: usize,
+    /// When the oldest entry was buffered. Entries do not carry their own
+    /// timestamps, so this is only reset once the queue empties.
+    pub buffered_at: Option<Instant>,
+    /// Wall-clock time each entry in `queue` was buffered, for
+    /// [`OutboundBuffer::discard_before`]. Entries prepended back after a
+    /// failed export count as buffered when the flush took them.
+    pushed_at: VecDeque<SystemTime>,
 }
 
 impl SignalBuffer {
     pub fn clear(&mut self) {
         self.queue.clear();
+        self.pushed_at.clear();
         self.size_bytes = 0;
+        self.processed = 0;
+        self.buffered_at = None;
     }
 
     pub fn is_empty(&self) -> bool {
@@ -75,24 +106,142 @@ impl SignalBuffer {
     /// Remove the oldest entry from the queue. Returns the number of bytes freed.
     pub fn evict_oldest(&mut self) -> usize {
         if let Some(entry) = self.queue.pop_front() {
+            self.pushed_at.pop_front();
             let freed = entry.len();
             self.size_bytes -= freed;
+            self.processed = self.processed.saturating_sub(1);
+            if self.queue.is_empty() {
+                self.buffered_at = None;
+            }
             freed
         } else {
             0
         }
     }
 
+    /// Free at least `needed` bytes from the oldest entry: take it out to
+    /// have its leading records trimmed if the entry is larger than that,
+    /// otherwise evict it whole. Either way it goes into `eviction`.
+    ///
+    /// Returns the bytes this frees once the entry is trimmed.
+    fn evict_oldest_into(
+        &mut self,
+        signal: Signal,
+        needed: usize,
+        eviction: &mut Eviction,
+    ) -> usize {
+        let processed = self.processed > 0;
+        let buffered_at = self.buffered_at;
+        let Some(payload) = self.queue.front().cloned() else {
+            return 0;
+        };
+        let pushed_at = self
+            .pushed_at
+            .front()
+            .copied()
+            .unwrap_or_else(SystemTime::now);
+        let len = self.evict_oldest();
+        if len > needed {
+            eviction.trims.push(Trim {
+                signal,
+                payload,
+                needed,
+                processed,
+                buffered_at,
+                pushed_at,
+            });
+            needed
+        } else {
+            eviction.dropped.push((signal, payload));
+            len
+        }
+    }
+
+    /// Put the remainder of a trimmed entry back in front of the entries
+    /// that are at the same processing stage.
+    fn restore(&mut self, remainder: Trim) {
+        self.size_bytes += remainder.payload.len();
+        if remainder.processed {
+            self.queue.push_front(remainder.payload);
+            self.pushed_at.push_front(remainder.pushed_at);
+            self.processed += 1;
+        } else {
+            self.queue.insert(self.processed, remainder.payload);
+            self.pushed_at.insert(self.processed, remainder.pushed_at);
+        }
+        self.buffered_at = match (self.buffered_at, remainder.buffered_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b).or_else(|| Some(Instant::now())),
+        };
+    }
+
+    /// Split off the entries that have not been through the processing
+    /// pipeline yet.
+    pub fn take_unprocessed(&mut self) -> VecDeque<Bytes> {
+        let fresh = self.queue.split_off(self.processed);
+        self.pushed_at.truncate(self.processed);
+        self.size_bytes -= fresh.iter().map(Bytes::len).sum::<usize>();
+        fresh
+    }
+
+    /// Append an entry produced by the processing pipeline.
+    pub fn push_processed(&mut self, payload: Bytes) {
+        debug_assert_eq!(self.processed, self.queue.len());
+        self.buffered_at.get_or_insert_with(Instant::now);
+        self.size_bytes += payload.len();
+        self.queue.push_back(payload);
+        self.pushed_at.push_back(SystemTime::now());
+        self.processed += 1;
+    }
+
     /// Prepend older data in front of current data.
     /// After this call, `self` contains `[older..., self...]`.
     fn prepend(&mut self, mut older: SignalBuffer) {
+        // The processed prefix only extends into `self` if all of `older` was processed.
+        if older.processed == older.queue.len() {
+            older.processed += self.processed;
+        }
         // append drains self.queue into older.queue, leaving self.queue empty.
         // self.size_bytes is stale after this line, but `*self = older` below
         // replaces self entirely so the stale value is never observed.
         older.queue.append(&mut self.queue);
+        older.pushed_at.append(&mut self.pushed_at);
         older.size_bytes += self.size_bytes;
+        older.buffered_at = match (older.buffered_at, self.buffered_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
         *self = older;
     }
+
+    /// Drop the entries buffered before `cutoff`. Entries are buffered in
+    /// order, so these are always at the front. Returns the bytes dropped.
+    fn discard_before(&mut self, cutoff: SystemTime) -> usize {
+        let mut dropped = 0;
+        while self.pushed_at.front().is_some_and(|&at| at < cutoff) {
+            dropped += self.evict_oldest();
+        }
+        if dropped > 0 && !self.is_empty() {
+            self.buffered_at = Some(Instant::now());
+        }
+        dropped
+    }
+
+    /// Whether any of the per-signal flush triggers has been reached.
+    fn threshold_reached(&self, thresholds: &FlushThresholds, now: Instant) -> bool {
+        thresholds.bytes.is_some_and(|max| self.size_bytes >= max)
+            || thresholds
+                .payloads
+                .is_some_and(|max| self.queue.len() >= max)
+            || self
+                .age_deadline(thresholds)
+                .is_some_and(|deadline| deadline <= now)
+    }
+
+    /// When the oldest entry reaches the age trigger.
+    fn age_deadline(&self, thresholds: &FlushThresholds) -> Option<Instant> {
+        Some(self.buffered_at? + thresholds.age?)
+    }
 }
 
 #[derive(Default)]
@@ -111,14 +260,37 @@ impl BufferData {
         self.traces.is_empty() && self.metrics.is_empty() && self.logs.is_empty()
     }
 
-    pub fn push(&mut self, signal: Signal, payload: Bytes) {
-        let buf = match signal {
+    pub fn signal(&self, signal: Signal) -> &SignalBuffer {
+        match signal {
+            Signal::Traces => &self.traces,
+            Signal::Metrics => &self.metrics,
+            Signal::Logs => &self.logs,
+        }
+    }
+
+    fn signal_mut(&mut self, signal: Signal) -> &mut SignalBuffer {
+        match signal {
             Signal::Traces => &mut self.traces,
             Signal::Metrics => &mut self.metrics,
             Signal::Logs => &mut self.logs,
-        };
+        }
+    }
+
+    pub fn push(&mut self, signal: Signal, payload: Bytes) {
+        let buf = self.signal_mut(signal);
+        buf.buffered_at.get_or_insert_with(Instant::now);
         buf.size_bytes += payload.len();
         buf.queue.push_back(payload);
+        buf.pushed_at.push_back(SystemTime::now());
+    }
+
+    /// Move the given signals out into a new `BufferData`, leaving the others.
+    pub fn take_signals(&mut self, signals: &[Signal]) -> BufferData {
+        let mut taken = BufferData::new();
+        for &signal in signals {
+            *taken.signal_mut(signal) = std::mem::take(self.signal_mut(signal));
+        }
+        taken
     }
 
     pub fn total_size_bytes(&self) -> usize {
@@ -132,128 +304,552 @@ impl BufferData {
         self.logs.prepend(older.logs);
     }
 
-    /// Round-robin evict oldest entries (traces -> metrics -> logs -> repeat)
-    /// until total size is at or below `max_bytes`, or all queues are empty.
-    pub fn evict_to(&mut self, max_bytes: usize) {
-        let mut dropped_bytes = [0usize; 3]; // traces, metrics, logs
-        let mut dropped_count = [0usize; 3];
+    /// Evict until total size is at or below `max_bytes`, or all queues are
+    /// empty, choosing victims according to `policy`:
+    ///
+    /// - `DropOldest`: oldest entries, round-robin (traces -> metrics -> logs
+    ///   -> repeat)
+    /// - `Priority`: oldest entries of the first non-empty signal in
+    ///   [`EVICTION_PRIORITY`]
+    /// - `Reject`: nothing. Everything buffered was acknowledged to the SDK,
+    ///   which the listener stops doing once the buffer is full.
+    ///
+    /// The last oldest entry evicted from a signal is taken out to be trimmed
+    /// rather than dropped whole when only part of it needs to go; see
+    /// [`Eviction`].
+    pub fn evict_with(&mut self, max_bytes: usize, policy: EvictionPolicy) -> Eviction {
+        let mut eviction = Eviction::default();
+        if policy == EvictionPolicy::Reject {
+            return eviction;
+        }
         let mut total = self.total_size_bytes();
 
         while total > max_bytes {
-            let mut any_evicted = false;
+            let victims: &[Signal] = match policy {
+                EvictionPolicy::Priority => match EVICTION_PRIORITY
+                    .iter()
+                    .position(|&signal| !self.signal(signal).is_empty())
+                {
+                    Some(i) => &EVICTION_PRIORITY[i..=i],
+                    None => &[],
+                },
+                EvictionPolicy::DropOldest | EvictionPolicy::Reject => &Signal::ALL,
+            };
 
-            let freed = self.traces.evict_oldest();
-            if freed > 0 {
-                total -= freed;
-                dropped_bytes[0] += freed;
-                dropped_count[0] += 1;
-                any_evicted = true;
-            }
-            if total > max_bytes {
-                let freed = self.metrics.evict_oldest();
-                if freed > 0 {
-                    total -= freed;
-                    dropped_bytes[1] += freed;
-                    dropped_count[1] += 1;
-                    any_evicted = true;
+            let mut any_evicted = false;
+            for &signal in victims {
+                if total <= max_bytes {
+                    break;
                 }
-            }
-            if total > max_bytes {
-                let freed = self.logs.evict_oldest();
+                let freed = self.signal_mut(signal).evict_oldest_into(
+                    signal,
+                    total - max_bytes,
+                    &mut eviction,
+                );
                 if freed > 0 {
                     total -= freed;
-                    dropped_bytes[2] += freed;
-                    dropped_count[2] += 1;
                     any_evicted = true;
                 }
             }
-
             if !any_evicted {
                 break;
             }
         }
 
-        if dropped_count[0] > 0 {
-            warn!(
-                bytes = dropped_bytes[0],
-                count = dropped_count[0],
-                "evicted traces data from buffer"
-            );
+        eviction
+    }
+
+    /// Evict the oldest entries of each signal over its quota (indexed by
+    /// `Signal as usize`). Nothing is evicted under `EvictionPolicy::Reject`;
+    /// see [`Self::evict_with`].
+    pub fn enforce_quotas(
+        &mut self,
+        quotas: &[Option<usize>; 3],
+        policy: EvictionPolicy,
+    ) -> Eviction {
+        let mut eviction = Eviction::default();
+        if policy == EvictionPolicy::Reject {
+            return eviction;
         }
-        if dropped_count[1] > 0 {
-            warn!(
-                bytes = dropped_bytes[1],
-                count = dropped_count[1],
-                "evicted metrics data from buffer"
-            );
+        for signal in Signal::ALL {
+            let Some(quota) = quotas[signal as usize] else {
+                continue;
+            };
+            let buf = self.signal_mut(signal);
+            while buf.size_bytes > quota {
+                let needed = buf.size_bytes - quota;
+                if buf.evict_oldest_into(signal, needed, &mut eviction) == 0 {
+                    break;
+                }
+            }
         }
-        if dropped_count[2] > 0 {
-            warn!(
-                bytes = dropped_bytes[2],
-                count = dropped_count[2],
-                "evicted logs data from buffer"
-            );
+        eviction
+    }
+
+    /// Drop the entries of every signal buffered before `cutoff`. Returns
+    /// the bytes dropped.
+    pub fn discard_before(&mut self, cutoff: SystemTime) -> usize {
+        Signal::ALL
+            .iter()
+            .map(|&signal| self.signal_mut(signal).discard_before(cutoff))
+            .sum()
+    }
+
+    /// Put back what is left of entries trimmed by [`Eviction::trim`].
+    pub fn restore(&mut self, remainders: Vec<Trim>) {
+        for remainder in remainders {
+            self.signal_mut(remainder.signal).restore(remainder);
+        }
+    }
+}
+
+/// Entries taken out of the buffer by an eviction pass.
+///
+/// Taking them out is cheap; decoding them, to count their records or trim
+/// them, happens in [`Eviction::trim`], which [`OutboundBuffer`] runs after
+/// releasing its lock.
+#[derive(Debug, Default)]
+pub struct Eviction {
+    /// Entries evicted whole.
+    dropped: Vec<(Signal, Bytes)>,
+    /// Oldest entries of which only the leading records have to go.
+    trims: Vec<Trim>,
+}
+
+/// The oldest entry of a signal, out of the buffer to be trimmed.
+#[derive(Debug)]
+pub struct Trim {
+    signal: Signal,
+    payload: Bytes,
+    /// Bytes the trim has to free.
+    needed: usize,
+    /// Whether the entry had been through the processing pipeline.
+    processed: bool,
+    buffered_at: Option<Instant>,
+    pushed_at: SystemTime,
+}
+
+impl Eviction {
+    pub fn is_empty(&self) -> bool {
+        self.dropped.is_empty() && self.trims.is_empty()
+    }
+
+    fn append(&mut self, mut other: Eviction) {
+        self.dropped.append(&mut other.dropped);
+        self.trims.append(&mut other.trims);
+    }
+
+    /// Trim the entries taken out for it and count what was removed.
+    /// Returns the remainders, for [`BufferData::restore`]. Entries that do
+    /// not decode, or would have nothing left, are evicted whole.
+    pub fn trim(self) -> (Evicted, Vec<Trim>) {
+        let mut evicted = Evicted::default();
+        for (signal, payload) in self.dropped {
+            evicted.add(signal, payload.len(), trim::count_records(signal, &payload));
         }
+        let mut remainders = Vec::new();
+        for entry in self.trims {
+            let len = entry.payload.len();
+            match trim::trim_front(entry.signal, &entry.payload, entry.needed) {
+                Some((rest, records)) if rest.len() < len => {
+                    evicted.add(entry.signal, len - rest.len(), records);
+                    remainders.push(Trim {
+                        payload: rest,
+                        ..entry
+                    });
+                }
+                _ => evicted.add(
+                    entry.signal,
+                    len,
+                    trim::count_records(entry.signal, &entry.payload),
+                ),
+            }
+        }
+        (evicted, remainders)
     }
 }
 
+/// What an eviction pass removed, indexed by `Signal as usize`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Evicted {
+    pub bytes: [usize; 3],
+    /// Spans, metric data points or log records removed. Payloads that do
+    /// not decode as OTLP count as zero.
+    pub records: [usize; 3],
+}
+
+impl Evicted {
+    fn add(&mut self, signal: Signal, bytes: usize, records: usize) {
+        self.bytes[signal as usize] += bytes;
+        self.records[signal as usize] += records;
+    }
+
+    fn log(&self) {
+        for signal in Signal::ALL {
+            let i = signal as usize;
+            if self.bytes[i] > 0 {
+                warn!(
+                    bytes = self.bytes[i],
+                    records = self.records[i],
+                    "evicted {} data from buffer",
+                    signal.name()
+                );
+            }
+        }
+    }
+}
+
+/// Order in which `EvictionPolicy::Priority` gives up signals: metrics are
+/// the cheapest to keep and the hardest to reconstruct, so they go last.
+const EVICTION_PRIORITY: [Signal; 3] = [Signal::Traces, Signal::Logs, Signal::Metrics];
+
+/// Result of a synchronous [`OutboundBuffer::flush`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlushOutcome {
+    /// Nothing was buffered.
+    Empty,
+    Exported,
+    /// Everything buffered was exported, if anything was, but the tail
+    /// sampler still holds spans of invocations that have not finished. They
+    /// are exported once their outcome is known. Only reported by on-demand
+    /// flushes.
+    Held,
+    /// The export failed; data for the failed signals was put back.
+    Failed(String),
+}
+
+/// Sent to the event loop when a flush completes, successful or not.
+#[derive(Debug, Clone, Copy)]
+pub struct FlushReport {
+    /// Buffered bytes taken for the flush, before processing.
+    pub bytes: usize,
+    /// Time spent processing and exporting them.
+    pub duration: Duration,
+}
+
+/// Outcome of the most recent export, for health reporting.
+#[derive(Debug, Clone)]
+pub struct ExportStatus {
+    pub at: SystemTime,
+    pub error: Option<String>,
+}
+
+/// Point-in-time view of the buffer for debugging.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SignalStats {
+    pub payloads: usize,
+    pub bytes: usize,
+    pub processed: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BufferStats {
+    pub traces: SignalStats,
+    pub metrics: SignalStats,
+    pub logs: SignalStats,
+    pub max_bytes: Option<usize>,
+    pub flush_in_flight: bool,
+}
+
 /// Internal state behind the single mutex — data and flush task together,
 /// so there is no lock ordering to get wrong.
 struct BufferState {
     data: BufferData,
     flush_task: Option<JoinHandle<()>>,
+    last_export: Option<ExportStatus>,
+    /// Global size limit; see [`OutboundBuffer::set_max_bytes`].
+    max_bytes: Option<usize>,
+    /// Bytes the listener accepted that have not reached the buffer yet,
+    /// indexed by `Signal as usize`. Only tracked under
+    /// `EvictionPolicy::Reject`.
+    reserved: [usize; 3],
+    /// Bytes taken by the running flush, indexed by `Signal as usize`. They
+    /// come back if the export fails.
+    in_flight: [usize; 3],
+    /// Set by [`OutboundBuffer::discard_before`]: data buffered before this
+    /// is dropped, including what an earlier flush hands back.
+    discarded_before: Option<SystemTime>,
+}
+
+impl BufferState {
+    /// Bytes of `signal` the buffer may have to hold: buffered, on their way
+    /// from the listener, or being flushed.
+    fn committed(&self, signal: Signal) -> usize {
+        let i = signal as usize;
+        self.data.signal(signal).size_bytes + self.reserved[i] + self.in_flight[i]
+    }
+
+    /// Whether `signal` is at its quota or the buffer at `max_bytes`.
+    fn full(&self, signal: Signal, quota: Option<usize>) -> bool {
+        quota.is_some_and(|quota| self.committed(signal) >= quota)
+            || self.max_bytes.is_some_and(|max| {
+                Signal::ALL
+                    .iter()
+                    .map(|&s| self.committed(s))
+                    .sum::<usize>()
+                    >= max
+            })
+    }
+
+    /// Take `signals` out for a flush, counting them as in flight.
+    /// Whether data buffered at `at` has been discarded.
+    fn discarded(&self, at: SystemTime) -> bool {
+        self.discarded_before.is_some_and(|cutoff| at < cutoff)
+    }
+
+    fn take_for_flush(&mut self, signals: &[Signal]) -> BufferData {
+        let snapshot = self.data.take_signals(signals);
+        for &signal in signals {
+            self.in_flight[signal as usize] = snapshot.signal(signal).size_bytes;
+        }
+        snapshot
+    }
 }
 
 /// Shared wrapper around `BufferData` that manages flush lifecycle.
 ///
 /// Uses `std::sync::Mutex` (not tokio) because the lock is never held across
-/// `.await` — all operations are sub-microsecond field swaps.
+/// `.await`. Nothing is decoded or encoded under it: eviction only takes
+/// entries out, and trims them once the lock is released.
 ///
 /// Sends notifications on each complete flush.
+///
+/// The processing [`Pipeline`] sits behind its own mutex so that decoding and
+/// re-encoding payloads never blocks producers pushing into the buffer. Only
+/// one flush runs at a time, so the pipeline lock is uncontended.
 #[derive(Clone)]
 pub struct OutboundBuffer {
     state: Arc<Mutex<BufferState>>,
-    max_bytes: Option<usize>,
-    flush_notify: mpsc::Sender<()>,
+    pipeline: Arc<Mutex<Pipeline>>,
+    metrics: Arc<RelayMetrics>,
+    /// Per-signal flush triggers, indexed by `Signal as usize`.
+    thresholds: [FlushThresholds; 3],
+    /// Per-signal hard limits, indexed by `Signal as usize`.
+    quotas: [Option<usize>; 3],
+    policy: EvictionPolicy,
+    flush_notify: mpsc::Sender<FlushReport>,
 }
 
 impl OutboundBuffer {
-    pub fn new(max_bytes: Option<usize>, flush_notify: mpsc::Sender<()>) -> Self {
+    pub fn new(
+        max_bytes: Option<usize>,
+        mut pipeline: Pipeline,
+        flush_notify: mpsc::Sender<FlushReport>,
+    ) -> Self {
+        let metrics = Arc::new(RelayMetrics::default());
+        pipeline.set_metrics(Arc::clone(&metrics));
         Self {
             state: Arc::new(Mutex::new(BufferState {
                 data: BufferData::new(),
                 flush_task: None,
+                last_export: None,
+                max_bytes,
+                reserved: [0; 3],
+                in_flight: [0; 3],
+                discarded_before: None,
             })),
+            pipeline: Arc::new(Mutex::new(pipeline)),
+            metrics,
             flush_notify,
-            max_bytes,
+            thresholds: Default::default(),
+            quotas: [None; 3],
+            policy: EvictionPolicy::DropOldest,
+        }
+    }
+
+    pub fn with_flush_thresholds(mut self, thresholds: [FlushThresholds; 3]) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    pub fn with_quotas(mut self, quotas: [Option<usize>; 3], policy: EvictionPolicy) -> Self {
+        self.quotas = quotas;
+        self.policy = policy;
+        self
+    }
+
+    /// Whether the listener should turn away new `signal` payloads: only
+    /// under `EvictionPolicy::Reject`, once the signal has used its quota or
+    /// the buffer its `max_bytes`. Payloads still in the listener channel and
+    /// data being flushed count towards both.
+    pub fn quota_exhausted(&self, signal: Signal) -> bool {
+        self.policy == EvictionPolicy::Reject
+            && self
+                .state
+                .lock()
+                .unwrap()
+                .full(signal, self.quotas[signal as usize])
+    }
+
+    /// Reserve room for a payload the listener is about to accept. Under
+    /// `EvictionPolicy::Reject` this fails once [`Self::quota_exhausted`];
+    /// otherwise it always succeeds and reserves nothing.
+    ///
+    /// The reservation is held until [`Self::release`], once the payload is
+    /// buffered or was not accepted after all, so that nothing acknowledged
+    /// ever has to be evicted.
+    pub fn reserve(&self, signal: Signal, bytes: usize) -> bool {
+        if self.policy != EvictionPolicy::Reject {
+            return true;
         }
+        let mut guard = self.state.lock().unwrap();
+        if guard.full(signal, self.quotas[signal as usize]) {
+            return false;
+        }
+        guard.reserved[signal as usize] += bytes;
+        true
+    }
+
+    /// Release a reservation made by [`Self::reserve`].
+    pub fn release(&self, signal: Signal, bytes: usize) {
+        if self.policy != EvictionPolicy::Reject {
+            return;
+        }
+        let mut guard = self.state.lock().unwrap();
+        let reserved = &mut guard.reserved[signal as usize];
+        *reserved = reserved.saturating_sub(bytes);
+    }
+
+    /// Change the global size limit. Takes effect from the next push or
+    /// failed export; nothing is evicted straight away.
+    pub fn set_max_bytes(&self, max_bytes: Option<usize>) {
+        self.state.lock().unwrap().max_bytes = max_bytes;
+    }
+
+    /// Self-observability counters for this buffer and its producers.
+    pub fn metrics(&self) -> &Arc<RelayMetrics> {
+        &self.metrics
     }
 
     pub fn push(&self, signal: Signal, payload: Bytes) {
-        self.state.lock().unwrap().data.push(signal, payload);
+        let mut guard = self.state.lock().unwrap();
+        guard.data.push(signal, payload);
+        self.record_size(&guard.data, signal);
+    }
+
+    pub fn last_export(&self) -> Option<ExportStatus> {
+        self.state.lock().unwrap().last_export.clone()
+    }
+
+    pub fn stats(&self) -> BufferStats {
+        let guard = self.state.lock().unwrap();
+        let stats = |buf: &SignalBuffer| SignalStats {
+            payloads: buf.queue.len(),
+            bytes: buf.size_bytes,
+            processed: buf.processed,
+        };
+        BufferStats {
+            traces: stats(&guard.data.traces),
+            metrics: stats(&guard.data.metrics),
+            logs: stats(&guard.data.logs),
+            max_bytes: guard.max_bytes,
+            flush_in_flight: guard
+                .flush_task
+                .as_ref()
+                .is_some_and(|handle| !handle.is_finished()),
+        }
+    }
+
+    fn record_size(&self, data: &BufferData, signal: Signal) {
+        self.metrics
+            .record_buffer_size(signal, data.signal(signal).size_bytes);
+    }
+
+    /// Drop processing state taken over from a SnapStart snapshot.
+    pub fn reset_pipeline(&self) {
+        self.pipeline.lock().unwrap().reset();
     }
 
     /// Take all data out of the buffer, leaving it empty.
+    #[cfg(test)]
     pub fn take(&self) -> BufferData {
         std::mem::take(&mut self.state.lock().unwrap().data)
     }
 
-    /// Prepend failed export data back into the buffer and evict if over capacity.
-    /// No-op if `data` is empty.
-    fn prepend_failed(&self, data: BufferData) {
+    /// Drop everything buffered before `cutoff` (SnapStart restore), now and
+    /// when a flush that took it fails, leaving data buffered since.
+    /// Returns the bytes dropped from the buffer.
+    pub fn discard_before(&self, cutoff: SystemTime) -> usize {
+        let mut guard = self.state.lock().unwrap();
+        guard.discarded_before = guard.discarded_before.max(Some(cutoff));
+        let dropped = guard.data.discard_before(cutoff);
+        for signal in Signal::ALL {
+            self.record_size(&guard.data, signal);
+        }
+        dropped
+    }
+
+    /// Finish a flush that took its data at `taken_at`: prepend failed
+    /// export data back into the buffer and evict if over capacity. Nothing
+    /// is evicted under `EvictionPolicy::Reject`.
+    fn prepend_failed(&self, mut data: BufferData, taken_at: SystemTime) {
         if data.is_empty() {
+            self.state.lock().unwrap().in_flight = [0; 3];
             return;
         }
+        // Restart the age clock so a failing collector is retried once per
+        // age trigger rather than immediately.
+        let now = Instant::now();
+        for signal in Signal::ALL {
+            let buf = data.signal_mut(signal);
+            if !buf.is_empty() {
+                buf.buffered_at = Some(now);
+            }
+            buf.pushed_at.iter_mut().for_each(|at| *at = taken_at);
+        }
         let mut guard = self.state.lock().unwrap();
+        guard.in_flight = [0; 3];
+        if guard.discarded(taken_at) {
+            warn!(
+                bytes = data.total_size_bytes(),
+                "discarded failed export of data buffered before the snapshot"
+            );
+            return;
+        }
         guard.data.prepend(data);
-        if let Some(max) = self.max_bytes {
-            guard.data.evict_to(max);
+        let mut eviction = guard.data.enforce_quotas(&self.quotas, self.policy);
+        if let Some(max) = guard.max_bytes {
+            eviction.append(guard.data.evict_with(max, self.policy));
+        }
+        drop(guard);
+        self.settle(eviction);
+    }
+
+    /// Trim the entries an eviction pass took out, without holding the lock,
+    /// and put back what is left of them.
+    fn settle(&self, eviction: Eviction) {
+        if eviction.is_empty() {
+            return;
+        }
+        let (evicted, mut remainders) = eviction.trim();
+        if !remainders.is_empty() {
+            let mut guard = self.state.lock().unwrap();
+            remainders.retain(|remainder| !guard.discarded(remainder.pushed_at));
+            guard.data.restore(remainders);
+        }
+        evicted.log();
+        self.record_evicted(evicted);
+    }
+
+    fn record_evicted(&self, evicted: Evicted) {
+        for signal in Signal::ALL {
+            let i = signal as usize;
+            self.metrics
+                .record_evicted(signal, evicted.bytes[i], evicted.records[i]);
         }
     }
 
     /// Push a payload and, if over the byte threshold, try to spawn a background
     /// flush — all under a single lock acquisition.
     ///
+    /// Crossing the global `max_bytes` flushes every signal; reaching one of
+    /// the signal's own thresholds flushes that signal alone, so a busy signal
+    /// never drags the others out before their own batches are ready.
+    ///
+    /// Going over the signal's quota also flushes it; if a flush is already in
+    /// flight the signal is evicted back down to its quota instead, except
+    /// under `EvictionPolicy::Reject`.
+    ///
     /// Returns `true` if a flush was spawned.
     pub fn push_and_maybe_flush<E: Exporter>(
         &self,
@@ -263,23 +859,93 @@ impl OutboundBuffer {
     ) -> bool {
         let mut guard = self.state.lock().unwrap();
         guard.data.push(signal, payload);
-        match self.max_bytes {
+        self.record_size(&guard.data, signal);
+        let quota = self.quotas[signal as usize];
+        let max_bytes = guard.max_bytes;
+        match max_bytes {
             Some(max) if guard.data.total_size_bytes() > max => {
-                self.try_spawn_flush(&mut guard, exporter)
+                self.try_spawn_flush(&mut guard, exporter, &Signal::ALL)
+            }
+            _ if quota.is_some_and(|quota| guard.data.signal(signal).size_bytes > quota) => {
+                if self.try_spawn_flush(&mut guard, exporter, &[signal]) {
+                    return true;
+                }
+                let eviction = guard.data.enforce_quotas(&self.quotas, self.policy);
+                drop(guard);
+                self.settle(eviction);
+                false
+            }
+            _ if guard
+                .data
+                .signal(signal)
+                .threshold_reached(&self.thresholds[signal as usize], Instant::now()) =>
+            {
+                self.try_spawn_flush(&mut guard, exporter, &[signal])
             }
             _ => false,
         }
     }
 
+    /// Earliest time a signal reaches its age trigger, if any is configured
+    /// and pending. `None` while a flush is in flight: its completion
+    /// notification wakes the event loop to ask again.
+    pub fn age_deadline(&self) -> Option<Instant> {
+        let guard = self.state.lock().unwrap();
+        if guard
+            .flush_task
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+        {
+            return None;
+        }
+        Signal::ALL
+            .iter()
+            .filter_map(|&signal| {
+                guard
+                    .data
+                    .signal(signal)
+                    .age_deadline(&self.thresholds[signal as usize])
+            })
+            .min()
+    }
+
+    /// Spawn a background flush of the signals whose age trigger has passed.
+    ///
+    /// Returns `true` if a flush was spawned.
+    pub fn flush_aged<E: Exporter>(&self, exporter: &Arc<E>) -> bool {
+        let mut guard = self.state.lock().unwrap();
+        let now = Instant::now();
+        let due: Vec<Signal> = Signal::ALL
+            .into_iter()
+            .filter(|&signal| {
+                guard
+                    .data
+                    .signal(signal)
+                    .age_deadline(&self.thresholds[signal as usize])
+                    .is_some_and(|deadline| deadline <= now)
+            })
+            .collect();
+        if due.is_empty() {
+            return false;
+        }
+        self.try_spawn_flush(&mut guard, exporter, &due)
+    }
+
     /// Spawn a background flush. Returns `true` if a flush was spawned, `false` if
     /// skipped (already in-flight or buffer empty).
     pub fn spawn_flush<E: Exporter>(&self, exporter: &Arc<E>) -> bool {
         let mut guard = self.state.lock().unwrap();
-        self.try_spawn_flush(&mut guard, exporter)
+        self.try_spawn_flush(&mut guard, exporter, &Signal::ALL)
     }
 
-    /// Inner spawn logic, called with the lock already held.
-    fn try_spawn_flush<E: Exporter>(&self, state: &mut BufferState, exporter: &Arc<E>) -> bool {
+    /// Inner spawn logic, called with the lock already held. Only `signals`
+    /// are taken for the flush.
+    fn try_spawn_flush<E: Exporter>(
+        &self,
+        state: &mut BufferState,
+        exporter: &Arc<E>,
+        signals: &[Signal],
+    ) -> bool {
         // Skip if a flush is already in-flight
         if let Some(handle) = state.flush_task.as_ref()
             && !handle.is_finished()
@@ -296,7 +962,8 @@ impl OutboundBuffer {
             }
         }
 
-        let mut snapshot = std::mem::take(&mut state.data);
+        let taken_at = SystemTime::now();
+        let mut snapshot = state.take_for_flush(signals);
         if snapshot.is_empty() {
             return false;
         }
@@ -307,18 +974,48 @@ impl OutboundBuffer {
         {
             let flush_notify = self.flush_notify.clone();
             state.flush_task = Some(tokio::spawn(async move {
-                if let Err(e) = exporter.export(&mut snapshot).await {
+                let bytes = snapshot.total_size_bytes();
+                let started = Instant::now();
+                buffer.process(&mut snapshot);
+                if let Err(e) = buffer.export(&*exporter, &mut snapshot).await {
                     error!(error = %e, "background flush failed");
                 }
                 // Prepend any remaining data (failed signals). No-op if export cleared everything.
-                buffer.prepend_failed(snapshot);
-                notify_flush_complete(&flush_notify);
+                buffer.prepend_failed(snapshot, taken_at);
+                notify_flush_complete(
+                    &flush_notify,
+                    FlushReport {
+                        bytes,
+                        duration: started.elapsed(),
+                    },
+                );
             }));
         }
 
         true
     }
 
+    /// Run the processing pipeline over a snapshot about to be exported.
+    fn process(&self, snapshot: &mut BufferData) {
+        self.pipeline.lock().unwrap().process(snapshot);
+    }
+
+    /// Export a snapshot, recording the outcome and latency.
+    async fn export<E: Exporter>(
+        &self,
+        exporter: &E,
+        snapshot: &mut BufferData,
+    ) -> Result<(), ExportError> {
+        let started = Instant::now();
+        let result = exporter.export(snapshot).await;
+        self.metrics.record_flush(result.is_ok(), started.elapsed());
+        self.state.lock().unwrap().last_export = Some(ExportStatus {
+            at: SystemTime::now(),
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+        result
+    }
+
     /// Join any in-flight background flush to completion.
     pub async fn join_flush_task(&self) {
         let handle = self.state.lock().unwrap().flush_task.take();
@@ -330,25 +1027,38 @@ impl OutboundBuffer {
     }
 
     /// Synchronous flush: join in-flight background flush, then take + export + handle failures.
-    /// Returns `true` if data was exported, `false` if the buffer was empty.
-    pub async fn flush<E: Exporter>(&self, exporter: &E) -> bool {
+    pub async fn flush<E: Exporter>(&self, exporter: &E) -> FlushOutcome {
         self.join_flush_task().await;
-        let mut snapshot = self.take();
+        let taken_at = SystemTime::now();
+        let mut snapshot = self.state.lock().unwrap().take_for_flush(&Signal::ALL);
         if snapshot.is_empty() {
-            return false;
-        }
-        if let Err(e) = exporter.export(&mut snapshot).await {
-            error!(error = %e, "flush failed");
+            return FlushOutcome::Empty;
         }
-        self.prepend_failed(snapshot);
-        notify_flush_complete(&self.flush_notify);
+        let bytes = snapshot.total_size_bytes();
+        let started = Instant::now();
+        self.process(&mut snapshot);
+        let outcome = match self.export(exporter, &mut snapshot).await {
+            Ok(()) => FlushOutcome::Exported,
+            Err(e) => {
+                error!(error = %e, "flush failed");
+                FlushOutcome::Failed(e.to_string())
+            }
+        };
+        self.prepend_failed(snapshot, taken_at);
+        notify_flush_complete(
+            &self.flush_notify,
+            FlushReport {
+                bytes,
+                duration: started.elapsed(),
+            },
+        );
 
-        true
+        outcome
     }
 }
 
-fn notify_flush_complete(tx: &mpsc::Sender<()>) {
-    if let Err(e) = tx.try_send(()) {
+fn notify_flush_complete(tx: &mpsc::Sender<FlushReport>, report: FlushReport) {
+    if let Err(e) = tx.try_send(report) {
         error!(error = %e, "notify flush complete failed");
     };
 }
@@ -3,8 +3,17 @@ use std::sync::Arc;
 use bytes::Bytes;
 
 use super::*;
+use crate::processor::Pipeline;
 use crate::testing::SlowExporter;
 
+/// Run the decoding half of an eviction pass, as the `OutboundBuffer` does
+/// once it has released its lock.
+fn settle(buf: &mut BufferData, eviction: Eviction) -> Evicted {
+    let (evicted, remainders) = eviction.trim();
+    buf.restore(remainders);
+    evicted
+}
+
 #[test]
 fn push_to_traces() {
     let mut buf = BufferData::new();
@@ -96,7 +105,7 @@ fn evict_to_stops_at_threshold() {
     buf.push(Signal::Traces, Bytes::from("bbbb")); // 4
     buf.push(Signal::Traces, Bytes::from("cccc")); // 4
     // total = 12, evict to 8 => should drop "aaaa"
-    buf.evict_to(8);
+    buf.evict_with(8, EvictionPolicy::DropOldest);
     assert_eq!(buf.total_size_bytes(), 8);
     assert_eq!(buf.traces.queue.len(), 2);
 }
@@ -109,7 +118,7 @@ fn evict_to_round_robins_across_signals() {
     buf.push(Signal::Metrics, Bytes::from("mmmmmmmmmm"));
     buf.push(Signal::Logs, Bytes::from("llllllllll"));
     // evict to 0 => should evict one from each per round
-    buf.evict_to(0);
+    buf.evict_with(0, EvictionPolicy::DropOldest);
     assert!(buf.is_empty());
     assert_eq!(buf.total_size_bytes(), 0);
 }
@@ -118,7 +127,7 @@ fn evict_to_round_robins_across_signals() {
 fn evict_to_noop_when_under_threshold() {
     let mut buf = BufferData::new();
     buf.push(Signal::Traces, Bytes::from("aa")); // 2
-    buf.evict_to(100);
+    buf.evict_with(100, EvictionPolicy::DropOldest);
     assert_eq!(buf.total_size_bytes(), 2);
     assert_eq!(buf.traces.queue.len(), 1);
 }
@@ -130,7 +139,7 @@ fn evict_to_single_signal_only() {
     buf.push(Signal::Logs, Bytes::from("bbbb")); // 4
     buf.push(Signal::Logs, Bytes::from("cccc")); // 4
     // total = 12, evict to 4
-    buf.evict_to(4);
+    buf.evict_with(4, EvictionPolicy::DropOldest);
     assert_eq!(buf.total_size_bytes(), 4);
     assert_eq!(buf.logs.queue.len(), 1);
     assert_eq!(buf.logs.queue[0], Bytes::from("cccc"));
@@ -178,7 +187,7 @@ use tokio::sync::mpsc;
 #[test]
 fn prepend_and_evict_drops_oldest_first() {
     let (tx, _) = mpsc::channel(1);
-    let buf = OutboundBuffer::new(Some(10), tx);
+    let buf = OutboundBuffer::new(Some(10), Pipeline::default(), tx);
 
     // Push 6 bytes of current data
     buf.push(Signal::Traces, Bytes::from("cur123")); // 6 bytes
@@ -187,7 +196,7 @@ fn prepend_and_evict_drops_oldest_first() {
     let mut older = BufferData::new();
     older.push(Signal::Traces, Bytes::from("old1")); // 4 bytes
     older.push(Signal::Traces, Bytes::from("old2")); // 4 bytes
-    buf.prepend_failed(older);
+    buf.prepend_failed(older, SystemTime::now());
 
     // Should have evicted oldest ("old1") to get to 10 bytes
     let data = buf.take();
@@ -199,7 +208,7 @@ fn prepend_and_evict_drops_oldest_first() {
 #[test]
 fn shared_take_and_prepend_round_trip() {
     let (tx, _) = mpsc::channel(1);
-    let buf = OutboundBuffer::new(None, tx);
+    let buf = OutboundBuffer::new(None, Pipeline::default(), tx);
     buf.push(Signal::Traces, Bytes::from("t1"));
     buf.push(Signal::Metrics, Bytes::from("m1"));
 
@@ -207,7 +216,7 @@ fn shared_take_and_prepend_round_trip() {
     assert!(buf.take().is_empty());
     assert!(!taken.is_empty());
 
-    buf.prepend_failed(taken);
+    buf.prepend_failed(taken, SystemTime::now());
     let restored = buf.take();
     assert!(!restored.is_empty());
     assert_eq!(restored.total_size_bytes(), 4); // "t1" + "m1"
@@ -216,7 +225,7 @@ fn shared_take_and_prepend_round_trip() {
 #[tokio::test]
 async fn spawn_flush_skips_if_in_flight() {
     let (tx, _) = mpsc::channel(1);
-    let buffer = OutboundBuffer::new(None, tx);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx);
     buffer.push(Signal::Traces, Bytes::from("batch1"));
 
     let exporter = Arc::new(SlowExporter);
@@ -237,7 +246,7 @@ async fn spawn_flush_skips_if_in_flight() {
 #[tokio::test]
 async fn spawn_flush_returns_true_when_spawned() {
     let (tx, _) = mpsc::channel(1);
-    let buffer = OutboundBuffer::new(None, tx);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx);
     buffer.push(Signal::Traces, Bytes::from("data"));
     let exporter = Arc::new(crate::testing::MockExporter);
     assert!(buffer.spawn_flush(&exporter));
@@ -246,7 +255,7 @@ async fn spawn_flush_returns_true_when_spawned() {
 #[tokio::test]
 async fn spawn_flush_returns_false_when_empty() {
     let (tx, _) = mpsc::channel(1);
-    let buffer = OutboundBuffer::new(None, tx);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx);
     let exporter = Arc::new(crate::testing::MockExporter);
     assert!(!buffer.spawn_flush(&exporter));
 }
@@ -255,33 +264,498 @@ async fn spawn_flush_returns_false_when_empty() {
 async fn flush_notifies_when_complete() {
     let (tx, mut rx) = mpsc::channel(1);
 
-    let buffer = OutboundBuffer::new(None, tx);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx);
     buffer.push(Signal::Traces, Bytes::from("cur123"));
 
     let exporter = crate::testing::MockExporter {};
     buffer.flush(&exporter).await;
 
-    assert_eq!(
-        rx.try_recv().unwrap(),
-        (),
-        "Expected notification for flush completion"
-    );
+    let report = rx
+        .try_recv()
+        .expect("Expected notification for flush completion");
+    assert_eq!(report.bytes, 6);
 }
 
 #[tokio::test]
 async fn spawn_flush_notifies_when_complete() {
     let (tx, mut rx) = mpsc::channel(1);
 
-    let buffer = OutboundBuffer::new(None, tx);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx);
     buffer.push(Signal::Traces, Bytes::from("cur123"));
 
     let exporter = Arc::new(crate::testing::MockExporter);
     buffer.spawn_flush(&exporter);
     buffer.join_flush_task().await;
 
+    let report = rx
+        .try_recv()
+        .expect("Expected notification for flush completion");
+    assert_eq!(report.bytes, 6);
+}
+
+#[test]
+fn evict_oldest_shrinks_processed_prefix() {
+    let mut buf = BufferData::new();
+    buf.push(Signal::Traces, Bytes::from("first"));
+    buf.push(Signal::Traces, Bytes::from("second"));
+    buf.traces.processed = 1;
+    buf.traces.evict_oldest();
+    assert_eq!(buf.traces.processed, 0);
+    buf.traces.evict_oldest();
+    assert_eq!(buf.traces.processed, 0);
+}
+
+#[test]
+fn prepend_extends_processed_prefix_only_when_contiguous() {
+    let mut current = BufferData::new();
+    current.push(Signal::Traces, Bytes::from("new"));
+
+    let mut older = BufferData::new();
+    older.push(Signal::Traces, Bytes::from("old1"));
+    older.push(Signal::Traces, Bytes::from("old2"));
+    older.traces.processed = 2;
+
+    current.prepend(older);
+    assert_eq!(current.traces.processed, 2);
+}
+
+#[test]
+fn take_unprocessed_and_push_processed() {
+    let mut buf = BufferData::new();
+    buf.push(Signal::Logs, Bytes::from("done"));
+    buf.push(Signal::Logs, Bytes::from("fresh1"));
+    buf.push(Signal::Logs, Bytes::from("fresh2"));
+    buf.logs.processed = 1;
+
+    let fresh = buf.logs.take_unprocessed();
+    assert_eq!(fresh, [Bytes::from("fresh1"), Bytes::from("fresh2")]);
+    assert_eq!(buf.logs.size_bytes, 4);
+
+    buf.logs.push_processed(Bytes::from("merged"));
+    assert_eq!(buf.logs.processed, 2);
+    assert_eq!(buf.logs.size_bytes, 10);
+}
+
+#[test]
+fn evict_to_reports_bytes_per_signal() {
+    let mut buf = BufferData::new();
+    buf.push(Signal::Traces, Bytes::from("aaaa"));
+    buf.push(Signal::Logs, Bytes::from("bb"));
+    buf.push(Signal::Logs, Bytes::from("cc"));
+
+    let eviction = buf.evict_with(3, EvictionPolicy::DropOldest);
+    let evicted = settle(&mut buf, eviction);
+
+    assert_eq!(evicted.bytes, [4, 0, 2]);
+}
+
+#[tokio::test]
+async fn records_flush_outcome_eviction_and_high_water() {
+    let (tx, _rx) = mpsc::channel(8);
+    let buffer = OutboundBuffer::new(Some(10), Pipeline::default(), tx);
+    buffer.push(Signal::Traces, Bytes::from("0123456789"));
+    buffer.push(Signal::Logs, Bytes::from("0123"));
+
+    assert!(matches!(
+        buffer.flush(&crate::testing::FailingExporter).await,
+        FlushOutcome::Failed(_)
+    ));
+    buffer.push(Signal::Traces, Bytes::from("x"));
+    assert_eq!(
+        buffer.flush(&crate::testing::MockExporter).await,
+        FlushOutcome::Exported
+    );
+
+    let metrics = buffer.metrics();
+    assert_eq!(metrics.flushes(), (1, 1));
+    assert_eq!(metrics.high_water(Signal::Traces), 10);
+    assert_eq!(metrics.high_water(Signal::Logs), 4);
+    // The failed 14-byte snapshot was prepended back over the 10-byte limit.
+    assert_eq!(metrics.evicted(Signal::Traces), 10);
+    assert_eq!(metrics.evicted(Signal::Logs), 0);
+}
+
+fn thresholds(signal: Signal, thresholds: FlushThresholds) -> [FlushThresholds; 3] {
+    let mut all = [FlushThresholds::default(); 3];
+    all[signal as usize] = thresholds;
+    all
+}
+
+#[tokio::test]
+async fn signal_byte_threshold_flushes_only_that_signal() {
+    let (tx, _) = mpsc::channel(1);
+    let buffer = OutboundBuffer::new(Some(1_000_000), Pipeline::default(), tx)
+        .with_flush_thresholds(thresholds(
+            Signal::Logs,
+            FlushThresholds {
+                bytes: Some(8),
+                ..Default::default()
+            },
+        ));
+    let exporter = Arc::new(crate::testing::MockExporter);
+
+    assert!(!buffer.push_and_maybe_flush(Signal::Traces, Bytes::from("trace-data"), &exporter));
+    assert!(!buffer.push_and_maybe_flush(Signal::Logs, Bytes::from("log1"), &exporter));
+    assert!(buffer.push_and_maybe_flush(Signal::Logs, Bytes::from("log2"), &exporter));
+    buffer.join_flush_task().await;
+
+    let data = buffer.take();
+    assert!(data.logs.is_empty());
+    assert_eq!(data.traces.queue.len(), 1);
+}
+
+#[tokio::test]
+async fn other_signal_push_does_not_trigger_threshold() {
+    let (tx, _) = mpsc::channel(1);
+    let buffer =
+        OutboundBuffer::new(None, Pipeline::default(), tx).with_flush_thresholds(thresholds(
+            Signal::Logs,
+            FlushThresholds {
+                payloads: Some(1),
+                ..Default::default()
+            },
+        ));
+    let exporter = Arc::new(crate::testing::MockExporter);
+
+    // Logs are over their threshold, but a trace only checks trace triggers.
+    buffer.push(Signal::Logs, Bytes::from("log"));
+    assert!(!buffer.push_and_maybe_flush(Signal::Traces, Bytes::from("t"), &exporter));
+    assert_eq!(buffer.stats().logs.payloads, 1);
+}
+
+#[tokio::test]
+async fn payload_threshold_counts_queue_entries() {
+    let (tx, _) = mpsc::channel(1);
+    let buffer =
+        OutboundBuffer::new(None, Pipeline::default(), tx).with_flush_thresholds(thresholds(
+            Signal::Metrics,
+            FlushThresholds {
+                payloads: Some(3),
+                ..Default::default()
+            },
+        ));
+    let exporter = Arc::new(crate::testing::MockExporter);
+
+    assert!(!buffer.push_and_maybe_flush(Signal::Metrics, Bytes::from("a"), &exporter));
+    assert!(!buffer.push_and_maybe_flush(Signal::Metrics, Bytes::from("b"), &exporter));
+    assert!(buffer.push_and_maybe_flush(Signal::Metrics, Bytes::from("c"), &exporter));
+}
+
+#[tokio::test(start_paused = true)]
+async fn age_threshold_sets_deadline_and_flushes_aged_signals() {
+    let (tx, _) = mpsc::channel(1);
+    let buffer =
+        OutboundBuffer::new(None, Pipeline::default(), tx).with_flush_thresholds(thresholds(
+            Signal::Traces,
+            FlushThresholds {
+                age: Some(Duration::from_secs(5)),
+                ..Default::default()
+            },
+        ));
+    let exporter = Arc::new(crate::testing::MockExporter);
+    assert_eq!(buffer.age_deadline(), None);
+
+    let start = Instant::now();
+    buffer.push(Signal::Traces, Bytes::from("old"));
+    tokio::time::advance(Duration::from_secs(2)).await;
+    buffer.push(Signal::Traces, Bytes::from("new"));
+    buffer.push(Signal::Logs, Bytes::from("log"));
+
+    // The deadline follows the oldest entry.
+    assert_eq!(buffer.age_deadline(), Some(start + Duration::from_secs(5)));
+    assert!(!buffer.flush_aged(&exporter));
+
+    tokio::time::advance(Duration::from_secs(3)).await;
+    assert!(buffer.flush_aged(&exporter));
+    buffer.join_flush_task().await;
+
+    let data = buffer.take();
+    assert!(data.traces.is_empty());
+    assert_eq!(data.logs.queue.len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_flush_restarts_age_clock() {
+    let (tx, _) = mpsc::channel(1);
+    let buffer =
+        OutboundBuffer::new(None, Pipeline::default(), tx).with_flush_thresholds(thresholds(
+            Signal::Traces,
+            FlushThresholds {
+                age: Some(Duration::from_secs(5)),
+                ..Default::default()
+            },
+        ));
+    buffer.push(Signal::Traces, Bytes::from("data"));
+    tokio::time::advance(Duration::from_secs(6)).await;
+
+    assert!(buffer.flush_aged(&Arc::new(crate::testing::FailingExporter)));
+    buffer.join_flush_task().await;
+
+    assert_eq!(
+        buffer.age_deadline(),
+        Some(Instant::now() + Duration::from_secs(5))
+    );
+}
+
+#[test]
+fn reject_policy_never_evicts() {
+    let mut buf = BufferData::new();
+    buf.push(Signal::Traces, Bytes::from("old1"));
+    buf.push(Signal::Traces, Bytes::from("new1"));
+    assert!(buf.evict_with(4, EvictionPolicy::Reject).is_empty());
+    assert!(
+        buf.enforce_quotas(&[Some(4), None, None], EvictionPolicy::Reject)
+            .is_empty()
+    );
+    assert_eq!(buf.traces.queue.len(), 2);
+}
+
+#[test]
+fn evict_with_priority_keeps_metrics_longest() {
+    let mut buf = BufferData::new();
+    buf.push(Signal::Metrics, Bytes::from("mmmm"));
+    buf.push(Signal::Logs, Bytes::from("llll"));
+    buf.push(Signal::Traces, Bytes::from("tttt"));
+    buf.push(Signal::Traces, Bytes::from("tttt"));
+
+    // Traces go first, then logs; metrics survive.
+    let eviction = buf.evict_with(4, EvictionPolicy::Priority);
+    let evicted = settle(&mut buf, eviction);
+    assert_eq!(evicted.bytes, [8, 0, 4]);
+    assert_eq!(buf.metrics.queue.len(), 1);
+}
+
+#[test]
+fn enforce_quotas_only_touches_signals_over_quota() {
+    let mut buf = BufferData::new();
+    buf.push(Signal::Logs, Bytes::from("log1"));
+    buf.push(Signal::Logs, Bytes::from("log2"));
+    buf.push(Signal::Logs, Bytes::from("log3"));
+    buf.push(Signal::Traces, Bytes::from("trace-data"));
+
+    let eviction = buf.enforce_quotas(&[None, None, Some(8)], EvictionPolicy::DropOldest);
+    let evicted = settle(&mut buf, eviction);
+
+    assert_eq!(evicted.bytes, [0, 0, 4]);
+    assert_eq!(buf.logs.queue, [Bytes::from("log2"), Bytes::from("log3")]);
+    assert_eq!(buf.traces.queue.len(), 1);
+}
+
+#[tokio::test]
+async fn failed_export_enforces_quota() {
+    let (tx, _) = mpsc::channel(1);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx)
+        .with_quotas([Some(4), None, None], EvictionPolicy::DropOldest);
+    buffer.push(Signal::Traces, Bytes::from("old1"));
+    buffer.push(Signal::Traces, Bytes::from("new1"));
+
+    buffer.flush(&crate::testing::FailingExporter).await;
+
+    let data = buffer.take();
+    assert_eq!(data.traces.queue, [Bytes::from("new1")]);
+    assert_eq!(buffer.metrics().evicted(Signal::Traces), 4);
+}
+
+#[tokio::test]
+async fn over_quota_push_flushes_signal_or_evicts_when_busy() {
+    let (tx, _) = mpsc::channel(1);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx)
+        .with_quotas([None, None, Some(4)], EvictionPolicy::DropOldest);
+    let exporter = Arc::new(SlowExporter);
+
+    buffer.push(Signal::Traces, Bytes::from("trace"));
+    buffer.push(Signal::Logs, Bytes::from("log1"));
+    assert!(buffer.push_and_maybe_flush(Signal::Logs, Bytes::from("log2"), &exporter));
+
+    // The log flush is in flight: the next over-quota push evicts instead.
+    buffer.push(Signal::Logs, Bytes::from("log3"));
+    assert!(!buffer.push_and_maybe_flush(Signal::Logs, Bytes::from("log4"), &exporter));
+    assert_eq!(buffer.stats().logs.payloads, 1);
+    assert_eq!(buffer.stats().traces.payloads, 1);
+    buffer.join_flush_task().await;
+}
+
+#[test]
+fn quota_exhausted_only_under_reject_policy() {
+    let (tx, _) = mpsc::channel(1);
+    let quotas = [None, Some(4), None];
+    let reject = OutboundBuffer::new(None, Pipeline::default(), tx.clone())
+        .with_quotas(quotas, EvictionPolicy::Reject);
+    let drop_oldest = OutboundBuffer::new(None, Pipeline::default(), tx)
+        .with_quotas(quotas, EvictionPolicy::DropOldest);
+    for buffer in [&reject, &drop_oldest] {
+        buffer.push(Signal::Metrics, Bytes::from("mmmm"));
+    }
+
+    assert!(reject.quota_exhausted(Signal::Metrics));
+    assert!(!reject.quota_exhausted(Signal::Traces));
+    assert!(!drop_oldest.quota_exhausted(Signal::Metrics));
+}
+
+#[tokio::test]
+async fn reservations_and_flushed_data_count_towards_reject_quota() {
+    let (tx, _rx) = mpsc::channel(1);
+    let buffer = OutboundBuffer::new(Some(12), Pipeline::default(), tx)
+        .with_quotas([Some(8), None, None], EvictionPolicy::Reject);
+
+    assert!(buffer.reserve(Signal::Traces, 4));
+    assert!(buffer.reserve(Signal::Traces, 4));
+    assert!(!buffer.reserve(Signal::Traces, 4));
+    assert!(buffer.quota_exhausted(Signal::Traces));
+
+    // Buffered payloads swap their reservation for buffer space.
+    buffer.push(Signal::Traces, Bytes::from("t001"));
+    buffer.release(Signal::Traces, 4);
+    assert!(buffer.quota_exhausted(Signal::Traces));
+
+    // Data being flushed may come back, so it still counts.
+    assert!(buffer.spawn_flush(&Arc::new(SlowExporter)));
+    assert!(buffer.quota_exhausted(Signal::Traces));
+    buffer.join_flush_task().await;
+    assert!(!buffer.quota_exhausted(Signal::Traces));
+
+    // `max_bytes` covers every signal.
+    assert!(buffer.reserve(Signal::Logs, 8));
+    assert!(!buffer.reserve(Signal::Metrics, 1));
+}
+
+#[tokio::test]
+async fn failed_export_keeps_everything_under_reject_policy() {
+    let (tx, _rx) = mpsc::channel(1);
+    let buffer = OutboundBuffer::new(Some(4), Pipeline::default(), tx)
+        .with_quotas([Some(4), None, None], EvictionPolicy::Reject);
+    buffer.push(Signal::Traces, Bytes::from("old1"));
+    buffer.push(Signal::Traces, Bytes::from("new1"));
+
+    buffer.flush(&crate::testing::FailingExporter).await;
+
+    assert_eq!(buffer.stats().traces.payloads, 2);
+    assert_eq!(buffer.metrics().evicted(Signal::Traces), 0);
+}
+
+#[test]
+fn enforce_quotas_trims_oldest_payload_by_record() {
+    use prost::Message;
+
+    use crate::proto::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+    use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Span};
+
+    let spans = (0..10)
+        .map(|i| Span {
+            name: format!("span-{i}"),
+            ..Default::default()
+        })
+        .collect();
+    let payload = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans,
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    }
+    .encode_to_vec();
+    let len = payload.len();
+    let mut buf = BufferData::new();
+    buf.push(Signal::Traces, Bytes::from(payload));
+
+    let eviction = buf.enforce_quotas(&[Some(len - 10), None, None], EvictionPolicy::DropOldest);
+    // The payload is out of the buffer until it has been trimmed.
+    assert_eq!(buf.traces.size_bytes, 0);
+    let evicted = settle(&mut buf, eviction);
+
+    // Only the leading spans go; the rest of the payload stays buffered.
+    assert_eq!(evicted.records, [1, 0, 0]);
+    assert_eq!(buf.traces.queue.len(), 1);
+    assert_eq!(buf.traces.size_bytes, len - evicted.bytes[0]);
+    assert!(buf.traces.size_bytes <= len - 10);
+    let rest = ExportTraceServiceRequest::decode(buf.traces.queue[0].clone()).unwrap();
     assert_eq!(
-        rx.try_recv().unwrap(),
-        (),
-        "Expected notification for flush completion"
+        rest.resource_spans[0].scope_spans[0].spans[0].name,
+        "span-1"
     );
 }
+
+#[test]
+fn trimmed_remainder_goes_back_in_front_of_its_processing_stage() {
+    use prost::Message;
+
+    use crate::proto::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+    use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Span};
+
+    let payload = Bytes::from(
+        ExportTraceServiceRequest {
+            resource_spans: vec![ResourceSpans {
+                resource: None,
+                scope_spans: vec![ScopeSpans {
+                    scope: None,
+                    spans: vec![Span::default(); 10],
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        }
+        .encode_to_vec(),
+    );
+    let mut buf = BufferData::new();
+    buf.traces.push_processed(payload.clone());
+    let eviction = buf.evict_with(payload.len() - 1, EvictionPolicy::DropOldest);
+    assert!(buf.is_empty());
+
+    // A failed flush puts processed data back while the trim runs.
+    buf.traces.push_processed(Bytes::from("failed"));
+    buf.push(Signal::Traces, Bytes::from("fresh"));
+    let evicted = settle(&mut buf, eviction);
+
+    assert_eq!(evicted.records, [1, 0, 0]);
+    assert_eq!(buf.traces.queue.len(), 3);
+    assert_eq!(buf.traces.processed, 2);
+    assert_eq!(buf.traces.queue[1], Bytes::from("failed"));
+    assert_eq!(
+        buf.traces.size_bytes,
+        buf.traces.queue.iter().map(Bytes::len).sum::<usize>()
+    );
+    assert!(buf.traces.buffered_at.is_some());
+}
+
+#[test]
+fn discard_before_drops_only_entries_buffered_earlier() {
+    let (tx, _) = mpsc::channel(1);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx);
+    buffer.push(Signal::Traces, Bytes::from("old1"));
+    buffer.push(Signal::Logs, Bytes::from("old2"));
+    std::thread::sleep(Duration::from_millis(2));
+    let cutoff = SystemTime::now();
+    std::thread::sleep(Duration::from_millis(2));
+    buffer.push(Signal::Traces, Bytes::from("new1"));
+
+    assert_eq!(buffer.discard_before(cutoff), 8);
+    let data = buffer.take();
+    assert_eq!(data.traces.queue, [Bytes::from("new1")]);
+    assert!(data.logs.is_empty());
+}
+
+#[test]
+fn failed_flush_taken_before_discard_is_dropped() {
+    let (tx, _) = mpsc::channel(1);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx);
+    buffer.push(Signal::Traces, Bytes::from("old1"));
+    let taken_at = SystemTime::now();
+    let taken = buffer.take();
+    std::thread::sleep(Duration::from_millis(2));
+    let cutoff = SystemTime::now();
+    std::thread::sleep(Duration::from_millis(2));
+    buffer.push(Signal::Traces, Bytes::from("new1"));
+
+    assert_eq!(buffer.discard_before(cutoff), 0);
+    buffer.prepend_failed(taken, taken_at);
+    assert_eq!(buffer.stats().traces.payloads, 1);
+
+    // Data a flush took after the cutoff comes back as usual.
+    let taken = buffer.take();
+    buffer.prepend_failed(taken, SystemTime::now());
+    assert_eq!(buffer.take().traces.queue, [Bytes::from("new1")]);
+}
@@ -0,0 +1,206 @@
+//! Record-level eviction.
+//!
+//! Instead of discarding a whole OTLP request to free a few bytes, the oldest
+//! payload is decoded and its leading records (spans, metric data points, log
+//! records) are removed until enough bytes are freed. Metrics, scopes and
+//! resources left empty are pruned and the remainder is re-encoded.
+
+use bytes::Bytes;
+use prost::Message;
+use prost::encoding::encoded_len_varint;
+
+use super::Signal;
+use crate::proto::opentelemetry::proto::collector::{
+    logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+    trace::v1::ExportTraceServiceRequest,
+};
+use crate::proto::opentelemetry::proto::metrics::v1::{Metric, metric::Data};
+
+/// Remove records from the front of `payload` until at least `needed` bytes
+/// are freed.
+///
+/// Returns the re-encoded remainder and the number of records removed, or
+/// `None` if the payload does not decode or nothing would be left of it; the
+/// caller then evicts the payload whole.
+pub fn trim_front(signal: Signal, payload: &[u8], needed: usize) -> Option<(Bytes, usize)> {
+    match signal {
+        Signal::Traces => trim::<ExportTraceServiceRequest>(payload, needed),
+        Signal::Metrics => trim::<ExportMetricsServiceRequest>(payload, needed),
+        Signal::Logs => trim::<ExportLogsServiceRequest>(payload, needed),
+    }
+}
+
+/// Number of records in `payload`, or 0 if it does not decode.
+pub fn count_records(signal: Signal, payload: &[u8]) -> usize {
+    match signal {
+        Signal::Traces => count::<ExportTraceServiceRequest>(payload),
+        Signal::Metrics => count::<ExportMetricsServiceRequest>(payload),
+        Signal::Logs => count::<ExportLogsServiceRequest>(payload),
+    }
+}
+
+/// The per-signal request shapes, reduced to what trimming needs.
+trait Records: Message + Default {
+    fn count(&self) -> usize;
+
+    /// Drop leading records until their encoded size adds up to `needed`,
+    /// prune empty containers, and return how many records were dropped.
+    fn drop_front(&mut self, needed: usize) -> usize;
+}
+
+fn trim<R: Records>(payload: &[u8], needed: usize) -> Option<(Bytes, usize)> {
+    let mut req = R::decode(payload).ok()?;
+    let dropped = req.drop_front(needed);
+    if dropped == 0 || req.count() == 0 {
+        return None;
+    }
+    Some((Bytes::from(req.encode_to_vec()), dropped))
+}
+
+fn count<R: Records>(payload: &[u8]) -> usize {
+    R::decode(payload).map_or(0, |req| req.count())
+}
+
+/// Size of `item` as a length-delimited field of its parent. Every repeated
+/// field involved has a number below 16, so the tag is one byte.
+fn framed_len<M: Message>(item: &M) -> usize {
+    let len = item.encoded_len();
+    1 + encoded_len_varint(len as u64) + len
+}
+
+/// Remove leading `items` until `freed` reaches `needed`. Returns the number
+/// removed.
+///
+/// Parent length prefixes only shrink as children are removed, so the real
+/// saving is at least the sum of the framed sizes.
+fn drain_front<M: Message>(items: &mut Vec<M>, needed: usize, freed: &mut usize) -> usize {
+    let n = items
+        .iter()
+        .take_while(|item| {
+            if *freed >= needed {
+                return false;
+            }
+            *freed += framed_len(*item);
+            true
+        })
+        .count();
+    items.drain(..n);
+    n
+}
+
+impl Records for ExportTraceServiceRequest {
+    fn count(&self) -> usize {
+        self.resource_spans
+            .iter()
+            .flat_map(|rs| &rs.scope_spans)
+            .map(|ss| ss.spans.len())
+            .sum()
+    }
+
+    fn drop_front(&mut self, needed: usize) -> usize {
+        let mut freed = 0;
+        let mut dropped = 0;
+        for ss in self
+            .resource_spans
+            .iter_mut()
+            .flat_map(|rs| &mut rs.scope_spans)
+        {
+            dropped += drain_front(&mut ss.spans, needed, &mut freed);
+        }
+        for rs in &mut self.resource_spans {
+            rs.scope_spans.retain(|ss| !ss.spans.is_empty());
+        }
+        self.resource_spans.retain(|rs| !rs.scope_spans.is_empty());
+        dropped
+    }
+}
+
+/// Number of data points of `metric`.
+fn point_count(metric: &Metric) -> usize {
+    match &metric.data {
+        Some(Data::Gauge(g)) => g.data_points.len(),
+        Some(Data::Sum(s)) => s.data_points.len(),
+        Some(Data::Histogram(h)) => h.data_points.len(),
+        Some(Data::ExponentialHistogram(h)) => h.data_points.len(),
+        Some(Data::Summary(s)) => s.data_points.len(),
+        None => 0,
+    }
+}
+
+/// [`drain_front`] over the data points of `metric`.
+fn drain_points(metric: &mut Metric, needed: usize, freed: &mut usize) -> usize {
+    match &mut metric.data {
+        Some(Data::Gauge(g)) => drain_front(&mut g.data_points, needed, freed),
+        Some(Data::Sum(s)) => drain_front(&mut s.data_points, needed, freed),
+        Some(Data::Histogram(h)) => drain_front(&mut h.data_points, needed, freed),
+        Some(Data::ExponentialHistogram(h)) => drain_front(&mut h.data_points, needed, freed),
+        Some(Data::Summary(s)) => drain_front(&mut s.data_points, needed, freed),
+        None => 0,
+    }
+}
+
+/// Metrics are trimmed by data point: a single metric often carries most of
+/// a request, one point per attribute set.
+impl Records for ExportMetricsServiceRequest {
+    fn count(&self) -> usize {
+        self.resource_metrics
+            .iter()
+            .flat_map(|rm| &rm.scope_metrics)
+            .flat_map(|sm| &sm.metrics)
+            .map(point_count)
+            .sum()
+    }
+
+    fn drop_front(&mut self, needed: usize) -> usize {
+        let mut freed = 0;
+        let mut dropped = 0;
+        for sm in self
+            .resource_metrics
+            .iter_mut()
+            .flat_map(|rm| &mut rm.scope_metrics)
+        {
+            sm.metrics.retain_mut(|metric| {
+                let n = drain_points(metric, needed, &mut freed);
+                dropped += n;
+                n == 0 || point_count(metric) > 0
+            });
+        }
+        for rm in &mut self.resource_metrics {
+            rm.scope_metrics.retain(|sm| !sm.metrics.is_empty());
+        }
+        self.resource_metrics
+            .retain(|rm| !rm.scope_metrics.is_empty());
+        dropped
+    }
+}
+
+impl Records for ExportLogsServiceRequest {
+    fn count(&self) -> usize {
+        self.resource_logs
+            .iter()
+            .flat_map(|rl| &rl.scope_logs)
+            .map(|sl| sl.log_records.len())
+            .sum()
+    }
+
+    fn drop_front(&mut self, needed: usize) -> usize {
+        let mut freed = 0;
+        let mut dropped = 0;
+        for sl in self
+            .resource_logs
+            .iter_mut()
+            .flat_map(|rl| &mut rl.scope_logs)
+        {
+            dropped += drain_front(&mut sl.log_records, needed, &mut freed);
+        }
+        for rl in &mut self.resource_logs {
+            rl.scope_logs.retain(|sl| !sl.log_records.is_empty());
+        }
+        self.resource_logs.retain(|rl| !rl.scope_logs.is_empty());
+        dropped
+    }
+}
+
+#[cfg(test)]
+#[path = "trim_tests.rs"]
+mod tests;
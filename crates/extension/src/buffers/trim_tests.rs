@@ -0,0 +1,132 @@
+use super::*;
+use crate::proto::opentelemetry::proto::logs::v1::{LogRecord, ResourceLogs, ScopeLogs};
+use crate::proto::opentelemetry::proto::metrics::v1::{
+    Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, metric::Data,
+};
+use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Span};
+
+fn span(name: &str) -> Span {
+    Span {
+        name: name.to_string(),
+        span_id: vec![1; 8],
+        ..Default::default()
+    }
+}
+
+fn scope(names: &[&str]) -> ScopeSpans {
+    ScopeSpans {
+        scope: None,
+        spans: names.iter().map(|n| span(n)).collect(),
+        schema_url: String::new(),
+    }
+}
+
+fn request(scopes: Vec<Vec<ScopeSpans>>) -> Bytes {
+    let req = ExportTraceServiceRequest {
+        resource_spans: scopes
+            .into_iter()
+            .map(|scope_spans| ResourceSpans {
+                resource: None,
+                scope_spans,
+                schema_url: String::new(),
+            })
+            .collect(),
+    };
+    Bytes::from(req.encode_to_vec())
+}
+
+fn span_names(payload: &[u8]) -> Vec<Vec<Vec<String>>> {
+    ExportTraceServiceRequest::decode(payload)
+        .unwrap()
+        .resource_spans
+        .iter()
+        .map(|rs| {
+            rs.scope_spans
+                .iter()
+                .map(|ss| ss.spans.iter().map(|s| s.name.clone()).collect())
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn trims_oldest_records_until_enough_is_freed() {
+    let payload = request(vec![vec![scope(&["a", "b", "c", "d"])]]);
+    let one = framed_len(&span("a"));
+
+    let (rest, dropped) = trim_front(Signal::Traces, &payload, one + 1).unwrap();
+    assert_eq!(dropped, 2);
+    assert_eq!(span_names(&rest), vec![vec![vec!["c", "d"]]]);
+    assert!(payload.len() - rest.len() > one + 1);
+}
+
+#[test]
+fn prunes_emptied_scopes_and_resources() {
+    let payload = request(vec![
+        vec![scope(&["a"]), scope(&["b"])],
+        vec![scope(&["c", "d"])],
+    ]);
+    let two = 2 * framed_len(&span("a"));
+
+    let (rest, dropped) = trim_front(Signal::Traces, &payload, two).unwrap();
+    assert_eq!(dropped, 2);
+    assert_eq!(span_names(&rest), vec![vec![vec!["c", "d"]]]);
+}
+
+#[test]
+fn nothing_left_falls_back_to_whole_eviction() {
+    let payload = request(vec![vec![scope(&["a", "b"])]]);
+    assert!(trim_front(Signal::Traces, &payload, payload.len()).is_none());
+}
+
+#[test]
+fn undecodable_payload_is_not_trimmed() {
+    assert!(trim_front(Signal::Traces, &[0xff, 0xff], 1).is_none());
+    assert_eq!(count_records(Signal::Traces, &[0xff, 0xff]), 0);
+}
+
+#[test]
+fn counts_log_records() {
+    let req = ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs {
+            resource: None,
+            scope_logs: vec![ScopeLogs {
+                scope: None,
+                log_records: vec![LogRecord::default(); 3],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+    assert_eq!(count_records(Signal::Logs, &req.encode_to_vec()), 3);
+}
+
+#[test]
+fn trims_metrics_by_data_point() {
+    let gauge = |name: &str, points: usize| Metric {
+        name: name.to_owned(),
+        data: Some(Data::Gauge(Gauge {
+            data_points: vec![NumberDataPoint::default(); points],
+        })),
+        ..Default::default()
+    };
+    let req = ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            scope_metrics: vec![ScopeMetrics {
+                metrics: vec![gauge("a", 1), gauge("b", 10)],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    };
+    let point = framed_len(&NumberDataPoint::default());
+    assert_eq!(count_records(Signal::Metrics, &req.encode_to_vec()), 11);
+
+    let (rest, dropped) = trim_front(Signal::Metrics, &req.encode_to_vec(), 2 * point).unwrap();
+    assert_eq!(dropped, 2);
+    let rest = ExportMetricsServiceRequest::decode(rest).unwrap();
+    let metrics = &rest.resource_metrics[0].scope_metrics[0].metrics;
+    assert_eq!(metrics.len(), 1);
+    assert_eq!(metrics[0].name, "b");
+    assert_eq!(point_count(&metrics[0]), 9);
+}
@@ -3,11 +3,13 @@ use std::env;
 use std::path::Path;
 use std::time::Duration;
 
+use regex::Regex;
 use thiserror::Error;
 use url::Url;
 
 use crate::buffers::{EnabledSignals, Signal};
 use crate::flush_strategy::{FlushStrategy, FlushStrategyError};
+use crate::proto::opentelemetry::proto::{logs::v1::SeverityNumber, trace::v1::span::SpanKind};
 use crate::runtime_mode::RuntimeMode;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -20,7 +22,11 @@ pub enum Compression {
 pub enum ExportProtocol {
     #[default]
     HttpProtobuf,
+    HttpJson,
     Grpc,
+    /// X-Ray segment documents, sent to the daemon over UDP or to the
+    /// `PutTraceSegments` API.
+    Xray,
 }
 
 #[derive(Debug, Error)]
@@ -31,6 +37,9 @@ pub enum ConfigError {
     #[error("LAMBDA_OTEL_RELAY_ENDPOINT is not a valid URL: {0}")]
     EndpointInvalidUrl(String),
 
+    #[error("LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_ENDPOINT is not a valid URL: {0}")]
+    RemoteWriteEndpointInvalidUrl(String),
+
     #[error("{0} has invalid value: {1}")]
     InvalidNumeric(String, String),
 
@@ -52,7 +61,8 @@ pub enum ConfigError {
 
     #[error(
         "LAMBDA_OTEL_RELAY_ENDPOINT_SIGV4_SERVICE is set but AWS credentials are missing \
-             (need AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, and AWS_SESSION_TOKEN)"
+             (need AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, and AWS_SESSION_TOKEN, \
+             or AWS_CONTAINER_CREDENTIALS_FULL_URI)"
     )]
     SigV4MissingCredentials,
 
@@ -62,6 +72,19 @@ pub enum ConfigError {
     )]
     SigV4MissingRegion,
 
+    #[error(
+        "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4 is set but AWS credentials are missing \
+             (need AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, and AWS_SESSION_TOKEN, \
+             or AWS_CONTAINER_CREDENTIALS_FULL_URI)"
+    )]
+    RemoteWriteSigV4MissingCredentials,
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4 is set but no AWS region found \
+             (set LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4_REGION, AWS_REGION, or AWS_DEFAULT_REGION)"
+    )]
+    RemoteWriteSigV4MissingRegion,
+
     #[error(
         "LAMBDA_OTEL_RELAY_SIGNALS contains unknown signal: {0} (expected \"traces\", \"metrics\", or \"logs\")"
     )]
@@ -71,9 +94,97 @@ pub enum ConfigError {
     NoSignalsEnabled,
 
     #[error(
-        "LAMBDA_OTEL_RELAY_PROTOCOL has invalid value: {0} (expected \"http/protobuf\" or \"grpc\")"
+        "LAMBDA_OTEL_RELAY_PROTOCOL has invalid value: {0} (expected \"http/protobuf\", \"http/json\", \"grpc\", or \"xray\")"
     )]
     InvalidProtocol(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_ENDPOINT has unsupported scheme for the xray protocol: {0} \
+             (expected udp://, http:// or https://)"
+    )]
+    InvalidXrayEndpoint(String),
+
+    #[error("{0} has invalid value: {1} (expected a number between 0 and 1)")]
+    InvalidRatio(String, String),
+
+    #[error("{0} has invalid value: {1} (expected \"true\" or \"false\")")]
+    InvalidBool(String, String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_SAMPLING_MODE has invalid value: {0} (expected \"head\" or \"tail\")"
+    )]
+    InvalidSamplingMode(String),
+
+    #[error("LAMBDA_OTEL_RELAY_SAMPLING_MODE is set but LAMBDA_OTEL_RELAY_SAMPLING_RATIO is not")]
+    SamplingRatioMissing,
+
+    #[error("{0} is not a valid regular expression: {1}")]
+    InvalidRegex(String, String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_FILTER_SPAN_KINDS contains unknown span kind: {0} \
+             (expected \"internal\", \"server\", \"client\", \"producer\", or \"consumer\")"
+    )]
+    InvalidSpanKind(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_FILTER_SPAN_ATTRIBUTES contains invalid rule: {0} (expected key=value)"
+    )]
+    InvalidAttributeRule(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_FILTER_LOG_MIN_SEVERITY has invalid value: {0} \
+             (expected 1-24 or \"trace\", \"debug\", \"info\", \"warn\", \"error\", \"fatal\")"
+    )]
+    InvalidSeverity(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_EVICTION_POLICY has invalid value: {0} \
+             (expected \"drop-oldest\", \"reject\", or \"priority\")"
+    )]
+    InvalidEvictionPolicy(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_RESTORE_BUFFER_POLICY has invalid value: {0} \
+             (expected \"discard\" or \"keep\")"
+    )]
+    InvalidRestorePolicy(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_EMF_MODE has invalid value: {0} \
+             (expected \"standalone\" or \"alongside\")"
+    )]
+    InvalidEmfMode(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY has invalid value: {0} \
+             (expected \"delta\" or \"cumulative\")"
+    )]
+    InvalidTemporality(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY_INITIAL_VALUE has invalid value: {0} \
+             (expected \"auto\", \"keep\", or \"drop\")"
+    )]
+    InvalidInitialValue(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_SPANMETRICS_BUCKETS_MS has invalid value: {0} \
+             (expected increasing numbers separated by commas)"
+    )]
+    InvalidBuckets(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_RESOURCE_ATTRIBUTES contains invalid member: {0} \
+             (expected key=value, optionally followed by \";insert\" or \";delete\")"
+    )]
+    InvalidResourceAttribute(String),
+
+    #[error(
+        "LAMBDA_OTEL_RELAY_TRANSFORM contains invalid rule: {0} \
+             (expected rename(from, to), copy(from, to), or set_if_absent(to, value))"
+    )]
+    InvalidTransformRule(String),
 }
 
 /// Configuration for AWS SigV4 request signing.
@@ -87,15 +198,294 @@ pub enum ConfigError {
 /// The signing region is read from `LAMBDA_OTEL_RELAY_ENDPOINT_SIGV4_REGION`, falling
 /// back to `AWS_REGION` then `AWS_DEFAULT_REGION`.
 ///
-/// AWS credentials (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`,
-/// `AWS_SESSION_TOKEN`) must be present at startup and are re-read on each
-/// export to handle Lambda credential rotation.
+/// AWS credentials come from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+/// `AWS_SESSION_TOKEN`, re-read on each export to handle Lambda credential
+/// rotation, or, where Lambda does not set those (SnapStart), from the
+/// container credentials endpoint in `AWS_CONTAINER_CREDENTIALS_FULL_URI`,
+/// authorized with `AWS_CONTAINER_AUTHORIZATION_TOKEN`. One of the two must
+/// be available at startup.
 #[derive(Debug, Clone)]
 pub struct SigV4Config {
     pub service: String,
     pub region: String,
 }
 
+/// Prometheus remote-write export for metrics, set up by
+/// `LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_ENDPOINT`.
+///
+/// Headers and SigV4 signing are configured separately from the OTLP
+/// endpoint's, from `LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_HEADERS` and
+/// `LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4`. The signing service
+/// defaults to `aps` (Amazon Managed Service for Prometheus).
+#[derive(Debug, Clone)]
+pub struct RemoteWriteConfig {
+    pub endpoint: Url,
+    pub headers: Vec<(String, String)>,
+    pub sigv4: Option<SigV4Config>,
+}
+
+/// What happens when a signal's quota or the whole buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Evict the oldest payloads, round-robin across signals.
+    #[default]
+    DropOldest,
+    /// Reject new payloads at the listener with 503 once a signal's quota or
+    /// the buffer is full, and never evict accepted payloads.
+    Reject,
+    /// Evict the oldest payloads of the least important signal first:
+    /// traces, then logs, then metrics.
+    Priority,
+}
+
+/// What happens to data buffered before a SnapStart snapshot when the
+/// environment is restored from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestorePolicy {
+    /// Drop it. Every environment restored from the snapshot holds the same
+    /// data, so exporting it would duplicate it once per restore.
+    #[default]
+    Discard,
+    /// Export it with the next flush.
+    Keep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SamplingMode {
+    /// Sample each buffered span by trace ID at export time.
+    #[default]
+    Head,
+    /// Hold an invocation's spans until `platform.runtimeDone`, keep all of
+    /// them for failed or slow invocations and sample the rest.
+    Tail,
+}
+
+/// Configuration for probabilistic trace sampling.
+///
+/// Enabled by setting `LAMBDA_OTEL_RELAY_SAMPLING_RATIO` to the fraction of
+/// traces to keep. With `LAMBDA_OTEL_RELAY_SAMPLING_KEEP_ERRORS=true`, spans
+/// with an error status are kept regardless of the sampling decision.
+///
+/// `LAMBDA_OTEL_RELAY_SAMPLING_MODE=tail` switches to invocation-scoped tail
+/// sampling; `LAMBDA_OTEL_RELAY_SAMPLING_LATENCY_THRESHOLD_MS` then keeps
+/// every invocation that ran at least that long.
+#[derive(Debug, Clone)]
+pub struct SamplingConfig {
+    pub ratio: f64,
+    pub keep_errors: bool,
+    pub mode: SamplingMode,
+    pub latency_threshold: Option<Duration>,
+}
+
+/// Whether metrics written as CloudWatch EMF are also exported over OTLP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmfMode {
+    /// EMF only: metrics are not exported.
+    #[default]
+    Standalone,
+    /// EMF and OTLP export.
+    Alongside,
+}
+
+/// CloudWatch Embedded Metric Format output for metrics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmfConfig {
+    pub namespace: String,
+    /// Attributes used as dimensions, looked up on the data point and then
+    /// the resource.
+    pub dimensions: Vec<String>,
+    pub mode: EmfMode,
+}
+
+/// Aggregation temporality that sums and histograms are converted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Temporality {
+    Delta,
+    Cumulative,
+}
+
+/// What happens to the first cumulative point of a stream when converting to
+/// delta: there is no earlier point to subtract, so its whole value would
+/// become the first delta. The same applies after the producer restarted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InitialValue {
+    /// Keep it if the stream started after the relay did (a fresh runtime in
+    /// a fresh environment), drop it otherwise.
+    #[default]
+    Auto,
+    Keep,
+    Drop,
+}
+
+/// Temporality conversion for metrics, keyed by stream identity: resource,
+/// scope, metric name and data point attributes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemporalityConfig {
+    pub target: Temporality,
+    pub initial_value: InitialValue,
+    /// Streams without a point for this long are forgotten. `None` keeps
+    /// them for the lifetime of the environment.
+    pub max_staleness: Option<Duration>,
+    /// Most streams tracked at once. `None` for no limit.
+    pub max_streams: Option<usize>,
+}
+
+impl TemporalityConfig {
+    /// Conversion to delta with the default settings, used for EMF output
+    /// of metrics that are still cumulative.
+    pub fn delta() -> Self {
+        Self {
+            target: Temporality::Delta,
+            initial_value: InitialValue::Auto,
+            max_staleness: None,
+            max_streams: Some(DEFAULT_TEMPORALITY_MAX_STREAMS),
+        }
+    }
+}
+
+/// Request, error and duration metrics derived from spans.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanMetricsConfig {
+    /// Span attributes added to the metrics, on top of the span name, kind
+    /// and status code.
+    pub dimensions: Vec<String>,
+    /// Duration histogram bucket bounds, in milliseconds.
+    pub buckets: Vec<f64>,
+}
+
+/// Multi-concurrency handling on Lambda Managed Instances, where invocations
+/// are followed through Telemetry API platform events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConcurrencyConfig {
+    /// How long the environment must stay idle (no invocation running) before
+    /// the buffer is flushed.
+    pub idle_flush_delay: Duration,
+    /// Buffer budget per concurrent invocation. The buffer limit grows with
+    /// the most invocations seen at once, up to `buffer_max_bytes`.
+    pub bytes_per_invocation: Option<usize>,
+}
+
+/// Configuration for attribute redaction.
+///
+/// Enabled when any of the `LAMBDA_OTEL_RELAY_REDACT_*` variables is set.
+/// `DELETE` and `HASH` are comma-separated attribute key globs (`*` matches
+/// any run of characters); `MASK` is a regular expression whose matches are
+/// replaced in string values that were neither deleted nor hashed.
+#[derive(Debug, Clone)]
+pub struct RedactionConfig {
+    pub delete_keys: Vec<String>,
+    pub hash_keys: Vec<String>,
+    pub hash_salt: String,
+    pub mask: Option<Regex>,
+}
+
+/// A change applied to the attributes of every resource before export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceAttribute {
+    pub key: String,
+    pub action: ResourceAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceAction {
+    /// Set the value unless the resource already has the key.
+    Insert(String),
+    /// Set the value, replacing any existing one.
+    Upsert(String),
+    Delete,
+}
+
+/// An attribute transform rule, e.g. `rename(http.method, http.request.method)`.
+///
+/// `to` is set from `from` on every span, metric data point or log record;
+/// when the two are on different levels, attributes are promoted or demoted
+/// between the record and its scope or resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransformRule {
+    pub op: TransformOp,
+    pub from: TransformSource,
+    pub to: AttributeRef,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformOp {
+    /// Set `to` and remove `from`.
+    Rename,
+    /// Set `to`, keeping `from`.
+    Copy,
+    /// Set `to` unless it is already set.
+    SetIfAbsent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransformSource {
+    Attribute(AttributeRef),
+    /// A quoted string, e.g. `"prod"`.
+    Literal(String),
+}
+
+/// An attribute key, prefixed with `resource:` or `scope:` unless it belongs
+/// to the record itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeRef {
+    pub level: AttributeLevel,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeLevel {
+    Resource,
+    Scope,
+    /// A span, metric data point or log record.
+    Record,
+}
+
+/// Size limits enforced on spans and log records before export, so that
+/// oversized payloads aren't rejected by the collector and retried forever.
+///
+/// Enabled when any of the limits is set; each one is off when unset or `0`.
+/// String and byte values longer than `attribute_value_length` are truncated
+/// in every attribute list. Attributes beyond `attribute_count` on a span,
+/// span event, link or log record, and events and links beyond their counts
+/// on a span, are dropped and added to the matching `dropped_*_count`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LimitsConfig {
+    pub attribute_value_length: Option<usize>,
+    pub attribute_count: Option<usize>,
+    pub event_count: Option<usize>,
+    pub link_count: Option<usize>,
+}
+
+/// Configuration for drop rules applied before export.
+///
+/// A span is dropped when its name matches a `FILTER_SPAN_NAMES` glob, its
+/// kind is listed in `FILTER_SPAN_KINDS`, or one of its attributes matches a
+/// `FILTER_SPAN_ATTRIBUTES` `key=value` rule (the value is a glob). Metrics
+/// are dropped by name prefix, log records below a minimum severity number,
+/// and whole scopes of any signal by instrumentation scope name glob.
+#[derive(Debug, Clone, Default)]
+pub struct FilterConfig {
+    pub span_names: Vec<String>,
+    pub span_kinds: Vec<SpanKind>,
+    pub span_attributes: Vec<(String, String)>,
+    pub metric_prefixes: Vec<String>,
+    pub log_min_severity: Option<i32>,
+    pub scopes: Vec<String>,
+}
+
+/// Per-signal flush triggers, separate from the `BUFFER_MAX_BYTES` eviction
+/// cap.
+///
+/// Read from `LAMBDA_OTEL_RELAY_<SIGNAL>_FLUSH_BYTES`, `_FLUSH_PAYLOADS` and
+/// `_FLUSH_AGE_MS`. Reaching any of them starts a background flush of that
+/// signal alone; `0` or unset disables the trigger.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FlushThresholds {
+    pub bytes: Option<usize>,
+    pub payloads: Option<usize>,
+    pub age: Option<Duration>,
+}
+
 #[derive(Debug)]
 pub struct Config {
     pub endpoint: Url,
@@ -105,6 +495,12 @@ pub struct Config {
     pub compression: Compression,
     pub export_headers: Vec<(String, String)>,
     pub buffer_max_bytes: Option<usize>,
+    /// Indexed by `Signal as usize`.
+    pub flush_thresholds: [FlushThresholds; 3],
+    /// Hard per-signal byte limits, indexed by `Signal as usize`.
+    pub buffer_quotas: [Option<usize>; 3],
+    pub eviction_policy: EvictionPolicy,
+    pub restore_policy: RestorePolicy,
     pub flush_strategy: FlushStrategy,
     pub tls_ca: Option<Vec<u8>>,
     pub tls_client_cert: Option<Vec<u8>>,
@@ -112,6 +508,32 @@ pub struct Config {
     pub sigv4: Option<SigV4Config>,
     pub enabled_signals: EnabledSignals,
     pub protocol: ExportProtocol,
+    pub sampling: Option<SamplingConfig>,
+    pub redaction: Option<RedactionConfig>,
+    pub filter: Option<FilterConfig>,
+    pub limits: Option<LimitsConfig>,
+    pub resource_attributes: Vec<ResourceAttribute>,
+    pub transform: Vec<TransformRule>,
+    /// How often to inject the relay's own metrics, if enabled.
+    pub self_metrics_interval: Option<Duration>,
+    /// Set on Lambda Managed Instances only.
+    pub concurrency: Option<ConcurrencyConfig>,
+    /// Parent root spans on the invocation's X-Ray segment.
+    pub xray_parent: bool,
+    /// Span attributes exported as X-Ray annotations rather than metadata.
+    pub xray_annotations: Vec<String>,
+    /// Export spans whose trace ID does not start with a recent timestamp
+    /// to X-Ray instead of dropping them.
+    pub xray_skip_timestamp_validation: bool,
+    /// Most attribute sets exported per metric, including the overflow series.
+    pub metrics_cardinality_limit: Option<usize>,
+    /// Merge data points of the same stream within each flush.
+    pub aggregate_metrics: bool,
+    pub temporality: Option<TemporalityConfig>,
+    pub emf: Option<EmfConfig>,
+    pub spanmetrics: Option<SpanMetricsConfig>,
+    /// Metrics are sent with Prometheus remote-write instead of OTLP.
+    pub remote_write: Option<RemoteWriteConfig>,
 }
 
 impl Config {
@@ -123,13 +545,29 @@ impl Config {
     }
 
     fn parse(vars: &HashMap<String, String>, mode: RuntimeMode) -> Result<Self, ConfigError> {
-        let endpoint = parse_endpoint(vars)?;
+        let protocol = parse_protocol(vars)?;
+        let endpoint = match protocol {
+            ExportProtocol::Xray => parse_xray_endpoint(vars)?,
+            _ => parse_endpoint(vars)?,
+        };
         let listener_port = parse_port(vars, "LAMBDA_OTEL_RELAY_LISTENER_PORT", 4318)?;
         let telemetry_port = parse_port(vars, "LAMBDA_OTEL_RELAY_TELEMETRY_PORT", 4319)?;
         let export_timeout = parse_duration_ms(vars, "LAMBDA_OTEL_RELAY_EXPORT_TIMEOUT_MS", 5000)?;
         let compression = parse_compression(vars)?;
-        let export_headers = parse_headers(vars);
+        let export_headers = parse_headers(vars, "LAMBDA_OTEL_RELAY_EXPORT_HEADERS");
         let buffer_max_bytes = parse_buffer_max_bytes(vars, "LAMBDA_OTEL_RELAY_BUFFER_MAX_BYTES")?;
+        let flush_thresholds = [
+            parse_flush_thresholds(vars, Signal::Traces)?,
+            parse_flush_thresholds(vars, Signal::Metrics)?,
+            parse_flush_thresholds(vars, Signal::Logs)?,
+        ];
+        let buffer_quotas = [
+            parse_optional_count(vars, "LAMBDA_OTEL_RELAY_TRACES_QUOTA_BYTES")?,
+            parse_optional_count(vars, "LAMBDA_OTEL_RELAY_METRICS_QUOTA_BYTES")?,
+            parse_optional_count(vars, "LAMBDA_OTEL_RELAY_LOGS_QUOTA_BYTES")?,
+        ];
+        let eviction_policy = parse_eviction_policy(vars)?;
+        let restore_policy = parse_restore_policy(vars)?;
         let raw_strategy = vars
             .get("LAMBDA_OTEL_RELAY_FLUSH_STRATEGY")
             .map(|s| s.as_str())
@@ -155,6 +593,19 @@ impl Config {
             flush_strategy
         };
 
+        let xray_parent = parse_bool(vars, "LAMBDA_OTEL_RELAY_XRAY_PARENT", false)?;
+        let concurrency = if mode.is_managed_instances() {
+            Some(ConcurrencyConfig {
+                idle_flush_delay: parse_duration_ms(vars, "LAMBDA_OTEL_RELAY_IDLE_FLUSH_MS", 100)?,
+                bytes_per_invocation: parse_optional_count(
+                    vars,
+                    "LAMBDA_OTEL_RELAY_BUFFER_BYTES_PER_INVOCATION",
+                )?,
+            })
+        } else {
+            None
+        };
+
         let tls_ca = parse_certificate_file(vars, "LAMBDA_OTEL_RELAY_CERTIFICATE")?;
         let tls_client_cert = parse_certificate_file(vars, "LAMBDA_OTEL_RELAY_CLIENT_CERT")?;
         let tls_client_key = parse_certificate_file(vars, "LAMBDA_OTEL_RELAY_CLIENT_KEY")?;
@@ -164,8 +615,57 @@ impl Config {
         }
 
         let sigv4 = parse_sigv4(vars)?;
-        let enabled_signals = parse_enabled_signals(vars)?;
-        let protocol = parse_protocol(vars)?;
+        let mut enabled_signals = parse_enabled_signals(vars)?;
+        let mut emf = parse_emf(vars)?;
+        let remote_write = parse_remote_write(vars)?;
+        if protocol == ExportProtocol::Xray {
+            if vars
+                .get("LAMBDA_OTEL_RELAY_SIGNALS")
+                .is_some_and(|s| !s.is_empty())
+                && enabled_signals.is_enabled(Signal::Logs)
+            {
+                tracing::warn!("the xray protocol exports traces only; ignoring logs");
+            }
+            // Metrics can still be written as EMF or sent with remote-write.
+            let metrics = enabled_signals.is_enabled(Signal::Metrics)
+                && (emf.is_some() || remote_write.is_some());
+            if let Some(emf) = emf
+                .as_mut()
+                .filter(|emf| emf.mode == EmfMode::Alongside && remote_write.is_none())
+            {
+                tracing::warn!("the xray protocol cannot export metrics; writing them as EMF only");
+                emf.mode = EmfMode::Standalone;
+            }
+            enabled_signals = EnabledSignals::from_signals(
+                [Signal::Traces]
+                    .into_iter()
+                    .chain(metrics.then_some(Signal::Metrics)),
+            );
+        }
+        let xray_annotations = parse_list(vars, "LAMBDA_OTEL_RELAY_XRAY_ANNOTATIONS");
+        let xray_skip_timestamp_validation = parse_bool(
+            vars,
+            "LAMBDA_OTEL_RELAY_XRAY_SKIP_TIMESTAMP_VALIDATION",
+            false,
+        )?;
+        let sampling = parse_sampling(vars)?;
+        let redaction = parse_redaction(vars)?;
+        let filter = parse_filter(vars)?;
+        let limits = parse_limits(vars)?;
+        let resource_attributes = parse_resource_attributes(vars)?;
+        let transform = parse_transform(vars)?;
+        let metrics_cardinality_limit =
+            parse_optional_count(vars, "LAMBDA_OTEL_RELAY_METRICS_CARDINALITY_LIMIT")?;
+        let aggregate_metrics = parse_bool(vars, "LAMBDA_OTEL_RELAY_METRICS_AGGREGATE", false)?;
+        let temporality = parse_temporality(vars)?;
+        let mut spanmetrics = parse_spanmetrics(vars)?;
+        if spanmetrics.is_some() && !enabled_signals.is_enabled(Signal::Metrics) {
+            tracing::warn!("span metrics need the metrics signal to be exported; ignoring");
+            spanmetrics = None;
+        }
+        let self_metrics_interval = parse_bool(vars, "LAMBDA_OTEL_RELAY_SELF_METRICS", false)?
+            .then(|| parse_duration_ms(vars, "LAMBDA_OTEL_RELAY_SELF_METRICS_INTERVAL_MS", 60_000))
+            .transpose()?;
 
         Ok(Self {
             endpoint,
@@ -175,6 +675,10 @@ impl Config {
             compression,
             export_headers,
             buffer_max_bytes,
+            flush_thresholds,
+            buffer_quotas,
+            eviction_policy,
+            restore_policy,
             flush_strategy,
             tls_ca,
             tls_client_cert,
@@ -182,8 +686,34 @@ impl Config {
             sigv4,
             enabled_signals,
             protocol,
+            sampling,
+            redaction,
+            filter,
+            limits,
+            resource_attributes,
+            transform,
+            self_metrics_interval,
+            concurrency,
+            xray_parent,
+            xray_annotations,
+            xray_skip_timestamp_validation,
+            metrics_cardinality_limit,
+            aggregate_metrics,
+            temporality,
+            emf,
+            spanmetrics,
+            remote_write,
         })
     }
+
+    /// Signals the OTLP listener accepts: the exported ones, plus traces when
+    /// they are only used to derive span metrics.
+    pub fn accepted_signals(&self) -> EnabledSignals {
+        EnabledSignals::from_signals(Signal::ALL.into_iter().filter(|&signal| {
+            self.enabled_signals.is_enabled(signal)
+                || (signal == Signal::Traces && self.spanmetrics.is_some())
+        }))
+    }
 }
 
 fn parse_endpoint(vars: &HashMap<String, String>) -> Result<Url, ConfigError> {
@@ -195,6 +725,76 @@ fn parse_endpoint(vars: &HashMap<String, String>) -> Result<Url, ConfigError> {
     Url::parse(raw).map_err(|_| ConfigError::EndpointInvalidUrl(raw.clone()))
 }
 
+/// The X-Ray exporter defaults to the daemon Lambda runs next to the
+/// function. `AWS_XRAY_DAEMON_ADDRESS` is either `host:port` or
+/// `tcp:host:port udp:host:port`.
+fn parse_xray_endpoint(vars: &HashMap<String, String>) -> Result<Url, ConfigError> {
+    if vars
+        .get("LAMBDA_OTEL_RELAY_ENDPOINT")
+        .is_some_and(|s| !s.is_empty())
+    {
+        let url = parse_endpoint(vars)?;
+        return match url.scheme() {
+            "udp" | "http" | "https" => Ok(url),
+            _ => Err(ConfigError::InvalidXrayEndpoint(url.to_string())),
+        };
+    }
+    let daemon = vars
+        .get("AWS_XRAY_DAEMON_ADDRESS")
+        .filter(|s| !s.is_empty())
+        .ok_or(ConfigError::EndpointMissing)?;
+    let address = daemon
+        .split_whitespace()
+        .find_map(|token| token.strip_prefix("udp:"))
+        .unwrap_or(daemon.trim());
+    Url::parse(&format!("udp://{address}"))
+        .ok()
+        .filter(|url| url.host().is_some() && url.port().is_some())
+        .ok_or_else(|| ConfigError::EndpointInvalidUrl(daemon.clone()))
+}
+
+fn parse_remote_write(
+    vars: &HashMap<String, String>,
+) -> Result<Option<RemoteWriteConfig>, ConfigError> {
+    let Some(raw) = vars
+        .get("LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_ENDPOINT")
+        .filter(|s| !s.is_empty())
+    else {
+        return Ok(None);
+    };
+    let endpoint =
+        Url::parse(raw).map_err(|_| ConfigError::RemoteWriteEndpointInvalidUrl(raw.clone()))?;
+
+    let sigv4 = if parse_bool(
+        vars,
+        "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4",
+        false,
+    )? {
+        let service = vars
+            .get("LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4_SERVICE")
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .unwrap_or_else(|| "aps".to_owned());
+        let region = sigv4_region(
+            vars,
+            "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4_REGION",
+        )
+        .ok_or(ConfigError::RemoteWriteSigV4MissingRegion)?;
+        if !has_aws_credentials(vars) {
+            return Err(ConfigError::RemoteWriteSigV4MissingCredentials);
+        }
+        Some(SigV4Config { service, region })
+    } else {
+        None
+    };
+
+    Ok(Some(RemoteWriteConfig {
+        endpoint,
+        headers: parse_headers(vars, "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_HEADERS"),
+        sigv4,
+    }))
+}
+
 fn parse_port(
     vars: &HashMap<String, String>,
     name: &str,
@@ -254,6 +854,162 @@ fn parse_buffer_max_bytes(
     }
 }
 
+/// A count where `0` means "disabled".
+fn parse_optional_count(
+    vars: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<usize>, ConfigError> {
+    match vars.get(name) {
+        Some(val) => {
+            let n: usize = val
+                .parse()
+                .map_err(|_| ConfigError::InvalidNumeric(name.to_owned(), val.clone()))?;
+            Ok((n > 0).then_some(n))
+        }
+        None => Ok(None),
+    }
+}
+
+fn parse_flush_thresholds(
+    vars: &HashMap<String, String>,
+    signal: Signal,
+) -> Result<FlushThresholds, ConfigError> {
+    let prefix = format!(
+        "LAMBDA_OTEL_RELAY_{}_FLUSH",
+        signal.name().to_ascii_uppercase()
+    );
+    let age_var = format!("{prefix}_AGE_MS");
+    let age = match vars.get(&age_var) {
+        Some(_) => Some(parse_duration_ms(vars, &age_var, 0)?).filter(|age| !age.is_zero()),
+        None => None,
+    };
+    Ok(FlushThresholds {
+        bytes: parse_optional_count(vars, &format!("{prefix}_BYTES"))?,
+        payloads: parse_optional_count(vars, &format!("{prefix}_PAYLOADS"))?,
+        age,
+    })
+}
+
+fn parse_eviction_policy(vars: &HashMap<String, String>) -> Result<EvictionPolicy, ConfigError> {
+    match vars
+        .get("LAMBDA_OTEL_RELAY_EVICTION_POLICY")
+        .map(|s| s.as_str())
+    {
+        None | Some("") | Some("drop-oldest") => Ok(EvictionPolicy::DropOldest),
+        Some("reject") | Some("drop-newest") => Ok(EvictionPolicy::Reject),
+        Some("priority") => Ok(EvictionPolicy::Priority),
+        Some(other) => Err(ConfigError::InvalidEvictionPolicy(other.to_owned())),
+    }
+}
+
+fn parse_restore_policy(vars: &HashMap<String, String>) -> Result<RestorePolicy, ConfigError> {
+    match vars
+        .get("LAMBDA_OTEL_RELAY_RESTORE_BUFFER_POLICY")
+        .map(|s| s.as_str())
+    {
+        None | Some("") | Some("discard") => Ok(RestorePolicy::Discard),
+        Some("keep") => Ok(RestorePolicy::Keep),
+        Some(other) => Err(ConfigError::InvalidRestorePolicy(other.to_owned())),
+    }
+}
+
+const DEFAULT_TEMPORALITY_MAX_STREAMS: usize = 10_000;
+
+fn parse_temporality(
+    vars: &HashMap<String, String>,
+) -> Result<Option<TemporalityConfig>, ConfigError> {
+    let target = match vars
+        .get("LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY")
+        .map(|s| s.as_str())
+    {
+        None | Some("") => return Ok(None),
+        Some("delta") => Temporality::Delta,
+        Some("cumulative") => Temporality::Cumulative,
+        Some(other) => return Err(ConfigError::InvalidTemporality(other.to_owned())),
+    };
+    let initial_value = match vars
+        .get("LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY_INITIAL_VALUE")
+        .map(|s| s.as_str())
+    {
+        None | Some("") | Some("auto") => InitialValue::Auto,
+        Some("keep") => InitialValue::Keep,
+        Some("drop") => InitialValue::Drop,
+        Some(other) => return Err(ConfigError::InvalidInitialValue(other.to_owned())),
+    };
+    let max_staleness = parse_duration_ms(
+        vars,
+        "LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY_MAX_STALENESS_MS",
+        0,
+    )?;
+    let max_streams = match vars.get("LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY_MAX_STREAMS") {
+        Some(_) => parse_optional_count(vars, "LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY_MAX_STREAMS")?,
+        None => Some(DEFAULT_TEMPORALITY_MAX_STREAMS),
+    };
+    Ok(Some(TemporalityConfig {
+        target,
+        initial_value,
+        max_staleness: (!max_staleness.is_zero()).then_some(max_staleness),
+        max_streams,
+    }))
+}
+
+const DEFAULT_SPANMETRICS_BUCKETS_MS: [f64; 16] = [
+    2.0, 4.0, 6.0, 8.0, 10.0, 50.0, 100.0, 200.0, 400.0, 800.0, 1000.0, 1400.0, 2000.0, 5000.0,
+    10_000.0, 15_000.0,
+];
+
+fn parse_spanmetrics(
+    vars: &HashMap<String, String>,
+) -> Result<Option<SpanMetricsConfig>, ConfigError> {
+    if !parse_bool(vars, "LAMBDA_OTEL_RELAY_SPANMETRICS", false)? {
+        return Ok(None);
+    }
+    let buckets = match vars
+        .get("LAMBDA_OTEL_RELAY_SPANMETRICS_BUCKETS_MS")
+        .filter(|s| !s.is_empty())
+    {
+        None => DEFAULT_SPANMETRICS_BUCKETS_MS.to_vec(),
+        Some(raw) => {
+            let buckets: Vec<f64> = parse_list(vars, "LAMBDA_OTEL_RELAY_SPANMETRICS_BUCKETS_MS")
+                .iter()
+                .map(|b| b.parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| ConfigError::InvalidBuckets(raw.clone()))?;
+            if buckets.iter().any(|b| !b.is_finite()) || !buckets.is_sorted_by(|a, b| a < b) {
+                return Err(ConfigError::InvalidBuckets(raw.clone()));
+            }
+            buckets
+        }
+    };
+    Ok(Some(SpanMetricsConfig {
+        dimensions: parse_list(vars, "LAMBDA_OTEL_RELAY_SPANMETRICS_DIMENSIONS"),
+        buckets,
+    }))
+}
+
+fn parse_emf(vars: &HashMap<String, String>) -> Result<Option<EmfConfig>, ConfigError> {
+    let Some(namespace) = vars
+        .get("LAMBDA_OTEL_RELAY_EMF_NAMESPACE")
+        .filter(|s| !s.is_empty())
+    else {
+        return Ok(None);
+    };
+    let mode = match vars.get("LAMBDA_OTEL_RELAY_EMF_MODE").map(|s| s.as_str()) {
+        None | Some("") | Some("standalone") => EmfMode::Standalone,
+        Some("alongside") => EmfMode::Alongside,
+        Some(other) => return Err(ConfigError::InvalidEmfMode(other.to_owned())),
+    };
+    let dimensions = match vars.get("LAMBDA_OTEL_RELAY_EMF_DIMENSIONS") {
+        Some(_) => parse_list(vars, "LAMBDA_OTEL_RELAY_EMF_DIMENSIONS"),
+        None => vec!["service.name".to_owned()],
+    };
+    Ok(Some(EmfConfig {
+        namespace: namespace.clone(),
+        dimensions,
+        mode,
+    }))
+}
+
 fn parse_certificate_file(
     vars: &HashMap<String, String>,
     name: &'static str,
@@ -282,28 +1038,42 @@ fn parse_sigv4(vars: &HashMap<String, String>) -> Result<Option<SigV4Config>, Co
         None => return Ok(None),
     };
 
-    let region = vars
-        .get("LAMBDA_OTEL_RELAY_ENDPOINT_SIGV4_REGION")
+    let region = sigv4_region(vars, "LAMBDA_OTEL_RELAY_ENDPOINT_SIGV4_REGION")
+        .ok_or(ConfigError::SigV4MissingRegion)?;
+    if !has_aws_credentials(vars) {
+        return Err(ConfigError::SigV4MissingCredentials);
+    }
+
+    Ok(Some(SigV4Config { service, region }))
+}
+
+/// The signing region: `name`, falling back to `AWS_REGION` then
+/// `AWS_DEFAULT_REGION`.
+fn sigv4_region(vars: &HashMap<String, String>, name: &str) -> Option<String> {
+    vars.get(name)
         .or_else(|| vars.get("AWS_REGION"))
         .or_else(|| vars.get("AWS_DEFAULT_REGION"))
         .filter(|s| !s.is_empty())
         .cloned()
-        .ok_or(ConfigError::SigV4MissingRegion)?;
+}
 
+/// Whether AWS credentials are available to sign with, either static or
+/// from the container credentials endpoint.
+fn has_aws_credentials(vars: &HashMap<String, String>) -> bool {
     let has_key = vars.get("AWS_ACCESS_KEY_ID").is_some_and(|s| !s.is_empty());
     let has_secret = vars
         .get("AWS_SECRET_ACCESS_KEY")
         .is_some_and(|s| !s.is_empty());
     let has_token = vars.get("AWS_SESSION_TOKEN").is_some_and(|s| !s.is_empty());
-    if !has_key || !has_secret || !has_token {
-        return Err(ConfigError::SigV4MissingCredentials);
-    }
-
-    Ok(Some(SigV4Config { service, region }))
+    let has_static = has_key && has_secret && has_token;
+    let has_container_uri = vars
+        .get("AWS_CONTAINER_CREDENTIALS_FULL_URI")
+        .is_some_and(|s| !s.is_empty());
+    has_static || has_container_uri
 }
 
-fn parse_headers(vars: &HashMap<String, String>) -> Vec<(String, String)> {
-    vars.get("LAMBDA_OTEL_RELAY_EXPORT_HEADERS")
+fn parse_headers(vars: &HashMap<String, String>, name: &str) -> Vec<(String, String)> {
+    vars.get(name)
         .filter(|s| !s.is_empty())
         .map(|raw| {
             raw.split(',')
@@ -352,10 +1122,325 @@ fn parse_enabled_signals(vars: &HashMap<String, String>) -> Result<EnabledSignal
 fn parse_protocol(vars: &HashMap<String, String>) -> Result<ExportProtocol, ConfigError> {
     match vars.get("LAMBDA_OTEL_RELAY_PROTOCOL").map(|s| s.as_str()) {
         Some("http/protobuf") | None => Ok(ExportProtocol::HttpProtobuf),
+        Some("http/json") => Ok(ExportProtocol::HttpJson),
         Some("grpc") => Ok(ExportProtocol::Grpc),
+        Some("xray") => Ok(ExportProtocol::Xray),
         Some(other) => Err(ConfigError::InvalidProtocol(other.to_owned())),
     }
 }
 
+fn parse_bool(
+    vars: &HashMap<String, String>,
+    name: &str,
+    default: bool,
+) -> Result<bool, ConfigError> {
+    match vars.get(name).map(|s| s.as_str()) {
+        None | Some("") => Ok(default),
+        Some(val) if val.eq_ignore_ascii_case("true") => Ok(true),
+        Some(val) if val.eq_ignore_ascii_case("false") => Ok(false),
+        Some(val) => Err(ConfigError::InvalidBool(name.to_owned(), val.to_owned())),
+    }
+}
+
+fn parse_ratio(vars: &HashMap<String, String>, name: &str) -> Result<Option<f64>, ConfigError> {
+    match vars.get(name).filter(|s| !s.is_empty()) {
+        None => Ok(None),
+        Some(val) => match val.parse::<f64>() {
+            Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(Some(ratio)),
+            _ => Err(ConfigError::InvalidRatio(name.to_owned(), val.clone())),
+        },
+    }
+}
+
+fn parse_sampling(vars: &HashMap<String, String>) -> Result<Option<SamplingConfig>, ConfigError> {
+    let mode = match vars
+        .get("LAMBDA_OTEL_RELAY_SAMPLING_MODE")
+        .map(|s| s.as_str())
+    {
+        None | Some("") => None,
+        Some("head") => Some(SamplingMode::Head),
+        Some("tail") => Some(SamplingMode::Tail),
+        Some(other) => return Err(ConfigError::InvalidSamplingMode(other.to_owned())),
+    };
+    let Some(ratio) = parse_ratio(vars, "LAMBDA_OTEL_RELAY_SAMPLING_RATIO")? else {
+        return match mode {
+            Some(_) => Err(ConfigError::SamplingRatioMissing),
+            None => Ok(None),
+        };
+    };
+    let keep_errors = parse_bool(vars, "LAMBDA_OTEL_RELAY_SAMPLING_KEEP_ERRORS", false)?;
+    let latency_threshold = match vars.get("LAMBDA_OTEL_RELAY_SAMPLING_LATENCY_THRESHOLD_MS") {
+        Some(_) => Some(parse_duration_ms(
+            vars,
+            "LAMBDA_OTEL_RELAY_SAMPLING_LATENCY_THRESHOLD_MS",
+            0,
+        )?),
+        None => None,
+    };
+    Ok(Some(SamplingConfig {
+        ratio,
+        keep_errors,
+        mode: mode.unwrap_or_default(),
+        latency_threshold,
+    }))
+}
+
+fn parse_list(vars: &HashMap<String, String>, name: &str) -> Vec<String> {
+    vars.get(name)
+        .map(|raw| {
+            raw.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse_redaction(vars: &HashMap<String, String>) -> Result<Option<RedactionConfig>, ConfigError> {
+    let delete_keys = parse_list(vars, "LAMBDA_OTEL_RELAY_REDACT_DELETE");
+    let hash_keys = parse_list(vars, "LAMBDA_OTEL_RELAY_REDACT_HASH");
+    let hash_salt = vars
+        .get("LAMBDA_OTEL_RELAY_REDACT_HASH_SALT")
+        .cloned()
+        .unwrap_or_default();
+    let mask = match vars
+        .get("LAMBDA_OTEL_RELAY_REDACT_MASK")
+        .filter(|s| !s.is_empty())
+    {
+        Some(pattern) => Some(Regex::new(pattern).map_err(|e| {
+            ConfigError::InvalidRegex("LAMBDA_OTEL_RELAY_REDACT_MASK".to_owned(), e.to_string())
+        })?),
+        None => None,
+    };
+
+    if delete_keys.is_empty() && hash_keys.is_empty() && mask.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(RedactionConfig {
+        delete_keys,
+        hash_keys,
+        hash_salt,
+        mask,
+    }))
+}
+
+fn parse_limits(vars: &HashMap<String, String>) -> Result<Option<LimitsConfig>, ConfigError> {
+    let limits = LimitsConfig {
+        attribute_value_length: parse_optional_count(
+            vars,
+            "LAMBDA_OTEL_RELAY_ATTRIBUTE_VALUE_LENGTH_LIMIT",
+        )?,
+        attribute_count: parse_optional_count(vars, "LAMBDA_OTEL_RELAY_ATTRIBUTE_COUNT_LIMIT")?,
+        event_count: parse_optional_count(vars, "LAMBDA_OTEL_RELAY_SPAN_EVENT_COUNT_LIMIT")?,
+        link_count: parse_optional_count(vars, "LAMBDA_OTEL_RELAY_SPAN_LINK_COUNT_LIMIT")?,
+    };
+    Ok((limits != LimitsConfig::default()).then_some(limits))
+}
+
+/// Parse a W3C-baggage-style list: `key=value` members separated by commas,
+/// with percent-encoded values. A member's action is given as a property
+/// (`key=value;insert`, `key;delete`) and defaults to upsert.
+fn parse_resource_attributes(
+    vars: &HashMap<String, String>,
+) -> Result<Vec<ResourceAttribute>, ConfigError> {
+    let Some(raw) = vars.get("LAMBDA_OTEL_RELAY_RESOURCE_ATTRIBUTES") else {
+        return Ok(Vec::new());
+    };
+    let mut attributes = Vec::new();
+    for member in raw.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+        let invalid = || ConfigError::InvalidResourceAttribute(member.to_owned());
+        let mut parts = member.split(';').map(str::trim);
+        // `split` always yields at least one element.
+        let pair = parts.next().unwrap_or_default();
+        let property = parts.next();
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        let (key, value) = match pair.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (pair, None),
+        };
+        if key.is_empty() {
+            return Err(invalid());
+        }
+        let value = || {
+            let value = value.ok_or_else(invalid)?;
+            percent_decode(value).ok_or_else(invalid)
+        };
+        let action = match property {
+            None | Some("upsert") => ResourceAction::Upsert(value()?),
+            Some("insert") => ResourceAction::Insert(value()?),
+            Some("delete") => ResourceAction::Delete,
+            Some(_) => return Err(invalid()),
+        };
+        attributes.push(ResourceAttribute {
+            key: key.to_owned(),
+            action,
+        });
+    }
+    Ok(attributes)
+}
+
+/// Decode `%XX` escapes, or `None` if an escape is malformed or the result
+/// isn't UTF-8.
+fn percent_decode(raw: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut rest = raw.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .filter(|h| h.iter().all(u8::is_ascii_hexdigit))?;
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Parse `;`-separated rules like `rename(http.method, http.request.method)`.
+fn parse_transform(vars: &HashMap<String, String>) -> Result<Vec<TransformRule>, ConfigError> {
+    let Some(raw) = vars.get("LAMBDA_OTEL_RELAY_TRANSFORM") else {
+        return Ok(Vec::new());
+    };
+    split_unquoted(raw, ';')
+        .into_iter()
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(parse_transform_rule)
+        .collect()
+}
+
+fn parse_transform_rule(rule: &str) -> Result<TransformRule, ConfigError> {
+    let invalid = || ConfigError::InvalidTransformRule(rule.to_owned());
+    let (op, args) = rule
+        .strip_suffix(')')
+        .and_then(|rule| rule.split_once('('))
+        .ok_or_else(invalid)?;
+    let args: Vec<&str> = split_unquoted(args, ',')
+        .into_iter()
+        .map(str::trim)
+        .collect();
+    let [first, second] = args[..] else {
+        return Err(invalid());
+    };
+    let attribute = |arg: &str| {
+        let (level, key) = match arg.split_once(':') {
+            Some(("resource", key)) => (AttributeLevel::Resource, key),
+            Some(("scope", key)) => (AttributeLevel::Scope, key),
+            _ => (AttributeLevel::Record, arg),
+        };
+        let valid = !key.is_empty()
+            && !key.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '(' | ')'));
+        valid
+            .then(|| AttributeRef {
+                level,
+                key: key.to_owned(),
+            })
+            .ok_or_else(invalid)
+    };
+    let source = |arg: &str| match arg.strip_prefix('"') {
+        Some(quoted) => quoted
+            .strip_suffix('"')
+            .filter(|literal| !literal.contains('"'))
+            .map(|literal| TransformSource::Literal(literal.to_owned()))
+            .ok_or_else(invalid),
+        None => attribute(arg).map(TransformSource::Attribute),
+    };
+    let (op, from, to) = match op.trim() {
+        "rename" => (
+            TransformOp::Rename,
+            TransformSource::Attribute(attribute(first)?),
+            second,
+        ),
+        "copy" => (TransformOp::Copy, source(first)?, second),
+        "set_if_absent" => (TransformOp::SetIfAbsent, source(second)?, first),
+        _ => return Err(invalid()),
+    };
+    Ok(TransformRule {
+        op,
+        from,
+        to: attribute(to)?,
+    })
+}
+
+/// Split on `separator` wherever it is not inside double quotes.
+fn split_unquoted(raw: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in raw.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(&raw[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&raw[start..]);
+    parts
+}
+
+fn parse_span_kind(name: &str) -> Result<SpanKind, ConfigError> {
+    SpanKind::from_str_name(&format!("SPAN_KIND_{}", name.to_ascii_uppercase()))
+        .filter(|kind| *kind != SpanKind::Unspecified)
+        .ok_or_else(|| ConfigError::InvalidSpanKind(name.to_owned()))
+}
+
+fn parse_severity(raw: &str) -> Result<i32, ConfigError> {
+    if let Ok(number) = raw.parse::<i32>() {
+        return match number {
+            1..=24 => Ok(number),
+            _ => Err(ConfigError::InvalidSeverity(raw.to_owned())),
+        };
+    }
+    SeverityNumber::from_str_name(&format!("SEVERITY_NUMBER_{}", raw.to_ascii_uppercase()))
+        .filter(|severity| *severity != SeverityNumber::Unspecified)
+        .map(|severity| severity as i32)
+        .ok_or_else(|| ConfigError::InvalidSeverity(raw.to_owned()))
+}
+
+fn parse_filter(vars: &HashMap<String, String>) -> Result<Option<FilterConfig>, ConfigError> {
+    let span_kinds = parse_list(vars, "LAMBDA_OTEL_RELAY_FILTER_SPAN_KINDS")
+        .iter()
+        .map(|name| parse_span_kind(name))
+        .collect::<Result<Vec<_>, _>>()?;
+    let span_attributes = parse_list(vars, "LAMBDA_OTEL_RELAY_FILTER_SPAN_ATTRIBUTES")
+        .into_iter()
+        .map(|rule| match rule.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok((key.trim().to_owned(), value.trim().to_owned()))
+            }
+            _ => Err(ConfigError::InvalidAttributeRule(rule)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let log_min_severity = vars
+        .get("LAMBDA_OTEL_RELAY_FILTER_LOG_MIN_SEVERITY")
+        .filter(|s| !s.is_empty())
+        .map(|raw| parse_severity(raw.trim()))
+        .transpose()?;
+
+    let filter = FilterConfig {
+        span_names: parse_list(vars, "LAMBDA_OTEL_RELAY_FILTER_SPAN_NAMES"),
+        span_kinds,
+        span_attributes,
+        metric_prefixes: parse_list(vars, "LAMBDA_OTEL_RELAY_FILTER_METRIC_PREFIXES"),
+        log_min_severity,
+        scopes: parse_list(vars, "LAMBDA_OTEL_RELAY_FILTER_SCOPES"),
+    };
+
+    let enabled = !filter.span_names.is_empty()
+        || !filter.span_kinds.is_empty()
+        || !filter.span_attributes.is_empty()
+        || !filter.metric_prefixes.is_empty()
+        || filter.log_min_severity.is_some()
+        || !filter.scopes.is_empty();
+    Ok(enabled.then_some(filter))
+}
+
 #[cfg(test)]
 mod tests;
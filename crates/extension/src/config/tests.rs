@@ -286,6 +286,22 @@ fn explicit_end_flush_strategy() {
     assert!(matches!(config.flush_strategy, FlushStrategy::End));
 }
 
+#[test]
+fn adaptive_flush_strategy() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_FLUSH_STRATEGY", "adaptive,5000"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert!(matches!(
+        config.flush_strategy,
+        FlushStrategy::Adaptive { target_age } if target_age == Duration::from_secs(5)
+    ));
+}
+
 #[test]
 fn invalid_flush_strategy() {
     let err = Config::parse(
@@ -621,6 +637,25 @@ fn sigv4_missing_session_token_errors() {
     assert!(matches!(err, ConfigError::SigV4MissingCredentials));
 }
 
+#[test]
+fn sigv4_accepts_container_credentials() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_ENDPOINT_SIGV4_SERVICE", "aps"),
+            ("AWS_REGION", "us-east-1"),
+            (
+                "AWS_CONTAINER_CREDENTIALS_FULL_URI",
+                "http://127.0.0.1:9001/2021-04-23/credentials",
+            ),
+            ("AWS_CONTAINER_AUTHORIZATION_TOKEN", "TOKEN"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert_eq!(config.sigv4.unwrap().service, "aps");
+}
+
 #[test]
 fn managed_instances_overrides_default_to_continuously() {
     let config = Config::parse(
@@ -826,6 +861,19 @@ fn protocol_grpc() {
     assert_eq!(config.protocol, ExportProtocol::Grpc);
 }
 
+#[test]
+fn protocol_http_json() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_PROTOCOL", "http/json"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert_eq!(config.protocol, ExportProtocol::HttpJson);
+}
+
 #[test]
 fn invalid_protocol() {
     let err = Config::parse(
@@ -838,3 +886,967 @@ fn invalid_protocol() {
     .unwrap_err();
     assert!(matches!(err, ConfigError::InvalidProtocol(p) if p == "json"));
 }
+
+// ---------------------------------------------------------------------------
+// Sampling
+// ---------------------------------------------------------------------------
+
+#[test]
+fn sampling_disabled_by_default() {
+    let config = Config::parse(
+        &vars(&[("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318")]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert!(config.sampling.is_none());
+}
+
+#[test]
+fn sampling_ratio_and_keep_errors() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_RATIO", "0.25"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_KEEP_ERRORS", "true"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    let sampling = config.sampling.unwrap();
+    assert_eq!(sampling.ratio, 0.25);
+    assert!(sampling.keep_errors);
+}
+
+#[test]
+fn sampling_keep_errors_defaults_to_false() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_RATIO", "1"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert!(!config.sampling.unwrap().keep_errors);
+}
+
+#[test]
+fn rejects_out_of_range_sampling_ratio() {
+    for raw in ["1.5", "-0.1", "half", "NaN"] {
+        let err = Config::parse(
+            &vars(&[
+                ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+                ("LAMBDA_OTEL_RELAY_SAMPLING_RATIO", raw),
+            ]),
+            RuntimeMode::Standard,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, ConfigError::InvalidRatio(_, ref v) if v == raw),
+            "should reject {raw}"
+        );
+    }
+}
+
+#[test]
+fn rejects_invalid_bool() {
+    let err = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_RATIO", "0.5"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_KEEP_ERRORS", "yes"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::InvalidBool(_, _)));
+}
+
+#[test]
+fn sampling_mode_defaults_to_head() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_RATIO", "0.5"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    let sampling = config.sampling.unwrap();
+    assert_eq!(sampling.mode, SamplingMode::Head);
+    assert_eq!(sampling.latency_threshold, None);
+}
+
+#[test]
+fn tail_sampling_with_latency_threshold() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_RATIO", "0.1"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_MODE", "tail"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_LATENCY_THRESHOLD_MS", "2500"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    let sampling = config.sampling.unwrap();
+    assert_eq!(sampling.mode, SamplingMode::Tail);
+    assert_eq!(
+        sampling.latency_threshold,
+        Some(Duration::from_millis(2500))
+    );
+}
+
+#[test]
+fn rejects_unknown_sampling_mode() {
+    let err = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_RATIO", "0.1"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_MODE", "random"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::InvalidSamplingMode(m) if m == "random"));
+}
+
+#[test]
+fn sampling_mode_requires_ratio() {
+    let err = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_SAMPLING_MODE", "tail"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::SamplingRatioMissing));
+}
+
+#[test]
+fn redaction_disabled_by_default() {
+    let config = Config::parse(
+        &vars(&[("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318")]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert!(config.redaction.is_none());
+}
+
+#[test]
+fn redaction_rules() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            (
+                "LAMBDA_OTEL_RELAY_REDACT_DELETE",
+                "http.request.header.authorization, *.password",
+            ),
+            ("LAMBDA_OTEL_RELAY_REDACT_HASH", "user.email"),
+            ("LAMBDA_OTEL_RELAY_REDACT_HASH_SALT", "pepper"),
+            ("LAMBDA_OTEL_RELAY_REDACT_MASK", r"\d{4}-\d{4}"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    let redaction = config.redaction.unwrap();
+    assert_eq!(
+        redaction.delete_keys,
+        vec!["http.request.header.authorization", "*.password"]
+    );
+    assert_eq!(redaction.hash_keys, vec!["user.email"]);
+    assert_eq!(redaction.hash_salt, "pepper");
+    assert!(redaction.mask.unwrap().is_match("1234-5678"));
+}
+
+#[test]
+fn rejects_invalid_redaction_mask() {
+    let err = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_REDACT_MASK", "(unclosed"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ConfigError::InvalidRegex(var, _) if var == "LAMBDA_OTEL_RELAY_REDACT_MASK")
+    );
+}
+
+#[test]
+fn filter_rules() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_FILTER_SPAN_NAMES", "GET /health*"),
+            ("LAMBDA_OTEL_RELAY_FILTER_SPAN_KINDS", "internal, Client"),
+            ("LAMBDA_OTEL_RELAY_FILTER_SPAN_ATTRIBUTES", "url.path=/ping"),
+            ("LAMBDA_OTEL_RELAY_FILTER_METRIC_PREFIXES", "jvm."),
+            ("LAMBDA_OTEL_RELAY_FILTER_LOG_MIN_SEVERITY", "warn"),
+            ("LAMBDA_OTEL_RELAY_FILTER_SCOPES", "io.opentelemetry.jdbc*"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    let filter = config.filter.unwrap();
+    assert_eq!(filter.span_names, vec!["GET /health*"]);
+    assert_eq!(
+        filter.span_kinds,
+        vec![SpanKind::Internal, SpanKind::Client]
+    );
+    assert_eq!(
+        filter.span_attributes,
+        vec![("url.path".to_owned(), "/ping".to_owned())]
+    );
+    assert_eq!(filter.metric_prefixes, vec!["jvm."]);
+    assert_eq!(filter.log_min_severity, Some(13));
+    assert_eq!(filter.scopes, vec!["io.opentelemetry.jdbc*"]);
+}
+
+#[test]
+fn filter_disabled_by_default() {
+    let config = Config::parse(
+        &vars(&[("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318")]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert!(config.filter.is_none());
+}
+
+#[test]
+fn filter_log_min_severity_accepts_number() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_FILTER_LOG_MIN_SEVERITY", "10"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert_eq!(config.filter.unwrap().log_min_severity, Some(10));
+}
+
+#[test]
+fn rejects_invalid_filter_rules() {
+    let cases = [
+        ("LAMBDA_OTEL_RELAY_FILTER_SPAN_KINDS", "sideways"),
+        ("LAMBDA_OTEL_RELAY_FILTER_SPAN_KINDS", "unspecified"),
+        ("LAMBDA_OTEL_RELAY_FILTER_SPAN_ATTRIBUTES", "url.path"),
+        ("LAMBDA_OTEL_RELAY_FILTER_LOG_MIN_SEVERITY", "25"),
+        ("LAMBDA_OTEL_RELAY_FILTER_LOG_MIN_SEVERITY", "loud"),
+    ];
+    for (var, value) in cases {
+        let result = Config::parse(
+            &vars(&[
+                ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+                (var, value),
+            ]),
+            RuntimeMode::Standard,
+        );
+        assert!(result.is_err(), "{var}={value} should be rejected");
+    }
+}
+
+#[test]
+fn self_metrics_disabled_by_default() {
+    let config = Config::parse(
+        &vars(&[("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318")]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert!(config.self_metrics_interval.is_none());
+}
+
+#[test]
+fn self_metrics_interval() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_SELF_METRICS", "true"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert_eq!(config.self_metrics_interval, Some(Duration::from_secs(60)));
+
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_SELF_METRICS", "true"),
+            ("LAMBDA_OTEL_RELAY_SELF_METRICS_INTERVAL_MS", "5000"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert_eq!(config.self_metrics_interval, Some(Duration::from_secs(5)));
+}
+
+#[test]
+fn flush_thresholds_default_to_disabled() {
+    let config = Config::parse(
+        &vars(&[("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318")]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert_eq!(config.flush_thresholds, [FlushThresholds::default(); 3]);
+}
+
+#[test]
+fn flush_thresholds_per_signal() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_LOGS_FLUSH_BYTES", "524288"),
+            ("LAMBDA_OTEL_RELAY_LOGS_FLUSH_AGE_MS", "5000"),
+            ("LAMBDA_OTEL_RELAY_TRACES_FLUSH_BYTES", "2097152"),
+            ("LAMBDA_OTEL_RELAY_METRICS_FLUSH_PAYLOADS", "0"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert_eq!(
+        config.flush_thresholds[Signal::Logs as usize],
+        FlushThresholds {
+            bytes: Some(524_288),
+            payloads: None,
+            age: Some(Duration::from_secs(5)),
+        }
+    );
+    assert_eq!(
+        config.flush_thresholds[Signal::Traces as usize].bytes,
+        Some(2_097_152)
+    );
+    assert_eq!(
+        config.flush_thresholds[Signal::Metrics as usize],
+        FlushThresholds::default()
+    );
+}
+
+#[test]
+fn invalid_flush_threshold() {
+    let err = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_TRACES_FLUSH_PAYLOADS", "many"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, ConfigError::InvalidNumeric(ref name, _) if name == "LAMBDA_OTEL_RELAY_TRACES_FLUSH_PAYLOADS")
+    );
+}
+
+#[test]
+fn quotas_and_eviction_policy() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_LOGS_QUOTA_BYTES", "1048576"),
+            ("LAMBDA_OTEL_RELAY_EVICTION_POLICY", "priority"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert_eq!(config.buffer_quotas, [None, None, Some(1_048_576)]);
+    assert_eq!(config.eviction_policy, EvictionPolicy::Priority);
+}
+
+#[test]
+fn eviction_policy_defaults_to_drop_oldest() {
+    let config = Config::parse(
+        &vars(&[("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318")]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert_eq!(config.eviction_policy, EvictionPolicy::DropOldest);
+    assert_eq!(config.buffer_quotas, [None; 3]);
+}
+
+#[test]
+fn invalid_eviction_policy() {
+    let err = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_EVICTION_POLICY", "random"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ConfigError::InvalidEvictionPolicy(_)));
+}
+
+#[test]
+fn concurrency_only_on_managed_instances() {
+    let env = vars(&[
+        ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+        ("LAMBDA_OTEL_RELAY_IDLE_FLUSH_MS", "250"),
+        ("LAMBDA_OTEL_RELAY_BUFFER_BYTES_PER_INVOCATION", "65536"),
+    ]);
+    let standard = Config::parse(&env, RuntimeMode::Standard).unwrap();
+    assert_eq!(standard.concurrency, None);
+
+    let managed = Config::parse(&env, RuntimeMode::ManagedInstances).unwrap();
+    assert_eq!(
+        managed.concurrency,
+        Some(ConcurrencyConfig {
+            idle_flush_delay: Duration::from_millis(250),
+            bytes_per_invocation: Some(65_536),
+        })
+    );
+}
+
+#[test]
+fn concurrency_defaults() {
+    let config = Config::parse(
+        &vars(&[("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318")]),
+        RuntimeMode::ManagedInstances,
+    )
+    .unwrap();
+    let concurrency = config.concurrency.unwrap();
+    assert_eq!(concurrency.idle_flush_delay, Duration::from_millis(100));
+    assert_eq!(concurrency.bytes_per_invocation, None);
+}
+
+#[test]
+fn restore_policy() {
+    let parse = |value: &str| {
+        Config::parse(
+            &vars(&[
+                ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+                ("LAMBDA_OTEL_RELAY_RESTORE_BUFFER_POLICY", value),
+            ]),
+            RuntimeMode::Standard,
+        )
+    };
+    assert_eq!(parse("").unwrap().restore_policy, RestorePolicy::Discard);
+    assert_eq!(parse("keep").unwrap().restore_policy, RestorePolicy::Keep);
+    assert!(matches!(
+        parse("flush").unwrap_err(),
+        ConfigError::InvalidRestorePolicy(_)
+    ));
+}
+
+#[test]
+fn xray_parent() {
+    let parse = |pairs: &[(&str, &str)]| {
+        let mut pairs = pairs.to_vec();
+        pairs.push(("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"));
+        Config::parse(&vars(&pairs), RuntimeMode::Standard).unwrap()
+    };
+    assert!(!parse(&[]).xray_parent);
+    assert!(parse(&[("LAMBDA_OTEL_RELAY_XRAY_PARENT", "true")]).xray_parent);
+}
+
+#[test]
+fn xray_protocol_defaults_to_daemon_address() {
+    let parse = |pairs: &[(&str, &str)]| {
+        let mut pairs = pairs.to_vec();
+        pairs.push(("LAMBDA_OTEL_RELAY_PROTOCOL", "xray"));
+        Config::parse(&vars(&pairs), RuntimeMode::Standard)
+    };
+
+    let config = parse(&[("AWS_XRAY_DAEMON_ADDRESS", "169.254.79.129:2000")]).unwrap();
+    assert_eq!(config.protocol, ExportProtocol::Xray);
+    assert_eq!(config.endpoint.as_str(), "udp://169.254.79.129:2000");
+    assert!(config.enabled_signals.is_enabled(Signal::Traces));
+    assert!(!config.enabled_signals.is_enabled(Signal::Metrics));
+    assert!(!config.enabled_signals.is_enabled(Signal::Logs));
+
+    let config = parse(&[(
+        "AWS_XRAY_DAEMON_ADDRESS",
+        "tcp:127.0.0.1:2000 udp:127.0.0.2:2001",
+    )])
+    .unwrap();
+    assert_eq!(config.endpoint.as_str(), "udp://127.0.0.2:2001");
+
+    let config = parse(&[
+        ("AWS_XRAY_DAEMON_ADDRESS", "169.254.79.129:2000"),
+        (
+            "LAMBDA_OTEL_RELAY_ENDPOINT",
+            "https://xray.us-east-1.amazonaws.com",
+        ),
+        ("LAMBDA_OTEL_RELAY_XRAY_ANNOTATIONS", "tenant.id, user.tier"),
+        ("LAMBDA_OTEL_RELAY_XRAY_SKIP_TIMESTAMP_VALIDATION", "true"),
+    ])
+    .unwrap();
+    assert_eq!(config.endpoint.scheme(), "https");
+    assert_eq!(config.xray_annotations, ["tenant.id", "user.tier"]);
+    assert!(config.xray_skip_timestamp_validation);
+
+    assert!(matches!(
+        parse(&[]).unwrap_err(),
+        ConfigError::EndpointMissing
+    ));
+    assert!(matches!(
+        parse(&[("LAMBDA_OTEL_RELAY_ENDPOINT", "tcp://localhost:2000")]).unwrap_err(),
+        ConfigError::InvalidXrayEndpoint(_)
+    ));
+}
+
+#[test]
+fn emf() {
+    let parse = |pairs: &[(&str, &str)]| {
+        let mut pairs = pairs.to_vec();
+        pairs.push(("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"));
+        Config::parse(&vars(&pairs), RuntimeMode::Standard)
+    };
+    assert_eq!(parse(&[]).unwrap().emf, None);
+
+    let emf = parse(&[("LAMBDA_OTEL_RELAY_EMF_NAMESPACE", "checkout")])
+        .unwrap()
+        .emf
+        .unwrap();
+    assert_eq!(emf.namespace, "checkout");
+    assert_eq!(emf.dimensions, ["service.name"]);
+    assert_eq!(emf.mode, EmfMode::Standalone);
+
+    let emf = parse(&[
+        ("LAMBDA_OTEL_RELAY_EMF_NAMESPACE", "checkout"),
+        ("LAMBDA_OTEL_RELAY_EMF_DIMENSIONS", "route,region"),
+        ("LAMBDA_OTEL_RELAY_EMF_MODE", "alongside"),
+    ])
+    .unwrap()
+    .emf
+    .unwrap();
+    assert_eq!(emf.dimensions, ["route", "region"]);
+    assert_eq!(emf.mode, EmfMode::Alongside);
+
+    assert!(matches!(
+        parse(&[
+            ("LAMBDA_OTEL_RELAY_EMF_NAMESPACE", "checkout"),
+            ("LAMBDA_OTEL_RELAY_EMF_MODE", "both"),
+        ])
+        .unwrap_err(),
+        ConfigError::InvalidEmfMode(_)
+    ));
+}
+
+#[test]
+fn emf_keeps_metrics_with_xray_protocol() {
+    let config = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_PROTOCOL", "xray"),
+            ("AWS_XRAY_DAEMON_ADDRESS", "127.0.0.1:2000"),
+            ("LAMBDA_OTEL_RELAY_EMF_NAMESPACE", "checkout"),
+            ("LAMBDA_OTEL_RELAY_EMF_MODE", "alongside"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap();
+    assert!(config.enabled_signals.is_enabled(Signal::Metrics));
+    assert!(!config.enabled_signals.is_enabled(Signal::Logs));
+    assert_eq!(config.emf.unwrap().mode, EmfMode::Standalone);
+}
+
+#[test]
+fn remote_write_endpoint() {
+    let parse = |pairs: &[(&str, &str)]| {
+        let mut pairs = pairs.to_vec();
+        pairs.push(("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"));
+        Config::parse(&vars(&pairs), RuntimeMode::Standard)
+    };
+    assert!(parse(&[]).unwrap().remote_write.is_none());
+    let config = parse(&[(
+        "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_ENDPOINT",
+        "https://mimir.example.com/api/v1/push",
+    )])
+    .unwrap();
+    let remote_write = config.remote_write.unwrap();
+    assert_eq!(remote_write.endpoint.path(), "/api/v1/push");
+    assert!(remote_write.headers.is_empty());
+    assert!(remote_write.sigv4.is_none());
+    assert!(matches!(
+        parse(&[(
+            "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_ENDPOINT",
+            "not a url"
+        )])
+        .unwrap_err(),
+        ConfigError::RemoteWriteEndpointInvalidUrl(_)
+    ));
+}
+
+#[test]
+fn remote_write_headers_and_sigv4_are_separate_from_otlp() {
+    let parse = |pairs: &[(&str, &str)]| {
+        let mut pairs = pairs.to_vec();
+        pairs.extend([
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            ("LAMBDA_OTEL_RELAY_EXPORT_HEADERS", "Authorization=Bearer otlp"),
+            ("LAMBDA_OTEL_RELAY_ENDPOINT_SIGV4_SERVICE", "xray"),
+            (
+                "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_ENDPOINT",
+                "https://aps-workspaces.us-east-1.amazonaws.com/workspaces/ws-1/api/v1/remote_write",
+            ),
+            ("AWS_REGION", "us-east-1"),
+        ]);
+        Config::parse(&vars(&pairs), RuntimeMode::Standard)
+    };
+    let credentials = [
+        ("AWS_ACCESS_KEY_ID", "AKID"),
+        ("AWS_SECRET_ACCESS_KEY", "SECRET"),
+        ("AWS_SESSION_TOKEN", "TOKEN"),
+    ];
+
+    let config = parse(&credentials).unwrap();
+    let remote_write = config.remote_write.unwrap();
+    assert!(remote_write.headers.is_empty());
+    assert!(remote_write.sigv4.is_none());
+    assert_eq!(config.sigv4.unwrap().service, "xray");
+
+    let mut pairs = credentials.to_vec();
+    pairs.extend([
+        (
+            "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_HEADERS",
+            "X-Scope-OrgID=tenant",
+        ),
+        ("LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4", "true"),
+        (
+            "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4_REGION",
+            "eu-west-1",
+        ),
+    ]);
+    let remote_write = parse(&pairs).unwrap().remote_write.unwrap();
+    assert_eq!(
+        remote_write.headers,
+        [("X-Scope-OrgID".to_owned(), "tenant".to_owned())]
+    );
+    let sigv4 = remote_write.sigv4.unwrap();
+    assert_eq!(sigv4.service, "aps");
+    assert_eq!(sigv4.region, "eu-west-1");
+
+    pairs.push((
+        "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4_SERVICE",
+        "execute-api",
+    ));
+    let sigv4 = parse(&pairs).unwrap().remote_write.unwrap().sigv4.unwrap();
+    assert_eq!(sigv4.service, "execute-api");
+}
+
+#[test]
+fn remote_write_sigv4_missing_credentials_errors() {
+    let err = Config::parse(
+        &vars(&[
+            ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+            (
+                "LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_ENDPOINT",
+                "https://mimir.example.com/api/v1/push",
+            ),
+            ("LAMBDA_OTEL_RELAY_PROMETHEUS_REMOTE_WRITE_SIGV4", "true"),
+            ("AWS_REGION", "us-east-1"),
+        ]),
+        RuntimeMode::Standard,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ConfigError::RemoteWriteSigV4MissingCredentials
+    ));
+}
+
+#[test]
+fn metrics_temporality() {
+    let parse = |pairs: &[(&str, &str)]| {
+        let mut pairs = pairs.to_vec();
+        pairs.push(("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"));
+        Config::parse(&vars(&pairs), RuntimeMode::Standard)
+    };
+    assert_eq!(parse(&[]).unwrap().temporality, None);
+    assert_eq!(
+        parse(&[("LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY", "cumulative")])
+            .unwrap()
+            .temporality,
+        Some(TemporalityConfig {
+            target: Temporality::Cumulative,
+            initial_value: InitialValue::Auto,
+            max_staleness: None,
+            max_streams: Some(10_000),
+        })
+    );
+    let bounded = parse(&[
+        ("LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY", "delta"),
+        (
+            "LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY_MAX_STALENESS_MS",
+            "600000",
+        ),
+        ("LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY_MAX_STREAMS", "0"),
+    ])
+    .unwrap()
+    .temporality
+    .unwrap();
+    assert_eq!(bounded.max_staleness, Some(Duration::from_secs(600)));
+    assert_eq!(bounded.max_streams, None);
+    assert_eq!(
+        parse(&[
+            ("LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY", "delta"),
+            (
+                "LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY_INITIAL_VALUE",
+                "drop"
+            ),
+        ])
+        .unwrap()
+        .temporality
+        .unwrap()
+        .initial_value,
+        InitialValue::Drop
+    );
+    assert!(matches!(
+        parse(&[("LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY", "cumulitive")]).unwrap_err(),
+        ConfigError::InvalidTemporality(_)
+    ));
+    assert!(matches!(
+        parse(&[
+            ("LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY", "delta"),
+            (
+                "LAMBDA_OTEL_RELAY_METRICS_TEMPORALITY_INITIAL_VALUE",
+                "sometimes"
+            ),
+        ])
+        .unwrap_err(),
+        ConfigError::InvalidInitialValue(_)
+    ));
+}
+
+#[test]
+fn metrics_aggregate() {
+    let parse = |pairs: &[(&str, &str)]| {
+        let mut pairs = pairs.to_vec();
+        pairs.push(("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"));
+        Config::parse(&vars(&pairs), RuntimeMode::Standard)
+    };
+    assert!(!parse(&[]).unwrap().aggregate_metrics);
+    assert!(
+        parse(&[("LAMBDA_OTEL_RELAY_METRICS_AGGREGATE", "true")])
+            .unwrap()
+            .aggregate_metrics
+    );
+    assert!(matches!(
+        parse(&[("LAMBDA_OTEL_RELAY_METRICS_AGGREGATE", "yes")]).unwrap_err(),
+        ConfigError::InvalidBool(..)
+    ));
+}
+
+#[test]
+fn spanmetrics() {
+    let parse = |pairs: &[(&str, &str)]| {
+        let mut pairs = pairs.to_vec();
+        pairs.push(("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"));
+        Config::parse(&vars(&pairs), RuntimeMode::Standard)
+    };
+    assert_eq!(parse(&[]).unwrap().spanmetrics, None);
+
+    let config = parse(&[
+        ("LAMBDA_OTEL_RELAY_SPANMETRICS", "true"),
+        (
+            "LAMBDA_OTEL_RELAY_SPANMETRICS_DIMENSIONS",
+            "http.route, faas.name",
+        ),
+        ("LAMBDA_OTEL_RELAY_SIGNALS", "metrics"),
+    ])
+    .unwrap();
+    let spanmetrics = config.spanmetrics.as_ref().unwrap();
+    assert_eq!(spanmetrics.dimensions, ["http.route", "faas.name"]);
+    assert_eq!(spanmetrics.buckets.len(), 16);
+    assert!(!config.enabled_signals.is_enabled(Signal::Traces));
+    assert!(config.accepted_signals().is_enabled(Signal::Traces));
+    assert!(!config.accepted_signals().is_enabled(Signal::Logs));
+
+    let config = parse(&[
+        ("LAMBDA_OTEL_RELAY_SPANMETRICS", "true"),
+        ("LAMBDA_OTEL_RELAY_SPANMETRICS_BUCKETS_MS", "5,50,500"),
+    ])
+    .unwrap();
+    assert_eq!(config.spanmetrics.unwrap().buckets, [5.0, 50.0, 500.0]);
+
+    for buckets in ["5,abc", "50,5", "5,5"] {
+        assert!(matches!(
+            parse(&[
+                ("LAMBDA_OTEL_RELAY_SPANMETRICS", "true"),
+                ("LAMBDA_OTEL_RELAY_SPANMETRICS_BUCKETS_MS", buckets),
+            ])
+            .unwrap_err(),
+            ConfigError::InvalidBuckets(_)
+        ));
+    }
+
+    let traces_only = parse(&[
+        ("LAMBDA_OTEL_RELAY_SPANMETRICS", "true"),
+        ("LAMBDA_OTEL_RELAY_SIGNALS", "traces"),
+    ])
+    .unwrap();
+    assert_eq!(traces_only.spanmetrics, None, "needs the metrics signal");
+}
+
+#[test]
+fn metrics_cardinality_limit() {
+    let parse = |pairs: &[(&str, &str)]| {
+        let mut pairs = pairs.to_vec();
+        pairs.push(("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"));
+        Config::parse(&vars(&pairs), RuntimeMode::Standard)
+    };
+    assert_eq!(parse(&[]).unwrap().metrics_cardinality_limit, None);
+    assert_eq!(
+        parse(&[("LAMBDA_OTEL_RELAY_METRICS_CARDINALITY_LIMIT", "2000")])
+            .unwrap()
+            .metrics_cardinality_limit,
+        Some(2000)
+    );
+    assert_eq!(
+        parse(&[("LAMBDA_OTEL_RELAY_METRICS_CARDINALITY_LIMIT", "0")])
+            .unwrap()
+            .metrics_cardinality_limit,
+        None
+    );
+    assert!(matches!(
+        parse(&[("LAMBDA_OTEL_RELAY_METRICS_CARDINALITY_LIMIT", "lots")]).unwrap_err(),
+        ConfigError::InvalidNumeric(..)
+    ));
+}
+
+#[test]
+fn limits() {
+    let parse = |pairs: &[(&str, &str)]| {
+        let mut pairs = pairs.to_vec();
+        pairs.push(("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"));
+        Config::parse(&vars(&pairs), RuntimeMode::Standard)
+    };
+    assert_eq!(parse(&[]).unwrap().limits, None);
+    assert_eq!(
+        parse(&[("LAMBDA_OTEL_RELAY_ATTRIBUTE_COUNT_LIMIT", "0")])
+            .unwrap()
+            .limits,
+        None
+    );
+    assert_eq!(
+        parse(&[
+            ("LAMBDA_OTEL_RELAY_ATTRIBUTE_VALUE_LENGTH_LIMIT", "4096"),
+            ("LAMBDA_OTEL_RELAY_SPAN_EVENT_COUNT_LIMIT", "128"),
+        ])
+        .unwrap()
+        .limits,
+        Some(LimitsConfig {
+            attribute_value_length: Some(4096),
+            event_count: Some(128),
+            ..Default::default()
+        })
+    );
+    assert!(matches!(
+        parse(&[("LAMBDA_OTEL_RELAY_SPAN_LINK_COUNT_LIMIT", "-1")]).unwrap_err(),
+        ConfigError::InvalidNumeric(..)
+    ));
+}
+
+#[test]
+fn resource_attributes() {
+    let parse = |raw: &str| {
+        Config::parse(
+            &vars(&[
+                ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+                ("LAMBDA_OTEL_RELAY_RESOURCE_ATTRIBUTES", raw),
+            ]),
+            RuntimeMode::Standard,
+        )
+        .map(|c| c.resource_attributes)
+    };
+    let attribute = |key: &str, action| ResourceAttribute {
+        key: key.to_owned(),
+        action,
+    };
+    assert_eq!(parse("").unwrap(), []);
+    assert_eq!(
+        parse(
+            "deployment.environment=prod, team = Payments%20%26%20Billing;insert,host.name;delete"
+        )
+        .unwrap(),
+        [
+            attribute(
+                "deployment.environment",
+                ResourceAction::Upsert("prod".to_owned())
+            ),
+            attribute(
+                "team",
+                ResourceAction::Insert("Payments & Billing".to_owned())
+            ),
+            attribute("host.name", ResourceAction::Delete),
+        ]
+    );
+    for invalid in [
+        "team",
+        "=payments",
+        "team=payments;replace",
+        "team=%zz",
+        "team=%e2%28",
+    ] {
+        assert!(
+            matches!(
+                parse(invalid).unwrap_err(),
+                ConfigError::InvalidResourceAttribute(_)
+            ),
+            "{invalid}"
+        );
+    }
+}
+
+#[test]
+fn transform_rules() {
+    let parse = |raw: &str| {
+        Config::parse(
+            &vars(&[
+                ("LAMBDA_OTEL_RELAY_ENDPOINT", "http://localhost:4318"),
+                ("LAMBDA_OTEL_RELAY_TRANSFORM", raw),
+            ]),
+            RuntimeMode::Standard,
+        )
+        .map(|c| c.transform)
+    };
+    let attribute = |level, key: &str| AttributeRef {
+        level,
+        key: key.to_owned(),
+    };
+    assert_eq!(parse("").unwrap(), []);
+    assert_eq!(
+        parse(
+            "rename(http.method, http.request.method); \
+             copy(resource:faas.name, scope:function); \
+             set_if_absent(resource:deployment.environment, \"a; b, c\");"
+        )
+        .unwrap(),
+        [
+            TransformRule {
+                op: TransformOp::Rename,
+                from: TransformSource::Attribute(attribute(AttributeLevel::Record, "http.method")),
+                to: attribute(AttributeLevel::Record, "http.request.method"),
+            },
+            TransformRule {
+                op: TransformOp::Copy,
+                from: TransformSource::Attribute(attribute(AttributeLevel::Resource, "faas.name")),
+                to: attribute(AttributeLevel::Scope, "function"),
+            },
+            TransformRule {
+                op: TransformOp::SetIfAbsent,
+                from: TransformSource::Literal("a; b, c".to_owned()),
+                to: attribute(AttributeLevel::Resource, "deployment.environment"),
+            },
+        ]
+    );
+    for invalid in [
+        "rename(a)",
+        "rename(a, b, c)",
+        "rename(\"a\", b)",
+        "copy(a, \"b\")",
+        "move(a, b)",
+        "rename(a, resource:)",
+        "set_if_absent(a, \"b)",
+        "rename a b",
+    ] {
+        assert!(
+            matches!(
+                parse(invalid).unwrap_err(),
+                ConfigError::InvalidTransformRule(_)
+            ),
+            "{invalid}"
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! CloudWatch Embedded Metric Format output.
+//!
+//! Lambda sends the extension's stdout to the function's log group, where
+//! CloudWatch extracts metrics from log lines in the [Embedded Metric Format].
+//! Writing metrics there gives functions without a metrics backend CloudWatch
+//! metrics at the price of log ingestion.
+//!
+//! Data points that share a timestamp and dimension values are written as
+//! one document. Gauges and sums are written as their value, explicit-bucket
+//! histograms as `Values`/`Counts` pairs with one value per non-empty bucket.
+//! Exponential histograms and summaries are skipped.
+//!
+//! CloudWatch adds up the values written for a metric, so cumulative sums and
+//! histograms are first converted to delta by a converter of the sink's own,
+//! unless the pipeline already converted them.
+//!
+//! [Embedded Metric Format]: https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+use tracing::{debug, warn};
+
+use crate::config::{EmfConfig, TemporalityConfig};
+use crate::otlp_json::{Object, write_f64, write_list, write_str};
+use crate::processor::TemporalityConverter;
+use crate::proto::opentelemetry::proto::{
+    collector::metrics::v1::ExportMetricsServiceRequest,
+    common::v1::{KeyValue, any_value::Value},
+    metrics::v1::{
+        AggregationTemporality, HistogramDataPoint, NumberDataPoint, metric::Data,
+        number_data_point,
+    },
+};
+
+/// CloudWatch accepts at most this many metrics per document.
+const MAX_METRICS: usize = 100;
+
+/// CloudWatch accepts at most this many dimensions per dimension set.
+const MAX_DIMENSIONS: usize = 30;
+
+/// Writes metrics as EMF documents, one per line.
+pub struct EmfSink {
+    config: EmfConfig,
+    out: Box<dyn Write + Send>,
+    /// Turns cumulative sums and histograms into deltas before they are
+    /// written.
+    delta: TemporalityConverter,
+}
+
+impl EmfSink {
+    pub fn new(config: &EmfConfig) -> Self {
+        Self::with_writer(config, Box::new(io::stdout()))
+    }
+
+    pub fn with_writer(config: &EmfConfig, out: Box<dyn Write + Send>) -> Self {
+        Self {
+            config: config.clone(),
+            out,
+            delta: TemporalityConverter::new(&TemporalityConfig::delta()),
+        }
+    }
+
+    pub fn config(&self) -> &EmfConfig {
+        &self.config
+    }
+
+    /// Forget the streams converted to delta, e.g. after a SnapStart restore.
+    pub fn reset(&mut self) {
+        self.delta.reset();
+    }
+
+    pub fn write(&mut self, req: &ExportMetricsServiceRequest) {
+        let converted;
+        let req = if has_cumulative(req) {
+            let mut copy = req.clone();
+            self.delta.convert(&mut copy);
+            converted = copy;
+            &converted
+        } else {
+            req
+        };
+        let mut lines = String::new();
+        for document in documents(req, &self.config) {
+            lines.push_str(&document);
+            lines.push('\n');
+        }
+        if lines.is_empty() {
+            return;
+        }
+        if let Err(e) = self
+            .out
+            .write_all(lines.as_bytes())
+            .and_then(|()| self.out.flush())
+        {
+            warn!(error = %e, "failed to write EMF metrics");
+        }
+    }
+}
+
+fn has_cumulative(req: &ExportMetricsServiceRequest) -> bool {
+    let cumulative = AggregationTemporality::Cumulative as i32;
+    req.resource_metrics
+        .iter()
+        .flat_map(|rm| &rm.scope_metrics)
+        .flat_map(|sm| &sm.metrics)
+        .any(|metric| match &metric.data {
+            Some(Data::Sum(sum)) => sum.aggregation_temporality == cumulative,
+            Some(Data::Histogram(h)) => h.aggregation_temporality == cumulative,
+            _ => false,
+        })
+}
+
+/// A metric value inside a document.
+enum Sample<'a> {
+    Number(f64),
+    Histogram(&'a HistogramDataPoint),
+}
+
+/// The metrics of one document.
+struct Document<'a> {
+    timestamp_ms: u64,
+    dimensions: Vec<(&'a str, String)>,
+    metrics: Vec<(&'a str, Option<&'static str>, Sample<'a>)>,
+}
+
+/// Convert `req` into EMF documents.
+pub fn documents(req: &ExportMetricsServiceRequest, config: &EmfConfig) -> Vec<String> {
+    let now_ms = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let mut docs: Vec<Document> = Vec::new();
+    for rm in &req.resource_metrics {
+        let resource = rm.resource.as_ref().map_or(&[][..], |r| &r.attributes[..]);
+        for metric in rm.scope_metrics.iter().flat_map(|sm| &sm.metrics) {
+            let unit = cloudwatch_unit(&metric.unit);
+            let points: Vec<(&[KeyValue], u64, Sample)> = match &metric.data {
+                Some(Data::Gauge(gauge)) => number_points(&gauge.data_points),
+                Some(Data::Sum(sum)) => number_points(&sum.data_points),
+                Some(Data::Histogram(histogram)) => histogram
+                    .data_points
+                    .iter()
+                    .filter(|point| point.count > 0)
+                    .map(|point| {
+                        (
+                            &point.attributes[..],
+                            point.time_unix_nano,
+                            Sample::Histogram(point),
+                        )
+                    })
+                    .collect(),
+                Some(Data::ExponentialHistogram(_) | Data::Summary(_)) => {
+                    debug!(metric = %metric.name, "EMF output skips exponential histograms and summaries");
+                    continue;
+                }
+                None => continue,
+            };
+            for (attributes, time_unix_nano, sample) in points {
+                let timestamp_ms = match time_unix_nano {
+                    0 => now_ms,
+                    nanos => nanos / 1_000_000,
+                };
+                let dimensions = dimensions(&config.dimensions, attributes, resource);
+                let existing = docs.iter_mut().find(|doc| {
+                    doc.timestamp_ms == timestamp_ms
+                        && doc.dimensions == dimensions
+                        && doc.metrics.len() < MAX_METRICS
+                        && doc.metrics.iter().all(|(name, ..)| *name != metric.name)
+                });
+                let entry = (metric.name.as_str(), unit, sample);
+                match existing {
+                    Some(doc) => doc.metrics.push(entry),
+                    None => docs.push(Document {
+                        timestamp_ms,
+                        dimensions,
+                        metrics: vec![entry],
+                    }),
+                }
+            }
+        }
+    }
+    docs.iter()
+        .map(|doc| encode(doc, &config.namespace))
+        .collect()
+}
+
+fn number_points(points: &[NumberDataPoint]) -> Vec<(&[KeyValue], u64, Sample<'_>)> {
+    points
+        .iter()
+        .filter_map(|point| {
+            let value = match point.value? {
+                number_data_point::Value::AsDouble(v) => Some(v).filter(|v| v.is_finite())?,
+                number_data_point::Value::AsInt(v) => v as f64,
+            };
+            Some((
+                &point.attributes[..],
+                point.time_unix_nano,
+                Sample::Number(value),
+            ))
+        })
+        .collect()
+}
+
+/// The configured dimensions present on the data point or, failing that, the
+/// resource, in configuration order.
+fn dimensions<'a>(
+    keys: &'a [String],
+    attributes: &[KeyValue],
+    resource: &[KeyValue],
+) -> Vec<(&'a str, String)> {
+    keys.iter()
+        .filter_map(|key| {
+            let kv = attributes
+                .iter()
+                .chain(resource)
+                .find(|kv| kv.key == *key)?;
+            let value = match kv.value.as_ref()?.value.as_ref()? {
+                Value::StringValue(s) => s.clone(),
+                Value::BoolValue(b) => b.to_string(),
+                Value::IntValue(i) => i.to_string(),
+                Value::DoubleValue(d) => d.to_string(),
+                _ => return None,
+            };
+            Some((key.as_str(), value))
+        })
+        .take(MAX_DIMENSIONS)
+        .collect()
+}
+
+fn encode(doc: &Document, namespace: &str) -> String {
+    let mut out = String::with_capacity(256);
+    let mut o = Object::new(&mut out);
+    let mut aws = Object::new(o.key("_aws"));
+    let _ = write!(aws.key("Timestamp"), "{}", doc.timestamp_ms);
+    let directives = aws.key("CloudWatchMetrics");
+    directives.push('[');
+    let mut directive = Object::new(directives);
+    directive.str("Namespace", namespace);
+    let keys: Vec<&str> = doc.dimensions.iter().map(|(key, _)| *key).collect();
+    let dimension_sets = directive.key("Dimensions");
+    dimension_sets.push('[');
+    write_list(dimension_sets, &keys, |out, key| write_str(out, key));
+    dimension_sets.push(']');
+    write_list(
+        directive.key("Metrics"),
+        &doc.metrics,
+        |out, (name, unit, _)| {
+            let mut definition = Object::new(out);
+            definition.str("Name", name);
+            definition.str("Unit", unit.unwrap_or_default());
+            definition.finish();
+        },
+    );
+    directive.finish();
+    directives.push(']');
+    aws.finish();
+
+    for (key, value) in &doc.dimensions {
+        write_str(o.key(key), value);
+    }
+    for (name, _, sample) in &doc.metrics {
+        let out = o.key(name);
+        match sample {
+            Sample::Number(value) => write_f64(out, *value),
+            Sample::Histogram(point) => write_histogram(out, point),
+        }
+    }
+    o.finish();
+    out
+}
+
+/// One representative value per non-empty bucket: the bucket's midpoint, or
+/// for the open-ended outer buckets its finite bound, clamped to the point's
+/// min and max when known.
+fn write_histogram(out: &mut String, point: &HistogramDataPoint) {
+    let bounds = &point.explicit_bounds;
+    let mut values = Vec::new();
+    let mut counts = Vec::new();
+    for (i, &count) in point.bucket_counts.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let lower = i.checked_sub(1).and_then(|i| bounds.get(i)).copied();
+        let upper = bounds.get(i).copied();
+        let value = match (lower, upper) {
+            (Some(lower), Some(upper)) => (lower + upper) / 2.0,
+            (None, Some(bound)) | (Some(bound), None) => bound,
+            (None, None) => point.sum.unwrap_or_default() / point.count as f64,
+        };
+        let value = point.min.map_or(value, |min| value.max(min));
+        let value = point.max.map_or(value, |max| value.min(max));
+        values.push(value);
+        counts.push(count as f64);
+    }
+
+    let mut o = Object::new(out);
+    write_list(o.key("Values"), &values, |out, v| write_f64(out, *v));
+    write_list(o.key("Counts"), &counts, |out, v| write_f64(out, *v));
+    let sum = point.sum.unwrap_or_else(|| {
+        values
+            .iter()
+            .zip(&counts)
+            .map(|(value, count)| value * count)
+            .sum()
+    });
+    let min = point.min.or_else(|| values.first().copied());
+    let max = point.max.or_else(|| values.last().copied());
+    write_f64(o.key("Min"), min.unwrap_or_default());
+    write_f64(o.key("Max"), max.unwrap_or_default());
+    write_f64(o.key("Sum"), sum);
+    let _ = write!(o.key("Count"), "{}", point.count);
+    o.finish();
+}
+
+/// CloudWatch unit for a UCUM unit, if there is one.
+fn cloudwatch_unit(unit: &str) -> Option<&'static str> {
+    Some(match unit {
+        "s" => "Seconds",
+        "ms" => "Milliseconds",
+        "us" => "Microseconds",
+        "By" => "Bytes",
+        "kBy" => "Kilobytes",
+        "MBy" => "Megabytes",
+        "GBy" => "Gigabytes",
+        "bit" => "Bits",
+        "By/s" => "Bytes/Second",
+        "%" => "Percent",
+        "1" => "None",
+        _ if unit.starts_with('{') && unit.ends_with('}') => "Count",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests;
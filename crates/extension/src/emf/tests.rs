@@ -0,0 +1,220 @@
+use std::sync::{Arc, Mutex};
+
+use super::*;
+use crate::config::EmfMode;
+use crate::proto::opentelemetry::proto::{
+    common::v1::AnyValue,
+    metrics::v1::{Gauge, Histogram, Metric, ResourceMetrics, ScopeMetrics, Sum},
+    resource::v1::Resource,
+};
+
+const TIME: u64 = 1_700_000_000_000_000_000;
+
+fn config(dimensions: &[&str]) -> EmfConfig {
+    EmfConfig {
+        namespace: "checkout".to_owned(),
+        dimensions: dimensions.iter().map(|s| (*s).to_owned()).collect(),
+        mode: EmfMode::Standalone,
+    }
+}
+
+fn string(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value.to_owned())),
+        }),
+    }
+}
+
+fn point(value: f64, attributes: Vec<KeyValue>) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes,
+        time_unix_nano: TIME,
+        value: Some(number_data_point::Value::AsDouble(value)),
+        ..Default::default()
+    }
+}
+
+fn request(metrics: Vec<Metric>) -> ExportMetricsServiceRequest {
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Some(Resource {
+                attributes: vec![string("service.name", "cart")],
+                ..Default::default()
+            }),
+            scope_metrics: vec![ScopeMetrics {
+                metrics,
+                ..Default::default()
+            }],
+            schema_url: String::new(),
+        }],
+    }
+}
+
+fn gauge(name: &str, unit: &str, points: Vec<NumberDataPoint>) -> Metric {
+    Metric {
+        name: name.to_owned(),
+        unit: unit.to_owned(),
+        data: Some(Data::Gauge(Gauge {
+            data_points: points,
+        })),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn writes_gauges_and_sums_with_dimensions() {
+    let sum = Metric {
+        name: "requests".to_owned(),
+        unit: "{request}".to_owned(),
+        data: Some(Data::Sum(Sum {
+            data_points: vec![NumberDataPoint {
+                time_unix_nano: TIME,
+                value: Some(number_data_point::Value::AsInt(3)),
+                ..Default::default()
+            }],
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    let docs = documents(
+        &request(vec![gauge("latency", "ms", vec![point(12.5, vec![])]), sum]),
+        &config(&["service.name"]),
+    );
+    assert_eq!(
+        docs,
+        [concat!(
+            r#"{"_aws":{"Timestamp":1700000000000,"CloudWatchMetrics":[{"Namespace":"checkout","#,
+            r#""Dimensions":[["service.name"]],"Metrics":[{"Name":"latency","Unit":"Milliseconds"},"#,
+            r#"{"Name":"requests","Unit":"Count"}]}]},"service.name":"cart","latency":12.5,"requests":3}"#
+        )]
+    );
+}
+
+#[test]
+fn splits_documents_by_dimension_values() {
+    let docs = documents(
+        &request(vec![gauge(
+            "latency",
+            "",
+            vec![
+                point(1.0, vec![string("route", "/a")]),
+                point(2.0, vec![string("route", "/b")]),
+                point(3.0, vec![string("other", "x")]),
+            ],
+        )]),
+        &config(&["route"]),
+    );
+    assert_eq!(docs.len(), 3);
+    assert!(docs[0].contains(r#""Dimensions":[["route"]]"#) && docs[0].contains(r#""route":"/a""#));
+    assert!(docs[1].contains(r#""route":"/b""#));
+    assert!(docs[2].contains(r#""Dimensions":[[]]"#));
+    assert!(!docs[0].contains("Unit"));
+}
+
+#[test]
+fn writes_histograms_as_values_and_counts() {
+    let histogram = Metric {
+        name: "duration".to_owned(),
+        unit: "ms".to_owned(),
+        data: Some(Data::Histogram(Histogram {
+            data_points: vec![HistogramDataPoint {
+                time_unix_nano: TIME,
+                count: 6,
+                sum: Some(160.0),
+                bucket_counts: vec![1, 0, 3, 2],
+                explicit_bounds: vec![10.0, 20.0, 40.0],
+                min: Some(4.0),
+                max: Some(55.0),
+                ..Default::default()
+            }],
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    let docs = documents(&request(vec![histogram]), &config(&[]));
+    assert!(docs[0].ends_with(concat!(
+        r#""duration":{"Values":[10,30,40],"Counts":[1,3,2],"#,
+        r#""Min":4,"Max":55,"Sum":160,"Count":6}}"#
+    )));
+}
+
+#[test]
+fn maps_units() {
+    assert_eq!(cloudwatch_unit("s"), Some("Seconds"));
+    assert_eq!(cloudwatch_unit("By"), Some("Bytes"));
+    assert_eq!(cloudwatch_unit("{invocation}"), Some("Count"));
+    assert_eq!(cloudwatch_unit("furlong"), None);
+}
+
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn sink_writes_one_line_per_document() {
+    let buf = SharedBuf::default();
+    let mut sink = EmfSink::with_writer(&config(&["route"]), Box::new(buf.clone()));
+    sink.write(&request(vec![gauge(
+        "latency",
+        "ms",
+        vec![
+            point(1.0, vec![string("route", "/a")]),
+            point(2.0, vec![string("route", "/b")]),
+        ],
+    )]));
+    sink.write(&ExportMetricsServiceRequest::default());
+
+    let written = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(written.lines().count(), 2);
+    assert!(written.ends_with('\n'));
+}
+
+#[test]
+fn sink_writes_cumulative_sums_as_deltas() {
+    let cumulative = |time, value| {
+        request(vec![Metric {
+            name: "requests".to_owned(),
+            data: Some(Data::Sum(Sum {
+                data_points: vec![NumberDataPoint {
+                    start_time_unix_nano: TIME - 1_000_000_000,
+                    time_unix_nano: time,
+                    value: Some(number_data_point::Value::AsInt(value)),
+                    ..Default::default()
+                }],
+                aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                is_monotonic: true,
+            })),
+            ..Default::default()
+        }])
+    };
+    let buf = SharedBuf::default();
+    let mut sink = EmfSink::with_writer(&config(&[]), Box::new(buf.clone()));
+
+    // The stream started before the relay: its running total is not a delta.
+    sink.write(&cumulative(TIME, 5));
+    assert!(buf.0.lock().unwrap().is_empty());
+
+    let req = cumulative(TIME + 1_000_000_000, 8);
+    sink.write(&req);
+    let written = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+    assert!(written.contains(r#""requests":3"#), "{written}");
+    // The request itself, exported in `alongside` mode, stays cumulative.
+    let Some(Data::Sum(sum)) = &req.resource_metrics[0].scope_metrics[0].metrics[0].data else {
+        panic!("expected a sum");
+    };
+    assert_eq!(
+        sum.data_points[0].value,
+        Some(number_data_point::Value::AsInt(8))
+    );
+}
@@ -1,7 +1,8 @@
 use std::ops::ControlFlow;
 use std::sync::atomic::Ordering;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use crate::buffers::Signal;
 use crate::extensions_api::ExtensionsApiEvent;
 use crate::flush_strategy::FlushStrategy;
 use crate::testing::{MockApi, MockExporter, dummy_config};
@@ -169,3 +170,456 @@ async fn continuously_timer_flushes_buffer_via_tick() {
     state.release.notify_one();
     let _ = event_loop.run().await;
 }
+
+/// With self-metrics enabled, the interval branch pushes the relay's own
+/// metrics into the metrics buffer.
+#[tokio::test]
+async fn self_metrics_injected_on_interval() {
+    let (mock, state) = MockApi::new(vec![Ok(ExtensionsApiEvent::Shutdown {
+        reason: "test".into(),
+    })]);
+
+    let mut config = dummy_config().await;
+    config.self_metrics_interval = Some(Duration::from_millis(10));
+
+    let mut event_loop = EventLoop::new(&mock, MockExporter, &config).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let _ = event_loop.tick().await;
+
+    let data = event_loop.buffer.take();
+    assert_eq!(data.metrics.queue.len(), 1);
+    assert!(data.traces.is_empty());
+
+    state.release.notify_one();
+    let _ = event_loop.run().await;
+}
+
+/// `POST /flush` is served by the event loop, which first drains payloads
+/// already accepted by the listener so they are part of the export.
+#[tokio::test]
+async fn flush_endpoint_exports_pending_payloads() {
+    let (mock, state) = MockApi::new(vec![Ok(ExtensionsApiEvent::Shutdown {
+        reason: "test".into(),
+    })]);
+
+    let config = dummy_config().await;
+    let mut event_loop = EventLoop::new(&mock, MockExporter, &config).await.unwrap();
+
+    let client = reqwest::Client::new();
+    let base = format!("http://127.0.0.1:{}", config.listener_port);
+    let resp = client
+        .post(format!("{base}/v1/traces"))
+        .body(b"\x0a\x00".to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // The payload is still in the channel; only the flush request drives it.
+    let flush = client.post(format!("{base}/flush")).send();
+    tokio::pin!(flush);
+    let resp = loop {
+        tokio::select! {
+            resp = &mut flush => break resp.unwrap(),
+            _ = event_loop.tick() => {}
+        }
+    };
+    assert_eq!(resp.status(), 200);
+    assert!(
+        resp.text()
+            .await
+            .unwrap()
+            .contains(r#""outcome":"exported""#)
+    );
+    assert!(event_loop.buffer.take().is_empty());
+
+    let resp = client.get(format!("{base}/health")).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    assert!(resp.text().await.unwrap().contains(r#""success":true"#));
+
+    state.release.notify_one();
+    let _ = event_loop.run().await;
+}
+
+/// `X-Relay-Flush: sync` holds the OTLP response until the payload has been
+/// exported, and the flush counts towards the coordinator's dedup window.
+#[tokio::test]
+async fn sync_flush_header_exports_before_responding() {
+    let (mock, state) = MockApi::new(vec![Ok(ExtensionsApiEvent::Shutdown {
+        reason: "test".into(),
+    })]);
+
+    let mut config = dummy_config().await;
+    config.flush_strategy = FlushStrategy::End;
+    let mut event_loop = EventLoop::new(&mock, MockExporter, &config).await.unwrap();
+
+    let client = reqwest::Client::new();
+    let post = client
+        .post(format!(
+            "http://127.0.0.1:{}/v1/traces",
+            config.listener_port
+        ))
+        .header("X-Relay-Flush", "sync")
+        .body(b"\x0a\x00".to_vec())
+        .send();
+    tokio::pin!(post);
+    let resp = loop {
+        tokio::select! {
+            resp = &mut post => break resp.unwrap(),
+            _ = event_loop.tick() => {}
+        }
+    };
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["x-relay-flush"], "exported");
+    assert!(event_loop.buffer.take().is_empty());
+    assert!(!event_loop.flush_coordinator.should_flush_at_boundary());
+
+    state.release.notify_one();
+    let _ = event_loop.run().await;
+}
+
+/// With tail sampling, a sync flush during an invocation cannot export its
+/// spans yet: the outcome says they are still held.
+#[tokio::test]
+async fn sync_flush_reports_spans_held_for_tail_sampling() {
+    use crate::config::{SamplingConfig, SamplingMode};
+
+    let (mock, state) = MockApi::new(vec![Ok(ExtensionsApiEvent::Shutdown {
+        reason: "test".into(),
+    })]);
+
+    let mut config = dummy_config().await;
+    config.flush_strategy = FlushStrategy::End;
+    config.sampling = Some(SamplingConfig {
+        ratio: 1.0,
+        keep_errors: false,
+        mode: SamplingMode::Tail,
+        latency_threshold: None,
+    });
+    let mut event_loop = EventLoop::new(&mock, MockExporter, &config).await.unwrap();
+    let tail = event_loop.tail_sampler.as_mut().unwrap();
+    assert!(tail.start("req-1").is_empty());
+
+    let client = reqwest::Client::new();
+    let base = format!("http://127.0.0.1:{}", config.listener_port);
+    let post = client
+        .post(format!("{base}/v1/traces"))
+        .header("X-Relay-Flush", "sync")
+        .body(b"\x0a\x00".to_vec())
+        .send();
+    tokio::pin!(post);
+    let resp = loop {
+        tokio::select! {
+            resp = &mut post => break resp.unwrap(),
+            _ = event_loop.tick() => {}
+        }
+    };
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["x-relay-flush"], "held");
+
+    // Once the invocation has finished, its spans are exported.
+    let tail = event_loop.tail_sampler.as_mut().unwrap();
+    let released = tail.finish("req-1", "error", None);
+    assert_eq!(released.len(), 1);
+    event_loop.push_traces(released);
+    let flush = client.post(format!("{base}/flush")).send();
+    tokio::pin!(flush);
+    let resp = loop {
+        tokio::select! {
+            resp = &mut flush => break resp.unwrap(),
+            _ = event_loop.tick() => {}
+        }
+    };
+    assert!(
+        resp.text()
+            .await
+            .unwrap()
+            .contains(r#""outcome":"exported""#)
+    );
+
+    state.release.notify_one();
+    let _ = event_loop.run().await;
+}
+
+/// On Lambda Managed Instances the event loop follows invocations through a
+/// stream of platform events: it attributes payloads to the running
+/// invocation, sizes the buffer by concurrency and flushes once idle.
+#[tokio::test]
+async fn managed_instances_follow_platform_events() {
+    use prost::Message;
+
+    use crate::config::ConcurrencyConfig;
+    use crate::invocations::INVOCATION_ID_KEY;
+    use crate::proto::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+    use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Span};
+
+    let (mock, state) = MockApi::new(vec![Ok(ExtensionsApiEvent::Shutdown {
+        reason: "test".into(),
+    })]);
+
+    let mut config = dummy_config().await;
+    config.flush_strategy = FlushStrategy::Continuously {
+        interval: Duration::from_secs(60),
+    };
+    config.concurrency = Some(ConcurrencyConfig {
+        idle_flush_delay: Duration::from_millis(10),
+        bytes_per_invocation: Some(1_000),
+    });
+    let mut event_loop = EventLoop::new(&mock, MockExporter, &config).await.unwrap();
+    assert_eq!(event_loop.buffer.stats().max_bytes, Some(1_000));
+
+    let client = reqwest::Client::new();
+    let platform = |events: &[(&str, &str)]| {
+        let batch: Vec<String> = events
+            .iter()
+            .map(|(kind, request_id)| {
+                format!(
+                    r#"{{"type":"platform.{kind}","time":"2024-01-01T00:00:00Z","record":{{"requestId":"{request_id}","status":"success"}}}}"#
+                )
+            })
+            .collect();
+        client
+            .post(format!("http://127.0.0.1:{}/", config.telemetry_port))
+            .body(format!("[{}]", batch.join(",")))
+            .send()
+    };
+
+    assert_eq!(
+        platform(&[("start", "a"), ("start", "b")])
+            .await
+            .unwrap()
+            .status(),
+        200
+    );
+    let _ = event_loop.tick().await;
+    let _ = event_loop.tick().await;
+    assert_eq!(event_loop.buffer.stats().max_bytes, Some(2_000));
+
+    platform(&[("runtimeDone", "b")]).await.unwrap();
+    let _ = event_loop.tick().await;
+    assert!(event_loop.idle_flush_at.is_none());
+
+    let payload = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans: vec![Span::default()],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+    client
+        .post(format!(
+            "http://127.0.0.1:{}/v1/traces",
+            config.listener_port
+        ))
+        .body(payload.encode_to_vec())
+        .send()
+        .await
+        .unwrap();
+    let _ = event_loop.tick().await;
+    let data = event_loop.buffer.take();
+    let received = ExportTraceServiceRequest::decode(data.traces.queue[0].clone()).unwrap();
+    let span = &received.resource_spans[0].scope_spans[0].spans[0];
+    assert_eq!(span.attributes[0].key, INVOCATION_ID_KEY);
+    event_loop
+        .buffer
+        .push(Signal::Traces, data.traces.queue[0].clone());
+
+    // The last invocation finishing starts the idle delay, then a flush.
+    platform(&[("runtimeDone", "a")]).await.unwrap();
+    let _ = event_loop.tick().await;
+    assert!(event_loop.idle_flush_at.is_some());
+    let _ = event_loop.tick().await;
+    event_loop.buffer.join_flush_task().await;
+    assert!(event_loop.buffer.take().is_empty());
+    // Peak concurrency is remembered after the environment goes idle.
+    assert_eq!(event_loop.buffer.stats().max_bytes, Some(2_000));
+
+    state.release.notify_one();
+    let _ = event_loop.run().await;
+}
+
+/// Format `time` the way the Telemetry API does, e.g.
+/// `2024-01-01T00:00:00.000Z`.
+fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+    let secs = since_epoch.as_secs();
+    // Civil date from days since the epoch, proleptic Gregorian.
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let secs = secs % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs / 3_600,
+        secs / 60 % 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// `platform.restoreStart` resets flush timing and, by default, discards
+/// data buffered before the SnapStart snapshot, including spans held by the
+/// tail sampler, along with the invocations and X-Ray contexts seen before it.
+/// The event arrives late: data sent after the restore but before it is
+/// handled is kept.
+#[tokio::test]
+async fn restore_discards_pre_snapshot_data() {
+    use crate::config::{ConcurrencyConfig, SamplingConfig, SamplingMode};
+    use crate::xray::XrayContext;
+
+    const HEADER: &str =
+        "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1";
+
+    let (mock, state) = MockApi::new(vec![Ok(ExtensionsApiEvent::Shutdown {
+        reason: "test".into(),
+    })]);
+
+    let mut config = dummy_config().await;
+    config.flush_strategy = FlushStrategy::Periodically {
+        interval: Duration::from_secs(60),
+    };
+    config.sampling = Some(SamplingConfig {
+        ratio: 1.0,
+        keep_errors: false,
+        mode: SamplingMode::Tail,
+        latency_threshold: None,
+    });
+    config.concurrency = Some(ConcurrencyConfig {
+        idle_flush_delay: Duration::from_millis(10),
+        bytes_per_invocation: None,
+    });
+    config.xray_parent = true;
+    let mut event_loop = EventLoop::new(&mock, MockExporter, &config).await.unwrap();
+    event_loop.buffer.push(Signal::Traces, "snapshot".into());
+    event_loop.flush_coordinator.record_flush();
+    let tail = event_loop.tail_sampler.as_mut().unwrap();
+    assert!(tail.start("init").is_empty());
+    assert!(tail.hold("held".into()).is_empty());
+    event_loop
+        .invocations
+        .as_mut()
+        .unwrap()
+        .start("init", Some(HEADER));
+    event_loop
+        .xray_bridge
+        .as_mut()
+        .unwrap()
+        .record(XrayContext::parse(HEADER).unwrap());
+
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let restored_at = rfc3339(SystemTime::now());
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    // Restore hooks and the first invocation send data before the
+    // restoreStart event is handled.
+    event_loop.buffer.push(Signal::Traces, "restored".into());
+    let tail = event_loop.tail_sampler.as_mut().unwrap();
+    assert!(tail.start("first").is_empty());
+    assert!(tail.hold("first span".into()).is_empty());
+
+    let resp = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{}/", config.telemetry_port))
+        .body(format!(
+            r#"[{{"type":"platform.restoreStart","time":"{restored_at}","record":{{}}}}]"#
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let _ = event_loop.tick().await;
+
+    assert_eq!(
+        event_loop.buffer.take().traces.queue,
+        [bytes::Bytes::from("restored")]
+    );
+    assert!(event_loop.flush_coordinator.should_flush_at_boundary());
+    assert_eq!(
+        event_loop.tail_sampler.as_mut().unwrap().release(),
+        [bytes::Bytes::from("first span")]
+    );
+    assert_eq!(event_loop.invocations.as_ref().unwrap().in_flight(), 0);
+    assert!(event_loop.xray_bridge.as_ref().unwrap().is_empty());
+
+    state.release.notify_one();
+    let _ = event_loop.run().await;
+}
+
+/// Payloads still in the channel at shutdown take the same path as live
+/// ones: in tail mode they are seen by span metrics and the tail sampler
+/// before it is released.
+#[tokio::test]
+async fn shutdown_drains_the_channel_through_receive() {
+    use prost::Message;
+
+    use crate::config::{SamplingConfig, SamplingMode, SpanMetricsConfig};
+    use crate::proto::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+    use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Span};
+    use crate::testing::FailingExporter;
+
+    let (mock, state) = MockApi::new(vec![Ok(ExtensionsApiEvent::Shutdown {
+        reason: "test".into(),
+    })]);
+
+    let mut config = dummy_config().await;
+    config.sampling = Some(SamplingConfig {
+        ratio: 1.0,
+        keep_errors: false,
+        mode: SamplingMode::Tail,
+        latency_threshold: None,
+    });
+    config.spanmetrics = Some(SpanMetricsConfig {
+        dimensions: Vec::new(),
+        buckets: vec![10.0],
+    });
+    let mut event_loop = EventLoop::new(&mock, FailingExporter, &config)
+        .await
+        .unwrap();
+
+    let payload = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            scope_spans: vec![ScopeSpans {
+                spans: vec![Span {
+                    trace_id: vec![1; 16],
+                    span_id: vec![2; 8],
+                    name: "handler".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    };
+    let resp = reqwest::Client::new()
+        .post(format!(
+            "http://127.0.0.1:{}/v1/traces",
+            config.listener_port
+        ))
+        .body(payload.encode_to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    state.release.notify_one();
+    let _ = event_loop.run().await;
+
+    // The final flush failed, so everything drained is back in the buffer.
+    let data = event_loop.buffer.take();
+    assert_eq!(data.traces.queue.len(), 1);
+    assert!(!data.metrics.queue.is_empty());
+}
@@ -1,19 +1,25 @@
 use std::ops::ControlFlow;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use bytes::Bytes;
 use tokio::net::TcpListener;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
 use tokio_util::sync::{CancellationToken, ReusableBoxFuture};
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
-use crate::buffers::{OutboundBuffer, Signal};
-use crate::config::Config;
+use crate::buffers::{FlushOutcome, FlushReport, OutboundBuffer, Signal};
+use crate::config::{ConcurrencyConfig, Config, RestorePolicy, SamplingMode};
 use crate::exporter::Exporter;
 use crate::extensions_api::{self, ApiError, ExitError, ExtensionsApi, ExtensionsApiEvent};
 use crate::flush_strategy::{FlushCoordinator, TimerMode};
+use crate::invocations::{self, Invocations};
+use crate::otlp_listener::{ControlRequest, ListenerState};
+use crate::processor::{Pipeline, TailSampler};
 use crate::telemetry_listener::TelemetryEvent;
+use crate::xray::{XrayBridge, XrayContext};
 use crate::{otlp_listener, telemetry_listener};
 
 /// Owns all state for the extension's main select! loop.
@@ -25,7 +31,24 @@ pub struct EventLoop<'a, A: ExtensionsApi, E: Exporter> {
     exporter: Arc<E>,
     buffer: OutboundBuffer,
     flush_coordinator: FlushCoordinator,
-    flush_rx: mpsc::Receiver<()>,
+    tail_sampler: Option<TailSampler>,
+    /// Span metrics derived before tail sampling, which would otherwise hide
+    /// the spans it drops from the pipeline. Only runs the stages before span
+    /// metrics; see [`Pipeline::tail_spanmetrics`].
+    tail_spanmetrics: Option<Pipeline>,
+    /// Running invocations, followed through platform events on Lambda
+    /// Managed Instances.
+    invocations: Option<Invocations>,
+    concurrency: Option<ConcurrencyConfig>,
+    /// `buffer_max_bytes` from the config, the most the buffer may grow to.
+    buffer_ceiling: Option<usize>,
+    /// When the environment went idle plus the idle flush delay.
+    idle_flush_at: Option<Instant>,
+    restore_policy: RestorePolicy,
+    xray_bridge: Option<XrayBridge>,
+    self_metrics_interval: Option<Interval>,
+    flush_rx: mpsc::Receiver<FlushReport>,
+    control_rx: mpsc::Receiver<ControlRequest>,
     otlp_rx: mpsc::Receiver<(Signal, Bytes)>,
     telemetry_rx: mpsc::Receiver<TelemetryEvent>,
     cancel: CancellationToken,
@@ -45,7 +68,8 @@ impl<'a, A: ExtensionsApi, E: Exporter> EventLoop<'a, A, E> {
         let cancel = CancellationToken::new();
         let (otlp_tx, otlp_rx) = mpsc::channel::<(Signal, Bytes)>(128);
         let (telemetry_tx, telemetry_rx) = mpsc::channel::<TelemetryEvent>(64);
-        let (flush_tx, flush_rx) = mpsc::channel::<()>(1);
+        let (flush_tx, flush_rx) = mpsc::channel::<FlushReport>(1);
+        let (control_tx, control_rx) = mpsc::channel::<ControlRequest>(8);
 
         let otlp_listener = TcpListener::bind(("127.0.0.1", config.listener_port))
             .await
@@ -56,11 +80,30 @@ impl<'a, A: ExtensionsApi, E: Exporter> EventLoop<'a, A, E> {
             .map_err(|e| ApiError::InitFailed(format!("failed to bind telemetry listener: {e}")))?;
         api.register_telemetry(config.telemetry_port).await?;
 
+        let max_bytes = match config
+            .concurrency
+            .as_ref()
+            .and_then(|c| c.bytes_per_invocation)
+        {
+            Some(per_invocation) => Some(invocations::buffer_limit(
+                per_invocation,
+                1,
+                config.buffer_max_bytes,
+            )),
+            None => config.buffer_max_bytes,
+        };
+        let buffer = OutboundBuffer::new(max_bytes, Pipeline::new(config), flush_tx)
+            .with_flush_thresholds(config.flush_thresholds)
+            .with_quotas(config.buffer_quotas, config.eviction_policy);
         let otlp_task = tokio::spawn(otlp_listener::serve(
             otlp_listener,
-            otlp_tx,
+            ListenerState {
+                tx: otlp_tx,
+                enabled: config.accepted_signals(),
+                buffer: buffer.clone(),
+                control_tx,
+            },
             cancel.clone(),
-            config.enabled_signals,
         ));
         let telemetry_task = tokio::spawn(telemetry_listener::serve(
             telemetry_listener,
@@ -71,9 +114,30 @@ impl<'a, A: ExtensionsApi, E: Exporter> EventLoop<'a, A, E> {
         Ok(Self {
             api,
             exporter: Arc::new(exporter),
-            buffer: OutboundBuffer::new(config.buffer_max_bytes, flush_tx),
+            buffer,
             flush_coordinator: FlushCoordinator::new(config.flush_strategy.clone()),
+            tail_sampler: config
+                .sampling
+                .as_ref()
+                .filter(|s| s.mode == SamplingMode::Tail)
+                .map(|s| TailSampler::new(s, config.buffer_max_bytes)),
+            tail_spanmetrics: Pipeline::tail_spanmetrics(config),
+            invocations: config.concurrency.as_ref().map(|_| Invocations::new()),
+            concurrency: config.concurrency.clone(),
+            buffer_ceiling: config.buffer_max_bytes,
+            idle_flush_at: None,
+            restore_policy: config.restore_policy,
+            xray_bridge: config.xray_parent.then(XrayBridge::new),
+            self_metrics_interval: config
+                .self_metrics_interval
+                .filter(|_| config.enabled_signals.is_enabled(Signal::Metrics))
+                .map(|period| {
+                    let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+                    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                    interval
+                }),
             flush_rx,
+            control_rx,
             otlp_rx,
             telemetry_rx,
             cancel,
@@ -114,8 +178,20 @@ impl<'a, A: ExtensionsApi, E: Exporter> EventLoop<'a, A, E> {
                 match event {
                     Ok(ExtensionsApiEvent::Invoke { request_id }) => {
                         debug!(request_id, "Received invoke event");
+                        if let Some(tail) = &mut self.tail_sampler {
+                            let released = tail.start(&request_id);
+                            self.push_traces(released);
+                        }
+                        self.flush_coordinator.record_invocation();
                         if self.flush_coordinator.should_flush_at_boundary() {
-                            self.buffer.flush(&*self.exporter).await;
+                            match self.flush_coordinator.boundary_mode() {
+                                TimerMode::Sync => {
+                                    self.buffer.flush(&*self.exporter).await;
+                                }
+                                TimerMode::Background => {
+                                    self.buffer.spawn_flush(&self.exporter);
+                                }
+                            }
                         }
                     }
                     Ok(ExtensionsApiEvent::Shutdown { reason }) => {
@@ -128,9 +204,20 @@ impl<'a, A: ExtensionsApi, E: Exporter> EventLoop<'a, A, E> {
                         let _ = (&mut self.otlp_task).await;
                         let _ = (&mut self.telemetry_task).await;
 
-                        // Drain any payloads still in the channel
+                        // Drain any payloads still in the channel through the
+                        // same path as live ones, so they are linked, attributed
+                        // and seen by the tail sampler before it is released.
                         while let Ok((signal, payload)) = self.otlp_rx.try_recv() {
-                            self.buffer.push(signal, payload);
+                            self.receive(signal, payload);
+                        }
+                        self.control_rx.close();
+                        if self.self_metrics_interval.is_some() {
+                            self.inject_self_metrics();
+                        }
+                        if let Some(tail) = &mut self.tail_sampler {
+                            for payload in tail.release() {
+                                self.buffer.push(Signal::Traces, payload);
+                            }
                         }
 
                         // Best-effort final flush. prepend_failed inside flush is
@@ -147,9 +234,7 @@ impl<'a, A: ExtensionsApi, E: Exporter> EventLoop<'a, A, E> {
             }
             result = self.otlp_rx.recv() => {
                 match result {
-                    Some((signal, payload)) => {
-                        self.buffer.push_and_maybe_flush(signal, payload, &self.exporter);
-                    }
+                    Some((signal, payload)) => self.receive(signal, payload),
                     None if !self.cancel.is_cancelled() => {
                         return ControlFlow::Break(Err(ExitError::RuntimeFailure(
                             "OTLP listener died unexpectedly".into(),
@@ -161,14 +246,42 @@ impl<'a, A: ExtensionsApi, E: Exporter> EventLoop<'a, A, E> {
             result = self.telemetry_rx.recv() => {
                 match result {
                     Some(event) => match event {
-                        TelemetryEvent::RuntimeDone { request_id, status } => {
+                        TelemetryEvent::RuntimeDone { request_id, status, duration_ms } => {
                             debug!(request_id, status, "Received runtimeDone event");
-                            // TODO: update invocation state map, emit timeout log record
+                            if let Some(tail) = &mut self.tail_sampler {
+                                let decided = tail.finish(&request_id, &status, duration_ms);
+                                self.push_traces(decided);
+                            }
+                            if let Some(invocations) = &mut self.invocations
+                                && invocations.finish(&request_id)
+                                && let Some(concurrency) = &self.concurrency
+                            {
+                                self.idle_flush_at =
+                                    Some(Instant::now() + concurrency.idle_flush_delay);
+                            }
+                            // TODO: emit timeout log record
                         }
                         TelemetryEvent::Start { request_id, tracing_value } => {
-                            let _tracing_value = tracing_value;
                             debug!(request_id, "Received start event");
-                            // TODO: extract X-Ray trace ID, store in state map
+                            if let Some(bridge) = &mut self.xray_bridge
+                                && let Some(context) =
+                                    tracing_value.as_deref().and_then(XrayContext::parse)
+                            {
+                                bridge.record(context);
+                            }
+                            if let Some(invocations) = &mut self.invocations {
+                                invocations.start(&request_id, tracing_value.as_deref());
+                                debug!(in_flight = invocations.in_flight(), "invocation started");
+                                self.idle_flush_at = None;
+                                self.resize_buffer();
+                            }
+                        }
+                        TelemetryEvent::RestoreStart { time } => {
+                            debug!("Received restoreStart event");
+                            self.restore(time.unwrap_or_else(SystemTime::now));
+                        }
+                        TelemetryEvent::RestoreRuntimeDone { status } => {
+                            debug!(status, "Received restoreRuntimeDone event");
                         }
                     }
                     None if !self.cancel.is_cancelled() => {
@@ -179,8 +292,24 @@ impl<'a, A: ExtensionsApi, E: Exporter> EventLoop<'a, A, E> {
                     None => {}
                 }
             }
-            _ = self.flush_rx.recv() => {
+            Some(request) = self.control_rx.recv() => {
+                self.flush_on_demand(request).await;
+            }
+            _ = sleep_until_deadline(self.idle_flush_at) => {
+                // Any platform.start since going idle clears the deadline.
+                self.idle_flush_at = None;
+                debug!("environment idle, flushing");
+                self.buffer.spawn_flush(&self.exporter);
+            }
+            _ = sleep_until_deadline(self.buffer.age_deadline()) => {
+                self.buffer.flush_aged(&self.exporter);
+            }
+            _ = next_self_metrics_tick(&mut self.self_metrics_interval) => {
+                self.inject_self_metrics();
+            }
+            Some(report) = self.flush_rx.recv() => {
                 self.flush_coordinator.record_flush();
+                self.flush_coordinator.record_export(report.bytes, report.duration);
             }
             _ = self.flush_coordinator.next_tick() => {
                 if self.flush_coordinator.should_flush_on_timer() {
@@ -197,6 +326,174 @@ impl<'a, A: ExtensionsApi, E: Exporter> EventLoop<'a, A, E> {
         }
         ControlFlow::Continue(())
     }
+
+    /// Serve `/flush` and `X-Relay-Flush: sync` requests.
+    ///
+    /// A request is only sent after its payload was accepted, so every request
+    /// already queued has its payload in the OTLP channel: one flush answers
+    /// them all. The flush is recorded with the coordinator straight away so a
+    /// boundary or timer flush right behind it falls in the dedup window.
+    ///
+    /// Spans held by the tail sampler are not part of the flush, as their
+    /// invocation has yet to finish; the outcome is `Held` while there are any.
+    async fn flush_on_demand(&mut self, request: ControlRequest) {
+        let ControlRequest::Flush { reply } = request;
+        let mut replies = vec![reply];
+        while let Ok(ControlRequest::Flush { reply }) = self.control_rx.try_recv() {
+            replies.push(reply);
+        }
+        while let Ok((signal, payload)) = self.otlp_rx.try_recv() {
+            self.receive(signal, payload);
+        }
+
+        let mut outcome = self.buffer.flush(&*self.exporter).await;
+        if outcome != FlushOutcome::Empty {
+            self.flush_coordinator.record_flush();
+        }
+        if matches!(outcome, FlushOutcome::Empty | FlushOutcome::Exported)
+            && self
+                .tail_sampler
+                .as_ref()
+                .is_some_and(TailSampler::is_holding)
+        {
+            outcome = FlushOutcome::Held;
+        }
+        debug!(requests = replies.len(), ?outcome, "on-demand flush");
+        for reply in replies {
+            let _ = reply.send(outcome.clone());
+        }
+    }
+
+    /// Drop state taken over from a SnapStart snapshot restored at
+    /// `restored_at`: pooled connections, cached credentials, flush timing,
+    /// metric temporality state, the invocations and X-Ray contexts seen
+    /// before it and, under `RestorePolicy::Discard`, whatever was buffered
+    /// or held by the tail sampler before the snapshot.
+    ///
+    /// `platform.restoreStart` comes through the Telemetry API, so restore
+    /// hooks and the first invocation may already have sent data by the time
+    /// it is handled. Only data buffered before `restored_at` is dropped,
+    /// including data a failing flush hands back later. Under
+    /// `RestorePolicy::Keep` spans held for invocations started before it are
+    /// buffered unsampled, as those invocations will never finish.
+    fn restore(&mut self, restored_at: SystemTime) {
+        self.exporter.reset();
+        self.flush_coordinator.reset();
+        self.buffer.reset_pipeline();
+        if let Some(interval) = &mut self.self_metrics_interval {
+            interval.reset();
+        }
+        self.idle_flush_at = None;
+        if let Some(bridge) = &mut self.xray_bridge {
+            bridge.reset();
+        }
+        if let Some(invocations) = &mut self.invocations {
+            invocations.reset();
+        }
+        match self.restore_policy {
+            RestorePolicy::Discard => {
+                let held = self.tail_sampler.as_mut().map_or(0, |tail| {
+                    tail.reset(restored_at).iter().map(Bytes::len).sum()
+                });
+                let bytes = self.buffer.discard_before(restored_at) + held;
+                if bytes > 0 {
+                    warn!(bytes, "discarded data buffered before the snapshot");
+                }
+            }
+            RestorePolicy::Keep => {
+                if let Some(tail) = &mut self.tail_sampler {
+                    let released = tail.reset(restored_at);
+                    self.push_traces(released);
+                }
+            }
+        }
+    }
+
+    /// Route an OTLP payload into the buffer, via the tail sampler for traces,
+    /// then release the room the listener reserved for it.
+    fn receive(&mut self, signal: Signal, payload: Bytes) {
+        let reserved = payload.len();
+        self.route_payload(signal, payload);
+        self.buffer.release(signal, reserved);
+    }
+
+    fn route_payload(&mut self, signal: Signal, payload: Bytes) {
+        let payload = match &self.xray_bridge {
+            Some(bridge) if signal == Signal::Traces => bridge.link(payload),
+            _ => payload,
+        };
+        let payload = match &self.invocations {
+            Some(invocations) => invocations.attribute(signal, payload),
+            None => payload,
+        };
+        self.flush_coordinator.record_payload(payload.len());
+        match &mut self.tail_sampler {
+            Some(tail) if signal == Signal::Traces => {
+                if let Some(derived) = self
+                    .tail_spanmetrics
+                    .as_mut()
+                    .and_then(|s| s.derive_span_metrics(&payload))
+                {
+                    self.buffer
+                        .push_and_maybe_flush(Signal::Metrics, derived, &self.exporter);
+                }
+                let ready = tail.hold(payload);
+                self.push_traces(ready);
+            }
+            _ => {
+                self.buffer
+                    .push_and_maybe_flush(signal, payload, &self.exporter);
+            }
+        }
+    }
+
+    /// Grow the buffer limit to the most invocations seen running at once.
+    fn resize_buffer(&self) {
+        if let Some(invocations) = &self.invocations
+            && let Some(per_invocation) = self
+                .concurrency
+                .as_ref()
+                .and_then(|c| c.bytes_per_invocation)
+        {
+            self.buffer.set_max_bytes(Some(invocations::buffer_limit(
+                per_invocation,
+                invocations.peak(),
+                self.buffer_ceiling,
+            )));
+        }
+    }
+
+    /// Snapshot the relay's own metrics into the metrics buffer.
+    fn inject_self_metrics(&self) {
+        let payload = self.buffer.metrics().encode();
+        self.buffer.push(Signal::Metrics, payload);
+    }
+
+    /// Buffer trace payloads handed back by the tail sampler.
+    fn push_traces(&self, payloads: Vec<Bytes>) {
+        for payload in payloads {
+            self.buffer
+                .push_and_maybe_flush(Signal::Traces, payload, &self.exporter);
+        }
+    }
+}
+
+/// Await the next self-metrics tick. Pends forever if self-metrics are disabled.
+async fn next_self_metrics_tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Sleep until `deadline`. Pends forever if there is none.
+async fn sleep_until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
 }
 
 #[cfg(test)]
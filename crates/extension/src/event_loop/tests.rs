@@ -4,12 +4,13 @@ use bytes::Bytes;
 use tokio::sync::mpsc;
 
 use crate::buffers::{OutboundBuffer, Signal};
+use crate::processor::Pipeline;
 use crate::testing::{FailingExporter, MockExporter, PartialFailExporter};
 
 #[tokio::test]
 async fn failed_flush_prepends_data_back() {
     let (tx, _) = mpsc::channel(1);
-    let buffer = OutboundBuffer::new(Some(1_000_000), tx);
+    let buffer = OutboundBuffer::new(Some(1_000_000), Pipeline::default(), tx);
     buffer.push(Signal::Traces, Bytes::from("trace_data"));
     buffer.push(Signal::Metrics, Bytes::from("metric_data"));
 
@@ -29,7 +30,7 @@ async fn failed_flush_prepends_data_back() {
 #[tokio::test]
 async fn sync_flush_joins_background_task() {
     let (tx, _) = mpsc::channel(1);
-    let buffer = OutboundBuffer::new(None, tx);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx);
     buffer.push(Signal::Traces, Bytes::from("data"));
 
     let exporter = Arc::new(MockExporter);
@@ -49,7 +50,7 @@ async fn sync_flush_joins_background_task() {
 #[tokio::test]
 async fn partial_failure_preserves_only_failed_signals() {
     let (tx, _) = mpsc::channel(1);
-    let buffer = OutboundBuffer::new(None, tx);
+    let buffer = OutboundBuffer::new(None, Pipeline::default(), tx);
     buffer.push(Signal::Traces, Bytes::from("trace_data"));
     buffer.push(Signal::Metrics, Bytes::from("metric_data"));
     buffer.push(Signal::Logs, Bytes::from("log_data"));
@@ -0,0 +1,188 @@
+//! AWS credentials for SigV4 signing.
+//!
+//! Credentials in the environment are read fresh on each request because
+//! Lambda rotates them during the extension's lifetime. SnapStart functions
+//! get no credentials in the environment and use the container credentials
+//! endpoint (`AWS_CONTAINER_CREDENTIALS_FULL_URI`) instead. Those are cached
+//! until shortly before they expire, and dropped on restore: a cache taken
+//! over from the snapshot may belong to an environment long gone.
+
+#![allow(clippy::question_mark)] // nanoserde DeJson derive
+
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use aws_credential_types::Credentials;
+use nanoserde::DeJson;
+
+use super::ExportError;
+use crate::http_client::HttpClient;
+
+/// Cached credentials are refreshed this long before they expire.
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+const PROVIDER_NAME: &str = "lambda-otel-relay";
+
+#[derive(Default)]
+pub struct CredentialsProvider {
+    cached: Mutex<Option<Credentials>>,
+}
+
+impl CredentialsProvider {
+    pub async fn resolve(&self, client: &HttpClient) -> Result<Credentials, ExportError> {
+        if let Some(credentials) = from_env()? {
+            return Ok(credentials);
+        }
+        let uri = env_var("AWS_CONTAINER_CREDENTIALS_FULL_URI").ok_or_else(|| {
+            ExportError::Signing(
+                "neither AWS_ACCESS_KEY_ID nor AWS_CONTAINER_CREDENTIALS_FULL_URI is set".into(),
+            )
+        })?;
+        if let Some(credentials) = self.cached() {
+            return Ok(credentials);
+        }
+        let token = env_var("AWS_CONTAINER_AUTHORIZATION_TOKEN");
+        let credentials = fetch(client, &uri, token.as_deref()).await?;
+        *self.cached.lock().unwrap() = Some(credentials.clone());
+        Ok(credentials)
+    }
+
+    /// Forget cached credentials; the next request fetches new ones.
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+
+    fn cached(&self) -> Option<Credentials> {
+        let refresh_at = SystemTime::now() + REFRESH_MARGIN;
+        self.cached.lock().unwrap().clone().filter(|credentials| {
+            credentials
+                .expiry()
+                .is_none_or(|expiry| expiry > refresh_at)
+        })
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|s| !s.is_empty())
+}
+
+fn from_env() -> Result<Option<Credentials>, ExportError> {
+    let Some(access_key) = env_var("AWS_ACCESS_KEY_ID") else {
+        return Ok(None);
+    };
+    let secret_key = env_var("AWS_SECRET_ACCESS_KEY")
+        .ok_or_else(|| ExportError::Signing("AWS_SECRET_ACCESS_KEY not set".into()))?;
+    let session_token = env_var("AWS_SESSION_TOKEN");
+    Ok(Some(Credentials::new(
+        access_key,
+        secret_key,
+        session_token,
+        None,
+        PROVIDER_NAME,
+    )))
+}
+
+async fn fetch(
+    client: &HttpClient,
+    uri: &str,
+    token: Option<&str>,
+) -> Result<Credentials, ExportError> {
+    let headers: Vec<(String, String)> = token
+        .map(|token| ("authorization".to_owned(), token.to_owned()))
+        .into_iter()
+        .collect();
+    let resp = client.get(uri, &headers).await?;
+    if !resp.status.is_success() {
+        return Err(ExportError::Signing(format!(
+            "container credentials endpoint returned {}",
+            resp.status
+        )));
+    }
+    parse_container_credentials(&resp.body)
+}
+
+/// Response of the container credentials endpoint.
+#[derive(DeJson)]
+struct RawCredentials {
+    #[nserde(rename = "AccessKeyId")]
+    access_key_id: String,
+    #[nserde(rename = "SecretAccessKey")]
+    secret_access_key: String,
+    #[nserde(rename = "Token")]
+    token: Option<String>,
+    #[nserde(rename = "Expiration")]
+    expiration: Option<String>,
+}
+
+fn parse_container_credentials(body: &[u8]) -> Result<Credentials, ExportError> {
+    let invalid =
+        |detail: String| ExportError::Signing(format!("invalid container credentials: {detail}"));
+    let body = std::str::from_utf8(body).map_err(|e| invalid(e.to_string()))?;
+    let raw: RawCredentials = DeJson::deserialize_json(body).map_err(|e| invalid(e.to_string()))?;
+    let expiry = match raw.expiration.as_deref() {
+        Some(expiration) => Some(
+            parse_rfc3339(expiration)
+                .ok_or_else(|| invalid(format!("expiration {expiration:?}")))?,
+        ),
+        None => None,
+    };
+    Ok(Credentials::new(
+        raw.access_key_id,
+        raw.secret_access_key,
+        raw.token,
+        expiry,
+        PROVIDER_NAME,
+    ))
+}
+
+/// Parse a UTC timestamp such as `2024-01-01T12:00:00Z` or
+/// `2024-01-01T12:00:00.123Z`.
+pub(crate) fn parse_rfc3339(s: &str) -> Option<SystemTime> {
+    let s = s.strip_suffix('Z')?;
+    let (date, time) = s.split_once('T')?;
+    let (time, nanos) = match time.split_once('.') {
+        Some((whole, fraction)) => (whole, parse_fraction(fraction)?),
+        None => (time, 0),
+    };
+    let mut date = date.splitn(3, '-').map(str::parse::<u32>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year.into(), month, day)).ok()?;
+    let secs = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    Some(SystemTime::UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+/// Nanoseconds in the digits after the decimal point of a timestamp. Digits
+/// beyond nanosecond precision are ignored.
+fn parse_fraction(digits: &str) -> Option<u32> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let digits = &digits[..digits.len().min(9)];
+    let nanos: u32 = digits.parse().ok()?;
+    Some(nanos * 10u32.pow(9 - digits.len() as u32))
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * i64::from((month + 9) % 12) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+#[path = "credentials_tests.rs"]
+mod tests;
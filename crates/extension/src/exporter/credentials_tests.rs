@@ -0,0 +1,91 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use super::*;
+
+const BODY: &str = r#"{"AccessKeyId":"AKID","SecretAccessKey":"secret","Token":"session","AccountId":"123456789012","Expiration":"2030-01-01T00:00:00Z"}"#;
+
+fn client() -> HttpClient {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    HttpClient::new(Duration::from_secs(1), None, None, None).unwrap()
+}
+
+/// Serve one credentials response and return the raw request.
+async fn serve_once(listener: TcpListener, body: &str) -> String {
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let mut buf = vec![0u8; 4096];
+    let n = stream.read(&mut buf).await.unwrap();
+    let resp = format!(
+        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(resp.as_bytes()).await.unwrap();
+    String::from_utf8_lossy(&buf[..n]).to_lowercase()
+}
+
+#[test]
+fn parses_rfc3339() {
+    assert_eq!(
+        parse_rfc3339("1970-01-02T00:00:01Z"),
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(86_401))
+    );
+    assert_eq!(
+        parse_rfc3339("2024-03-01T12:30:00.250Z"),
+        Some(SystemTime::UNIX_EPOCH + Duration::new(1_709_296_200, 250_000_000))
+    );
+    assert_eq!(parse_rfc3339("2024-03-01T12:30:00.Z"), None);
+    assert_eq!(parse_rfc3339("2024-03-01T12:30:00+01:00"), None);
+    assert_eq!(parse_rfc3339("2024-13-01T12:30:00Z"), None);
+}
+
+#[test]
+fn parses_container_credentials() {
+    let credentials = parse_container_credentials(BODY.as_bytes()).unwrap();
+    assert_eq!(credentials.access_key_id(), "AKID");
+    assert_eq!(credentials.secret_access_key(), "secret");
+    assert_eq!(credentials.session_token(), Some("session"));
+    assert_eq!(credentials.expiry(), parse_rfc3339("2030-01-01T00:00:00Z"));
+}
+
+#[test]
+fn rejects_malformed_container_credentials() {
+    assert!(parse_container_credentials(b"not json").is_err());
+    assert!(
+        parse_container_credentials(
+            br#"{"AccessKeyId":"a","SecretAccessKey":"b","Expiration":"soon"}"#
+        )
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn fetch_sends_authorization_token() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let uri = format!("http://{}/creds", listener.local_addr().unwrap());
+    let client = client();
+
+    let (credentials, raw) = tokio::join!(
+        fetch(&client, &uri, Some("token-123")),
+        serve_once(listener, BODY)
+    );
+
+    assert_eq!(credentials.unwrap().access_key_id(), "AKID");
+    assert!(raw.starts_with("get /creds"));
+    assert!(raw.contains("authorization: token-123"));
+}
+
+#[test]
+fn expiring_or_invalidated_credentials_are_not_reused() {
+    let provider = CredentialsProvider::default();
+    let fresh = SystemTime::now() + Duration::from_secs(3_600);
+    *provider.cached.lock().unwrap() = Some(Credentials::new("a", "b", None, Some(fresh), "test"));
+    assert!(provider.cached().is_some());
+
+    provider.invalidate();
+    assert!(provider.cached().is_none());
+
+    let expiring = SystemTime::now() + Duration::from_secs(60);
+    *provider.cached.lock().unwrap() =
+        Some(Credentials::new("a", "b", None, Some(expiring), "test"));
+    assert!(provider.cached().is_none());
+}
@@ -0,0 +1,90 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+use super::{CommonExporter, ExportError, Exporter, compress_gzip};
+use crate::buffers::BufferData;
+use crate::config::Compression;
+use crate::merge;
+use crate::otlp_json::{self, ToJson};
+
+/// OTLP/HTTP exporter using the JSON encoding.
+///
+/// Unlike the protobuf exporters there is no single-payload fast path: every
+/// payload arrives as protobuf and has to be decoded before it can be
+/// re-encoded as JSON, so the queue always goes through `merge`.
+pub struct HttpJsonExporter(pub(super) CommonExporter);
+
+impl HttpJsonExporter {
+    async fn export_traces(&self, queue: &VecDeque<Bytes>) -> Result<(), ExportError> {
+        if queue.is_empty() {
+            return Ok(());
+        }
+        self.post("v1/traces", &merge::merge_traces(queue)).await
+    }
+
+    async fn export_metrics(&self, queue: &VecDeque<Bytes>) -> Result<(), ExportError> {
+        if queue.is_empty() {
+            return Ok(());
+        }
+        self.post("v1/metrics", &merge::merge_metrics(queue)).await
+    }
+
+    async fn export_logs(&self, queue: &VecDeque<Bytes>) -> Result<(), ExportError> {
+        if queue.is_empty() {
+            return Ok(());
+        }
+        self.post("v1/logs", &merge::merge_logs(queue)).await
+    }
+
+    async fn post(&self, path: &str, msg: &impl ToJson) -> Result<(), ExportError> {
+        let url = self.0.endpoint.join(path).expect("invalid export path");
+        let json = otlp_json::encode(msg);
+
+        let mut headers = vec![("content-type".to_owned(), "application/json".to_owned())];
+
+        let body = match self.0.compression {
+            Compression::Gzip => {
+                headers.push(("content-encoding".to_owned(), "gzip".to_owned()));
+                compress_gzip(&json)?
+            }
+            Compression::None => json,
+        };
+
+        let resp = self.0.send(&url, headers, body).await?;
+
+        if resp.status.is_success() {
+            Ok(())
+        } else {
+            Err(ExportError::Rejected {
+                status: resp.status,
+            })
+        }
+    }
+}
+
+impl Exporter for HttpJsonExporter {
+    async fn export(&self, data: &mut BufferData) -> Result<(), ExportError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let (t, m, l) = tokio::join!(
+            self.export_traces(&data.traces.queue),
+            self.export_metrics(&data.metrics.queue),
+            self.export_logs(&data.logs.queue),
+        );
+
+        if t.is_ok() {
+            data.traces.clear();
+        }
+        if m.is_ok() {
+            data.metrics.clear();
+        }
+        if l.is_ok() {
+            data.logs.clear();
+        }
+
+        t.and(m).and(l)
+    }
+}
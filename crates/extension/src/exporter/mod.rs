@@ -1,7 +1,10 @@
+mod credentials;
 mod grpc;
+mod http_json;
 mod http_protobuf;
+mod remote_write;
+mod xray;
 
-use std::env;
 use std::io::Write;
 use std::time::SystemTime;
 
@@ -17,8 +20,14 @@ use crate::config::{Compression, Config, ExportProtocol, SigV4Config};
 use crate::grpc as grpc_codec;
 use crate::http_client::{ClientError, HttpClient};
 
+use self::credentials::CredentialsProvider;
+
+pub(crate) use self::credentials::parse_rfc3339;
 pub use self::grpc::GrpcExporter;
+pub use self::http_json::HttpJsonExporter;
 pub use self::http_protobuf::HttpProtobufExporter;
+pub use self::remote_write::RemoteWriteExporter;
+pub use self::xray::XrayExporter;
 
 #[derive(Debug, Error)]
 pub enum ExportError {
@@ -36,12 +45,18 @@ pub enum ExportError {
 
     #[error("SigV4 signing failed: {0}")]
     Signing(String),
+
+    #[error("sending to the X-Ray daemon failed: {0}")]
+    Udp(std::io::Error),
 }
 
 #[derive(Debug, Error)]
 pub enum ExporterError {
     #[error("failed to build HTTP client: {0}")]
     ClientBuild(#[from] ClientError),
+
+    #[error("failed to open UDP socket: {0}")]
+    Socket(#[from] std::io::Error),
 }
 
 /// Abstraction over exporting telemetry data to a collector.
@@ -51,42 +66,106 @@ pub enum ExporterError {
 pub trait Exporter: Send + Sync + 'static {
     fn export(&self, data: &mut BufferData)
     -> impl Future<Output = Result<(), ExportError>> + Send;
+
+    /// Drop connections and cached credentials taken over from a SnapStart
+    /// snapshot.
+    fn reset(&self) {}
+}
+
+/// The configured exporter: OTLP (or X-Ray) for every signal, except metrics
+/// when Prometheus remote-write is configured.
+pub struct OtlpExporter {
+    protocol: ProtocolExporter,
+    remote_write: Option<RemoteWriteExporter>,
 }
 
-pub enum OtlpExporter {
+enum ProtocolExporter {
     HttpProtobuf(HttpProtobufExporter),
+    HttpJson(HttpJsonExporter),
     Grpc(GrpcExporter),
+    Xray(XrayExporter),
 }
 
 impl OtlpExporter {
     pub fn new(config: &Config) -> Result<Self, ExporterError> {
-        let client = HttpClient::new(
-            config.export_timeout,
-            config.tls_ca.as_deref(),
-            config.tls_client_cert.as_deref(),
-            config.tls_client_key.as_deref(),
+        let common = CommonExporter::new(
+            config,
+            config.endpoint.clone(),
+            config.export_headers.clone(),
+            config.sigv4.clone(),
         )?;
-
-        let common = CommonExporter {
-            client,
-            endpoint: config.endpoint.clone(),
-            compression: config.compression,
-            headers: config.export_headers.clone(),
-            sigv4: config.sigv4.clone(),
+        let protocol = match config.protocol {
+            ExportProtocol::HttpProtobuf => {
+                ProtocolExporter::HttpProtobuf(HttpProtobufExporter(common))
+            }
+            ExportProtocol::HttpJson => ProtocolExporter::HttpJson(HttpJsonExporter(common)),
+            ExportProtocol::Grpc => ProtocolExporter::Grpc(GrpcExporter(common)),
+            ExportProtocol::Xray => ProtocolExporter::Xray(XrayExporter::new(
+                common,
+                config.xray_annotations.clone(),
+                !config.xray_skip_timestamp_validation,
+            )?),
         };
-
-        Ok(match config.protocol {
-            ExportProtocol::HttpProtobuf => Self::HttpProtobuf(HttpProtobufExporter(common)),
-            ExportProtocol::Grpc => Self::Grpc(GrpcExporter(common)),
+        let remote_write = match &config.remote_write {
+            Some(remote_write) => Some(RemoteWriteExporter(CommonExporter::new(
+                config,
+                remote_write.endpoint.clone(),
+                remote_write.headers.clone(),
+                remote_write.sigv4.clone(),
+            )?)),
+            None => None,
+        };
+        Ok(Self {
+            protocol,
+            remote_write,
         })
     }
 }
 
-impl Exporter for OtlpExporter {
+impl Exporter for ProtocolExporter {
     async fn export(&self, data: &mut BufferData) -> Result<(), ExportError> {
         match self {
             Self::HttpProtobuf(e) => e.export(data).await,
+            Self::HttpJson(e) => e.export(data).await,
             Self::Grpc(e) => e.export(data).await,
+            Self::Xray(e) => e.export(data).await,
+        }
+    }
+
+    fn reset(&self) {
+        let common = match self {
+            Self::HttpProtobuf(e) => &e.0,
+            Self::HttpJson(e) => &e.0,
+            Self::Grpc(e) => &e.0,
+            Self::Xray(e) => {
+                e.reset();
+                return;
+            }
+        };
+        common.reset();
+    }
+}
+
+impl Exporter for OtlpExporter {
+    async fn export(&self, data: &mut BufferData) -> Result<(), ExportError> {
+        let Some(remote_write) = &self.remote_write else {
+            return self.protocol.export(data).await;
+        };
+        // Metrics are exported separately; whatever remote-write leaves in
+        // the buffer goes back into `data` for a retry.
+        let mut metrics = std::mem::take(&mut data.metrics);
+        let (signals, metrics_result) = tokio::join!(
+            self.protocol.export(data),
+            remote_write.export(&mut metrics)
+        );
+        data.metrics = metrics;
+        signals.and(metrics_result)
+    }
+
+    fn reset(&self) {
+        self.protocol.reset();
+        if let Some(remote_write) = &self.remote_write {
+            remote_write.0.reset();
         }
     }
 }
@@ -101,9 +180,40 @@ struct CommonExporter {
     compression: Compression,
     headers: Vec<(String, String)>,
     sigv4: Option<SigV4Config>,
+    credentials: CredentialsProvider,
 }
 
 impl CommonExporter {
+    /// Client settings come from `config`; the endpoint, headers and SigV4
+    /// signing are per destination.
+    fn new(
+        config: &Config,
+        endpoint: Url,
+        headers: Vec<(String, String)>,
+        sigv4: Option<SigV4Config>,
+    ) -> Result<Self, ExporterError> {
+        let client = HttpClient::new(
+            config.export_timeout,
+            config.tls_ca.as_deref(),
+            config.tls_client_cert.as_deref(),
+            config.tls_client_key.as_deref(),
+        )?;
+        Ok(Self {
+            client,
+            endpoint,
+            compression: config.compression,
+            headers,
+            sigv4,
+            credentials: CredentialsProvider::default(),
+        })
+    }
+
+    /// Drop pooled connections and cached credentials.
+    fn reset(&self) {
+        self.client.reset();
+        self.credentials.invalidate();
+    }
+
     /// Send a request with the given headers and body, applying custom headers
     /// and SigV4 signing.
     async fn send(
@@ -117,7 +227,9 @@ impl CommonExporter {
         }
 
         if let Some(sigv4) = &self.sigv4 {
-            let signing_headers = sign_request(sigv4, url.as_str(), &headers, body.as_ref())?;
+            let credentials = self.credentials.resolve(&self.client).await?;
+            let signing_headers =
+                sign_request(sigv4, credentials, url.as_str(), &headers, body.as_ref())?;
             headers.extend(signing_headers);
         }
 
@@ -129,34 +241,14 @@ impl CommonExporter {
 // Shared helpers
 // ---------------------------------------------------------------------------
 
-/// Read credentials from the environment and sign the request.
-///
-/// Returns the signing headers to add to the request. Credentials are read
-/// fresh on each call because Lambda rotates them during the extension's
-/// lifetime.
+/// Sign the request. Returns the signing headers to add to it.
 fn sign_request(
     sigv4: &SigV4Config,
+    credentials: Credentials,
     url: &str,
     headers: &[(String, String)],
     body: &[u8],
 ) -> Result<Vec<(String, String)>, ExportError> {
-    let access_key = env::var("AWS_ACCESS_KEY_ID")
-        .ok()
-        .filter(|s| !s.is_empty())
-        .ok_or_else(|| ExportError::Signing("AWS_ACCESS_KEY_ID not set".into()))?;
-    let secret_key = env::var("AWS_SECRET_ACCESS_KEY")
-        .ok()
-        .filter(|s| !s.is_empty())
-        .ok_or_else(|| ExportError::Signing("AWS_SECRET_ACCESS_KEY not set".into()))?;
-    let session_token = env::var("AWS_SESSION_TOKEN").ok().filter(|s| !s.is_empty());
-
-    let credentials = Credentials::new(
-        access_key,
-        secret_key,
-        session_token,
-        None,
-        "lambda-otel-relay",
-    );
     let identity = credentials.into();
 
     let signing_params = v4::SigningParams::builder()
@@ -187,7 +279,7 @@ fn sign_request(
         .collect())
 }
 
-/// Gzip-compress pre-encoded protobuf bytes.
+/// Gzip-compress a pre-encoded request body.
 fn compress_gzip(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
     let mut encoder = GzEncoder::new(Vec::with_capacity(data.len()), flate2::Compression::fast());
     encoder.write_all(data)?;
@@ -0,0 +1,48 @@
+use prost::Message;
+
+use super::{CommonExporter, ExportError};
+use crate::buffers::SignalBuffer;
+use crate::merge;
+use crate::prometheus::{self, snappy};
+
+/// Exports metrics with the Prometheus remote-write v1 protocol.
+///
+/// `endpoint` is the full URL requests are posted to, e.g.
+/// `https://mimir.example.com/api/v1/push`. Custom headers and SigV4 signing
+/// apply as for OTLP export; compression is always Snappy.
+pub struct RemoteWriteExporter(pub(super) CommonExporter);
+
+impl RemoteWriteExporter {
+    pub async fn export(&self, metrics: &mut SignalBuffer) -> Result<(), ExportError> {
+        if metrics.is_empty() {
+            return Ok(());
+        }
+        let req = prometheus::write_request(&merge::merge_metrics(&metrics.queue));
+        if !req.timeseries.is_empty() {
+            let body = snappy::compress(&req.encode_to_vec());
+            let headers = vec![
+                (
+                    "content-type".to_owned(),
+                    "application/x-protobuf".to_owned(),
+                ),
+                ("content-encoding".to_owned(), "snappy".to_owned()),
+                (
+                    "x-prometheus-remote-write-version".to_owned(),
+                    "0.1.0".to_owned(),
+                ),
+            ];
+            let resp = self.0.send(&self.0.endpoint, headers, body).await?;
+            if !resp.status.is_success() {
+                return Err(ExportError::Rejected {
+                    status: resp.status,
+                });
+            }
+        }
+        metrics.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path = "remote_write_tests.rs"]
+mod tests;
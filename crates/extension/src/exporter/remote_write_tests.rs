@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use url::Url;
+
+use super::*;
+use crate::config::Compression;
+use crate::exporter::credentials::CredentialsProvider;
+use crate::http_client::HttpClient;
+use crate::proto::opentelemetry::proto::{
+    collector::metrics::v1::ExportMetricsServiceRequest,
+    metrics::v1::{
+        Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, metric, number_data_point,
+    },
+};
+
+fn exporter(endpoint: &str) -> RemoteWriteExporter {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RemoteWriteExporter(CommonExporter {
+        client: HttpClient::new(Duration::from_secs(1), None, None, None).unwrap(),
+        endpoint: Url::parse(endpoint).unwrap(),
+        compression: Compression::Gzip,
+        headers: vec![],
+        sigv4: None,
+        credentials: CredentialsProvider::default(),
+    })
+}
+
+fn buffered_gauge() -> SignalBuffer {
+    let req = ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            scope_metrics: vec![ScopeMetrics {
+                metrics: vec![Metric {
+                    name: "queue.depth".to_owned(),
+                    data: Some(metric::Data::Gauge(Gauge {
+                        data_points: vec![NumberDataPoint {
+                            time_unix_nano: 1_700_000_000_000_000_000,
+                            value: Some(number_data_point::Value::AsInt(3)),
+                            ..Default::default()
+                        }],
+                    })),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    };
+    let mut buffer = SignalBuffer::default();
+    buffer.queue.push_back(Bytes::from(req.encode_to_vec()));
+    buffer
+}
+
+/// Answer one request with `status` and return it, lowercased.
+async fn serve_once(listener: TcpListener, status: &str) -> String {
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let mut buf = vec![0u8; 8192];
+    let n = stream.read(&mut buf).await.unwrap();
+    let resp = format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\n\r\n");
+    stream.write_all(resp.as_bytes()).await.unwrap();
+    String::from_utf8_lossy(&buf[..n]).to_lowercase()
+}
+
+#[tokio::test]
+async fn posts_snappy_write_request() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}/api/v1/push", listener.local_addr().unwrap());
+    let exporter = exporter(&endpoint);
+
+    let mut metrics = buffered_gauge();
+    let (result, request) = tokio::join!(
+        exporter.export(&mut metrics),
+        serve_once(listener, "204 No Content")
+    );
+
+    result.unwrap();
+    assert!(metrics.is_empty());
+    assert!(request.starts_with("post /api/v1/push"));
+    assert!(request.contains("content-encoding: snappy"));
+    assert!(request.contains("content-type: application/x-protobuf"));
+    assert!(request.contains("x-prometheus-remote-write-version: 0.1.0"));
+}
+
+#[tokio::test]
+async fn rejected_metrics_stay_buffered() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}/api/v1/push", listener.local_addr().unwrap());
+    let exporter = exporter(&endpoint);
+
+    let mut metrics = buffered_gauge();
+    let (result, _) = tokio::join!(
+        exporter.export(&mut metrics),
+        serve_once(listener, "500 Internal Server Error")
+    );
+
+    assert!(matches!(result, Err(ExportError::Rejected { .. })));
+    assert_eq!(metrics.queue.len(), 1);
+}
@@ -0,0 +1,256 @@
+#![allow(clippy::question_mark)] // nanoserde DeJson derive
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use nanoserde::DeJson;
+use prost::Message;
+use tokio::net::UdpSocket;
+use tracing::warn;
+
+use super::{CommonExporter, ExportError, Exporter, ExporterError};
+use crate::buffers::BufferData;
+use crate::merge;
+use crate::otlp_json::{Object, write_str};
+use crate::proto::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+use crate::xray::segment;
+
+/// Prefix of every datagram sent to the X-Ray daemon.
+const DAEMON_HEADER: &str = "{\"format\":\"json\",\"version\":1}\n";
+
+/// Largest segment document X-Ray accepts, both from the daemon and from
+/// `PutTraceSegments`.
+const MAX_DOCUMENT_BYTES: usize = 64 * 1024;
+
+/// Segment documents per `PutTraceSegments` call.
+const API_BATCH: usize = 50;
+
+/// Exports spans as X-Ray segment documents.
+///
+/// With a `udp://` endpoint each document is sent to the X-Ray daemon as one
+/// datagram. With an `http(s)://` endpoint documents are posted to the
+/// `PutTraceSegments` API, signed if SigV4 is configured.
+///
+/// If a datagram or batch fails, only the spans that were not sent yet stay
+/// buffered, so the retry resumes where the export stopped. Segments that
+/// `PutTraceSegments` reports as unprocessed are logged, not retried.
+pub struct XrayExporter {
+    transport: Transport,
+    annotations: Vec<String>,
+    /// Drop spans whose trace ID epoch X-Ray would reject.
+    validate_timestamps: bool,
+}
+
+enum Transport {
+    Daemon { socket: UdpSocket, address: String },
+    Api(Box<CommonExporter>),
+}
+
+#[derive(DeJson)]
+struct PutTraceSegmentsResponse {
+    #[nserde(rename = "UnprocessedTraceSegments")]
+    unprocessed: Option<Vec<UnprocessedTraceSegment>>,
+}
+
+#[derive(DeJson)]
+struct UnprocessedTraceSegment {
+    #[nserde(rename = "ErrorCode")]
+    error_code: Option<String>,
+    #[nserde(rename = "Message")]
+    message: Option<String>,
+}
+
+impl XrayExporter {
+    pub(super) fn new(
+        common: CommonExporter,
+        annotations: Vec<String>,
+        validate_timestamps: bool,
+    ) -> Result<Self, ExporterError> {
+        let transport = if common.endpoint.scheme() == "udp" {
+            let address = format!(
+                "{}:{}",
+                common.endpoint.host_str().unwrap_or_default(),
+                common.endpoint.port().unwrap_or_default()
+            );
+            let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
+            socket.set_nonblocking(true)?;
+            Transport::Daemon {
+                socket: UdpSocket::from_std(socket)?,
+                address,
+            }
+        } else {
+            Transport::Api(Box::new(common))
+        };
+        Ok(Self {
+            transport,
+            annotations,
+            validate_timestamps,
+        })
+    }
+
+    /// Returns the number of documents sent before a failure.
+    async fn send_to_daemon(
+        &self,
+        socket: &UdpSocket,
+        address: &str,
+        documents: &[String],
+    ) -> Result<(), (usize, ExportError)> {
+        for (sent, document) in documents.iter().enumerate() {
+            let datagram = format!("{DAEMON_HEADER}{document}");
+            socket
+                .send_to(datagram.as_bytes(), address)
+                .await
+                .map_err(|e| (sent, ExportError::Udp(e)))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of documents sent before a failed batch.
+    async fn put_trace_segments(
+        &self,
+        common: &CommonExporter,
+        documents: &[String],
+    ) -> Result<(), (usize, ExportError)> {
+        let url = common
+            .endpoint
+            .join("TraceSegments")
+            .expect("invalid export path");
+        for (i, batch) in documents.chunks(API_BATCH).enumerate() {
+            let sent = i * API_BATCH;
+            let mut body = String::new();
+            let mut o = Object::new(&mut body);
+            let out = o.key("TraceSegmentDocuments");
+            out.push('[');
+            for (i, document) in batch.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_str(out, document);
+            }
+            out.push(']');
+            o.finish();
+
+            let headers = vec![("content-type".to_owned(), "application/json".to_owned())];
+            let resp = common
+                .send(&url, headers, body.into_bytes())
+                .await
+                .map_err(|e| (sent, e))?;
+            if !resp.status.is_success() {
+                return Err((
+                    sent,
+                    ExportError::Rejected {
+                        status: resp.status,
+                    },
+                ));
+            }
+            warn_unprocessed(&resp.body);
+        }
+        Ok(())
+    }
+}
+
+/// Log the segments X-Ray accepted the request for but did not process.
+/// X-Ray does not say whether they would succeed on a retry, so, like the
+/// ADOT collector, they are not retried.
+fn warn_unprocessed(body: &[u8]) {
+    let Some(unprocessed) = std::str::from_utf8(body)
+        .ok()
+        .and_then(|body| PutTraceSegmentsResponse::deserialize_json(body).ok())
+        .and_then(|resp| resp.unprocessed)
+    else {
+        return;
+    };
+    if let Some(first) = unprocessed.first() {
+        warn!(
+            count = unprocessed.len(),
+            error_code = first.error_code.as_deref().unwrap_or_default(),
+            message = first.message.as_deref().unwrap_or_default(),
+            "X-Ray did not process some segment documents"
+        );
+    }
+}
+
+/// The spans of `req` whose index (counting spans in request order) is in
+/// the ascending `indices`. Empty resource and scope groups are dropped.
+fn retain_spans(
+    mut req: ExportTraceServiceRequest,
+    indices: &[usize],
+) -> ExportTraceServiceRequest {
+    let mut indices = indices.iter().copied().peekable();
+    let mut index = 0;
+    for rs in &mut req.resource_spans {
+        for ss in &mut rs.scope_spans {
+            ss.spans.retain(|_| {
+                let keep = indices.next_if_eq(&index).is_some();
+                index += 1;
+                keep
+            });
+        }
+        rs.scope_spans.retain(|ss| !ss.spans.is_empty());
+    }
+    req.resource_spans.retain(|rs| !rs.scope_spans.is_empty());
+    req
+}
+
+impl Exporter for XrayExporter {
+    async fn export(&self, data: &mut BufferData) -> Result<(), ExportError> {
+        if data.traces.is_empty() {
+            return Ok(());
+        }
+        let req = merge::merge_traces(&data.traces.queue);
+        let now = self.validate_timestamps.then(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        });
+        let segment::Documents {
+            mut documents,
+            invalid_epoch,
+        } = segment::documents(&req, &self.annotations, now);
+        if invalid_epoch > 0 {
+            warn!(
+                spans = invalid_epoch,
+                "dropping spans whose trace ID does not start with a timestamp X-Ray accepts"
+            );
+        }
+        documents.retain(|(_, document)| {
+            let fits = document.len() + DAEMON_HEADER.len() <= MAX_DOCUMENT_BYTES;
+            if !fits {
+                warn!(
+                    bytes = document.len(),
+                    "dropping X-Ray segment document over 64 KiB"
+                );
+            }
+            fits
+        });
+
+        let (spans, documents): (Vec<usize>, Vec<String>) = documents.into_iter().unzip();
+        let result = match &self.transport {
+            Transport::Daemon { socket, address } => {
+                self.send_to_daemon(socket, address, &documents).await
+            }
+            Transport::Api(common) => self.put_trace_segments(common, &documents).await,
+        };
+        let buffered_at = data.traces.buffered_at;
+        data.traces.clear();
+        let Err((sent, err)) = result else {
+            return Ok(());
+        };
+        let unsent = retain_spans(req, &spans[sent..]);
+        data.traces
+            .push_processed(Bytes::from(unsent.encode_to_vec()));
+        data.traces.buffered_at = buffered_at;
+        Err(err)
+    }
+
+    fn reset(&self) {
+        if let Transport::Api(common) = &self.transport {
+            common.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "xray_tests.rs"]
+mod tests;
@@ -0,0 +1,217 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use prost::Message;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time::Instant;
+use url::Url;
+
+use super::*;
+use crate::config::Compression;
+use crate::exporter::credentials::CredentialsProvider;
+use crate::http_client::HttpClient;
+use crate::proto::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Span};
+
+fn common(endpoint: &str) -> CommonExporter {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    CommonExporter {
+        client: HttpClient::new(Duration::from_secs(1), None, None, None).unwrap(),
+        endpoint: Url::parse(endpoint).unwrap(),
+        compression: Compression::None,
+        headers: vec![],
+        sigv4: None,
+        credentials: CredentialsProvider::default(),
+    }
+}
+
+fn buffered(spans: Vec<Span>) -> BufferData {
+    let req = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans,
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+    let mut data = BufferData::default();
+    data.traces
+        .queue
+        .push_back(Bytes::from(req.encode_to_vec()));
+    data
+}
+
+fn span(span_id: u8) -> Span {
+    Span {
+        trace_id: vec![1; 16],
+        span_id: vec![span_id; 8],
+        name: "handler".to_owned(),
+        ..Default::default()
+    }
+}
+
+fn buffered_span() -> BufferData {
+    buffered(vec![span(2)])
+}
+
+/// Answer one request per connection with each `(status, body)` in turn.
+/// Returns the request bodies.
+async fn serve(listener: TcpListener, responses: &'static [(&str, &str)]) -> Vec<String> {
+    let mut bodies = Vec::new();
+    for (status, body) in responses {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = vec![0u8; 65_536];
+        let body_len = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, rest)) = text.split_once("\r\n\r\n") {
+                let len: usize = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("content-length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                if rest.len() >= len {
+                    break rest.to_owned();
+                }
+            }
+        };
+        let resp = format!(
+            "HTTP/1.1 {status}\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(resp.as_bytes()).await.unwrap();
+        bodies.push(body_len);
+    }
+    bodies
+}
+
+fn buffered_span_ids(data: &BufferData) -> Vec<u8> {
+    merge::merge_traces(&data.traces.queue)
+        .resource_spans
+        .iter()
+        .flat_map(|rs| &rs.scope_spans)
+        .flat_map(|ss| &ss.spans)
+        .map(|span| span.span_id[0])
+        .collect()
+}
+
+#[tokio::test]
+async fn sends_segments_to_daemon() {
+    let daemon = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("udp://{}", daemon.local_addr().unwrap());
+    let exporter = XrayExporter::new(common(&endpoint), vec![], false).unwrap();
+
+    let mut data = buffered_span();
+    exporter.export(&mut data).await.unwrap();
+    assert!(data.traces.is_empty());
+
+    let mut buf = vec![0u8; 65_536];
+    let n = daemon.recv(&mut buf).await.unwrap();
+    let datagram = std::str::from_utf8(&buf[..n]).unwrap();
+    let document = datagram.strip_prefix(DAEMON_HEADER).unwrap();
+    assert!(document.starts_with(
+        r#"{"trace_id":"1-01010101-010101010101010101010101","id":"0202020202020202","name":"handler""#
+    ));
+}
+
+#[tokio::test]
+async fn puts_trace_segments() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let exporter = XrayExporter::new(common(&endpoint), vec![], false).unwrap();
+
+    let server = async {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = vec![0u8; 8192];
+        let n = stream.read(&mut buf).await.unwrap();
+        let body = r#"{"UnprocessedTraceSegments":[]}"#;
+        let resp = format!(
+            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(resp.as_bytes()).await.unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    };
+    let mut data = buffered_span();
+    let (result, request) = tokio::join!(exporter.export(&mut data), server);
+
+    result.unwrap();
+    assert!(request.starts_with("POST /TraceSegments"));
+    assert!(request.contains(r#"{"TraceSegmentDocuments":["{\"trace_id\":\"1-01010101-"#));
+}
+
+#[tokio::test]
+async fn failed_batch_keeps_only_unsent_spans() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let exporter = XrayExporter::new(common(&endpoint), vec![], false).unwrap();
+
+    let server = tokio::spawn(serve(
+        listener,
+        &[
+            ("200 OK", "{}"),
+            ("500 Internal Server Error", ""),
+            ("200 OK", "{}"),
+        ],
+    ));
+    let mut data = buffered((1..=60).map(span).collect());
+    let buffered_at = Instant::now();
+    data.traces.buffered_at = Some(buffered_at);
+    let result = exporter.export(&mut data).await;
+    assert!(matches!(result, Err(ExportError::Rejected { .. })));
+    assert_eq!(buffered_span_ids(&data), (51..=60).collect::<Vec<_>>());
+    assert_eq!(data.traces.processed, 1);
+    assert_eq!(data.traces.buffered_at, Some(buffered_at));
+
+    // The retry sends only the remaining spans.
+    exporter.export(&mut data).await.unwrap();
+    assert!(data.traces.is_empty());
+    let bodies = server.await.unwrap();
+    assert_eq!(bodies[0].matches("trace_id").count(), 50);
+    assert_eq!(bodies[2].matches("trace_id").count(), 10);
+}
+
+#[tokio::test]
+async fn unprocessed_segments_do_not_fail_the_export() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let exporter = XrayExporter::new(common(&endpoint), vec![], false).unwrap();
+
+    const BODY: &str = r#"{"UnprocessedTraceSegments":[{"Id":"0202020202020202","ErrorCode":"ThrottlingException","Message":"Rate exceeded"}]}"#;
+    let mut data = buffered_span();
+    let (result, _) = tokio::join!(
+        exporter.export(&mut data),
+        serve(listener, &[("200 OK", BODY)])
+    );
+    result.unwrap();
+    assert!(data.traces.is_empty());
+}
+
+#[tokio::test]
+async fn drops_spans_with_stale_trace_ids() {
+    let daemon = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("udp://{}", daemon.local_addr().unwrap());
+    let exporter = XrayExporter::new(common(&endpoint), vec![], true).unwrap();
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let mut current = span(3);
+    current.trace_id[..4].copy_from_slice(&now.to_be_bytes());
+    let mut data = buffered(vec![span(2), current]);
+    exporter.export(&mut data).await.unwrap();
+    assert!(data.traces.is_empty());
+
+    let mut buf = vec![0u8; 65_536];
+    let n = daemon.recv(&mut buf).await.unwrap();
+    let datagram = std::str::from_utf8(&buf[..n]).unwrap();
+    assert!(datagram.contains(r#""id":"0303030303030303""#));
+}
@@ -0,0 +1,130 @@
+//! Observations behind the `adaptive` flush strategy.
+//!
+//! The coordinator feeds in invocation arrivals, buffered payload sizes and
+//! completed exports. At each invocation boundary the strategy predicts
+//! whether the oldest buffered data would still be under the target age if the
+//! flush waited for the next boundary, and otherwise picks the cheapest flush
+//! that keeps it there.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Weight of the newest sample in the moving averages.
+const EWMA_ALPHA: f64 = 0.3;
+
+/// Exponentially weighted moving average. Empty until the first sample.
+#[derive(Debug, Default, Clone, Copy)]
+struct Ewma(Option<f64>);
+
+impl Ewma {
+    fn update(&mut self, sample: f64) {
+        self.0 = Some(match self.0 {
+            Some(avg) => avg + EWMA_ALPHA * (sample - avg),
+            None => sample,
+        });
+    }
+
+    fn get(&self) -> Option<f64> {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The next boundary is soon enough; keep batching.
+    Defer,
+    /// Flush without blocking. Invocations arrive often enough that an export
+    /// stalled by the environment freezing resumes within the target age.
+    Background,
+    /// Flush before the invocation proceeds. The environment may stay frozen
+    /// for longer than the target age after this invocation.
+    Sync,
+}
+
+#[derive(Debug, Default)]
+pub struct Observations {
+    last_invoke: Option<Instant>,
+    /// Seconds between invocations.
+    gap: Ewma,
+    /// Bytes buffered per second between invocations.
+    growth: Ewma,
+    /// Seconds per export.
+    export_latency: Ewma,
+    /// Bytes exported per second of export time.
+    export_rate: Ewma,
+    bytes_since_invoke: usize,
+    pending_bytes: usize,
+    oldest_pending: Option<Instant>,
+}
+
+impl Observations {
+    pub fn record_invocation(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_invoke {
+            let gap = now.duration_since(last).as_secs_f64();
+            if gap > 0.0 {
+                self.gap.update(gap);
+                self.growth.update(self.bytes_since_invoke as f64 / gap);
+            }
+        }
+        self.last_invoke = Some(now);
+        self.bytes_since_invoke = 0;
+    }
+
+    pub fn record_payload(&mut self, bytes: usize) {
+        self.pending_bytes += bytes;
+        self.bytes_since_invoke += bytes;
+        self.oldest_pending.get_or_insert_with(Instant::now);
+    }
+
+    /// Payloads received while a background flush was in flight are counted
+    /// as flushed too, so the age estimate errs on the young side by at most
+    /// one export.
+    pub fn record_flush(&mut self) {
+        self.pending_bytes = 0;
+        self.oldest_pending = None;
+    }
+
+    pub fn record_export(&mut self, bytes: usize, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        self.export_latency.update(secs);
+        if bytes > 0 && secs > 0.0 {
+            self.export_rate.update(bytes as f64 / secs);
+        }
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.oldest_pending.is_some()
+    }
+
+    pub fn decide(&self, target_age: Duration) -> Decision {
+        let Some(oldest) = self.oldest_pending else {
+            return Decision::Defer;
+        };
+        let target = target_age.as_secs_f64();
+        let age = oldest.elapsed().as_secs_f64();
+        // Until a second invocation arrives, assume the next one is far away.
+        let gap = self.gap.get().unwrap_or(f64::INFINITY);
+
+        if age + gap + self.predicted_export_secs(gap) < target {
+            Decision::Defer
+        } else if gap < target {
+            Decision::Background
+        } else {
+            Decision::Sync
+        }
+    }
+
+    /// Export time for what will be buffered by the next boundary.
+    fn predicted_export_secs(&self, gap: f64) -> f64 {
+        let Some(latency) = self.export_latency.get() else {
+            return 0.0;
+        };
+        let projected = self.pending_bytes as f64 + self.growth.get().unwrap_or(0.0) * gap;
+        match self.export_rate.get() {
+            Some(rate) if rate > 0.0 => latency.max(projected / rate),
+            _ => latency,
+        }
+    }
+}
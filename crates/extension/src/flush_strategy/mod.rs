@@ -4,12 +4,19 @@ use std::{fmt, str::FromStr};
 use thiserror::Error;
 use tokio::time::{Instant, Interval, MissedTickBehavior};
 
+mod adaptive;
+
+use adaptive::{Decision, Observations};
+
 /// Debounce window: flushes within this duration of the last flush are skipped.
 const DEDUP_WINDOW: Duration = Duration::from_millis(100);
 
 /// Default adaptive threshold: boundary flush when gap since last flush >= this.
 const DEFAULT_ADAPTIVE_THRESHOLD: Duration = Duration::from_secs(60);
 
+/// Default data age target for the `adaptive` strategy.
+const DEFAULT_TARGET_AGE: Duration = Duration::from_secs(10);
+
 #[derive(Debug, Error)]
 pub enum FlushStrategyError {
     #[error("unknown flush strategy: {0}")]
@@ -23,9 +30,20 @@ pub enum FlushStrategyError {
 pub enum FlushStrategy {
     Default,
     End,
-    EndPeriodically { interval: Duration },
-    Periodically { interval: Duration },
-    Continuously { interval: Duration },
+    EndPeriodically {
+        interval: Duration,
+    },
+    Periodically {
+        interval: Duration,
+    },
+    Continuously {
+        interval: Duration,
+    },
+    /// Keep buffered data younger than `target_age`, choosing per boundary
+    /// between deferring, a background flush and a blocking flush.
+    Adaptive {
+        target_age: Duration,
+    },
 }
 
 impl FromStr for FlushStrategy {
@@ -53,6 +71,15 @@ impl FromStr for FlushStrategy {
                     interval: Duration::from_millis(ms),
                 })
             }
+            "adaptive" => Ok(FlushStrategy::Adaptive {
+                target_age: DEFAULT_TARGET_AGE,
+            }),
+            _ if s.starts_with("adaptive,") => {
+                let ms = parse_ms_param("adaptive", s)?;
+                Ok(FlushStrategy::Adaptive {
+                    target_age: Duration::from_millis(ms),
+                })
+            }
             other => Err(FlushStrategyError::UnknownStrategy(other.to_owned())),
         }
     }
@@ -72,11 +99,14 @@ impl fmt::Display for FlushStrategy {
             FlushStrategy::Continuously { interval } => {
                 write!(f, "continuously,{}", interval.as_millis())
             }
+            FlushStrategy::Adaptive { target_age } => {
+                write!(f, "adaptive,{}", target_age.as_millis())
+            }
         }
     }
 }
 
-/// Whether a timer-triggered flush should block the event loop or run in the background.
+/// Whether a flush should block the event loop or run in the background.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TimerMode {
     Sync,
@@ -92,6 +122,7 @@ pub struct FlushCoordinator {
     strategy: FlushStrategy,
     last_flush: Option<Instant>,
     timer: FlushTimer,
+    observations: Observations,
 }
 
 impl FlushCoordinator {
@@ -106,11 +137,16 @@ impl FlushCoordinator {
             | FlushStrategy::Continuously { interval } => FlushTimer::Active {
                 interval: build_interval(*interval),
             },
+            // Covers stretches without invocations, when boundaries never come.
+            FlushStrategy::Adaptive { target_age } => FlushTimer::Active {
+                interval: build_interval(*target_age),
+            },
         };
         Self {
             strategy,
             last_flush: None,
             timer,
+            observations: Observations::default(),
         }
     }
 
@@ -134,6 +170,22 @@ impl FlushCoordinator {
             FlushStrategy::End | FlushStrategy::EndPeriodically { .. } => true,
             FlushStrategy::Periodically { interval } => self.elapsed_since_flush() >= *interval,
             FlushStrategy::Continuously { .. } => false,
+            FlushStrategy::Adaptive { target_age } => {
+                self.observations.decide(*target_age) != Decision::Defer
+            }
+        }
+    }
+
+    /// Whether a boundary flush should block the event loop.
+    ///
+    /// Only meaningful when `should_flush_at_boundary()` returns true.
+    pub fn boundary_mode(&self) -> TimerMode {
+        match &self.strategy {
+            FlushStrategy::Adaptive { target_age } => match self.observations.decide(*target_age) {
+                Decision::Background => TimerMode::Background,
+                Decision::Defer | Decision::Sync => TimerMode::Sync,
+            },
+            _ => TimerMode::Sync,
         }
     }
 
@@ -148,6 +200,7 @@ impl FlushCoordinator {
             | FlushStrategy::EndPeriodically { .. }
             | FlushStrategy::Periodically { .. }
             | FlushStrategy::Continuously { .. } => true,
+            FlushStrategy::Adaptive { .. } => self.observations.has_pending(),
         }
     }
 
@@ -161,13 +214,39 @@ impl FlushCoordinator {
             FlushStrategy::EndPeriodically { .. } | FlushStrategy::Periodically { .. } => {
                 TimerMode::Sync
             }
-            FlushStrategy::Default | FlushStrategy::Continuously { .. } => TimerMode::Background,
+            FlushStrategy::Default
+            | FlushStrategy::Continuously { .. }
+            | FlushStrategy::Adaptive { .. } => TimerMode::Background,
         }
     }
 
     /// Record that a flush just completed.
     pub fn record_flush(&mut self) {
         self.last_flush = Some(Instant::now());
+        self.observations.record_flush();
+        self.reset_timer();
+    }
+
+    /// Record an INVOKE event, before deciding on a boundary flush.
+    pub fn record_invocation(&mut self) {
+        self.observations.record_invocation();
+    }
+
+    /// Record a payload accepted into the buffer.
+    pub fn record_payload(&mut self, bytes: usize) {
+        self.observations.record_payload(bytes);
+    }
+
+    /// Record how long exporting `bytes` of buffered data took.
+    pub fn record_export(&mut self, bytes: usize, duration: Duration) {
+        self.observations.record_export(bytes, duration);
+    }
+
+    /// Forget flush timing from before a SnapStart snapshot. The environment
+    /// was frozen for an unknown time, so it describes nothing after restore.
+    pub fn reset(&mut self) {
+        self.last_flush = None;
+        self.observations = Observations::default();
         self.reset_timer();
     }
 
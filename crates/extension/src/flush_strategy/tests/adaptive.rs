@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use tokio::time;
+
+use super::super::*;
+
+fn adaptive() -> FlushCoordinator {
+    FlushCoordinator::new(FlushStrategy::Adaptive {
+        target_age: Duration::from_secs(10),
+    })
+}
+
+/// Drive `n` invocations `gap` apart, each buffering `bytes`.
+async fn invocations(coord: &mut FlushCoordinator, n: usize, gap: Duration, bytes: usize) {
+    for _ in 0..n {
+        coord.record_invocation();
+        coord.record_payload(bytes);
+        time::advance(gap).await;
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn nothing_buffered_defers() {
+    let mut coord = adaptive();
+    coord.record_invocation();
+    assert!(!coord.should_flush_at_boundary());
+    assert!(!coord.should_flush_on_timer());
+}
+
+#[tokio::test(start_paused = true)]
+async fn frequent_invocations_defer_while_young() {
+    let mut coord = adaptive();
+    invocations(&mut coord, 3, Duration::from_secs(1), 100).await;
+    coord.record_invocation();
+    // Oldest data is 3s old and the next boundary is ~1s away.
+    assert!(!coord.should_flush_at_boundary());
+}
+
+#[tokio::test(start_paused = true)]
+async fn frequent_invocations_flush_in_background_near_target() {
+    let mut coord = adaptive();
+    invocations(&mut coord, 9, Duration::from_secs(1), 100).await;
+    coord.record_invocation();
+    assert!(coord.should_flush_at_boundary());
+    assert_eq!(coord.boundary_mode(), TimerMode::Background);
+}
+
+#[tokio::test(start_paused = true)]
+async fn sparse_invocations_flush_synchronously() {
+    let mut coord = adaptive();
+    invocations(&mut coord, 2, Duration::from_secs(30), 100).await;
+    coord.record_invocation();
+    assert!(coord.should_flush_at_boundary());
+    assert_eq!(coord.boundary_mode(), TimerMode::Sync);
+}
+
+#[tokio::test(start_paused = true)]
+async fn slow_exports_flush_earlier() {
+    let mut fast = adaptive();
+    let mut slow = adaptive();
+    slow.record_export(1_000, Duration::from_secs(4));
+    for _ in 0..5 {
+        for coord in [&mut fast, &mut slow] {
+            coord.record_invocation();
+            coord.record_payload(1_000);
+        }
+        time::advance(Duration::from_secs(1)).await;
+    }
+    fast.record_invocation();
+    slow.record_invocation();
+    // Same traffic, but exporting what slow will hold by the next boundary
+    // would take past the target.
+    assert!(!fast.should_flush_at_boundary());
+    assert!(slow.should_flush_at_boundary());
+}
+
+#[tokio::test(start_paused = true)]
+async fn flush_resets_data_age() {
+    let mut coord = adaptive();
+    invocations(&mut coord, 9, Duration::from_secs(1), 100).await;
+    coord.record_flush();
+    time::advance(Duration::from_millis(500)).await;
+    coord.record_invocation();
+    assert!(!coord.should_flush_at_boundary());
+}
+
+#[tokio::test(start_paused = true)]
+async fn timer_flushes_pending_data_in_background() {
+    let mut coord = adaptive();
+    coord.record_payload(100);
+    coord.next_tick().await;
+    assert!(coord.should_flush_on_timer());
+    assert_eq!(coord.timer_mode(), TimerMode::Background);
+}
+
+#[tokio::test(start_paused = true)]
+async fn dedup_suppresses_boundary_flush() {
+    let mut coord = adaptive();
+    invocations(&mut coord, 2, Duration::from_secs(30), 100).await;
+    coord.record_flush();
+    coord.record_payload(100);
+    assert!(!coord.should_flush_at_boundary());
+}
+
+#[tokio::test(start_paused = true)]
+async fn reset_forgets_observations() {
+    let mut coord = adaptive();
+    invocations(&mut coord, 9, Duration::from_secs(1), 100).await;
+    coord.reset();
+    coord.record_invocation();
+    assert!(!coord.should_flush_at_boundary());
+    assert!(!coord.should_flush_on_timer());
+}
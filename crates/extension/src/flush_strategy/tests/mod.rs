@@ -1,3 +1,4 @@
+mod adaptive;
 mod continuously;
 mod default;
 mod end;
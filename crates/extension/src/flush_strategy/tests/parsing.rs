@@ -99,6 +99,30 @@ fn parse_continuously_zero() {
     assert!(matches!(err, FlushStrategyError::InvalidParameter { .. }));
 }
 
+#[test]
+fn parse_adaptive_defaults_target_age() {
+    let strategy = "adaptive".parse().unwrap();
+    assert!(matches!(
+        strategy,
+        FlushStrategy::Adaptive { target_age } if target_age == Duration::from_secs(10)
+    ));
+}
+
+#[test]
+fn parse_adaptive_with_target_age() {
+    let strategy = "adaptive,2500".parse().unwrap();
+    assert!(matches!(
+        strategy,
+        FlushStrategy::Adaptive { target_age } if target_age == Duration::from_millis(2500)
+    ));
+}
+
+#[test]
+fn parse_adaptive_zero() {
+    let err = "adaptive,0".parse::<FlushStrategy>().unwrap_err();
+    assert!(matches!(err, FlushStrategyError::InvalidParameter { .. }));
+}
+
 #[test]
 fn display_roundtrips_through_parse() {
     let cases: &[&str] = &[
@@ -107,6 +131,7 @@ fn display_roundtrips_through_parse() {
         "end,30000",
         "periodically,60000",
         "continuously,5000",
+        "adaptive,10000",
     ];
     for &input in cases {
         let strategy = input.parse::<FlushStrategy>().unwrap();
@@ -70,3 +70,14 @@ async fn dedup_between_boundary_and_timer() {
     // Timer fires within 100ms dedup window
     assert!(!coord.should_flush_on_timer());
 }
+
+#[tokio::test(start_paused = true)]
+async fn reset_forgets_last_flush() {
+    let mut coord = FlushCoordinator::new(FlushStrategy::Periodically {
+        interval: Duration::from_secs(60),
+    });
+    coord.record_flush();
+    time::advance(Duration::from_secs(10)).await;
+    coord.reset();
+    assert!(coord.should_flush_at_boundary());
+}
@@ -1,4 +1,5 @@
 use std::io::Cursor;
+use std::sync::Mutex;
 use std::time::Duration;
 
 use bytes::Bytes;
@@ -27,16 +28,18 @@ pub enum ClientError {
     Timeout,
 }
 
+type Connector = hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>;
+
 pub struct HttpClient {
-    client: Client<
-        hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>,
-        Full<Bytes>,
-    >,
+    connector: Connector,
+    /// Swapped for a fresh client (and connection pool) by [`Self::reset`].
+    client: Mutex<Client<Connector, Full<Bytes>>>,
     timeout: Duration,
 }
 
 pub struct HttpResponse {
     pub status: hyper::StatusCode,
+    pub body: Bytes,
     pub trailers: Option<hyper::HeaderMap>,
 }
 
@@ -86,9 +89,20 @@ impl HttpClient {
             .enable_all_versions()
             .build();
 
-        let client = Client::builder(TokioExecutor::new()).build(connector);
+        let client = Mutex::new(Client::builder(TokioExecutor::new()).build(connector.clone()));
+
+        Ok(Self {
+            connector,
+            client,
+            timeout,
+        })
+    }
 
-        Ok(Self { client, timeout })
+    /// Drop all pooled connections. Requests already in flight finish on
+    /// their old connection.
+    pub fn reset(&self) {
+        *self.client.lock().unwrap() =
+            Client::builder(TokioExecutor::new()).build(self.connector.clone());
     }
 
     pub async fn post(
@@ -97,19 +111,38 @@ impl HttpClient {
         headers: &[(String, String)],
         body: impl Into<Bytes>,
     ) -> Result<HttpResponse, ClientError> {
-        let mut builder = hyper::Request::builder()
-            .method(hyper::Method::POST)
-            .uri(url);
+        self.request(hyper::Method::POST, url, headers, body.into())
+            .await
+    }
+
+    pub async fn get(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+    ) -> Result<HttpResponse, ClientError> {
+        self.request(hyper::Method::GET, url, headers, Bytes::new())
+            .await
+    }
+
+    async fn request(
+        &self,
+        method: hyper::Method,
+        url: &str,
+        headers: &[(String, String)],
+        body: Bytes,
+    ) -> Result<HttpResponse, ClientError> {
+        let mut builder = hyper::Request::builder().method(method).uri(url);
 
         for (k, v) in headers {
             builder = builder.header(k.as_str(), v.as_str());
         }
 
         let request = builder
-            .body(Full::new(body.into()))
+            .body(Full::new(body))
             .map_err(|e| ClientError::Http(e.to_string()))?;
 
-        let resp = tokio::time::timeout(self.timeout, self.client.request(request))
+        let client = self.client.lock().unwrap().clone();
+        let resp = tokio::time::timeout(self.timeout, client.request(request))
             .await
             .map_err(|_| ClientError::Timeout)?
             .map_err(|e| ClientError::Http(e.to_string()))?;
@@ -123,7 +156,12 @@ impl HttpClient {
             .await
             .map_err(|e| ClientError::Http(e.to_string()))?;
         let trailers = collected.trailers().cloned();
+        let body = collected.to_bytes();
 
-        Ok(HttpResponse { status, trailers })
+        Ok(HttpResponse {
+            status,
+            body,
+            trailers,
+        })
     }
 }
@@ -0,0 +1,185 @@
+//! In-flight invocation tracking for Lambda Managed Instances.
+//!
+//! Managed Instances run several invocations in one execution environment at
+//! once and send no INVOKE events, so the relay follows invocations through
+//! the Telemetry API instead: `platform.start` opens one and
+//! `platform.runtimeDone` closes it. From that it knows how many invocations
+//! are running, when the environment goes idle, and which invocation a span or
+//! log record most likely belongs to.
+//!
+//! Attribution stamps `faas.invocation_id` on records that do not carry it:
+//! records whose trace ID matches the X-Ray trace of a running invocation go
+//! to that invocation, and anything else only when a single invocation is
+//! running. Metrics are aggregates and are never attributed.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bytes::Bytes;
+use prost::Message;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::buffers::Signal;
+use crate::proto::opentelemetry::proto::{
+    collector::{logs::v1::ExportLogsServiceRequest, trace::v1::ExportTraceServiceRequest},
+    common::v1::{AnyValue, KeyValue, any_value::Value},
+};
+use crate::xray::XrayContext;
+
+pub const INVOCATION_ID_KEY: &str = "faas.invocation_id";
+
+/// Longest an invocation can run. Entries older than this lost their
+/// `runtimeDone` event and are forgotten, so the environment can go idle.
+const MAX_INVOCATION_AGE: Duration = Duration::from_secs(15 * 60);
+
+struct Invocation {
+    started: Instant,
+    trace_id: Option<[u8; 16]>,
+}
+
+#[derive(Default)]
+pub struct Invocations {
+    in_flight: HashMap<String, Invocation>,
+    peak: usize,
+}
+
+impl Invocations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `platform.start`. `tracing_value` is the X-Ray header carried
+    /// by the event when active tracing is on.
+    pub fn start(&mut self, request_id: &str, tracing_value: Option<&str>) {
+        let now = Instant::now();
+        self.in_flight.retain(|request_id, inv| {
+            let stale = now.duration_since(inv.started) > MAX_INVOCATION_AGE;
+            if stale {
+                debug!(request_id, "forgetting invocation without runtimeDone");
+            }
+            !stale
+        });
+        self.in_flight.insert(
+            request_id.to_owned(),
+            Invocation {
+                started: now,
+                trace_id: tracing_value
+                    .and_then(XrayContext::parse)
+                    .map(|context| context.trace_id),
+            },
+        );
+        self.peak = self.peak.max(self.in_flight.len());
+    }
+
+    /// Record `platform.runtimeDone`. Returns `true` if this was the last
+    /// running invocation.
+    pub fn finish(&mut self, request_id: &str) -> bool {
+        self.in_flight.remove(request_id).is_some() && self.in_flight.is_empty()
+    }
+
+    /// Forget the running invocations (SnapStart restore): none of them will
+    /// report `runtimeDone` in the restored environment. The peak is kept,
+    /// since the buffer is already sized by it.
+    pub fn reset(&mut self) {
+        self.in_flight.clear();
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Most invocations seen running at the same time.
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    /// Stamp `faas.invocation_id` on the spans or log records of `payload`
+    /// that can be attributed. Payloads that do not decode, metrics, and
+    /// payloads with nothing to attribute are returned unchanged.
+    pub fn attribute(&self, signal: Signal, payload: Bytes) -> Bytes {
+        if self.in_flight.is_empty() {
+            return payload;
+        }
+        let attributed = match signal {
+            Signal::Traces => self.attribute_request::<ExportTraceServiceRequest>(&payload),
+            Signal::Logs => self.attribute_request::<ExportLogsServiceRequest>(&payload),
+            Signal::Metrics => None,
+        };
+        attributed.unwrap_or(payload)
+    }
+
+    fn attribute_request<R: Records>(&self, payload: &[u8]) -> Option<Bytes> {
+        let mut req = R::decode(payload).ok()?;
+        let mut changed = false;
+        req.for_each_record(&mut |trace_id, attributes| {
+            if attributes.iter().any(|kv| kv.key == INVOCATION_ID_KEY) {
+                return;
+            }
+            if let Some(request_id) = self.owner(trace_id) {
+                attributes.push(KeyValue {
+                    key: INVOCATION_ID_KEY.to_owned(),
+                    value: Some(AnyValue {
+                        value: Some(Value::StringValue(request_id.to_owned())),
+                    }),
+                });
+                changed = true;
+            }
+        });
+        changed.then(|| Bytes::from(req.encode_to_vec()))
+    }
+
+    /// The invocation a record with `trace_id` belongs to, if it can be told.
+    fn owner(&self, trace_id: &[u8]) -> Option<&str> {
+        let by_trace = self
+            .in_flight
+            .iter()
+            .find(|(_, inv)| inv.trace_id.is_some_and(|id| id == trace_id));
+        match by_trace {
+            Some((request_id, _)) => Some(request_id),
+            None if self.in_flight.len() == 1 => self.in_flight.keys().next().map(String::as_str),
+            None => None,
+        }
+    }
+}
+
+/// Buffer limit for `concurrency` invocations at `per_invocation` bytes each,
+/// capped at `ceiling`.
+pub fn buffer_limit(per_invocation: usize, concurrency: usize, ceiling: Option<usize>) -> usize {
+    let limit = per_invocation.saturating_mul(concurrency.max(1));
+    ceiling.map_or(limit, |ceiling| limit.min(ceiling))
+}
+
+/// Requests whose records carry a trace ID and attributes.
+trait Records: Message + Default {
+    fn for_each_record(&mut self, f: &mut dyn FnMut(&[u8], &mut Vec<KeyValue>));
+}
+
+impl Records for ExportTraceServiceRequest {
+    fn for_each_record(&mut self, f: &mut dyn FnMut(&[u8], &mut Vec<KeyValue>)) {
+        for span in self
+            .resource_spans
+            .iter_mut()
+            .flat_map(|rs| &mut rs.scope_spans)
+            .flat_map(|ss| &mut ss.spans)
+        {
+            f(&span.trace_id, &mut span.attributes);
+        }
+    }
+}
+
+impl Records for ExportLogsServiceRequest {
+    fn for_each_record(&mut self, f: &mut dyn FnMut(&[u8], &mut Vec<KeyValue>)) {
+        for record in self
+            .resource_logs
+            .iter_mut()
+            .flat_map(|rl| &mut rl.scope_logs)
+            .flat_map(|sl| &mut sl.log_records)
+        {
+            f(&record.trace_id, &mut record.attributes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,173 @@
+use super::*;
+use crate::proto::opentelemetry::proto::logs::v1::{LogRecord, ResourceLogs, ScopeLogs};
+use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Span};
+
+const XRAY: &str = "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1";
+
+fn xray_id() -> Vec<u8> {
+    vec![
+        0x57, 0x59, 0xe9, 0x88, 0xbd, 0x86, 0x2e, 0x3f, 0xe1, 0xbe, 0x46, 0xa9, 0x94, 0x27, 0x27,
+        0x93,
+    ]
+}
+
+fn traces(spans: Vec<Span>) -> Bytes {
+    let req = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans,
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+    Bytes::from(req.encode_to_vec())
+}
+
+fn span(trace_id: Vec<u8>) -> Span {
+    Span {
+        trace_id,
+        span_id: vec![1; 8],
+        ..Default::default()
+    }
+}
+
+/// The `faas.invocation_id` of each span, in order.
+fn invocation_ids(payload: &[u8]) -> Vec<Option<String>> {
+    ExportTraceServiceRequest::decode(payload)
+        .unwrap()
+        .resource_spans
+        .iter()
+        .flat_map(|rs| &rs.scope_spans)
+        .flat_map(|ss| &ss.spans)
+        .map(|span| {
+            span.attributes
+                .iter()
+                .find(|kv| kv.key == INVOCATION_ID_KEY)
+                .and_then(|kv| match kv.value.as_ref()?.value.as_ref()? {
+                    Value::StringValue(s) => Some(s.clone()),
+                    _ => None,
+                })
+        })
+        .collect()
+}
+
+#[test]
+fn tracks_concurrency_and_idle() {
+    let mut invocations = Invocations::new();
+    invocations.start("a", None);
+    invocations.start("b", None);
+    assert_eq!(invocations.in_flight(), 2);
+
+    assert!(!invocations.finish("a"));
+    assert!(!invocations.finish("unknown"));
+    assert!(invocations.finish("b"));
+    assert_eq!(invocations.in_flight(), 0);
+    assert_eq!(invocations.peak(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn forgets_invocations_without_runtime_done() {
+    let mut invocations = Invocations::new();
+    invocations.start("lost", None);
+    tokio::time::advance(MAX_INVOCATION_AGE + Duration::from_secs(1)).await;
+    invocations.start("next", None);
+    assert_eq!(invocations.in_flight(), 1);
+    assert!(invocations.finish("next"));
+}
+
+#[test]
+fn single_invocation_owns_everything() {
+    let mut invocations = Invocations::new();
+    invocations.start("only", None);
+    let payload = invocations.attribute(Signal::Traces, traces(vec![span(vec![7; 16])]));
+    assert_eq!(invocation_ids(&payload), [Some("only".to_owned())]);
+}
+
+#[test]
+fn concurrent_invocations_attributed_by_xray_trace() {
+    let mut invocations = Invocations::new();
+    invocations.start("traced", Some(XRAY));
+    invocations.start("other", None);
+
+    let payload = invocations.attribute(
+        Signal::Traces,
+        traces(vec![span(xray_id()), span(vec![7; 16])]),
+    );
+    assert_eq!(invocation_ids(&payload), [Some("traced".to_owned()), None]);
+}
+
+#[test]
+fn existing_invocation_id_is_kept() {
+    let mut invocations = Invocations::new();
+    invocations.start("only", None);
+    let mut tagged = span(vec![7; 16]);
+    tagged.attributes.push(KeyValue {
+        key: INVOCATION_ID_KEY.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue("sdk".to_owned())),
+        }),
+    });
+    let payload = traces(vec![tagged]);
+    assert_eq!(
+        invocations.attribute(Signal::Traces, payload.clone()),
+        payload
+    );
+}
+
+#[test]
+fn unattributable_payloads_pass_through() {
+    let mut invocations = Invocations::new();
+    let payload = traces(vec![span(vec![7; 16])]);
+    assert_eq!(
+        invocations.attribute(Signal::Traces, payload.clone()),
+        payload
+    );
+
+    invocations.start("a", None);
+    invocations.start("b", None);
+    assert_eq!(
+        invocations.attribute(Signal::Traces, payload.clone()),
+        payload
+    );
+
+    let garbage = Bytes::from_static(b"\xff\xff");
+    assert_eq!(
+        invocations.attribute(Signal::Traces, garbage.clone()),
+        garbage
+    );
+    assert_eq!(
+        invocations.attribute(Signal::Metrics, garbage.clone()),
+        garbage
+    );
+}
+
+#[test]
+fn log_records_are_attributed() {
+    let mut invocations = Invocations::new();
+    invocations.start("only", None);
+    let req = ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs {
+            resource: None,
+            scope_logs: vec![ScopeLogs {
+                scope: None,
+                log_records: vec![LogRecord::default()],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+    let payload = invocations.attribute(Signal::Logs, Bytes::from(req.encode_to_vec()));
+    let req = ExportLogsServiceRequest::decode(payload).unwrap();
+    let attributes = &req.resource_logs[0].scope_logs[0].log_records[0].attributes;
+    assert_eq!(attributes[0].key, INVOCATION_ID_KEY);
+}
+
+#[test]
+fn buffer_limit_scales_with_concurrency_up_to_ceiling() {
+    assert_eq!(buffer_limit(1_000, 0, None), 1_000);
+    assert_eq!(buffer_limit(1_000, 4, None), 4_000);
+    assert_eq!(buffer_limit(1_000, 4, Some(3_000)), 3_000);
+}
@@ -1,16 +1,23 @@
 mod buffers;
 mod config;
+mod emf;
 mod event_loop;
 mod exporter;
 mod extensions_api;
 mod flush_strategy;
 mod grpc;
 mod http_client;
+mod invocations;
 mod merge;
+mod otlp_json;
 mod otlp_listener;
+mod processor;
+mod prometheus;
 mod proto;
 mod runtime_mode;
+mod self_metrics;
 mod telemetry_listener;
+mod xray;
 
 #[cfg(test)]
 mod testing;
@@ -124,28 +124,33 @@ impl MergeableRequest for ExportLogsServiceRequest {
 
 /// Decode, deduplicate by resource identity, and merge scope entries.
 fn merge<M: MergeableRequest>(payloads: &VecDeque<Bytes>) -> M {
-    let capacity = payloads.len().min(8);
+    let items = payloads
+        .iter()
+        .flat_map(|payload| match M::decode(payload.as_ref()) {
+            Ok(req) => req.into_items(),
+            Err(e) => {
+                warn!(error = %e, "skipping malformed {} payload", M::signal_name());
+                Vec::new()
+            }
+        });
+    group(items, payloads.len().min(8))
+}
+
+/// Merge the scope entries of items with the same resource identity, in the
+/// order each identity first appears.
+fn group<M: MergeableRequest>(items: impl IntoIterator<Item = M::Item>, capacity: usize) -> M {
     let mut groups: HashMap<ResourceIdentity, M::Item> = HashMap::with_capacity(capacity);
     let mut order: Vec<ResourceIdentity> = Vec::with_capacity(capacity);
 
-    for payload in payloads {
-        let req = match M::decode(payload.as_ref()) {
-            Ok(r) => r,
-            Err(e) => {
-                warn!(error = %e, "skipping malformed {} payload", M::signal_name());
-                continue;
+    for item in items {
+        let id = M::identity(&item);
+        match groups.entry(id) {
+            Entry::Occupied(mut e) => {
+                M::extend_scopes(e.get_mut(), item);
             }
-        };
-        for item in req.into_items() {
-            let id = M::identity(&item);
-            match groups.entry(id) {
-                Entry::Occupied(mut e) => {
-                    M::extend_scopes(e.get_mut(), item);
-                }
-                Entry::Vacant(e) => {
-                    order.push(e.key().clone());
-                    e.insert(item);
-                }
+            Entry::Vacant(e) => {
+                order.push(e.key().clone());
+                e.insert(item);
             }
         }
     }
@@ -158,6 +163,14 @@ fn merge<M: MergeableRequest>(payloads: &VecDeque<Bytes>) -> M {
     )
 }
 
+/// Merge again after resources were changed, since resources that differed
+/// may now have the same identity.
+fn regroup<M: MergeableRequest>(req: &mut M) {
+    let items = std::mem::take(req).into_items();
+    let capacity = items.len();
+    *req = group(items, capacity);
+}
+
 pub fn merge_traces(payloads: &VecDeque<Bytes>) -> ExportTraceServiceRequest {
     merge(payloads)
 }
@@ -170,5 +183,17 @@ pub fn merge_logs(payloads: &VecDeque<Bytes>) -> ExportLogsServiceRequest {
     merge(payloads)
 }
 
+pub fn regroup_traces(req: &mut ExportTraceServiceRequest) {
+    regroup(req)
+}
+
+pub fn regroup_metrics(req: &mut ExportMetricsServiceRequest) {
+    regroup(req)
+}
+
+pub fn regroup_logs(req: &mut ExportLogsServiceRequest) {
+    regroup(req)
+}
+
 #[cfg(test)]
 mod tests;
@@ -175,3 +175,24 @@ fn different_schema_urls_stay_separate() {
         "same attributes but different schema_url should stay separate"
     );
 }
+
+#[test]
+fn regroup_merges_resources_that_became_identical() {
+    let mut req = ExportTraceServiceRequest {
+        resource_spans: vec![
+            resource_spans(resource(vec![kv("service.name", "a")]), 1),
+            resource_spans(resource(vec![kv("service.name", "b")]), 1),
+            resource_spans(resource(vec![kv("service.name", "c")]), 1),
+        ],
+    };
+    req.resource_spans[2].resource = resource(vec![kv("service.name", "a")]);
+
+    regroup_traces(&mut req);
+
+    let scopes: Vec<usize> = req
+        .resource_spans
+        .iter()
+        .map(|rs| rs.scope_spans.len())
+        .collect();
+    assert_eq!(scopes, [2, 1], "first-seen order is kept");
+}
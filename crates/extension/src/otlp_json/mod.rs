@@ -0,0 +1,638 @@
+//! Minimal OTLP/JSON encoder.
+//!
+//! Implements the [OTLP JSON Protobuf Encoding] for the three export request
+//! types without a serde dependency:
+//!
+//! - field names are lowerCamelCase
+//! - `traceId` / `spanId` / `parentSpanId` are lowercase hex, other `bytes` are base64
+//! - 64-bit integers are decimal strings, enums are integers
+//! - fields holding their proto3 default value are omitted
+//!
+//! [OTLP JSON Protobuf Encoding]: https://opentelemetry.io/docs/specs/otlp/#json-protobuf-encoding
+
+use std::fmt::Write;
+
+use crate::proto::opentelemetry::proto::{
+    collector::{
+        logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+        trace::v1::ExportTraceServiceRequest,
+    },
+    common::v1::{AnyValue, EntityRef, InstrumentationScope, KeyValue, any_value},
+    logs::v1::{LogRecord, ResourceLogs, ScopeLogs},
+    metrics::v1::{
+        Exemplar, ExponentialHistogramDataPoint, HistogramDataPoint, Metric, NumberDataPoint,
+        ResourceMetrics, ScopeMetrics, SummaryDataPoint, exemplar,
+        exponential_histogram_data_point::Buckets, metric, number_data_point,
+        summary_data_point::ValueAtQuantile,
+    },
+    resource::v1::Resource,
+    trace::v1::{ResourceSpans, ScopeSpans, Span, Status, span},
+};
+
+/// Encode a message as an OTLP/JSON document.
+pub fn encode(msg: &impl ToJson) -> Vec<u8> {
+    let mut out = String::with_capacity(256);
+    msg.write_json(&mut out);
+    out.into_bytes()
+}
+
+/// A protobuf message that can be written in the OTLP/JSON mapping.
+pub trait ToJson {
+    fn write_json(&self, out: &mut String);
+}
+
+// ---------------------------------------------------------------------------
+// Writer helpers
+// ---------------------------------------------------------------------------
+
+/// Writes a JSON object, emitting commas between fields and skipping fields
+/// that hold their default value.
+pub struct Object<'a> {
+    out: &'a mut String,
+    empty: bool,
+}
+
+impl<'a> Object<'a> {
+    pub fn new(out: &'a mut String) -> Self {
+        out.push('{');
+        Self { out, empty: true }
+    }
+
+    pub fn key(&mut self, name: &str) -> &mut String {
+        if !self.empty {
+            self.out.push(',');
+        }
+        self.empty = false;
+        write_str(self.out, name);
+        self.out.push(':');
+        self.out
+    }
+
+    pub fn str(&mut self, name: &str, value: &str) {
+        if !value.is_empty() {
+            let out = self.key(name);
+            write_str(out, value);
+        }
+    }
+
+    fn hex(&mut self, name: &str, value: &[u8]) {
+        if !value.is_empty() {
+            let out = self.key(name);
+            out.push('"');
+            for b in value {
+                let _ = write!(out, "{b:02x}");
+            }
+            out.push('"');
+        }
+    }
+
+    /// 64-bit integers are encoded as decimal strings.
+    fn u64(&mut self, name: &str, value: u64) {
+        if value != 0 {
+            let _ = write!(self.key(name), "\"{value}\"");
+        }
+    }
+
+    fn u32(&mut self, name: &str, value: u32) {
+        if value != 0 {
+            let _ = write!(self.key(name), "{value}");
+        }
+    }
+
+    fn i32(&mut self, name: &str, value: i32) {
+        if value != 0 {
+            let _ = write!(self.key(name), "{value}");
+        }
+    }
+
+    fn f64(&mut self, name: &str, value: f64) {
+        if value != 0.0 {
+            write_f64(self.key(name), value);
+        }
+    }
+
+    /// Explicitly-present `optional double` — written even when zero.
+    fn opt_f64(&mut self, name: &str, value: Option<f64>) {
+        if let Some(v) = value {
+            write_f64(self.key(name), v);
+        }
+    }
+
+    fn bool(&mut self, name: &str, value: bool) {
+        if value {
+            self.key(name).push_str("true");
+        }
+    }
+
+    fn msg(&mut self, name: &str, value: Option<&impl ToJson>) {
+        if let Some(v) = value {
+            v.write_json(self.key(name));
+        }
+    }
+
+    fn list<T: ToJson>(&mut self, name: &str, items: &[T]) {
+        if !items.is_empty() {
+            let out = self.key(name);
+            write_list(out, items, |out, item| item.write_json(out));
+        }
+    }
+
+    fn u64_list(&mut self, name: &str, items: &[u64]) {
+        if !items.is_empty() {
+            let out = self.key(name);
+            write_list(out, items, |out, v| {
+                let _ = write!(out, "\"{v}\"");
+            });
+        }
+    }
+
+    fn f64_list(&mut self, name: &str, items: &[f64]) {
+        if !items.is_empty() {
+            let out = self.key(name);
+            write_list(out, items, |out, v| write_f64(out, *v));
+        }
+    }
+
+    fn str_list(&mut self, name: &str, items: &[String]) {
+        if !items.is_empty() {
+            let out = self.key(name);
+            write_list(out, items, |out, v| write_str(out, v));
+        }
+    }
+
+    pub fn finish(self) {
+        self.out.push('}');
+    }
+}
+
+pub fn write_list<T>(out: &mut String, items: &[T], mut write_item: impl FnMut(&mut String, &T)) {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_item(out, item);
+    }
+    out.push(']');
+}
+
+pub fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Proto3 JSON encodes non-finite doubles as the strings `"NaN"`,
+/// `"Infinity"` and `"-Infinity"`.
+pub fn write_f64(out: &mut String, v: f64) {
+    if v.is_nan() {
+        out.push_str("\"NaN\"");
+    } else if v.is_infinite() {
+        out.push_str(if v > 0.0 {
+            "\"Infinity\""
+        } else {
+            "\"-Infinity\""
+        });
+    } else {
+        let _ = write!(out, "{v}");
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn write_base64(out: &mut String, data: &[u8]) {
+    out.push('"');
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out.push('"');
+}
+
+// ---------------------------------------------------------------------------
+// Export requests
+// ---------------------------------------------------------------------------
+
+impl ToJson for ExportTraceServiceRequest {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.list("resourceSpans", &self.resource_spans);
+        o.finish();
+    }
+}
+
+impl ToJson for ExportMetricsServiceRequest {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.list("resourceMetrics", &self.resource_metrics);
+        o.finish();
+    }
+}
+
+impl ToJson for ExportLogsServiceRequest {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.list("resourceLogs", &self.resource_logs);
+        o.finish();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Common
+// ---------------------------------------------------------------------------
+
+impl ToJson for AnyValue {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        match &self.value {
+            Some(any_value::Value::StringValue(v)) => write_str(o.key("stringValue"), v),
+            Some(any_value::Value::BoolValue(v)) => {
+                let _ = write!(o.key("boolValue"), "{v}");
+            }
+            Some(any_value::Value::IntValue(v)) => {
+                let _ = write!(o.key("intValue"), "\"{v}\"");
+            }
+            Some(any_value::Value::DoubleValue(v)) => write_f64(o.key("doubleValue"), *v),
+            Some(any_value::Value::ArrayValue(v)) => {
+                let out = o.key("arrayValue");
+                let mut inner = Object::new(out);
+                inner.list("values", &v.values);
+                inner.finish();
+            }
+            Some(any_value::Value::KvlistValue(v)) => {
+                let out = o.key("kvlistValue");
+                let mut inner = Object::new(out);
+                inner.list("values", &v.values);
+                inner.finish();
+            }
+            Some(any_value::Value::BytesValue(v)) => write_base64(o.key("bytesValue"), v),
+            None => {}
+        }
+        o.finish();
+    }
+}
+
+impl ToJson for KeyValue {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        write_str(o.key("key"), &self.key);
+        o.msg("value", self.value.as_ref());
+        o.finish();
+    }
+}
+
+impl ToJson for InstrumentationScope {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.str("name", &self.name);
+        o.str("version", &self.version);
+        o.list("attributes", &self.attributes);
+        o.u32("droppedAttributesCount", self.dropped_attributes_count);
+        o.finish();
+    }
+}
+
+impl ToJson for EntityRef {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.str("schemaUrl", &self.schema_url);
+        o.str("type", &self.r#type);
+        o.str_list("idKeys", &self.id_keys);
+        o.str_list("descriptionKeys", &self.description_keys);
+        o.finish();
+    }
+}
+
+impl ToJson for Resource {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.list("attributes", &self.attributes);
+        o.u32("droppedAttributesCount", self.dropped_attributes_count);
+        o.list("entityRefs", &self.entity_refs);
+        o.finish();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Traces
+// ---------------------------------------------------------------------------
+
+impl ToJson for ResourceSpans {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.msg("resource", self.resource.as_ref());
+        o.list("scopeSpans", &self.scope_spans);
+        o.str("schemaUrl", &self.schema_url);
+        o.finish();
+    }
+}
+
+impl ToJson for ScopeSpans {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.msg("scope", self.scope.as_ref());
+        o.list("spans", &self.spans);
+        o.str("schemaUrl", &self.schema_url);
+        o.finish();
+    }
+}
+
+impl ToJson for Span {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.hex("traceId", &self.trace_id);
+        o.hex("spanId", &self.span_id);
+        o.str("traceState", &self.trace_state);
+        o.hex("parentSpanId", &self.parent_span_id);
+        o.u32("flags", self.flags);
+        o.str("name", &self.name);
+        o.i32("kind", self.kind);
+        o.u64("startTimeUnixNano", self.start_time_unix_nano);
+        o.u64("endTimeUnixNano", self.end_time_unix_nano);
+        o.list("attributes", &self.attributes);
+        o.u32("droppedAttributesCount", self.dropped_attributes_count);
+        o.list("events", &self.events);
+        o.u32("droppedEventsCount", self.dropped_events_count);
+        o.list("links", &self.links);
+        o.u32("droppedLinksCount", self.dropped_links_count);
+        o.msg("status", self.status.as_ref());
+        o.finish();
+    }
+}
+
+impl ToJson for span::Event {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.u64("timeUnixNano", self.time_unix_nano);
+        o.str("name", &self.name);
+        o.list("attributes", &self.attributes);
+        o.u32("droppedAttributesCount", self.dropped_attributes_count);
+        o.finish();
+    }
+}
+
+impl ToJson for span::Link {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.hex("traceId", &self.trace_id);
+        o.hex("spanId", &self.span_id);
+        o.str("traceState", &self.trace_state);
+        o.list("attributes", &self.attributes);
+        o.u32("droppedAttributesCount", self.dropped_attributes_count);
+        o.u32("flags", self.flags);
+        o.finish();
+    }
+}
+
+impl ToJson for Status {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.str("message", &self.message);
+        o.i32("code", self.code);
+        o.finish();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Metrics
+// ---------------------------------------------------------------------------
+
+impl ToJson for ResourceMetrics {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.msg("resource", self.resource.as_ref());
+        o.list("scopeMetrics", &self.scope_metrics);
+        o.str("schemaUrl", &self.schema_url);
+        o.finish();
+    }
+}
+
+impl ToJson for ScopeMetrics {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.msg("scope", self.scope.as_ref());
+        o.list("metrics", &self.metrics);
+        o.str("schemaUrl", &self.schema_url);
+        o.finish();
+    }
+}
+
+impl ToJson for Metric {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.str("name", &self.name);
+        o.str("description", &self.description);
+        o.str("unit", &self.unit);
+        match &self.data {
+            Some(metric::Data::Gauge(g)) => {
+                let mut inner = Object::new(o.key("gauge"));
+                inner.list("dataPoints", &g.data_points);
+                inner.finish();
+            }
+            Some(metric::Data::Sum(s)) => {
+                let mut inner = Object::new(o.key("sum"));
+                inner.list("dataPoints", &s.data_points);
+                inner.i32("aggregationTemporality", s.aggregation_temporality);
+                inner.bool("isMonotonic", s.is_monotonic);
+                inner.finish();
+            }
+            Some(metric::Data::Histogram(h)) => {
+                let mut inner = Object::new(o.key("histogram"));
+                inner.list("dataPoints", &h.data_points);
+                inner.i32("aggregationTemporality", h.aggregation_temporality);
+                inner.finish();
+            }
+            Some(metric::Data::ExponentialHistogram(h)) => {
+                let mut inner = Object::new(o.key("exponentialHistogram"));
+                inner.list("dataPoints", &h.data_points);
+                inner.i32("aggregationTemporality", h.aggregation_temporality);
+                inner.finish();
+            }
+            Some(metric::Data::Summary(s)) => {
+                let mut inner = Object::new(o.key("summary"));
+                inner.list("dataPoints", &s.data_points);
+                inner.finish();
+            }
+            None => {}
+        }
+        o.list("metadata", &self.metadata);
+        o.finish();
+    }
+}
+
+impl ToJson for NumberDataPoint {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.list("attributes", &self.attributes);
+        o.u64("startTimeUnixNano", self.start_time_unix_nano);
+        o.u64("timeUnixNano", self.time_unix_nano);
+        match self.value {
+            Some(number_data_point::Value::AsDouble(v)) => write_f64(o.key("asDouble"), v),
+            Some(number_data_point::Value::AsInt(v)) => {
+                let _ = write!(o.key("asInt"), "\"{v}\"");
+            }
+            None => {}
+        }
+        o.list("exemplars", &self.exemplars);
+        o.u32("flags", self.flags);
+        o.finish();
+    }
+}
+
+impl ToJson for HistogramDataPoint {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.list("attributes", &self.attributes);
+        o.u64("startTimeUnixNano", self.start_time_unix_nano);
+        o.u64("timeUnixNano", self.time_unix_nano);
+        o.u64("count", self.count);
+        o.opt_f64("sum", self.sum);
+        o.u64_list("bucketCounts", &self.bucket_counts);
+        o.f64_list("explicitBounds", &self.explicit_bounds);
+        o.list("exemplars", &self.exemplars);
+        o.u32("flags", self.flags);
+        o.opt_f64("min", self.min);
+        o.opt_f64("max", self.max);
+        o.finish();
+    }
+}
+
+impl ToJson for ExponentialHistogramDataPoint {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.list("attributes", &self.attributes);
+        o.u64("startTimeUnixNano", self.start_time_unix_nano);
+        o.u64("timeUnixNano", self.time_unix_nano);
+        o.u64("count", self.count);
+        o.opt_f64("sum", self.sum);
+        o.i32("scale", self.scale);
+        o.u64("zeroCount", self.zero_count);
+        o.msg("positive", self.positive.as_ref());
+        o.msg("negative", self.negative.as_ref());
+        o.u32("flags", self.flags);
+        o.list("exemplars", &self.exemplars);
+        o.opt_f64("min", self.min);
+        o.opt_f64("max", self.max);
+        o.f64("zeroThreshold", self.zero_threshold);
+        o.finish();
+    }
+}
+
+impl ToJson for Buckets {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.i32("offset", self.offset);
+        o.u64_list("bucketCounts", &self.bucket_counts);
+        o.finish();
+    }
+}
+
+impl ToJson for SummaryDataPoint {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.list("attributes", &self.attributes);
+        o.u64("startTimeUnixNano", self.start_time_unix_nano);
+        o.u64("timeUnixNano", self.time_unix_nano);
+        o.u64("count", self.count);
+        o.f64("sum", self.sum);
+        o.list("quantileValues", &self.quantile_values);
+        o.u32("flags", self.flags);
+        o.finish();
+    }
+}
+
+impl ToJson for ValueAtQuantile {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.f64("quantile", self.quantile);
+        o.f64("value", self.value);
+        o.finish();
+    }
+}
+
+impl ToJson for Exemplar {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.list("filteredAttributes", &self.filtered_attributes);
+        o.u64("timeUnixNano", self.time_unix_nano);
+        match self.value {
+            Some(exemplar::Value::AsDouble(v)) => write_f64(o.key("asDouble"), v),
+            Some(exemplar::Value::AsInt(v)) => {
+                let _ = write!(o.key("asInt"), "\"{v}\"");
+            }
+            None => {}
+        }
+        o.hex("spanId", &self.span_id);
+        o.hex("traceId", &self.trace_id);
+        o.finish();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Logs
+// ---------------------------------------------------------------------------
+
+impl ToJson for ResourceLogs {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.msg("resource", self.resource.as_ref());
+        o.list("scopeLogs", &self.scope_logs);
+        o.str("schemaUrl", &self.schema_url);
+        o.finish();
+    }
+}
+
+impl ToJson for ScopeLogs {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.msg("scope", self.scope.as_ref());
+        o.list("logRecords", &self.log_records);
+        o.str("schemaUrl", &self.schema_url);
+        o.finish();
+    }
+}
+
+impl ToJson for LogRecord {
+    fn write_json(&self, out: &mut String) {
+        let mut o = Object::new(out);
+        o.u64("timeUnixNano", self.time_unix_nano);
+        o.u64("observedTimeUnixNano", self.observed_time_unix_nano);
+        o.i32("severityNumber", self.severity_number);
+        o.str("severityText", &self.severity_text);
+        o.msg("body", self.body.as_ref());
+        o.list("attributes", &self.attributes);
+        o.u32("droppedAttributesCount", self.dropped_attributes_count);
+        o.u32("flags", self.flags);
+        o.hex("traceId", &self.trace_id);
+        o.hex("spanId", &self.span_id);
+        o.str("eventName", &self.event_name);
+        o.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,230 @@
+use super::*;
+use crate::proto::opentelemetry::proto::{
+    common::v1::{ArrayValue, KeyValueList},
+    metrics::v1::{Gauge, Histogram, Sum},
+};
+
+fn to_string(msg: &impl ToJson) -> String {
+    String::from_utf8(encode(msg)).unwrap()
+}
+
+fn kv(key: &str, value: any_value::Value) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue { value: Some(value) }),
+    }
+}
+
+#[test]
+fn empty_request_is_empty_object() {
+    let req = ExportTraceServiceRequest::default();
+    assert_eq!(to_string(&req), "{}");
+}
+
+#[test]
+fn span_ids_are_hex_and_times_are_strings() {
+    let req = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: vec![ScopeSpans {
+                scope: Some(InstrumentationScope {
+                    name: "my-lib".to_owned(),
+                    ..Default::default()
+                }),
+                spans: vec![Span {
+                    trace_id: vec![
+                        0x5b, 0x8e, 0xff, 0xf7, 0x98, 0x03, 0x81, 0x03, 0xd2, 0x69, 0xb6, 0x33,
+                        0x81, 0x3f, 0xc6, 0x0c,
+                    ],
+                    span_id: vec![0xee, 0xe1, 0x9b, 0x7e, 0xc3, 0xc1, 0xb1, 0x74],
+                    name: "GET /".to_owned(),
+                    kind: 2,
+                    start_time_unix_nano: 1_544_712_660_000_000_000,
+                    status: Some(Status {
+                        message: String::new(),
+                        code: 2,
+                    }),
+                    ..Default::default()
+                }],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+
+    assert_eq!(
+        to_string(&req),
+        r#"{"resourceSpans":[{"scopeSpans":[{"scope":{"name":"my-lib"},"spans":[{"traceId":"5b8efff798038103d269b633813fc60c","spanId":"eee19b7ec3c1b174","name":"GET /","kind":2,"startTimeUnixNano":"1544712660000000000","status":{"code":2}}]}]}]}"#
+    );
+}
+
+#[test]
+fn any_value_variants() {
+    let attrs = vec![
+        kv("s", any_value::Value::StringValue("a\"b\n".to_owned())),
+        kv("b", any_value::Value::BoolValue(false)),
+        kv("i", any_value::Value::IntValue(-42)),
+        kv("d", any_value::Value::DoubleValue(1.5)),
+        kv("y", any_value::Value::BytesValue(b"hello".to_vec())),
+        kv(
+            "a",
+            any_value::Value::ArrayValue(ArrayValue {
+                values: vec![AnyValue {
+                    value: Some(any_value::Value::IntValue(1)),
+                }],
+            }),
+        ),
+        kv(
+            "m",
+            any_value::Value::KvlistValue(KeyValueList {
+                values: vec![kv("k", any_value::Value::StringValue("v".to_owned()))],
+            }),
+        ),
+    ];
+    let resource = Resource {
+        attributes: attrs,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        to_string(&resource),
+        concat!(
+            r#"{"attributes":["#,
+            r#"{"key":"s","value":{"stringValue":"a\"b\n"}},"#,
+            r#"{"key":"b","value":{"boolValue":false}},"#,
+            r#"{"key":"i","value":{"intValue":"-42"}},"#,
+            r#"{"key":"d","value":{"doubleValue":1.5}},"#,
+            r#"{"key":"y","value":{"bytesValue":"aGVsbG8="}},"#,
+            r#"{"key":"a","value":{"arrayValue":{"values":[{"intValue":"1"}]}}},"#,
+            r#"{"key":"m","value":{"kvlistValue":{"values":[{"key":"k","value":{"stringValue":"v"}}]}}}"#,
+            r#"]}"#
+        )
+    );
+}
+
+#[test]
+fn base64_padding() {
+    let cases: [(&[u8], &str); 4] = [
+        (b"", r#""""#),
+        (b"f", r#""Zg==""#),
+        (b"fo", r#""Zm8=""#),
+        (b"foo", r#""Zm9v""#),
+    ];
+    for (input, expected) in cases {
+        let mut out = String::new();
+        write_base64(&mut out, input);
+        assert_eq!(out, expected, "input {input:?}");
+    }
+}
+
+#[test]
+fn control_characters_are_escaped() {
+    let mut out = String::new();
+    write_str(&mut out, "a\u{1}b\\");
+    assert_eq!(out, r#""a\u0001b\\""#);
+}
+
+#[test]
+fn non_finite_doubles_are_strings() {
+    let mut out = String::new();
+    write_f64(&mut out, f64::NAN);
+    write_f64(&mut out, f64::INFINITY);
+    write_f64(&mut out, f64::NEG_INFINITY);
+    assert_eq!(out, r#""NaN""Infinity""-Infinity""#);
+}
+
+#[test]
+fn metrics_oneofs_and_optional_fields() {
+    let req = ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: None,
+            scope_metrics: vec![ScopeMetrics {
+                scope: None,
+                metrics: vec![
+                    Metric {
+                        name: "g".to_owned(),
+                        data: Some(metric::Data::Gauge(Gauge {
+                            data_points: vec![NumberDataPoint {
+                                value: Some(number_data_point::Value::AsInt(7)),
+                                ..Default::default()
+                            }],
+                        })),
+                        ..Default::default()
+                    },
+                    Metric {
+                        name: "s".to_owned(),
+                        data: Some(metric::Data::Sum(Sum {
+                            data_points: vec![NumberDataPoint {
+                                value: Some(number_data_point::Value::AsDouble(0.25)),
+                                ..Default::default()
+                            }],
+                            aggregation_temporality: 1,
+                            is_monotonic: true,
+                        })),
+                        ..Default::default()
+                    },
+                    Metric {
+                        name: "h".to_owned(),
+                        data: Some(metric::Data::Histogram(Histogram {
+                            data_points: vec![HistogramDataPoint {
+                                count: 3,
+                                sum: Some(0.0),
+                                bucket_counts: vec![1, 2],
+                                explicit_bounds: vec![10.0],
+                                ..Default::default()
+                            }],
+                            aggregation_temporality: 2,
+                        })),
+                        ..Default::default()
+                    },
+                ],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+
+    assert_eq!(
+        to_string(&req),
+        concat!(
+            r#"{"resourceMetrics":[{"scopeMetrics":[{"metrics":["#,
+            r#"{"name":"g","gauge":{"dataPoints":[{"asInt":"7"}]}},"#,
+            r#"{"name":"s","sum":{"dataPoints":[{"asDouble":0.25}],"aggregationTemporality":1,"isMonotonic":true}},"#,
+            r#"{"name":"h","histogram":{"dataPoints":[{"count":"3","sum":0,"bucketCounts":["1","2"],"explicitBounds":[10]}],"aggregationTemporality":2}}"#,
+            r#"]}]}]}"#
+        )
+    );
+}
+
+#[test]
+fn log_record_fields() {
+    let req = ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs {
+            resource: None,
+            scope_logs: vec![ScopeLogs {
+                scope: None,
+                log_records: vec![LogRecord {
+                    time_unix_nano: 1,
+                    severity_number: 9,
+                    severity_text: "INFO".to_owned(),
+                    body: Some(AnyValue {
+                        value: Some(any_value::Value::StringValue("hi".to_owned())),
+                    }),
+                    trace_id: vec![0xab; 16],
+                    ..Default::default()
+                }],
+                schema_url: String::new(),
+            }],
+            schema_url: "https://opentelemetry.io/schemas/1.21.0".to_owned(),
+        }],
+    };
+
+    assert_eq!(
+        to_string(&req),
+        concat!(
+            r#"{"resourceLogs":[{"scopeLogs":[{"logRecords":[{"timeUnixNano":"1","severityNumber":9,"#,
+            r#""severityText":"INFO","body":{"stringValue":"hi"},"traceId":"abababababababababababababababab"}]}],"#,
+            r#""schemaUrl":"https://opentelemetry.io/schemas/1.21.0"}]}"#
+        )
+    );
+}
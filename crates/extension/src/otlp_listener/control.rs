@@ -0,0 +1,208 @@
+//! Local health, debug and flush endpoints.
+//!
+//! Served on the same localhost listener as OTLP so that function handlers
+//! and integration tests can inspect the relay and force a flush without
+//! extra configuration:
+//!
+//! - `GET /health` — readiness and the outcome of the last export
+//! - `GET /debug/buffer` — per-signal queue lengths, sizes and filtered counts
+//! - `POST /flush` — flush the buffer through the event loop and report the outcome
+//!
+//! OTLP POSTs carrying `X-Relay-Flush: sync` trigger the same flush once their
+//! payload is accepted, and the response reports the outcome in the same header.
+
+use std::time::UNIX_EPOCH;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Method, Response, StatusCode};
+use nanoserde::SerJson;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::buffers::{FlushOutcome, OutboundBuffer, Signal, SignalStats};
+
+/// Request header asking for a synchronous flush, echoed back with the outcome.
+pub const FLUSH_HEADER: &str = "x-relay-flush";
+
+/// Requests from the listener that must be handled by the event loop.
+pub enum ControlRequest {
+    /// Flush the buffer now and reply with the outcome.
+    Flush {
+        reply: oneshot::Sender<FlushOutcome>,
+    },
+}
+
+#[derive(SerJson)]
+struct Health {
+    status: String,
+    last_export: Option<LastExport>,
+}
+
+#[derive(SerJson)]
+struct LastExport {
+    success: bool,
+    error: Option<String>,
+    unix_ms: u64,
+}
+
+#[derive(SerJson)]
+struct SignalView {
+    payloads: usize,
+    bytes: usize,
+    processed: usize,
+    /// Records dropped by the filter rules since startup.
+    filtered: u64,
+}
+
+impl SignalView {
+    fn new(stats: SignalStats, filtered: u64) -> Self {
+        Self {
+            payloads: stats.payloads,
+            bytes: stats.bytes,
+            processed: stats.processed,
+            filtered,
+        }
+    }
+}
+
+#[derive(SerJson)]
+struct BufferView {
+    traces: SignalView,
+    metrics: SignalView,
+    logs: SignalView,
+    total_bytes: usize,
+    max_bytes: Option<usize>,
+    flush_in_flight: bool,
+}
+
+#[derive(SerJson)]
+struct FlushView {
+    outcome: String,
+    error: Option<String>,
+}
+
+/// Whether `path` is one of the control endpoints.
+pub fn is_control_path(path: &str) -> bool {
+    matches!(path, "/health" | "/debug/buffer" | "/flush")
+}
+
+pub async fn handle(
+    method: &Method,
+    path: &str,
+    buffer: &OutboundBuffer,
+    control_tx: &mpsc::Sender<ControlRequest>,
+) -> Response<Full<Bytes>> {
+    match (method, path) {
+        (&Method::GET, "/health") => health(buffer, control_tx),
+        (&Method::GET, "/debug/buffer") => debug_buffer(buffer),
+        (&Method::POST, "/flush") => flush(control_tx).await,
+        _ => {
+            tracing::warn!(
+                reason = format!("{method} {path}"),
+                "control request rejected"
+            );
+            json(StatusCode::METHOD_NOT_ALLOWED, String::new())
+        }
+    }
+}
+
+fn health(
+    buffer: &OutboundBuffer,
+    control_tx: &mpsc::Sender<ControlRequest>,
+) -> Response<Full<Bytes>> {
+    // The event loop owns the receiver; if it is gone the relay can no longer export.
+    if control_tx.is_closed() {
+        let body = Health {
+            status: "unavailable".to_owned(),
+            last_export: None,
+        };
+        return json(StatusCode::SERVICE_UNAVAILABLE, body.serialize_json());
+    }
+
+    let last_export = buffer.last_export().map(|status| LastExport {
+        success: status.error.is_none(),
+        error: status.error,
+        unix_ms: status
+            .at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64),
+    });
+    let status = match &last_export {
+        Some(export) if !export.success => "degraded",
+        _ => "ok",
+    };
+    let body = Health {
+        status: status.to_owned(),
+        last_export,
+    };
+    json(StatusCode::OK, body.serialize_json())
+}
+
+fn debug_buffer(buffer: &OutboundBuffer) -> Response<Full<Bytes>> {
+    let stats = buffer.stats();
+    let filtered = |signal| buffer.metrics().filtered(signal);
+    let body = BufferView {
+        total_bytes: stats.traces.bytes + stats.metrics.bytes + stats.logs.bytes,
+        traces: SignalView::new(stats.traces, filtered(Signal::Traces)),
+        metrics: SignalView::new(stats.metrics, filtered(Signal::Metrics)),
+        logs: SignalView::new(stats.logs, filtered(Signal::Logs)),
+        max_bytes: stats.max_bytes,
+        flush_in_flight: stats.flush_in_flight,
+    };
+    json(StatusCode::OK, body.serialize_json())
+}
+
+async fn flush(control_tx: &mpsc::Sender<ControlRequest>) -> Response<Full<Bytes>> {
+    match request_flush(control_tx).await {
+        Some(outcome) => flush_response(outcome),
+        None => json(StatusCode::SERVICE_UNAVAILABLE, String::new()),
+    }
+}
+
+/// Ask the event loop to flush and wait for the export to finish.
+///
+/// Returns `None` if the event loop is gone (shutdown).
+pub async fn request_flush(control_tx: &mpsc::Sender<ControlRequest>) -> Option<FlushOutcome> {
+    let (reply, outcome) = oneshot::channel();
+    control_tx
+        .send(ControlRequest::Flush { reply })
+        .await
+        .ok()?;
+    outcome.await.ok()
+}
+
+/// Value of [`FLUSH_HEADER`] and of the `/flush` response `outcome` field.
+pub fn outcome_name(outcome: Option<&FlushOutcome>) -> &'static str {
+    match outcome {
+        Some(FlushOutcome::Empty) => "empty",
+        Some(FlushOutcome::Exported) => "exported",
+        Some(FlushOutcome::Held) => "held",
+        Some(FlushOutcome::Failed(_)) => "failed",
+        None => "unavailable",
+    }
+}
+
+pub fn flush_response(outcome: FlushOutcome) -> Response<Full<Bytes>> {
+    let name = outcome_name(Some(&outcome)).to_owned();
+    let (status, error) = match outcome {
+        FlushOutcome::Empty | FlushOutcome::Exported | FlushOutcome::Held => (StatusCode::OK, None),
+        FlushOutcome::Failed(error) => (StatusCode::BAD_GATEWAY, Some(error)),
+    };
+    let body = FlushView {
+        outcome: name,
+        error,
+    };
+    json(status, body.serialize_json())
+}
+
+fn json(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    let mut builder = Response::builder().status(status);
+    if !body.is_empty() {
+        builder = builder.header("Content-Type", "application/json");
+    }
+    builder.body(Full::new(Bytes::from(body))).unwrap()
+}
+
+#[cfg(test)]
+#[path = "control_tests.rs"]
+mod tests;
@@ -0,0 +1,128 @@
+use http_body_util::BodyExt;
+use hyper::{Method, StatusCode};
+
+use super::*;
+use crate::buffers::Signal;
+use crate::processor::Pipeline;
+use crate::testing::{FailingExporter, MockExporter};
+
+fn buffer() -> OutboundBuffer {
+    let (tx, _) = mpsc::channel(1);
+    OutboundBuffer::new(Some(1_000), Pipeline::default(), tx)
+}
+
+async fn body(resp: Response<Full<Bytes>>) -> String {
+    let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn health_is_ok_before_any_export() {
+    let (control_tx, _control_rx) = mpsc::channel(1);
+    let resp = handle(&Method::GET, "/health", &buffer(), &control_tx).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = body(resp).await;
+    assert!(body.contains(r#""status":"ok""#), "{body}");
+    // Absent optional fields are omitted rather than serialized as null.
+    assert!(!body.contains("last_export"), "{body}");
+}
+
+#[tokio::test]
+async fn health_reports_failed_export_as_degraded() {
+    let (control_tx, _control_rx) = mpsc::channel(1);
+    let buffer = buffer();
+    buffer.push(Signal::Traces, Bytes::from("data"));
+    buffer.flush(&FailingExporter).await;
+
+    let resp = handle(&Method::GET, "/health", &buffer, &control_tx).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = body(resp).await;
+    assert!(body.contains(r#""status":"degraded""#), "{body}");
+    assert!(body.contains(r#""success":false"#), "{body}");
+}
+
+#[tokio::test]
+async fn health_unavailable_once_event_loop_is_gone() {
+    let (control_tx, control_rx) = mpsc::channel(1);
+    drop(control_rx);
+    let resp = handle(&Method::GET, "/health", &buffer(), &control_tx).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn debug_buffer_reports_per_signal_sizes() {
+    let (control_tx, _control_rx) = mpsc::channel(1);
+    let buffer = buffer();
+    buffer.push(Signal::Traces, Bytes::from("abc"));
+    buffer.push(Signal::Traces, Bytes::from("de"));
+    buffer.push(Signal::Logs, Bytes::from("f"));
+
+    let resp = handle(&Method::GET, "/debug/buffer", &buffer, &control_tx).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = body(resp).await;
+    assert!(
+        body.contains(r#""traces":{"payloads":2,"bytes":5,"processed":0,"filtered":0}"#),
+        "{body}"
+    );
+    assert!(body.contains(r#""total_bytes":6"#), "{body}");
+    assert!(body.contains(r#""max_bytes":1000"#), "{body}");
+}
+
+#[tokio::test]
+async fn flush_goes_through_control_channel() {
+    let (control_tx, mut control_rx) = mpsc::channel(1);
+    let buffer = buffer();
+    buffer.push(Signal::Metrics, Bytes::from("data"));
+
+    let loop_buffer = buffer.clone();
+    let event_loop = tokio::spawn(async move {
+        let Some(ControlRequest::Flush { reply }) = control_rx.recv().await else {
+            panic!("expected flush request");
+        };
+        let _ = reply.send(loop_buffer.flush(&MockExporter).await);
+    });
+
+    let resp = handle(&Method::POST, "/flush", &buffer, &control_tx).await;
+    event_loop.await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body(resp).await.contains(r#""outcome":"exported""#));
+    assert!(buffer.take().is_empty());
+}
+
+#[test]
+fn failed_flush_maps_to_bad_gateway() {
+    let resp = flush_response(FlushOutcome::Failed("boom".to_owned()));
+    assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test]
+async fn held_spans_are_reported_with_ok() {
+    let resp = flush_response(FlushOutcome::Held);
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body(resp).await.contains(r#""outcome":"held""#));
+}
+
+#[tokio::test]
+async fn flush_unavailable_once_event_loop_is_gone() {
+    let (control_tx, control_rx) = mpsc::channel(1);
+    drop(control_rx);
+    let resp = handle(&Method::POST, "/flush", &buffer(), &control_tx).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn wrong_method_is_rejected() {
+    let (control_tx, _control_rx) = mpsc::channel(1);
+    for (method, path) in [
+        (Method::POST, "/health"),
+        (Method::DELETE, "/debug/buffer"),
+        (Method::GET, "/flush"),
+    ] {
+        let resp = handle(&method, path, &buffer(), &control_tx).await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::METHOD_NOT_ALLOWED,
+            "{method} {path}"
+        );
+    }
+}
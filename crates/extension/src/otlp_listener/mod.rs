@@ -2,6 +2,7 @@ use std::convert::Infallible;
 
 use bytes::Bytes;
 use http_body_util::{BodyExt, Full};
+use hyper::header::HeaderValue;
 use hyper::service::service_fn;
 use hyper::{Method, Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
@@ -10,7 +11,20 @@ use tokio::net::TcpListener;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
-use crate::buffers::{EnabledSignals, Signal};
+use crate::buffers::{EnabledSignals, OutboundBuffer, Signal};
+
+pub mod control;
+
+pub use control::ControlRequest;
+
+/// Everything a connection handler needs, cloned per connection.
+#[derive(Clone)]
+pub struct ListenerState {
+    pub tx: mpsc::Sender<(Signal, Bytes)>,
+    pub enabled: EnabledSignals,
+    pub buffer: OutboundBuffer,
+    pub control_tx: mpsc::Sender<ControlRequest>,
+}
 
 fn response(status: StatusCode) -> Response<Full<Bytes>> {
     Response::builder()
@@ -19,6 +33,24 @@ fn response(status: StatusCode) -> Response<Full<Bytes>> {
         .unwrap()
 }
 
+fn path_signal(path: &str) -> Option<Signal> {
+    match path {
+        "/v1/traces" => Some(Signal::Traces),
+        "/v1/metrics" => Some(Signal::Metrics),
+        "/v1/logs" => Some(Signal::Logs),
+        _ => None,
+    }
+}
+
+/// 503 asking the SDK to retry shortly.
+fn retry_later() -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header("Retry-After", "1")
+        .body(Full::default())
+        .unwrap()
+}
+
 /// Validate the incoming request: route, method, enabled signals, and body.
 async fn validate<B>(
     req: Request<B>,
@@ -30,26 +62,22 @@ where
     let path = req.uri().path().to_owned();
     let method = req.method().clone();
 
-    let signal = match path.as_str() {
-        "/v1/traces" => Ok(Signal::Traces),
-        "/v1/metrics" => Ok(Signal::Metrics),
-        "/v1/logs" => Ok(Signal::Logs),
-        _ => Err((StatusCode::NOT_FOUND, format!("unknown path: {path}"))),
-    }
-    .and_then(|signal| {
-        if method == Method::POST {
-            Ok(signal)
-        } else {
-            Err((StatusCode::METHOD_NOT_ALLOWED, format!("{method} {path}")))
-        }
-    })
-    .and_then(|signal| {
-        if enabled.is_enabled(signal) {
-            Ok(signal)
-        } else {
-            Err((StatusCode::NOT_FOUND, format!("disabled signal: {path}")))
-        }
-    })?;
+    let signal = path_signal(&path)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("unknown path: {path}")))
+        .and_then(|signal| {
+            if method == Method::POST {
+                Ok(signal)
+            } else {
+                Err((StatusCode::METHOD_NOT_ALLOWED, format!("{method} {path}")))
+            }
+        })
+        .and_then(|signal| {
+            if enabled.is_enabled(signal) {
+                Ok(signal)
+            } else {
+                Err((StatusCode::NOT_FOUND, format!("disabled signal: {path}")))
+            }
+        })?;
 
     let body = req.collect().await.map(|c| c.to_bytes()).map_err(|_| {
         (
@@ -61,10 +89,13 @@ where
     Ok((signal, body))
 }
 
+/// Reserve room for the payload in `buffer`, then hand it to the event loop.
+/// The event loop releases the reservation once the payload is buffered.
 async fn handle<B>(
     req: Request<B>,
     tx: mpsc::Sender<(Signal, Bytes)>,
     enabled: EnabledSignals,
+    buffer: &OutboundBuffer,
 ) -> Result<Response<Full<Bytes>>, Infallible>
 where
     B: hyper::body::Body<Data = Bytes> + Send + 'static,
@@ -78,35 +109,86 @@ where
     };
 
     use tokio::sync::mpsc::error::TrySendError;
+    let metrics = buffer.metrics();
+    let len = body.len();
+    if !buffer.reserve(signal, len) {
+        tracing::warn!(signal = signal.name(), "signal quota exhausted");
+        metrics.record_rejected(signal);
+        return Ok(retry_later());
+    }
     match tx.try_send((signal, body)) {
-        Ok(()) => Ok(response(StatusCode::OK)),
-        Err(TrySendError::Full(_)) => Ok(Response::builder()
-            .status(StatusCode::SERVICE_UNAVAILABLE)
-            .header("Retry-After", "1")
-            .body(Full::default())
-            .unwrap()),
+        Ok(()) => {
+            metrics.record_received(signal, len);
+            Ok(response(StatusCode::OK))
+        }
+        Err(TrySendError::Full(_)) => {
+            buffer.release(signal, len);
+            metrics.record_rejected(signal);
+            Ok(retry_later())
+        }
         // Channel closed means the receiver is gone (shutdown). Retrying won't
         // help — return 502 since this proxy's backend is no longer available.
-        Err(TrySendError::Closed(_)) => Ok(response(StatusCode::BAD_GATEWAY)),
+        Err(TrySendError::Closed(_)) => {
+            buffer.release(signal, len);
+            Ok(response(StatusCode::BAD_GATEWAY))
+        }
     }
 }
 
-pub async fn serve(
-    listener: TcpListener,
-    tx: mpsc::Sender<(Signal, Bytes)>,
-    cancel: CancellationToken,
-    enabled: EnabledSignals,
-) {
+/// Send control endpoints to [`control::handle`] and everything else to the
+/// OTLP handler.
+async fn route<B>(
+    req: Request<B>,
+    state: ListenerState,
+) -> Result<Response<Full<Bytes>>, Infallible>
+where
+    B: hyper::body::Body<Data = Bytes> + Send + 'static,
+{
+    let path = req.uri().path();
+    if control::is_control_path(path) {
+        let resp = control::handle(req.method(), path, &state.buffer, &state.control_tx).await;
+        return Ok(resp);
+    }
+    // Under the reject eviction policy, turn the payload away before reading
+    // it once the signal is full. `handle` checks again when it reserves room
+    // for the payload, so nothing accepted ever has to be evicted.
+    if let Some(signal) = path_signal(path)
+        && req.method() == Method::POST
+        && state.buffer.quota_exhausted(signal)
+    {
+        tracing::warn!(signal = signal.name(), "signal quota exhausted");
+        state.buffer.metrics().record_rejected(signal);
+        return Ok(retry_later());
+    }
+
+    let sync_flush = req
+        .headers()
+        .get(control::FLUSH_HEADER)
+        .is_some_and(|value| value == "sync");
+    let mut resp = handle(req, state.tx, state.enabled, &state.buffer).await?;
+
+    // The payload is already in the channel, so the event loop picks it up
+    // before flushing. Export failures are reported in the header rather than
+    // the status: the data was accepted and is retained for the next flush,
+    // so the SDK must not retry it.
+    if sync_flush && resp.status() == StatusCode::OK {
+        let outcome = control::request_flush(&state.control_tx).await;
+        resp.headers_mut().insert(
+            control::FLUSH_HEADER,
+            HeaderValue::from_static(control::outcome_name(outcome.as_ref())),
+        );
+    }
+    Ok(resp)
+}
+
+pub async fn serve(listener: TcpListener, state: ListenerState, cancel: CancellationToken) {
     loop {
         tokio::select! {
             result = listener.accept() => {
                 let (stream, _) = result.expect("failed to accept connection");
-                let tx = tx.clone();
+                let state = state.clone();
                 tokio::spawn(async move {
-                    let service = service_fn(move |req| {
-                        let tx = tx.clone();
-                        handle(req, tx, enabled)
-                    });
+                    let service = service_fn(move |req| route(req, state.clone()));
                     let _ = Builder::new(hyper_util::rt::TokioExecutor::new())
                         .serve_connection(TokioIo::new(stream), service)
                         .await;
@@ -13,6 +13,11 @@ fn post(path: &str, body: &[u8]) -> Request<Full<Bytes>> {
         .unwrap()
 }
 
+fn buffer() -> OutboundBuffer {
+    let (notify, _) = mpsc::channel(1);
+    OutboundBuffer::new(None, crate::processor::Pipeline::default(), notify)
+}
+
 fn get(path: &str) -> Request<Full<Bytes>> {
     Request::builder()
         .method(Method::GET)
@@ -28,6 +33,7 @@ async fn routes_traces_payload_to_channel() {
         post("/v1/traces", b"trace-payload"),
         tx,
         EnabledSignals::all(),
+        &buffer(),
     )
     .await
     .unwrap();
@@ -44,6 +50,7 @@ async fn routes_metrics_payload_to_channel() {
         post("/v1/metrics", b"metric-payload"),
         tx,
         EnabledSignals::all(),
+        &buffer(),
     )
     .await
     .unwrap();
@@ -56,9 +63,14 @@ async fn routes_metrics_payload_to_channel() {
 #[tokio::test]
 async fn routes_logs_payload_to_channel() {
     let (tx, mut rx) = mpsc::channel(8);
-    let resp = handle(post("/v1/logs", b"log-payload"), tx, EnabledSignals::all())
-        .await
-        .unwrap();
+    let resp = handle(
+        post("/v1/logs", b"log-payload"),
+        tx,
+        EnabledSignals::all(),
+        &buffer(),
+    )
+    .await
+    .unwrap();
     assert_eq!(resp.status(), StatusCode::OK);
     let (signal, body) = rx.recv().await.unwrap();
     assert!(matches!(signal, Signal::Logs));
@@ -68,16 +80,21 @@ async fn routes_logs_payload_to_channel() {
 #[tokio::test]
 async fn rejects_unknown_path_with_404() {
     let (tx, _rx) = mpsc::channel(8);
-    let resp = handle(post("/v1/unknown", b""), tx, EnabledSignals::all())
-        .await
-        .unwrap();
+    let resp = handle(
+        post("/v1/unknown", b""),
+        tx,
+        EnabledSignals::all(),
+        &buffer(),
+    )
+    .await
+    .unwrap();
     assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 }
 
 #[tokio::test]
 async fn rejects_non_post_with_405() {
     let (tx, _rx) = mpsc::channel(8);
-    let resp = handle(get("/v1/traces"), tx, EnabledSignals::all())
+    let resp = handle(get("/v1/traces"), tx, EnabledSignals::all(), &buffer())
         .await
         .unwrap();
     assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
@@ -87,20 +104,58 @@ async fn rejects_non_post_with_405() {
 async fn returns_503_with_retry_when_channel_full() {
     let (tx, _rx) = mpsc::channel(1);
     tx.try_send((Signal::Traces, Bytes::new())).unwrap();
-    let resp = handle(post("/v1/traces", b"overflow"), tx, EnabledSignals::all())
-        .await
-        .unwrap();
+    let resp = handle(
+        post("/v1/traces", b"overflow"),
+        tx,
+        EnabledSignals::all(),
+        &buffer(),
+    )
+    .await
+    .unwrap();
     assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
     assert_eq!(resp.headers()["Retry-After"], "1");
 }
 
+#[tokio::test]
+async fn records_received_and_rejected_payloads() {
+    let buffer = buffer();
+    let (tx, _rx) = mpsc::channel(1);
+
+    let resp = handle(
+        post("/v1/logs", b"12345"),
+        tx.clone(),
+        EnabledSignals::all(),
+        &buffer,
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = handle(
+        post("/v1/logs", b"overflow"),
+        tx,
+        EnabledSignals::all(),
+        &buffer,
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    assert_eq!(buffer.metrics().received(Signal::Logs), (1, 5));
+    assert_eq!(buffer.metrics().rejected(Signal::Logs), 1);
+}
+
 #[tokio::test]
 async fn returns_502_when_channel_closed() {
     let (tx, rx) = mpsc::channel(8);
     drop(rx);
-    let resp = handle(post("/v1/traces", b"orphan"), tx, EnabledSignals::all())
-        .await
-        .unwrap();
+    let resp = handle(
+        post("/v1/traces", b"orphan"),
+        tx,
+        EnabledSignals::all(),
+        &buffer(),
+    )
+    .await
+    .unwrap();
     assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
     assert!(resp.headers().get("Retry-After").is_none());
 }
@@ -128,7 +183,9 @@ async fn returns_400_when_body_read_fails() {
         .uri("/v1/traces")
         .body(FailBody)
         .unwrap();
-    let resp = handle(req, tx, EnabledSignals::all()).await.unwrap();
+    let resp = handle(req, tx, EnabledSignals::all(), &buffer())
+        .await
+        .unwrap();
     assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
 }
 
@@ -137,12 +194,17 @@ async fn disabled_signal_returns_404() {
     let traces_only = EnabledSignals::from_signals([Signal::Traces].into_iter());
     let (tx, _rx) = mpsc::channel(8);
 
-    let resp = handle(post("/v1/metrics", b"payload"), tx.clone(), traces_only)
-        .await
-        .unwrap();
+    let resp = handle(
+        post("/v1/metrics", b"payload"),
+        tx.clone(),
+        traces_only,
+        &buffer(),
+    )
+    .await
+    .unwrap();
     assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 
-    let resp = handle(post("/v1/logs", b"payload"), tx, traces_only)
+    let resp = handle(post("/v1/logs", b"payload"), tx, traces_only, &buffer())
         .await
         .unwrap();
     assert_eq!(resp.status(), StatusCode::NOT_FOUND);
@@ -153,11 +215,132 @@ async fn enabled_signal_routes_normally_when_others_disabled() {
     let traces_only = EnabledSignals::from_signals([Signal::Traces].into_iter());
     let (tx, mut rx) = mpsc::channel(8);
 
-    let resp = handle(post("/v1/traces", b"trace-data"), tx, traces_only)
-        .await
-        .unwrap();
+    let resp = handle(
+        post("/v1/traces", b"trace-data"),
+        tx,
+        traces_only,
+        &buffer(),
+    )
+    .await
+    .unwrap();
     assert_eq!(resp.status(), StatusCode::OK);
     let (signal, body) = rx.recv().await.unwrap();
     assert!(matches!(signal, Signal::Traces));
     assert_eq!(body.as_ref(), b"trace-data");
 }
+
+#[tokio::test]
+async fn sync_flush_header_reports_unavailable_event_loop() {
+    let (tx, mut rx) = mpsc::channel(8);
+    let (notify, _) = mpsc::channel(1);
+    let (control_tx, control_rx) = mpsc::channel(1);
+    drop(control_rx);
+    let state = ListenerState {
+        tx,
+        enabled: EnabledSignals::all(),
+        buffer: OutboundBuffer::new(None, crate::processor::Pipeline::default(), notify),
+        control_tx,
+    };
+    let mut req = post("/v1/logs", b"log-payload");
+    req.headers_mut()
+        .insert(control::FLUSH_HEADER, HeaderValue::from_static("sync"));
+
+    let resp = route(req, state).await.unwrap();
+
+    // The payload was still accepted.
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()[control::FLUSH_HEADER], "unavailable");
+    assert!(rx.recv().await.is_some());
+}
+
+#[tokio::test]
+async fn exhausted_quota_rejects_with_retry_after() {
+    let (tx, mut rx) = mpsc::channel(8);
+    let (notify, _) = mpsc::channel(1);
+    let (control_tx, _control_rx) = mpsc::channel(1);
+    let buffer = OutboundBuffer::new(None, crate::processor::Pipeline::default(), notify)
+        .with_quotas([Some(4), None, None], crate::config::EvictionPolicy::Reject);
+    buffer.push(Signal::Traces, Bytes::from("full"));
+    let state = ListenerState {
+        tx,
+        enabled: EnabledSignals::all(),
+        buffer: buffer.clone(),
+        control_tx,
+    };
+
+    let resp = route(post("/v1/traces", b"more"), state.clone())
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(resp.headers()["Retry-After"], "1");
+    assert_eq!(buffer.metrics().rejected(Signal::Traces), 1);
+
+    // Other signals are unaffected.
+    let resp = route(post("/v1/logs", b"log"), state).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(matches!(rx.recv().await, Some((Signal::Logs, _))));
+}
+
+/// Fails every export, once released.
+struct GatedFailingExporter(tokio::sync::Notify);
+
+impl crate::exporter::Exporter for GatedFailingExporter {
+    async fn export(
+        &self,
+        _data: &mut crate::buffers::BufferData,
+    ) -> Result<(), crate::exporter::ExportError> {
+        self.0.notified().await;
+        Err(crate::exporter::ExportError::Rejected {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+        })
+    }
+}
+
+#[tokio::test]
+async fn reject_policy_keeps_every_acknowledged_payload() {
+    let (tx, mut rx) = mpsc::channel(128);
+    let (notify, _notify_rx) = mpsc::channel(1);
+    let (control_tx, _control_rx) = mpsc::channel(1);
+    let buffer = OutboundBuffer::new(None, crate::processor::Pipeline::default(), notify)
+        .with_quotas(
+            [Some(64), None, None],
+            crate::config::EvictionPolicy::Reject,
+        );
+    let state = ListenerState {
+        tx,
+        enabled: EnabledSignals::all(),
+        buffer: buffer.clone(),
+        control_tx,
+    };
+    let exporter = std::sync::Arc::new(GatedFailingExporter(tokio::sync::Notify::new()));
+    buffer.push(Signal::Traces, Bytes::from(vec![0; 32]));
+    assert!(buffer.spawn_flush(&exporter));
+
+    // The flush is in flight and the event loop has not drained the channel.
+    let mut accepted = Vec::new();
+    for i in 1..=16 {
+        let body = [i; 8];
+        let resp = route(post("/v1/traces", &body), state.clone())
+            .await
+            .unwrap();
+        match resp.status() {
+            StatusCode::OK => accepted.push(Bytes::copy_from_slice(&body)),
+            status => assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE),
+        }
+    }
+    assert_eq!(accepted.len(), 4);
+
+    // The event loop buffers the channel, then the flush fails.
+    while let Ok((signal, payload)) = rx.try_recv() {
+        let len = payload.len();
+        buffer.push(signal, payload);
+        buffer.release(signal, len);
+    }
+    exporter.0.notify_one();
+    buffer.join_flush_task().await;
+
+    let data = buffer.take();
+    assert_eq!(data.traces.queue.len(), 1 + accepted.len());
+    assert!(accepted.iter().all(|p| data.traces.queue.contains(p)));
+    assert_eq!(buffer.metrics().evicted(Signal::Traces), 0);
+}
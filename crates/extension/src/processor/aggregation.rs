@@ -0,0 +1,388 @@
+//! Metric aggregation within a flush.
+//!
+//! `merge::merge_metrics` only concatenates scopes, so a function exporting
+//! once per invocation hands the collector one data point per stream and
+//! invocation. This stage merges scopes with the same identity, metrics with
+//! the same name, unit and shape, and then data points with the same
+//! attributes:
+//!
+//! - delta sums are added up;
+//! - delta histograms are merged bucket by bucket, as long as their bounds
+//!   (or, for exponential histograms, scale and zero threshold) match.
+//!   Exponential histograms are downscaled when the merged buckets would
+//!   span more than [`MAX_EXPONENTIAL_BUCKETS`];
+//! - gauges, cumulative points and summaries keep the latest point.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use super::temporality::{add, min_max};
+use super::{attributes_key, has_no_value, scope_key};
+use crate::proto::opentelemetry::proto::{
+    collector::metrics::v1::ExportMetricsServiceRequest,
+    common::v1::KeyValue,
+    metrics::v1::{
+        AggregationTemporality, ExponentialHistogramDataPoint, HistogramDataPoint, Metric,
+        NumberDataPoint, ScopeMetrics, SummaryDataPoint, exponential_histogram_data_point::Buckets,
+        metric::Data,
+    },
+};
+
+/// Most buckets one side of a merged exponential histogram may span, the
+/// default maximum size of the OpenTelemetry SDKs' exponential histograms.
+const MAX_EXPONENTIAL_BUCKETS: usize = 160;
+
+/// Aggregate the data points of every stream in `req`.
+pub fn aggregate(req: &mut ExportMetricsServiceRequest) {
+    for rm in &mut req.resource_metrics {
+        let scopes = group(std::mem::take(&mut rm.scope_metrics), |sm| {
+            let mut key = scope_key(sm.scope.as_ref());
+            key.extend_from_slice(sm.schema_url.as_bytes());
+            key
+        });
+        rm.scope_metrics = scopes
+            .into_iter()
+            .map(|mut group| {
+                let mut first = group.remove(0);
+                first
+                    .metrics
+                    .extend(group.into_iter().flat_map(|sm| sm.metrics));
+                aggregate_scope(&mut first);
+                first
+            })
+            .collect();
+    }
+}
+
+fn aggregate_scope(sm: &mut ScopeMetrics) {
+    let metrics = group(std::mem::take(&mut sm.metrics), metric_key);
+    sm.metrics = metrics
+        .into_iter()
+        .map(|group| {
+            let mut group = group.into_iter();
+            // `group` never returns an empty group.
+            let mut first = group.next().unwrap_or_default();
+            for metric in group {
+                append_points(&mut first.data, metric.data);
+            }
+            aggregate_points(&mut first.data);
+            first
+        })
+        .collect();
+}
+
+/// Metrics are only merged with metrics of the same name, unit, type,
+/// temporality and monotonicity.
+fn metric_key(metric: &Metric) -> Vec<u8> {
+    let (kind, temporality, monotonic) = match &metric.data {
+        None => (0, 0, false),
+        Some(Data::Gauge(_)) => (1, 0, false),
+        Some(Data::Sum(sum)) => (2, sum.aggregation_temporality, sum.is_monotonic),
+        Some(Data::Histogram(h)) => (3, h.aggregation_temporality, false),
+        Some(Data::ExponentialHistogram(h)) => (4, h.aggregation_temporality, false),
+        Some(Data::Summary(_)) => (5, 0, false),
+    };
+    let mut key = vec![kind, monotonic as u8];
+    key.extend_from_slice(&temporality.to_le_bytes());
+    for part in [&metric.name, &metric.unit] {
+        prost::encoding::encode_varint(part.len() as u64, &mut key);
+        key.extend_from_slice(part.as_bytes());
+    }
+    key
+}
+
+/// Move the data points of `other` into `data`, which has the same shape.
+pub(super) fn append_points(data: &mut Option<Data>, other: Option<Data>) {
+    match (data, other) {
+        (Some(Data::Gauge(a)), Some(Data::Gauge(b))) => a.data_points.extend(b.data_points),
+        (Some(Data::Sum(a)), Some(Data::Sum(b))) => a.data_points.extend(b.data_points),
+        (Some(Data::Histogram(a)), Some(Data::Histogram(b))) => a.data_points.extend(b.data_points),
+        (Some(Data::ExponentialHistogram(a)), Some(Data::ExponentialHistogram(b))) => {
+            a.data_points.extend(b.data_points)
+        }
+        (Some(Data::Summary(a)), Some(Data::Summary(b))) => a.data_points.extend(b.data_points),
+        _ => {}
+    }
+}
+
+/// Combine the data points of one metric.
+pub(super) fn aggregate_points(data: &mut Option<Data>) {
+    let delta = AggregationTemporality::Delta as i32;
+    match data {
+        Some(Data::Gauge(gauge)) => reduce(&mut gauge.data_points, number_key, latest),
+        Some(Data::Sum(sum)) if sum.aggregation_temporality == delta => {
+            reduce(&mut sum.data_points, number_key, add_sum)
+        }
+        Some(Data::Sum(sum)) => reduce(&mut sum.data_points, number_key, latest),
+        Some(Data::Histogram(h)) if h.aggregation_temporality == delta => {
+            reduce(&mut h.data_points, histogram_key, add_histogram)
+        }
+        Some(Data::Histogram(h)) => reduce(&mut h.data_points, |_| Vec::new(), latest),
+        Some(Data::ExponentialHistogram(h)) if h.aggregation_temporality == delta => {
+            reduce(&mut h.data_points, exponential_key, add_exponential)
+        }
+        Some(Data::ExponentialHistogram(h)) => reduce(&mut h.data_points, |_| Vec::new(), latest),
+        Some(Data::Summary(summary)) => reduce(&mut summary.data_points, |_| Vec::new(), latest),
+        None => {}
+    }
+}
+
+/// Split `items` into groups with the same key, in order of first
+/// appearance.
+fn group<T>(items: Vec<T>, key: impl Fn(&T) -> Vec<u8>) -> Vec<Vec<T>> {
+    let mut groups: Vec<Vec<T>> = Vec::new();
+    let mut index: HashMap<Vec<u8>, usize> = HashMap::new();
+    for item in items {
+        match index.entry(key(&item)) {
+            Entry::Occupied(e) => groups[*e.get()].push(item),
+            Entry::Vacant(e) => {
+                e.insert(groups.len());
+                groups.push(vec![item]);
+            }
+        }
+    }
+    groups
+}
+
+/// Add up the data points of one metric whatever their temporality, for
+/// cumulative points of distinct streams that are reported as one.
+pub(super) fn add_points(data: &mut Option<Data>) {
+    match data {
+        Some(Data::Sum(sum)) => reduce(&mut sum.data_points, number_key, add_sum),
+        Some(Data::Histogram(h)) => reduce(&mut h.data_points, histogram_key, add_histogram),
+        Some(Data::ExponentialHistogram(h)) => {
+            reduce(&mut h.data_points, exponential_key, add_exponential)
+        }
+        _ => aggregate_points(data),
+    }
+}
+
+/// Combine points with the same attributes and extra `key` with `combine`.
+fn reduce<P: Point>(points: &mut Vec<P>, key: fn(&P) -> Vec<u8>, combine: fn(&mut P, P)) {
+    if points.len() < 2 {
+        return;
+    }
+    let groups = group(std::mem::take(points), |p| {
+        let mut k = attributes_key(p.attributes());
+        k.extend(key(p));
+        k
+    });
+    *points = groups
+        .into_iter()
+        .filter_map(|group| {
+            let mut group = group.into_iter();
+            let first = group.next()?;
+            Some(group.fold(first, |mut acc, p| {
+                combine(&mut acc, p);
+                acc
+            }))
+        })
+        .collect();
+}
+
+/// What every aggregated data point type has in common.
+trait Point {
+    fn times(&self) -> (u64, u64);
+    fn flags(&self) -> u32;
+    fn attributes(&self) -> &[KeyValue];
+}
+
+macro_rules! impl_point {
+    ($($ty:ty),*) => {$(
+        impl Point for $ty {
+            fn times(&self) -> (u64, u64) {
+                (self.start_time_unix_nano, self.time_unix_nano)
+            }
+            fn flags(&self) -> u32 {
+                self.flags
+            }
+            fn attributes(&self) -> &[KeyValue] {
+                &self.attributes
+            }
+        }
+    )*};
+}
+
+impl_point!(
+    NumberDataPoint,
+    HistogramDataPoint,
+    ExponentialHistogramDataPoint,
+    SummaryDataPoint
+);
+
+fn latest<P: Point>(acc: &mut P, point: P) {
+    if point.times().1 >= acc.times().1 {
+        *acc = point;
+    }
+}
+
+/// Handle points without a recorded value when adding `point` to `acc`,
+/// handing `point` back if it still has to be added.
+fn skip_missing<P: Point>(acc: &mut P, point: P) -> Result<(), P> {
+    if has_no_value(point.flags()) {
+        return Ok(());
+    }
+    if has_no_value(acc.flags()) {
+        *acc = point;
+        return Ok(());
+    }
+    Err(point)
+}
+
+fn number_key(_: &NumberDataPoint) -> Vec<u8> {
+    Vec::new()
+}
+
+/// Points merge only when both their bounds and their number of buckets
+/// match, so a malformed point never has its buckets dropped or misaligned.
+fn histogram_key(point: &HistogramDataPoint) -> Vec<u8> {
+    let mut key = (point.bucket_counts.len() as u64).to_le_bytes().to_vec();
+    key.extend(point.explicit_bounds.iter().flat_map(|b| b.to_le_bytes()));
+    key
+}
+
+fn exponential_key(point: &ExponentialHistogramDataPoint) -> Vec<u8> {
+    let mut key = point.scale.to_le_bytes().to_vec();
+    key.extend_from_slice(&point.zero_threshold.to_le_bytes());
+    key
+}
+
+fn widen_interval<P: Point>(acc: &P, point: &P) -> (u64, u64) {
+    let (start, time) = acc.times();
+    let (other_start, other_time) = point.times();
+    (start.min(other_start), time.max(other_time))
+}
+
+fn add_sum(acc: &mut NumberDataPoint, point: NumberDataPoint) {
+    let Err(point) = skip_missing(acc, point) else {
+        return;
+    };
+    (acc.start_time_unix_nano, acc.time_unix_nano) = widen_interval(acc, &point);
+    acc.value = add(&acc.value, &point.value);
+    acc.exemplars.extend(point.exemplars);
+}
+
+fn add_histogram(acc: &mut HistogramDataPoint, point: HistogramDataPoint) {
+    let Err(point) = skip_missing(acc, point) else {
+        return;
+    };
+    (acc.start_time_unix_nano, acc.time_unix_nano) = widen_interval(acc, &point);
+    acc.count += point.count;
+    acc.sum = acc.sum.zip(point.sum).map(|(a, b)| a + b);
+    for (count, other) in acc.bucket_counts.iter_mut().zip(&point.bucket_counts) {
+        *count += other;
+    }
+    acc.min = min_max(acc.min, point.min, f64::min);
+    acc.max = min_max(acc.max, point.max, f64::max);
+    acc.exemplars.extend(point.exemplars);
+}
+
+fn add_exponential(acc: &mut ExponentialHistogramDataPoint, point: ExponentialHistogramDataPoint) {
+    let Err(mut point) = skip_missing(acc, point) else {
+        return;
+    };
+    let scale = merged_scale(acc, &point);
+    downscale(acc, scale);
+    downscale(&mut point, scale);
+    (acc.start_time_unix_nano, acc.time_unix_nano) = widen_interval(acc, &point);
+    acc.count += point.count;
+    acc.sum = acc.sum.zip(point.sum).map(|(a, b)| a + b);
+    acc.zero_count += point.zero_count;
+    merge_buckets(&mut acc.positive, point.positive);
+    merge_buckets(&mut acc.negative, point.negative);
+    acc.min = min_max(acc.min, point.min, f64::min);
+    acc.max = min_max(acc.max, point.max, f64::max);
+    acc.exemplars.extend(point.exemplars);
+}
+
+/// The highest scale at or below both points' at which the merged buckets
+/// of each side span at most [`MAX_EXPONENTIAL_BUCKETS`].
+fn merged_scale(a: &ExponentialHistogramDataPoint, b: &ExponentialHistogramDataPoint) -> i32 {
+    let scale = a.scale.min(b.scale);
+    // Bucket index range of each side at `scale`.
+    let range = |sides: [&Option<Buckets>; 2], scales: [i32; 2]| {
+        sides
+            .into_iter()
+            .zip(scales)
+            .filter_map(|(buckets, from)| {
+                let buckets = buckets.as_ref().filter(|b| !b.bucket_counts.is_empty())?;
+                let shift = from - scale;
+                let first = i64::from(buckets.offset);
+                let last = first + buckets.bucket_counts.len() as i64 - 1;
+                Some((first >> shift, last >> shift))
+            })
+            .reduce(|(lo, hi), (first, last)| (lo.min(first), hi.max(last)))
+    };
+    let scales = [a.scale, b.scale];
+    let mut reduction = 0;
+    for range in [
+        range([&a.positive, &b.positive], scales),
+        range([&a.negative, &b.negative], scales),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let (lo, hi) = range;
+        while (hi >> reduction) - (lo >> reduction) >= MAX_EXPONENTIAL_BUCKETS as i64 {
+            reduction += 1;
+        }
+    }
+    scale.saturating_sub(reduction)
+}
+
+/// Lower the scale of `point` to `scale`, merging its buckets accordingly.
+fn downscale(point: &mut ExponentialHistogramDataPoint, scale: i32) {
+    let shift = i64::from(point.scale) - i64::from(scale);
+    if shift <= 0 {
+        return;
+    }
+    let shift = shift.min(63);
+    for buckets in [&mut point.positive, &mut point.negative]
+        .into_iter()
+        .flatten()
+        .filter(|b| !b.bucket_counts.is_empty())
+    {
+        let first = i64::from(buckets.offset);
+        let offset = first >> shift;
+        let last = (first + buckets.bucket_counts.len() as i64 - 1) >> shift;
+        let mut counts = vec![0; (last - offset + 1) as usize];
+        for (i, count) in buckets.bucket_counts.iter().enumerate() {
+            counts[(((first + i as i64) >> shift) - offset) as usize] += count;
+        }
+        *buckets = Buckets {
+            offset: offset as i32,
+            bucket_counts: counts,
+        };
+    }
+    point.scale = scale;
+}
+
+/// Add exponential buckets of the same scale, widening the range to cover
+/// both.
+fn merge_buckets(acc: &mut Option<Buckets>, other: Option<Buckets>) {
+    let Some(other) = other.filter(|b| !b.bucket_counts.is_empty()) else {
+        return;
+    };
+    let Some(buckets) = acc.as_mut().filter(|b| !b.bucket_counts.is_empty()) else {
+        *acc = Some(other);
+        return;
+    };
+    let end = |b: &Buckets| i64::from(b.offset) + b.bucket_counts.len() as i64;
+    let offset = buckets.offset.min(other.offset);
+    let len = (end(buckets).max(end(&other)) - i64::from(offset)) as usize;
+    let mut counts = vec![0; len];
+    for b in [&*buckets, &other] {
+        let shift = (b.offset - offset) as usize;
+        for (i, count) in b.bucket_counts.iter().enumerate() {
+            counts[shift + i] += count;
+        }
+    }
+    *buckets = Buckets {
+        offset,
+        bucket_counts: counts,
+    };
+}
+
+#[cfg(test)]
+#[path = "aggregation_tests.rs"]
+mod tests;
@@ -0,0 +1,325 @@
+use super::*;
+use crate::proto::opentelemetry::proto::{
+    common::v1::{AnyValue, InstrumentationScope, any_value},
+    metrics::v1::{
+        DataPointFlags, ExponentialHistogram, Gauge, Histogram, ResourceMetrics, Sum,
+        number_data_point::Value,
+    },
+};
+
+fn attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_owned())),
+        }),
+    }
+}
+
+fn point(time: u64, value: i64, route: &str) -> NumberDataPoint {
+    NumberDataPoint {
+        start_time_unix_nano: time - 1,
+        time_unix_nano: time,
+        value: Some(Value::AsInt(value)),
+        attributes: vec![attribute("route", route)],
+        ..Default::default()
+    }
+}
+
+fn metric(name: &str, data: Data) -> Metric {
+    Metric {
+        name: name.to_owned(),
+        data: Some(data),
+        ..Default::default()
+    }
+}
+
+fn delta_sum(points: Vec<NumberDataPoint>) -> Data {
+    Data::Sum(Sum {
+        data_points: points,
+        aggregation_temporality: AggregationTemporality::Delta as i32,
+        is_monotonic: true,
+    })
+}
+
+fn scope(name: &str, metrics: Vec<Metric>) -> ScopeMetrics {
+    ScopeMetrics {
+        scope: Some(InstrumentationScope {
+            name: name.to_owned(),
+            ..Default::default()
+        }),
+        metrics,
+        ..Default::default()
+    }
+}
+
+/// One scope per invocation, as `merge::merge_metrics` leaves them.
+fn request(scopes: Vec<ScopeMetrics>) -> ExportMetricsServiceRequest {
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            scope_metrics: scopes,
+            ..Default::default()
+        }],
+    }
+}
+
+fn only_metric(req: &ExportMetricsServiceRequest) -> &Metric {
+    let scopes = &req.resource_metrics[0].scope_metrics;
+    assert_eq!(scopes.len(), 1);
+    assert_eq!(scopes[0].metrics.len(), 1);
+    &scopes[0].metrics[0]
+}
+
+fn values(data: &Option<Data>) -> Vec<(u64, u64, i64)> {
+    let points = match data {
+        Some(Data::Sum(sum)) => &sum.data_points,
+        Some(Data::Gauge(gauge)) => &gauge.data_points,
+        _ => panic!("expected a sum or gauge"),
+    };
+    points
+        .iter()
+        .map(|p| {
+            let Some(Value::AsInt(v)) = p.value else {
+                panic!("expected an int value");
+            };
+            (p.start_time_unix_nano, p.time_unix_nano, v)
+        })
+        .collect()
+}
+
+#[test]
+fn delta_sums_are_added_per_stream() {
+    let mut req = request(vec![
+        scope(
+            "app",
+            vec![metric("requests", delta_sum(vec![point(10, 1, "/a")]))],
+        ),
+        scope(
+            "app",
+            vec![metric(
+                "requests",
+                delta_sum(vec![point(20, 2, "/a"), point(20, 5, "/b")]),
+            )],
+        ),
+        scope(
+            "app",
+            vec![metric("requests", delta_sum(vec![point(30, 3, "/a")]))],
+        ),
+    ]);
+
+    aggregate(&mut req);
+
+    assert_eq!(values(&only_metric(&req).data), [(9, 30, 6), (19, 20, 5)]);
+}
+
+#[test]
+fn gauges_and_cumulative_sums_keep_the_latest_point() {
+    let gauge = |time, value| {
+        metric(
+            "queue.depth",
+            Data::Gauge(Gauge {
+                data_points: vec![point(time, value, "/a")],
+            }),
+        )
+    };
+    let mut req = request(vec![
+        scope("app", vec![gauge(20, 7)]),
+        scope("app", vec![gauge(10, 3)]),
+    ]);
+    aggregate(&mut req);
+    assert_eq!(values(&only_metric(&req).data), [(19, 20, 7)]);
+
+    let cumulative = |time, value| {
+        metric(
+            "requests",
+            Data::Sum(Sum {
+                data_points: vec![point(time, value, "/a")],
+                aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                is_monotonic: true,
+            }),
+        )
+    };
+    let mut req = request(vec![
+        scope("app", vec![cumulative(10, 3)]),
+        scope("app", vec![cumulative(20, 8)]),
+    ]);
+    aggregate(&mut req);
+    assert_eq!(values(&only_metric(&req).data), [(19, 20, 8)]);
+}
+
+#[test]
+fn different_scopes_and_shapes_stay_apart() {
+    let cumulative = metric(
+        "requests",
+        Data::Sum(Sum {
+            data_points: vec![point(10, 1, "/a")],
+            aggregation_temporality: AggregationTemporality::Cumulative as i32,
+            is_monotonic: true,
+        }),
+    );
+    let mut req = request(vec![
+        scope(
+            "app",
+            vec![metric("requests", delta_sum(vec![point(10, 1, "/a")]))],
+        ),
+        scope("app", vec![cumulative]),
+        scope(
+            "lib",
+            vec![metric("requests", delta_sum(vec![point(10, 1, "/a")]))],
+        ),
+    ]);
+
+    aggregate(&mut req);
+
+    let scopes = &req.resource_metrics[0].scope_metrics;
+    assert_eq!(scopes.len(), 2);
+    assert_eq!(scopes[0].metrics.len(), 2);
+    assert_eq!(scopes[1].metrics.len(), 1);
+}
+
+#[test]
+fn points_without_a_value_do_not_add_up() {
+    let mut missing = point(20, 0, "/a");
+    missing.flags = DataPointFlags::NoRecordedValueMask as u32;
+    let mut req = request(vec![scope(
+        "app",
+        vec![metric(
+            "requests",
+            delta_sum(vec![missing, point(10, 1, "/a"), point(30, 2, "/a")]),
+        )],
+    )]);
+
+    aggregate(&mut req);
+
+    assert_eq!(values(&only_metric(&req).data), [(9, 30, 3)]);
+}
+
+#[test]
+fn delta_histograms_merge_when_bounds_match() {
+    let histogram = |time, buckets: Vec<u64>, bounds: Vec<f64>| HistogramDataPoint {
+        start_time_unix_nano: time - 1,
+        time_unix_nano: time,
+        count: buckets.iter().sum(),
+        sum: Some(1.0),
+        bucket_counts: buckets,
+        explicit_bounds: bounds,
+        min: Some(time as f64),
+        max: Some(time as f64),
+        ..Default::default()
+    };
+    let mut req = request(vec![scope(
+        "app",
+        vec![metric(
+            "latency",
+            Data::Histogram(Histogram {
+                data_points: vec![
+                    histogram(10, vec![1, 0], vec![1.0]),
+                    histogram(20, vec![2, 3], vec![1.0]),
+                    histogram(30, vec![1, 1, 1], vec![1.0, 2.0]),
+                    histogram(40, vec![4], vec![1.0]),
+                ],
+                aggregation_temporality: AggregationTemporality::Delta as i32,
+            }),
+        )],
+    )]);
+
+    aggregate(&mut req);
+
+    let Some(Data::Histogram(h)) = &only_metric(&req).data else {
+        panic!("expected a histogram");
+    };
+    assert_eq!(h.data_points.len(), 3);
+    assert_eq!(h.data_points[2].bucket_counts, [4]);
+    let merged = &h.data_points[0];
+    assert_eq!(merged.count, 6);
+    assert_eq!(merged.sum, Some(2.0));
+    assert_eq!(merged.bucket_counts, [3, 3]);
+    assert_eq!((merged.min, merged.max), (Some(10.0), Some(20.0)));
+    assert_eq!(
+        (merged.start_time_unix_nano, merged.time_unix_nano),
+        (9, 20)
+    );
+}
+
+#[test]
+fn exponential_buckets_are_realigned() {
+    let histogram = |offset, counts: Vec<u64>| ExponentialHistogramDataPoint {
+        time_unix_nano: 10,
+        count: counts.iter().sum::<u64>() + 1,
+        zero_count: 1,
+        scale: 3,
+        positive: Some(Buckets {
+            offset,
+            bucket_counts: counts,
+        }),
+        ..Default::default()
+    };
+    let mut req = request(vec![scope(
+        "app",
+        vec![metric(
+            "latency",
+            Data::ExponentialHistogram(ExponentialHistogram {
+                data_points: vec![histogram(2, vec![1, 2]), histogram(-1, vec![4, 0, 0, 1])],
+                aggregation_temporality: AggregationTemporality::Delta as i32,
+            }),
+        )],
+    )]);
+
+    aggregate(&mut req);
+
+    let Some(Data::ExponentialHistogram(h)) = &only_metric(&req).data else {
+        panic!("expected an exponential histogram");
+    };
+    assert_eq!(h.data_points.len(), 1);
+    let merged = &h.data_points[0];
+    assert_eq!((merged.count, merged.zero_count), (10, 2));
+    assert_eq!(
+        merged.positive,
+        Some(Buckets {
+            offset: -1,
+            bucket_counts: vec![4, 0, 0, 2, 2],
+        })
+    );
+}
+
+#[test]
+fn widely_separated_exponential_buckets_are_downscaled() {
+    let histogram = |offset, counts: Vec<u64>| ExponentialHistogramDataPoint {
+        time_unix_nano: 10,
+        count: counts.iter().sum(),
+        scale: 3,
+        positive: Some(Buckets {
+            offset,
+            bucket_counts: counts,
+        }),
+        ..Default::default()
+    };
+    let mut req = request(vec![scope(
+        "app",
+        vec![metric(
+            "latency",
+            Data::ExponentialHistogram(ExponentialHistogram {
+                data_points: vec![
+                    histogram(-1_000_000, vec![1, 2]),
+                    histogram(1_000_000, vec![3]),
+                ],
+                aggregation_temporality: AggregationTemporality::Delta as i32,
+            }),
+        )],
+    )]);
+
+    aggregate(&mut req);
+
+    let Some(Data::ExponentialHistogram(h)) = &only_metric(&req).data else {
+        panic!("expected an exponential histogram");
+    };
+    assert_eq!(h.data_points.len(), 1);
+    let merged = &h.data_points[0];
+    let buckets = merged.positive.as_ref().unwrap();
+    assert!(buckets.bucket_counts.len() <= MAX_EXPONENTIAL_BUCKETS);
+    assert_eq!(merged.scale, 3 - 14);
+    assert_eq!(buckets.bucket_counts.iter().sum::<u64>(), 6);
+    assert_eq!(buckets.bucket_counts.first(), Some(&3));
+    assert_eq!(buckets.bucket_counts.last(), Some(&3));
+    assert_eq!(merged.count, 6);
+}
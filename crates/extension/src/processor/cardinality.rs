@@ -0,0 +1,327 @@
+//! Per-metric cardinality limit.
+//!
+//! Tracks the distinct attribute sets seen for each metric (resource, scope
+//! and name) for the lifetime of the environment. Once a metric has reached
+//! the limit, data points with any new attribute set are folded into a single
+//! overflow series with the attribute `otel.metric.overflow=true`, as the
+//! OpenTelemetry SDK specification does. The overflow series counts towards
+//! the limit, so a metric never exports more than `limit` series.
+//!
+//! Folded points are combined like [`aggregation`](super::aggregation) does:
+//! delta sums and histograms are added up, gauges and summaries keep the
+//! latest point. Cumulative sums and histograms carry running totals, so the
+//! latest point of every folded attribute set is kept and the overflow series
+//! reports their sum. At most `limit` folded attribute sets are tracked per
+//! metric: past that, the one reported longest ago is retired into a running
+//! total. A retired attribute set that reports again is counted afresh, which
+//! overstates the overflow series rather than letting it go backwards.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use tracing::warn;
+
+use super::aggregation::{add_points, aggregate_points, append_points};
+use super::{attributes_key, resource_key, scope_key};
+use crate::proto::opentelemetry::proto::{
+    collector::metrics::v1::ExportMetricsServiceRequest,
+    common::v1::{AnyValue, KeyValue, any_value::Value},
+    metrics::v1::{AggregationTemporality, ExponentialHistogram, Histogram, Sum, metric::Data},
+};
+
+pub const OVERFLOW_ATTRIBUTE: &str = "otel.metric.overflow";
+
+pub struct CardinalityLimiter {
+    limit: usize,
+    metrics: HashMap<Vec<u8>, MetricSeries>,
+}
+
+#[derive(Default)]
+struct MetricSeries {
+    /// Attribute sets admitted so far, at most `limit - 1`.
+    seen: HashSet<Vec<u8>>,
+    /// Set once the metric first overflowed, so it's only logged once.
+    overflowed: bool,
+    /// Latest point of the folded attribute sets of a cumulative metric,
+    /// each as a metric of its own. At most `limit` of them.
+    folded: BTreeMap<Vec<u8>, Data>,
+    /// Latest points of the attribute sets retired from `folded`, added up
+    /// into one overflow point.
+    retired: Option<Data>,
+}
+
+impl CardinalityLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            metrics: HashMap::new(),
+        }
+    }
+
+    /// Forget every metric, e.g. after a SnapStart restore.
+    pub fn reset(&mut self) {
+        self.metrics.clear();
+    }
+
+    pub fn limit(&mut self, req: &mut ExportMetricsServiceRequest) {
+        for rm in &mut req.resource_metrics {
+            let resource = resource_key(rm.resource.as_ref(), &rm.schema_url);
+            for sm in &mut rm.scope_metrics {
+                let scope = scope_key(sm.scope.as_ref());
+                for metric in &mut sm.metrics {
+                    let mut key = resource.clone();
+                    key.extend_from_slice(&scope);
+                    key.extend_from_slice(metric.name.as_bytes());
+                    let series = self.metrics.entry(key).or_default();
+                    let cumulative = is_cumulative(&metric.data);
+                    let mut folded = HashSet::new();
+                    let mut admit = |attributes: &mut Vec<KeyValue>| {
+                        let key = attributes_key(attributes);
+                        if series.admit(&key, self.limit) {
+                            return;
+                        }
+                        // Cumulative points keep their attributes until they
+                        // are taken out below.
+                        if !cumulative {
+                            *attributes = vec![overflow_attribute()];
+                        }
+                        folded.insert(key);
+                    };
+                    match &mut metric.data {
+                        Some(Data::Gauge(g)) => g
+                            .data_points
+                            .iter_mut()
+                            .for_each(|p| admit(&mut p.attributes)),
+                        Some(Data::Sum(s)) => s
+                            .data_points
+                            .iter_mut()
+                            .for_each(|p| admit(&mut p.attributes)),
+                        Some(Data::Histogram(h)) => h
+                            .data_points
+                            .iter_mut()
+                            .for_each(|p| admit(&mut p.attributes)),
+                        Some(Data::ExponentialHistogram(h)) => h
+                            .data_points
+                            .iter_mut()
+                            .for_each(|p| admit(&mut p.attributes)),
+                        Some(Data::Summary(s)) => s
+                            .data_points
+                            .iter_mut()
+                            .for_each(|p| admit(&mut p.attributes)),
+                        None => {}
+                    }
+                    if folded.is_empty() {
+                        continue;
+                    }
+                    if !series.overflowed {
+                        series.overflowed = true;
+                        warn!(
+                            metric = metric.name,
+                            limit = self.limit,
+                            "metric exceeded its cardinality limit, folding new attribute sets \
+                             into the overflow series"
+                        );
+                    }
+                    if cumulative {
+                        series.fold_cumulative(&mut metric.data, folded, self.limit);
+                    }
+                    aggregate_points(&mut metric.data);
+                }
+            }
+        }
+    }
+}
+
+impl MetricSeries {
+    /// Whether a point with these attributes keeps them, admitting new ones
+    /// while there is room left next to the overflow series.
+    fn admit(&mut self, attributes: &[u8], limit: usize) -> bool {
+        if self.seen.contains(attributes) {
+            return true;
+        }
+        if self.seen.len() + 1 < limit {
+            self.seen.insert(attributes.to_vec());
+            return true;
+        }
+        false
+    }
+
+    /// Replace the points of `data` with a `folded` attribute set by one
+    /// overflow point adding up the latest point of every folded attribute
+    /// set seen so far. An attribute set is folded for the whole flush once
+    /// it is folded at all, as nothing is admitted after it.
+    fn fold_cumulative(&mut self, data: &mut Option<Data>, folded: HashSet<Vec<u8>>, limit: usize) {
+        let Some(shape) = data.as_ref().map(empty_like) else {
+            return;
+        };
+        for point in split_off(data, |attributes| {
+            folded.contains(&attributes_key(attributes))
+        }) {
+            let key = attributes_key(point_attributes(&point));
+            match self.folded.get(&key) {
+                Some(stored) if time(stored) > time(&point) => {}
+                Some(_) => {
+                    self.folded.insert(key, point);
+                }
+                None => {
+                    if self.folded.len() >= limit {
+                        self.retire_stalest();
+                    }
+                    self.folded.insert(key, point);
+                }
+            }
+        }
+        let mut overflow = Some(shape);
+        append_points(&mut overflow, self.retired.clone());
+        for point in self.folded.values_mut() {
+            append_points(&mut overflow, Some(point.clone()));
+            // Exemplars are exported with the flush that brought them only.
+            clear_exemplars(point);
+        }
+        set_attributes(&mut overflow, vec![overflow_attribute()]);
+        add_points(&mut overflow);
+        append_points(data, overflow);
+    }
+
+    /// Add the folded attribute set reported longest ago to `retired`.
+    fn retire_stalest(&mut self) {
+        let Some(key) = self
+            .folded
+            .iter()
+            .min_by_key(|(_, point)| time(point))
+            .map(|(key, _)| key.clone())
+        else {
+            return;
+        };
+        let mut point = self.folded.remove(&key);
+        point.iter_mut().for_each(clear_exemplars);
+        set_attributes(&mut point, vec![overflow_attribute()]);
+        if self.retired.is_none() {
+            self.retired = point;
+        } else {
+            append_points(&mut self.retired, point);
+            add_points(&mut self.retired);
+        }
+    }
+}
+
+fn is_cumulative(data: &Option<Data>) -> bool {
+    let cumulative = AggregationTemporality::Cumulative as i32;
+    match data {
+        Some(Data::Sum(s)) => s.aggregation_temporality == cumulative,
+        Some(Data::Histogram(h)) => h.aggregation_temporality == cumulative,
+        Some(Data::ExponentialHistogram(h)) => h.aggregation_temporality == cumulative,
+        _ => false,
+    }
+}
+
+/// `data` without its points.
+fn empty_like(data: &Data) -> Data {
+    match data {
+        Data::Sum(s) => Data::Sum(Sum {
+            data_points: Vec::new(),
+            ..*s
+        }),
+        Data::Histogram(h) => Data::Histogram(Histogram {
+            data_points: Vec::new(),
+            ..*h
+        }),
+        Data::ExponentialHistogram(h) => Data::ExponentialHistogram(ExponentialHistogram {
+            data_points: Vec::new(),
+            ..*h
+        }),
+        other => other.clone(),
+    }
+}
+
+/// Take the points matching `take` out of a cumulative metric, each as a
+/// single-point metric of the same shape.
+fn split_off(data: &mut Option<Data>, mut take: impl FnMut(&[KeyValue]) -> bool) -> Vec<Data> {
+    let Some(data) = data else {
+        return Vec::new();
+    };
+    let shape = empty_like(data);
+    macro_rules! split {
+        ($points:expr, $variant:ident) => {{
+            let (taken, kept) = std::mem::take($points)
+                .into_iter()
+                .partition::<Vec<_>, _>(|p| take(&p.attributes));
+            *$points = kept;
+            taken
+                .into_iter()
+                .map(|p| {
+                    let mut single = shape.clone();
+                    if let Data::$variant(m) = &mut single {
+                        m.data_points.push(p);
+                    }
+                    single
+                })
+                .collect()
+        }};
+    }
+    match data {
+        Data::Sum(s) => split!(&mut s.data_points, Sum),
+        Data::Histogram(h) => split!(&mut h.data_points, Histogram),
+        Data::ExponentialHistogram(h) => split!(&mut h.data_points, ExponentialHistogram),
+        _ => Vec::new(),
+    }
+}
+
+/// Attributes of the only point of a single-point metric.
+fn point_attributes(data: &Data) -> &[KeyValue] {
+    match data {
+        Data::Sum(s) => &s.data_points[0].attributes,
+        Data::Histogram(h) => &h.data_points[0].attributes,
+        Data::ExponentialHistogram(h) => &h.data_points[0].attributes,
+        _ => &[],
+    }
+}
+
+/// Timestamp of the only point of a single-point metric.
+fn time(data: &Data) -> u64 {
+    match data {
+        Data::Sum(s) => s.data_points[0].time_unix_nano,
+        Data::Histogram(h) => h.data_points[0].time_unix_nano,
+        Data::ExponentialHistogram(h) => h.data_points[0].time_unix_nano,
+        _ => 0,
+    }
+}
+
+fn clear_exemplars(data: &mut Data) {
+    match data {
+        Data::Sum(s) => s.data_points.iter_mut().for_each(|p| p.exemplars.clear()),
+        Data::Histogram(h) => h.data_points.iter_mut().for_each(|p| p.exemplars.clear()),
+        Data::ExponentialHistogram(h) => h.data_points.iter_mut().for_each(|p| p.exemplars.clear()),
+        _ => {}
+    }
+}
+
+fn set_attributes(data: &mut Option<Data>, attributes: Vec<KeyValue>) {
+    match data {
+        Some(Data::Sum(s)) => s
+            .data_points
+            .iter_mut()
+            .for_each(|p| p.attributes = attributes.clone()),
+        Some(Data::Histogram(h)) => h
+            .data_points
+            .iter_mut()
+            .for_each(|p| p.attributes = attributes.clone()),
+        Some(Data::ExponentialHistogram(h)) => h
+            .data_points
+            .iter_mut()
+            .for_each(|p| p.attributes = attributes.clone()),
+        _ => {}
+    }
+}
+
+fn overflow_attribute() -> KeyValue {
+    KeyValue {
+        key: OVERFLOW_ATTRIBUTE.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::BoolValue(true)),
+        }),
+    }
+}
+
+#[cfg(test)]
+#[path = "cardinality_tests.rs"]
+mod tests;
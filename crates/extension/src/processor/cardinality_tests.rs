@@ -0,0 +1,195 @@
+use super::*;
+use crate::proto::opentelemetry::proto::metrics::v1::{
+    AggregationTemporality, Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum,
+    number_data_point,
+};
+
+fn point(request_id: &str, value: i64) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes: vec![KeyValue {
+            key: "request.id".to_owned(),
+            value: Some(AnyValue {
+                value: Some(Value::StringValue(request_id.to_owned())),
+            }),
+        }],
+        time_unix_nano: 1,
+        value: Some(number_data_point::Value::AsInt(value)),
+        ..Default::default()
+    }
+}
+
+fn request(name: &str, data: Data) -> ExportMetricsServiceRequest {
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            scope_metrics: vec![ScopeMetrics {
+                metrics: vec![Metric {
+                    name: name.to_owned(),
+                    data: Some(data),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+}
+
+fn delta_sum(name: &str, points: Vec<NumberDataPoint>) -> ExportMetricsServiceRequest {
+    request(
+        name,
+        Data::Sum(Sum {
+            data_points: points,
+            aggregation_temporality: AggregationTemporality::Delta as i32,
+            is_monotonic: true,
+        }),
+    )
+}
+
+/// `(attribute, value)` for every point of the only metric.
+fn series(req: &ExportMetricsServiceRequest) -> Vec<(String, i64)> {
+    let points = match &req.resource_metrics[0].scope_metrics[0].metrics[0].data {
+        Some(Data::Sum(sum)) => &sum.data_points,
+        Some(Data::Gauge(gauge)) => &gauge.data_points,
+        _ => panic!("expected a sum or gauge"),
+    };
+    points
+        .iter()
+        .map(|p| {
+            let attribute = match p.attributes[0]
+                .value
+                .as_ref()
+                .and_then(|v| v.value.as_ref())
+            {
+                Some(Value::StringValue(s)) => s.clone(),
+                Some(Value::BoolValue(_)) => p.attributes[0].key.clone(),
+                other => panic!("unexpected value {other:?}"),
+            };
+            let Some(number_data_point::Value::AsInt(v)) = p.value else {
+                panic!("expected an int value");
+            };
+            (attribute, v)
+        })
+        .collect()
+}
+
+fn owned(series: &[(&str, i64)]) -> Vec<(String, i64)> {
+    series.iter().map(|(a, v)| (a.to_string(), *v)).collect()
+}
+
+#[test]
+fn folds_new_attribute_sets_beyond_the_limit() {
+    let mut limiter = CardinalityLimiter::new(3);
+    let mut req = delta_sum(
+        "requests",
+        vec![point("a", 1), point("b", 1), point("c", 2), point("d", 3)],
+    );
+    limiter.limit(&mut req);
+    assert_eq!(
+        series(&req),
+        owned(&[("a", 1), ("b", 1), (OVERFLOW_ATTRIBUTE, 5)])
+    );
+
+    // Known attribute sets keep their series in later flushes.
+    let mut req = delta_sum("requests", vec![point("e", 1), point("a", 4)]);
+    limiter.limit(&mut req);
+    assert_eq!(series(&req), owned(&[(OVERFLOW_ATTRIBUTE, 1), ("a", 4)]));
+}
+
+#[test]
+fn limits_apply_per_metric() {
+    let mut limiter = CardinalityLimiter::new(2);
+    let mut req = delta_sum("requests", vec![point("a", 1), point("b", 1)]);
+    limiter.limit(&mut req);
+    assert_eq!(series(&req), owned(&[("a", 1), (OVERFLOW_ATTRIBUTE, 1)]));
+
+    let mut req = delta_sum("errors", vec![point("b", 1)]);
+    limiter.limit(&mut req);
+    assert_eq!(series(&req), owned(&[("b", 1)]));
+}
+
+#[test]
+fn overflowing_gauges_keep_the_latest_point() {
+    let mut limiter = CardinalityLimiter::new(1);
+    let mut late = point("b", 7);
+    late.time_unix_nano = 2;
+    let mut req = request(
+        "queue.depth",
+        Data::Gauge(Gauge {
+            data_points: vec![point("a", 3), late],
+        }),
+    );
+    limiter.limit(&mut req);
+    assert_eq!(series(&req), owned(&[(OVERFLOW_ATTRIBUTE, 7)]));
+}
+
+#[test]
+fn cumulative_overflow_adds_up_the_latest_point_of_each_series() {
+    let cumulative_sum = |points| {
+        request(
+            "requests",
+            Data::Sum(Sum {
+                data_points: points,
+                aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                is_monotonic: true,
+            }),
+        )
+    };
+    let mut limiter = CardinalityLimiter::new(2);
+    let mut req = cumulative_sum(vec![point("a", 5), point("b", 10), point("c", 3)]);
+    limiter.limit(&mut req);
+    assert_eq!(series(&req), owned(&[("a", 5), (OVERFLOW_ATTRIBUTE, 13)]));
+
+    // `c` is not reported again, but its running total still counts.
+    let mut late = point("b", 12);
+    late.time_unix_nano = 2;
+    let mut req = cumulative_sum(vec![late, point("b", 11), point("d", 1)]);
+    limiter.limit(&mut req);
+    assert_eq!(series(&req), owned(&[(OVERFLOW_ATTRIBUTE, 16)]));
+}
+
+#[test]
+fn reset_forgets_attribute_sets() {
+    let mut limiter = CardinalityLimiter::new(2);
+    limiter.limit(&mut delta_sum("requests", vec![point("a", 1)]));
+    limiter.reset();
+    let mut req = delta_sum("requests", vec![point("b", 1)]);
+    limiter.limit(&mut req);
+    assert_eq!(series(&req), owned(&[("b", 1)]));
+}
+
+#[test]
+fn folded_cumulative_state_stays_bounded() {
+    let cumulative_sum = |points| {
+        request(
+            "requests",
+            Data::Sum(Sum {
+                data_points: points,
+                aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                is_monotonic: true,
+            }),
+        )
+    };
+    let mut limiter = CardinalityLimiter::new(4);
+    let mut total = 0;
+    for flush in 0..50 {
+        let points = (0..20)
+            .map(|i| {
+                let mut p = point(&format!("{flush}-{i}"), 1);
+                p.time_unix_nano = flush + 1;
+                p
+            })
+            .collect();
+        let mut req = cumulative_sum(points);
+        limiter.limit(&mut req);
+        total += 20;
+        // The first three sets were admitted; every other one is folded and
+        // still counts once retired.
+        assert_eq!(
+            series(&req).last(),
+            Some(&(OVERFLOW_ATTRIBUTE.to_owned(), total - 3))
+        );
+    }
+    let series = limiter.metrics.values().next().unwrap();
+    assert_eq!(series.folded.len(), 4);
+    assert!(series.retired.is_some());
+}
@@ -0,0 +1,77 @@
+//! Static resource attributes.
+//!
+//! Applies the `LAMBDA_OTEL_RELAY_RESOURCE_ATTRIBUTES` members, in order, to
+//! the resource of every payload, so attributes like `deployment.environment`
+//! are present whether or not the function's SDK sets them. Payloads without a
+//! resource get one.
+
+use crate::config::{ResourceAction, ResourceAttribute};
+use crate::proto::opentelemetry::proto::{
+    collector::{
+        logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+        trace::v1::ExportTraceServiceRequest,
+    },
+    common::v1::{AnyValue, KeyValue, any_value::Value},
+    resource::v1::Resource,
+};
+
+pub struct ResourceEnricher {
+    attributes: Vec<ResourceAttribute>,
+}
+
+impl ResourceEnricher {
+    pub fn new(attributes: &[ResourceAttribute]) -> Self {
+        Self {
+            attributes: attributes.to_vec(),
+        }
+    }
+
+    pub fn enrich_traces(&self, req: &mut ExportTraceServiceRequest) {
+        for rs in &mut req.resource_spans {
+            self.enrich(&mut rs.resource);
+        }
+    }
+
+    pub fn enrich_metrics(&self, req: &mut ExportMetricsServiceRequest) {
+        for rm in &mut req.resource_metrics {
+            self.enrich(&mut rm.resource);
+        }
+    }
+
+    pub fn enrich_logs(&self, req: &mut ExportLogsServiceRequest) {
+        for rl in &mut req.resource_logs {
+            self.enrich(&mut rl.resource);
+        }
+    }
+
+    fn enrich(&self, resource: &mut Option<Resource>) {
+        let attrs = &mut resource.get_or_insert_with(Resource::default).attributes;
+        for attribute in &self.attributes {
+            let existing = attrs.iter().position(|kv| kv.key == attribute.key);
+            match (&attribute.action, existing) {
+                (ResourceAction::Insert(_), Some(_)) => {}
+                (ResourceAction::Insert(value) | ResourceAction::Upsert(value), None) => {
+                    attrs.push(string(&attribute.key, value));
+                }
+                (ResourceAction::Upsert(value), Some(i)) => {
+                    attrs[i] = string(&attribute.key, value);
+                }
+                (ResourceAction::Delete, Some(_)) => attrs.retain(|kv| kv.key != attribute.key),
+                (ResourceAction::Delete, None) => {}
+            }
+        }
+    }
+}
+
+fn string(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value.to_owned())),
+        }),
+    }
+}
+
+#[cfg(test)]
+#[path = "enrichment_tests.rs"]
+mod tests;
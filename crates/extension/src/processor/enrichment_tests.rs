@@ -0,0 +1,81 @@
+use super::*;
+use crate::proto::opentelemetry::proto::{
+    logs::v1::ResourceLogs, metrics::v1::ResourceMetrics, trace::v1::ResourceSpans,
+};
+
+fn attribute(key: &str, action: ResourceAction) -> ResourceAttribute {
+    ResourceAttribute {
+        key: key.to_owned(),
+        action,
+    }
+}
+
+fn enricher() -> ResourceEnricher {
+    ResourceEnricher::new(&[
+        attribute(
+            "deployment.environment",
+            ResourceAction::Upsert("prod".to_owned()),
+        ),
+        attribute("team", ResourceAction::Insert("payments".to_owned())),
+        attribute("host.name", ResourceAction::Delete),
+    ])
+}
+
+#[test]
+fn inserts_upserts_and_deletes() {
+    let mut req = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Some(Resource {
+                attributes: vec![
+                    string("service.name", "checkout"),
+                    string("deployment.environment", "dev"),
+                    string("team", "orders"),
+                    string("host.name", "169.254.1.1"),
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }],
+    };
+
+    enricher().enrich_traces(&mut req);
+
+    assert_eq!(
+        req.resource_spans[0].resource.as_ref().unwrap().attributes,
+        [
+            string("service.name", "checkout"),
+            string("deployment.environment", "prod"),
+            string("team", "orders"),
+        ]
+    );
+}
+
+#[test]
+fn adds_a_resource_where_there_is_none() {
+    let mut metrics = ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics::default()],
+    };
+    let mut logs = ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs::default()],
+    };
+
+    enricher().enrich_metrics(&mut metrics);
+    enricher().enrich_logs(&mut logs);
+
+    let expected = [
+        string("deployment.environment", "prod"),
+        string("team", "payments"),
+    ];
+    assert_eq!(
+        metrics.resource_metrics[0]
+            .resource
+            .as_ref()
+            .unwrap()
+            .attributes,
+        expected
+    );
+    assert_eq!(
+        logs.resource_logs[0].resource.as_ref().unwrap().attributes,
+        expected
+    );
+}
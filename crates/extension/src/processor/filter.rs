@@ -0,0 +1,190 @@
+//! Drop rules.
+//!
+//! Removes spans, metrics, log records and whole instrumentation scopes that
+//! match the configured [`FilterConfig`], pruning scopes and resources left
+//! empty. Runs before the other stages so they don't spend time on data that
+//! is about to be discarded.
+
+use tracing::debug;
+
+use super::glob_match;
+use crate::config::FilterConfig;
+use crate::proto::opentelemetry::proto::{
+    collector::{
+        logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+        trace::v1::ExportTraceServiceRequest,
+    },
+    common::v1::{AnyValue, InstrumentationScope, KeyValue, any_value::Value},
+    logs::v1::LogRecord,
+    trace::v1::Span,
+};
+
+/// Running totals of dropped items since startup.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DropCounts {
+    pub spans: u64,
+    pub metrics: u64,
+    pub log_records: u64,
+    pub scopes: u64,
+}
+
+pub struct Filter {
+    config: FilterConfig,
+    dropped: DropCounts,
+}
+
+impl Filter {
+    pub fn new(config: &FilterConfig) -> Self {
+        Self {
+            config: config.clone(),
+            dropped: DropCounts::default(),
+        }
+    }
+
+    /// Returns the number of spans dropped, including those of dropped scopes.
+    pub fn filter_traces(&mut self, req: &mut ExportTraceServiceRequest) -> u64 {
+        let before = self.dropped;
+        let mut records = 0;
+        for rs in &mut req.resource_spans {
+            rs.scope_spans.retain_mut(|ss| {
+                if self.drop_scope(&ss.scope) {
+                    records += ss.spans.len() as u64;
+                    return false;
+                }
+                let len = ss.spans.len();
+                ss.spans.retain(|span| !self.drop_span(span));
+                self.dropped.spans += (len - ss.spans.len()) as u64;
+                records += (len - ss.spans.len()) as u64;
+                !ss.spans.is_empty()
+            });
+        }
+        req.resource_spans.retain(|rs| !rs.scope_spans.is_empty());
+        self.log_dropped(before);
+        records
+    }
+
+    /// Returns the number of metrics dropped, including those of dropped
+    /// scopes.
+    pub fn filter_metrics(&mut self, req: &mut ExportMetricsServiceRequest) -> u64 {
+        let before = self.dropped;
+        let mut records = 0;
+        for rm in &mut req.resource_metrics {
+            rm.scope_metrics.retain_mut(|sm| {
+                if self.drop_scope(&sm.scope) {
+                    records += sm.metrics.len() as u64;
+                    return false;
+                }
+                let len = sm.metrics.len();
+                sm.metrics.retain(|metric| {
+                    !self
+                        .config
+                        .metric_prefixes
+                        .iter()
+                        .any(|prefix| metric.name.starts_with(prefix.as_str()))
+                });
+                self.dropped.metrics += (len - sm.metrics.len()) as u64;
+                records += (len - sm.metrics.len()) as u64;
+                !sm.metrics.is_empty()
+            });
+        }
+        req.resource_metrics
+            .retain(|rm| !rm.scope_metrics.is_empty());
+        self.log_dropped(before);
+        records
+    }
+
+    /// Returns the number of log records dropped, including those of dropped
+    /// scopes.
+    pub fn filter_logs(&mut self, req: &mut ExportLogsServiceRequest) -> u64 {
+        let before = self.dropped;
+        let mut records = 0;
+        for rl in &mut req.resource_logs {
+            rl.scope_logs.retain_mut(|sl| {
+                if self.drop_scope(&sl.scope) {
+                    records += sl.log_records.len() as u64;
+                    return false;
+                }
+                let len = sl.log_records.len();
+                sl.log_records.retain(|record| !self.drop_log(record));
+                self.dropped.log_records += (len - sl.log_records.len()) as u64;
+                records += (len - sl.log_records.len()) as u64;
+                !sl.log_records.is_empty()
+            });
+        }
+        req.resource_logs.retain(|rl| !rl.scope_logs.is_empty());
+        self.log_dropped(before);
+        records
+    }
+
+    /// Counts the scope when it is dropped; its contents are not counted
+    /// individually.
+    fn drop_scope(&mut self, scope: &Option<InstrumentationScope>) -> bool {
+        let name = scope.as_ref().map_or("", |s| s.name.as_str());
+        let drop = self.config.scopes.iter().any(|glob| glob_match(glob, name));
+        if drop {
+            self.dropped.scopes += 1;
+        }
+        drop
+    }
+
+    fn drop_span(&self, span: &Span) -> bool {
+        self.config
+            .span_names
+            .iter()
+            .any(|glob| glob_match(glob, &span.name))
+            || self
+                .config
+                .span_kinds
+                .iter()
+                .any(|kind| span.kind == *kind as i32)
+            || self
+                .config
+                .span_attributes
+                .iter()
+                .any(|(key, glob)| attribute_matches(&span.attributes, key, glob))
+    }
+
+    /// Records without a severity number are kept: there is nothing to
+    /// compare against.
+    fn drop_log(&self, record: &LogRecord) -> bool {
+        self.config
+            .log_min_severity
+            .is_some_and(|min| record.severity_number != 0 && record.severity_number < min)
+    }
+
+    fn log_dropped(&self, before: DropCounts) {
+        if self.dropped != before {
+            debug!(
+                spans = self.dropped.spans - before.spans,
+                metrics = self.dropped.metrics - before.metrics,
+                log_records = self.dropped.log_records - before.log_records,
+                scopes = self.dropped.scopes - before.scopes,
+                total = ?self.dropped,
+                "filter dropped telemetry"
+            );
+        }
+    }
+}
+
+fn attribute_matches(attrs: &[KeyValue], key: &str, glob: &str) -> bool {
+    attrs
+        .iter()
+        .filter(|kv| kv.key == key)
+        .filter_map(|kv| kv.value.as_ref())
+        .filter_map(scalar_to_string)
+        .any(|value| glob_match(glob, &value))
+}
+
+fn scalar_to_string(value: &AnyValue) -> Option<String> {
+    match value.value.as_ref()? {
+        Value::StringValue(s) => Some(s.clone()),
+        Value::BoolValue(b) => Some(b.to_string()),
+        Value::IntValue(i) => Some(i.to_string()),
+        Value::DoubleValue(d) => Some(d.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+#[path = "filter_tests.rs"]
+mod tests;
@@ -0,0 +1,213 @@
+use super::*;
+use crate::proto::opentelemetry::proto::{
+    logs::v1::{ResourceLogs, ScopeLogs},
+    metrics::v1::{Metric, ResourceMetrics, ScopeMetrics},
+    trace::v1::{ResourceSpans, ScopeSpans, span::SpanKind},
+};
+
+fn span(name: &str, kind: SpanKind, attributes: Vec<KeyValue>) -> Span {
+    Span {
+        name: name.to_owned(),
+        kind: kind as i32,
+        attributes,
+        ..Default::default()
+    }
+}
+
+fn kv(key: &str, value: Value) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue { value: Some(value) }),
+    }
+}
+
+fn scope(name: &str) -> Option<InstrumentationScope> {
+    Some(InstrumentationScope {
+        name: name.to_owned(),
+        ..Default::default()
+    })
+}
+
+fn traces(scopes: Vec<(&str, Vec<Span>)>) -> ExportTraceServiceRequest {
+    ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: scopes
+                .into_iter()
+                .map(|(name, spans)| ScopeSpans {
+                    scope: scope(name),
+                    spans,
+                    schema_url: String::new(),
+                })
+                .collect(),
+            schema_url: String::new(),
+        }],
+    }
+}
+
+fn span_names(req: &ExportTraceServiceRequest) -> Vec<&str> {
+    req.resource_spans
+        .iter()
+        .flat_map(|rs| &rs.scope_spans)
+        .flat_map(|ss| &ss.spans)
+        .map(|s| s.name.as_str())
+        .collect()
+}
+
+#[test]
+fn drops_spans_by_name_kind_and_attribute() {
+    let mut filter = Filter::new(&FilterConfig {
+        span_names: vec!["GET /health*".to_owned()],
+        span_kinds: vec![SpanKind::Internal],
+        span_attributes: vec![("url.path".to_owned(), "/ping".to_owned())],
+        ..Default::default()
+    });
+    let mut req = traces(vec![(
+        "lib",
+        vec![
+            span("GET /healthz", SpanKind::Server, vec![]),
+            span("compute", SpanKind::Internal, vec![]),
+            span(
+                "GET",
+                SpanKind::Server,
+                vec![kv("url.path", Value::StringValue("/ping".to_owned()))],
+            ),
+            span(
+                "GET /orders",
+                SpanKind::Server,
+                vec![kv("url.path", Value::StringValue("/orders".to_owned()))],
+            ),
+        ],
+    )]);
+
+    filter.filter_traces(&mut req);
+
+    assert_eq!(span_names(&req), vec!["GET /orders"]);
+    assert_eq!(filter.dropped.spans, 3);
+}
+
+#[test]
+fn attribute_rule_matches_non_string_values() {
+    let mut filter = Filter::new(&FilterConfig {
+        span_attributes: vec![("http.response.status_code".to_owned(), "2*".to_owned())],
+        ..Default::default()
+    });
+    let mut req = traces(vec![(
+        "lib",
+        vec![
+            span(
+                "ok",
+                SpanKind::Server,
+                vec![kv("http.response.status_code", Value::IntValue(204))],
+            ),
+            span(
+                "fail",
+                SpanKind::Server,
+                vec![kv("http.response.status_code", Value::IntValue(500))],
+            ),
+        ],
+    )]);
+
+    filter.filter_traces(&mut req);
+
+    assert_eq!(span_names(&req), vec!["fail"]);
+}
+
+#[test]
+fn drops_whole_scopes_and_prunes_empty_resources() {
+    let mut filter = Filter::new(&FilterConfig {
+        scopes: vec!["io.opentelemetry.jdbc*".to_owned()],
+        ..Default::default()
+    });
+    let mut req = traces(vec![(
+        "io.opentelemetry.jdbc-4.0",
+        vec![span("SELECT", SpanKind::Client, vec![])],
+    )]);
+
+    filter.filter_traces(&mut req);
+
+    assert!(req.resource_spans.is_empty());
+    assert_eq!(filter.dropped.scopes, 1);
+    assert_eq!(filter.dropped.spans, 0);
+}
+
+#[test]
+fn drops_metrics_by_name_prefix() {
+    let metric = |name: &str| Metric {
+        name: name.to_owned(),
+        ..Default::default()
+    };
+    let mut filter = Filter::new(&FilterConfig {
+        metric_prefixes: vec!["jvm.".to_owned(), "process.runtime".to_owned()],
+        ..Default::default()
+    });
+    let mut req = ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: None,
+            scope_metrics: vec![ScopeMetrics {
+                scope: None,
+                metrics: vec![
+                    metric("jvm.memory.used"),
+                    metric("process.runtime.gc"),
+                    metric("http.server.duration"),
+                ],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+
+    filter.filter_metrics(&mut req);
+
+    let metrics = &req.resource_metrics[0].scope_metrics[0].metrics;
+    assert_eq!(metrics.len(), 1);
+    assert_eq!(metrics[0].name, "http.server.duration");
+    assert_eq!(filter.dropped.metrics, 2);
+}
+
+#[test]
+fn drops_logs_below_min_severity() {
+    let record = |severity_number: i32| LogRecord {
+        severity_number,
+        ..Default::default()
+    };
+    let mut filter = Filter::new(&FilterConfig {
+        log_min_severity: Some(9),
+        ..Default::default()
+    });
+    let mut req = ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs {
+            resource: None,
+            scope_logs: vec![ScopeLogs {
+                scope: None,
+                log_records: vec![record(5), record(9), record(17), record(0)],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+
+    filter.filter_logs(&mut req);
+
+    let severities: Vec<i32> = req.resource_logs[0].scope_logs[0]
+        .log_records
+        .iter()
+        .map(|r| r.severity_number)
+        .collect();
+    // Unspecified severity is kept.
+    assert_eq!(severities, vec![9, 17, 0]);
+    assert_eq!(filter.dropped.log_records, 1);
+}
+
+#[test]
+fn counts_accumulate_across_requests() {
+    let mut filter = Filter::new(&FilterConfig {
+        span_names: vec!["noise".to_owned()],
+        ..Default::default()
+    });
+    for _ in 0..3 {
+        let mut req = traces(vec![("lib", vec![span("noise", SpanKind::Server, vec![])])]);
+        filter.filter_traces(&mut req);
+    }
+    assert_eq!(filter.dropped.spans, 3);
+}
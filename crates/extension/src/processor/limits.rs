@@ -0,0 +1,193 @@
+//! Attribute, event and link limits.
+//!
+//! Enforces the limits of the OpenTelemetry SDK specification on data that
+//! reaches the relay, whatever the SDK was configured with:
+//!
+//! - string values longer than the value length limit are truncated to that
+//!   many characters, byte values to that many bytes, also inside arrays and
+//!   maps, in every attribute list of a request;
+//! - spans, span events, links and log records keep their first attributes
+//!   up to the count limit;
+//! - spans keep their first events and links up to their count limits.
+//!
+//! Everything dropped is added to the `dropped_*_count` of its parent, so
+//! backends can still tell that data is missing.
+
+use crate::config::LimitsConfig;
+use crate::proto::opentelemetry::proto::{
+    collector::{
+        logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+        trace::v1::ExportTraceServiceRequest,
+    },
+    common::v1::{AnyValue, InstrumentationScope, KeyValue, any_value::Value},
+    metrics::v1::{Exemplar, metric::Data},
+    resource::v1::Resource,
+};
+
+pub struct Limits {
+    config: LimitsConfig,
+}
+
+impl Limits {
+    pub fn new(config: &LimitsConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    pub fn limit_traces(&self, req: &mut ExportTraceServiceRequest) {
+        for rs in &mut req.resource_spans {
+            self.resource(&mut rs.resource);
+            for ss in &mut rs.scope_spans {
+                self.scope(&mut ss.scope);
+                for span in &mut ss.spans {
+                    self.counted(&mut span.attributes, &mut span.dropped_attributes_count);
+                    add(
+                        &mut span.dropped_events_count,
+                        truncate_list(&mut span.events, self.config.event_count),
+                    );
+                    add(
+                        &mut span.dropped_links_count,
+                        truncate_list(&mut span.links, self.config.link_count),
+                    );
+                    for event in &mut span.events {
+                        self.counted(&mut event.attributes, &mut event.dropped_attributes_count);
+                    }
+                    for link in &mut span.links {
+                        self.counted(&mut link.attributes, &mut link.dropped_attributes_count);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Metric data points have no dropped attribute count, so only their
+    /// values are truncated.
+    pub fn limit_metrics(&self, req: &mut ExportMetricsServiceRequest) {
+        let Some(max) = self.config.attribute_value_length else {
+            return;
+        };
+        for rm in &mut req.resource_metrics {
+            self.resource(&mut rm.resource);
+            for sm in &mut rm.scope_metrics {
+                self.scope(&mut sm.scope);
+                for metric in &mut sm.metrics {
+                    match &mut metric.data {
+                        Some(Data::Gauge(g)) => {
+                            for dp in &mut g.data_points {
+                                truncate_values(&mut dp.attributes, max);
+                                exemplars(&mut dp.exemplars, max);
+                            }
+                        }
+                        Some(Data::Sum(s)) => {
+                            for dp in &mut s.data_points {
+                                truncate_values(&mut dp.attributes, max);
+                                exemplars(&mut dp.exemplars, max);
+                            }
+                        }
+                        Some(Data::Histogram(h)) => {
+                            for dp in &mut h.data_points {
+                                truncate_values(&mut dp.attributes, max);
+                                exemplars(&mut dp.exemplars, max);
+                            }
+                        }
+                        Some(Data::ExponentialHistogram(h)) => {
+                            for dp in &mut h.data_points {
+                                truncate_values(&mut dp.attributes, max);
+                                exemplars(&mut dp.exemplars, max);
+                            }
+                        }
+                        Some(Data::Summary(s)) => {
+                            for dp in &mut s.data_points {
+                                truncate_values(&mut dp.attributes, max);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn limit_logs(&self, req: &mut ExportLogsServiceRequest) {
+        for rl in &mut req.resource_logs {
+            self.resource(&mut rl.resource);
+            for sl in &mut rl.scope_logs {
+                self.scope(&mut sl.scope);
+                for record in &mut sl.log_records {
+                    self.counted(&mut record.attributes, &mut record.dropped_attributes_count);
+                }
+            }
+        }
+    }
+
+    fn resource(&self, resource: &mut Option<Resource>) {
+        if let (Some(resource), Some(max)) = (resource, self.config.attribute_value_length) {
+            truncate_values(&mut resource.attributes, max);
+        }
+    }
+
+    fn scope(&self, scope: &mut Option<InstrumentationScope>) {
+        if let (Some(scope), Some(max)) = (scope, self.config.attribute_value_length) {
+            truncate_values(&mut scope.attributes, max);
+        }
+    }
+
+    /// Apply both attribute limits to a list that has a dropped count.
+    fn counted(&self, attrs: &mut Vec<KeyValue>, dropped: &mut u32) {
+        add(dropped, truncate_list(attrs, self.config.attribute_count));
+        if let Some(max) = self.config.attribute_value_length {
+            truncate_values(attrs, max);
+        }
+    }
+}
+
+/// Keep the first `max` items, returning how many were dropped.
+fn truncate_list<T>(items: &mut Vec<T>, max: Option<usize>) -> u32 {
+    let Some(max) = max.filter(|&max| items.len() > max) else {
+        return 0;
+    };
+    let dropped = items.len() - max;
+    items.truncate(max);
+    u32::try_from(dropped).unwrap_or(u32::MAX)
+}
+
+fn add(count: &mut u32, dropped: u32) {
+    *count = count.saturating_add(dropped);
+}
+
+fn exemplars(exemplars: &mut [Exemplar], max: usize) {
+    for exemplar in exemplars {
+        truncate_values(&mut exemplar.filtered_attributes, max);
+    }
+}
+
+fn truncate_values(attrs: &mut [KeyValue], max: usize) {
+    for kv in attrs {
+        if let Some(value) = &mut kv.value {
+            truncate_value(value, max);
+        }
+    }
+}
+
+fn truncate_value(value: &mut AnyValue, max: usize) {
+    match &mut value.value {
+        Some(Value::StringValue(s)) => {
+            if let Some((end, _)) = s.char_indices().nth(max) {
+                s.truncate(end);
+            }
+        }
+        Some(Value::BytesValue(b)) => b.truncate(max),
+        Some(Value::ArrayValue(array)) => {
+            for value in &mut array.values {
+                truncate_value(value, max);
+            }
+        }
+        Some(Value::KvlistValue(kvlist)) => truncate_values(&mut kvlist.values, max),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+#[path = "limits_tests.rs"]
+mod tests;
@@ -0,0 +1,168 @@
+use super::*;
+use crate::proto::opentelemetry::proto::{
+    common::v1::{ArrayValue, KeyValueList},
+    logs::v1::{LogRecord, ResourceLogs, ScopeLogs},
+    trace::v1::{
+        ResourceSpans, ScopeSpans, Span,
+        span::{Event, Link},
+    },
+};
+
+fn kv(key: &str, value: Value) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue { value: Some(value) }),
+    }
+}
+
+fn string(key: &str, value: &str) -> KeyValue {
+    kv(key, Value::StringValue(value.to_owned()))
+}
+
+fn limits(
+    attribute_value_length: Option<usize>,
+    attribute_count: Option<usize>,
+    event_count: Option<usize>,
+    link_count: Option<usize>,
+) -> Limits {
+    Limits::new(&LimitsConfig {
+        attribute_value_length,
+        attribute_count,
+        event_count,
+        link_count,
+    })
+}
+
+fn traces(span: Span) -> ExportTraceServiceRequest {
+    ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Some(Resource {
+                attributes: vec![string("service.name", "checkout-service")],
+                ..Default::default()
+            }),
+            scope_spans: vec![ScopeSpans {
+                spans: vec![span],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+}
+
+fn event(name: &str, attributes: Vec<KeyValue>) -> Event {
+    Event {
+        name: name.to_owned(),
+        attributes,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn drops_attributes_events_and_links_beyond_the_limits() {
+    let mut req = traces(Span {
+        attributes: vec![string("a", "1"), string("b", "2"), string("c", "3")],
+        dropped_attributes_count: 1,
+        events: vec![
+            event("first", vec![string("a", "1"), string("b", "2")]),
+            event("second", vec![]),
+            event("third", vec![]),
+        ],
+        links: vec![Link::default(), Link::default()],
+        dropped_links_count: 4,
+        ..Default::default()
+    });
+
+    limits(None, Some(1), Some(2), Some(1)).limit_traces(&mut req);
+
+    let span = &req.resource_spans[0].scope_spans[0].spans[0];
+    assert_eq!(span.attributes, [string("a", "1")]);
+    assert_eq!(span.dropped_attributes_count, 3);
+    let events: Vec<_> = span.events.iter().map(|e| &e.name[..]).collect();
+    assert_eq!(events, ["first", "second"]);
+    assert_eq!(span.dropped_events_count, 1);
+    assert_eq!(span.events[0].attributes, [string("a", "1")]);
+    assert_eq!(span.events[0].dropped_attributes_count, 1);
+    assert_eq!(span.links.len(), 1);
+    assert_eq!(span.dropped_links_count, 5);
+}
+
+#[test]
+fn truncates_values_by_character() {
+    let mut req = traces(Span {
+        attributes: vec![
+            string("exception.stacktrace", "héllo world"),
+            string("short", "abc"),
+            kv("body", Value::BytesValue(b"0123456789".to_vec())),
+            kv(
+                "nested",
+                Value::ArrayValue(ArrayValue {
+                    values: vec![AnyValue {
+                        value: Some(Value::KvlistValue(KeyValueList {
+                            values: vec![string("inner", "abcdefgh")],
+                        })),
+                    }],
+                }),
+            ),
+            kv("count", Value::IntValue(1234567)),
+        ],
+        ..Default::default()
+    });
+
+    limits(Some(5), None, None, None).limit_traces(&mut req);
+
+    let rs = &req.resource_spans[0];
+    assert_eq!(
+        rs.resource.as_ref().unwrap().attributes,
+        [string("service.name", "check")]
+    );
+    let span = &rs.scope_spans[0].spans[0];
+    assert_eq!(
+        span.attributes,
+        [
+            string("exception.stacktrace", "héllo"),
+            string("short", "abc"),
+            kv("body", Value::BytesValue(b"01234".to_vec())),
+            kv(
+                "nested",
+                Value::ArrayValue(ArrayValue {
+                    values: vec![AnyValue {
+                        value: Some(Value::KvlistValue(KeyValueList {
+                            values: vec![string("inner", "abcde")],
+                        })),
+                    }],
+                }),
+            ),
+            kv("count", Value::IntValue(1234567)),
+        ]
+    );
+    assert_eq!(span.dropped_attributes_count, 0);
+}
+
+#[test]
+fn limits_log_record_attributes() {
+    let mut req = ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs {
+            scope_logs: vec![ScopeLogs {
+                log_records: vec![LogRecord {
+                    attributes: vec![string("a", "request body"), string("b", "2")],
+                    body: Some(AnyValue {
+                        value: Some(Value::StringValue("left as is".to_owned())),
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    };
+
+    limits(Some(7), Some(1), None, None).limit_logs(&mut req);
+
+    let record = &req.resource_logs[0].scope_logs[0].log_records[0];
+    assert_eq!(record.attributes, [string("a", "request")]);
+    assert_eq!(record.dropped_attributes_count, 1);
+    assert_eq!(
+        record.body.as_ref().unwrap().value,
+        Some(Value::StringValue("left as is".to_owned()))
+    );
+}
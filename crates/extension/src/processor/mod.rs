@@ -0,0 +1,412 @@
+//! Processing stages applied to buffered payloads before export.
+//!
+//! The [`Pipeline`] runs on the snapshot taken by `OutboundBuffer` just before
+//! it is handed to the exporter. Payloads that already went through the
+//! pipeline (data prepended back after a failed export) are skipped, so every
+//! payload is processed exactly once.
+//!
+//! Metrics written as CloudWatch EMF are written by the last stage, which
+//! makes sure every data point is written once even when exports are retried.
+
+mod aggregation;
+mod cardinality;
+mod enrichment;
+mod filter;
+mod limits;
+mod redaction;
+mod sampling;
+mod spanmetrics;
+mod tail_sampling;
+mod temporality;
+mod transform;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use prost::Message;
+use tracing::warn;
+
+use crate::buffers::{BufferData, Signal, SignalBuffer};
+use crate::config::{Config, EmfMode, SamplingMode};
+use crate::emf::EmfSink;
+use crate::merge;
+use crate::proto::opentelemetry::proto::{
+    collector::{
+        logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+        trace::v1::ExportTraceServiceRequest,
+    },
+    common::v1::{InstrumentationScope, KeyValue},
+    metrics::v1::DataPointFlags,
+    resource::v1::Resource,
+};
+use crate::self_metrics::RelayMetrics;
+
+use self::cardinality::CardinalityLimiter;
+use self::enrichment::ResourceEnricher;
+use self::filter::Filter;
+use self::limits::Limits;
+use self::redaction::Redactor;
+use self::sampling::TraceSampler;
+use self::spanmetrics::SpanMetrics;
+pub use self::tail_sampling::TailSampler;
+pub use self::temporality::TemporalityConverter;
+use self::transform::Transformer;
+
+/// The configured processing stages, in the order they run.
+#[derive(Default)]
+pub struct Pipeline {
+    enricher: Option<ResourceEnricher>,
+    transformer: Option<Transformer>,
+    filter: Option<Filter>,
+    spanmetrics: Option<SpanMetrics>,
+    /// Traces are only accepted to derive span metrics from.
+    drop_traces: bool,
+    sampler: Option<TraceSampler>,
+    redactor: Option<Redactor>,
+    limits: Option<Limits>,
+    cardinality: Option<CardinalityLimiter>,
+    aggregate_metrics: bool,
+    temporality: Option<TemporalityConverter>,
+    emf: Option<EmfSink>,
+    /// Shared with the buffer, which sets it.
+    metrics: Arc<RelayMetrics>,
+}
+
+impl Pipeline {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            enricher: (!config.resource_attributes.is_empty())
+                .then(|| ResourceEnricher::new(&config.resource_attributes)),
+            transformer: (!config.transform.is_empty())
+                .then(|| Transformer::new(&config.transform)),
+            filter: config.filter.as_ref().map(Filter::new),
+            // Under tail sampling, spans are counted as they arrive instead.
+            spanmetrics: config
+                .spanmetrics
+                .as_ref()
+                .filter(|_| {
+                    !config
+                        .sampling
+                        .as_ref()
+                        .is_some_and(|s| s.mode == SamplingMode::Tail)
+                })
+                .map(SpanMetrics::new),
+            drop_traces: !config.enabled_signals.is_enabled(Signal::Traces),
+            // Tail sampling decides per invocation in the event loop instead.
+            sampler: config
+                .sampling
+                .as_ref()
+                .filter(|s| s.mode == SamplingMode::Head)
+                .map(TraceSampler::new),
+            redactor: config.redaction.as_ref().map(Redactor::new),
+            limits: config.limits.as_ref().map(Limits::new),
+            cardinality: config
+                .metrics_cardinality_limit
+                .map(CardinalityLimiter::new),
+            aggregate_metrics: config.aggregate_metrics,
+            temporality: config.temporality.as_ref().map(TemporalityConverter::new),
+            emf: config.emf.as_ref().map(EmfSink::new),
+            metrics: Arc::default(),
+        }
+    }
+
+    /// The stages that run before span metrics are derived, for deriving
+    /// them ahead of tail sampling in the event loop, or `None` without span
+    /// metrics under tail sampling. Spans are enriched, transformed and
+    /// filtered as in the pipeline, so both sampling modes derive the same
+    /// metrics.
+    pub fn tail_spanmetrics(config: &Config) -> Option<Self> {
+        let spanmetrics = config.spanmetrics.as_ref().filter(|_| {
+            config
+                .sampling
+                .as_ref()
+                .is_some_and(|s| s.mode == SamplingMode::Tail)
+        })?;
+        let Self {
+            enricher,
+            transformer,
+            filter,
+            ..
+        } = Self::new(config);
+        Some(Self {
+            enricher,
+            transformer,
+            filter,
+            spanmetrics: Some(SpanMetrics::new(spanmetrics)),
+            ..Default::default()
+        })
+    }
+
+    fn has_trace_stages(&self) -> bool {
+        self.changes_resources()
+            || self.filter.is_some()
+            || self.spanmetrics.is_some()
+            || self.drop_traces
+            || self.sampler.is_some()
+            || self.redactor.is_some()
+            || self.limits.is_some()
+    }
+
+    fn has_metric_stages(&self) -> bool {
+        self.changes_resources()
+            || self.filter.is_some()
+            || self.redactor.is_some()
+            || self.limits.is_some()
+            || self.cardinality.is_some()
+            || self.aggregate_metrics
+            || self.temporality.is_some()
+            || self.emf.is_some()
+    }
+
+    fn has_log_stages(&self) -> bool {
+        self.changes_resources()
+            || self.filter.is_some()
+            || self.redactor.is_some()
+            || self.limits.is_some()
+    }
+
+    fn changes_resources(&self) -> bool {
+        self.enricher.is_some() || self.transformer.is_some()
+    }
+
+    /// Record self-observability counters, e.g. filtered records, in `metrics`.
+    pub fn set_metrics(&mut self, metrics: Arc<RelayMetrics>) {
+        self.metrics = metrics;
+    }
+
+    /// Drop state taken over from a SnapStart snapshot.
+    pub fn reset(&mut self) {
+        if let Some(cardinality) = &mut self.cardinality {
+            cardinality.reset();
+        }
+        if let Some(temporality) = &mut self.temporality {
+            temporality.reset();
+        }
+        if let Some(emf) = &mut self.emf {
+            emf.reset();
+        }
+    }
+
+    /// Run all stages over the unprocessed payloads in `data`.
+    pub fn process(&mut self, data: &mut BufferData) {
+        if self.has_trace_stages() {
+            let mut derived = None;
+            process_signal(&mut data.traces, merge::merge_traces, |req| {
+                derived = self.process_traces(req)
+            });
+            // Derived metrics go through the metric stages below.
+            if let Some(metrics) = derived.filter(|m| !m.resource_metrics.is_empty()) {
+                data.push(Signal::Metrics, Bytes::from(metrics.encode_to_vec()));
+            }
+        }
+        if self.has_metric_stages() {
+            process_signal(&mut data.metrics, merge::merge_metrics, |req| {
+                self.process_metrics(req)
+            });
+        }
+        if self.has_log_stages() {
+            process_signal(&mut data.logs, merge::merge_logs, |req| {
+                self.process_logs(req)
+            });
+        }
+    }
+
+    /// Encoded span metrics for one encoded trace payload, derived after the
+    /// stages that precede them in the pipeline, or `None` if no spans are
+    /// left. The payload itself is left as it is.
+    pub fn derive_span_metrics(&mut self, payload: &[u8]) -> Option<Bytes> {
+        self.spanmetrics.as_ref()?;
+        let mut req = match ExportTraceServiceRequest::decode(payload) {
+            Ok(req) => req,
+            Err(e) => {
+                warn!(error = %e, "cannot derive span metrics from malformed payload");
+                return None;
+            }
+        };
+        // Filtered spans are counted when the payload reaches the pipeline.
+        self.prepare_traces(&mut req);
+        let derived = self.spanmetrics.as_ref()?.derive(&req);
+        (!derived.resource_metrics.is_empty()).then(|| Bytes::from(derived.encode_to_vec()))
+    }
+
+    /// The stages before span metrics are derived. Returns the number of
+    /// records filtered.
+    fn prepare_traces(&mut self, req: &mut ExportTraceServiceRequest) -> u64 {
+        if let Some(enricher) = &self.enricher {
+            enricher.enrich_traces(req);
+        }
+        if let Some(transformer) = &self.transformer {
+            transformer.transform_traces(req);
+        }
+        // Resources that now have the same attributes are merged again.
+        if self.changes_resources() {
+            merge::regroup_traces(req);
+        }
+        self.filter
+            .as_mut()
+            .map_or(0, |filter| filter.filter_traces(req))
+    }
+
+    /// Returns the span metrics derived from `req`, if enabled.
+    fn process_traces(
+        &mut self,
+        req: &mut ExportTraceServiceRequest,
+    ) -> Option<ExportMetricsServiceRequest> {
+        let dropped = self.prepare_traces(req);
+        self.metrics.record_filtered(Signal::Traces, dropped);
+        let derived = self.spanmetrics.as_ref().map(|s| s.derive(req));
+        if self.drop_traces {
+            req.resource_spans.clear();
+        }
+        if let Some(sampler) = &self.sampler {
+            sampler.sample(req);
+        }
+        if let Some(redactor) = &self.redactor {
+            redactor.redact_traces(req);
+        }
+        if let Some(limits) = &self.limits {
+            limits.limit_traces(req);
+        }
+        derived
+    }
+
+    fn process_metrics(&mut self, req: &mut ExportMetricsServiceRequest) {
+        if let Some(enricher) = &self.enricher {
+            enricher.enrich_metrics(req);
+        }
+        if let Some(transformer) = &self.transformer {
+            transformer.transform_metrics(req);
+        }
+        // Resources that now have the same attributes are merged again.
+        if self.changes_resources() {
+            merge::regroup_metrics(req);
+        }
+        if let Some(filter) = &mut self.filter {
+            let dropped = filter.filter_metrics(req);
+            self.metrics.record_filtered(Signal::Metrics, dropped);
+        }
+        if let Some(redactor) = &self.redactor {
+            redactor.redact_metrics(req);
+        }
+        if let Some(limits) = &self.limits {
+            limits.limit_metrics(req);
+        }
+        if let Some(cardinality) = &mut self.cardinality {
+            cardinality.limit(req);
+        }
+        if self.aggregate_metrics {
+            aggregation::aggregate(req);
+        }
+        if let Some(temporality) = &mut self.temporality {
+            temporality.convert(req);
+        }
+        if let Some(emf) = &mut self.emf {
+            emf.write(req);
+            if emf.config().mode == EmfMode::Standalone {
+                req.resource_metrics.clear();
+            }
+        }
+    }
+
+    fn process_logs(&mut self, req: &mut ExportLogsServiceRequest) {
+        if let Some(enricher) = &self.enricher {
+            enricher.enrich_logs(req);
+        }
+        if let Some(transformer) = &self.transformer {
+            transformer.transform_logs(req);
+        }
+        // Resources that now have the same attributes are merged again.
+        if self.changes_resources() {
+            merge::regroup_logs(req);
+        }
+        if let Some(filter) = &mut self.filter {
+            let dropped = filter.filter_logs(req);
+            self.metrics.record_filtered(Signal::Logs, dropped);
+        }
+        if let Some(redactor) = &self.redactor {
+            redactor.redact_logs(req);
+        }
+        if let Some(limits) = &self.limits {
+            limits.limit_logs(req);
+        }
+    }
+}
+
+/// Merge the unprocessed payloads of one signal into a single request, run
+/// `stages` over it and put the re-encoded result back as one processed
+/// payload. Nothing is pushed back if the stages emptied the request.
+fn process_signal<M: Message + Default>(
+    buf: &mut SignalBuffer,
+    merge: fn(&VecDeque<Bytes>) -> M,
+    stages: impl FnOnce(&mut M),
+) {
+    let fresh = buf.take_unprocessed();
+    if fresh.is_empty() {
+        return;
+    }
+    let mut req = merge(&fresh);
+    stages(&mut req);
+    if req.encoded_len() > 0 {
+        buf.push_processed(Bytes::from(req.encode_to_vec()));
+    }
+}
+
+/// Match `text` against a pattern where `*` stands for any run of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // `split` always yields at least one element.
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*` in the pattern: exact match.
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Attributes encoded in key order, so that attribute order doesn't change
+/// the identity.
+pub(super) fn attributes_key(attributes: &[KeyValue]) -> Vec<u8> {
+    let sorted: BTreeMap<&str, &KeyValue> = attributes.iter().map(|kv| (&kv.key[..], kv)).collect();
+    let mut key = Vec::new();
+    for kv in sorted.into_values() {
+        kv.encode_length_delimited(&mut key)
+            .expect("Vec grows as needed");
+    }
+    key
+}
+
+pub(super) fn resource_key(resource: Option<&Resource>, schema_url: &str) -> Vec<u8> {
+    let mut key = resource.map_or_else(Vec::new, |r| attributes_key(&r.attributes));
+    key.extend_from_slice(schema_url.as_bytes());
+    key
+}
+
+pub(super) fn scope_key(scope: Option<&InstrumentationScope>) -> Vec<u8> {
+    let Some(scope) = scope else {
+        return Vec::new();
+    };
+    let mut key = attributes_key(&scope.attributes);
+    for part in [&scope.name, &scope.version] {
+        prost::encoding::encode_varint(part.len() as u64, &mut key);
+        key.extend_from_slice(part.as_bytes());
+    }
+    key
+}
+
+pub(super) fn has_no_value(flags: u32) -> bool {
+    flags & DataPointFlags::NoRecordedValueMask as u32 != 0
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,220 @@
+//! Attribute redaction.
+//!
+//! Applies the configured rules to every attribute list in a request —
+//! resource, scope, span, span event and link, metric data point and
+//! exemplar, and log record attributes — and to log bodies:
+//!
+//! - keys matching a delete glob are removed,
+//! - values of keys matching a hash glob are replaced with the hex SHA-256
+//!   of the salt followed by the value, so they can still be correlated,
+//! - every other string value has matches of the mask pattern replaced.
+//!
+//! Rules are applied recursively to map and array values.
+
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use super::glob_match;
+use crate::config::RedactionConfig;
+use crate::proto::opentelemetry::proto::{
+    collector::{
+        logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+        trace::v1::ExportTraceServiceRequest,
+    },
+    common::v1::{AnyValue, InstrumentationScope, KeyValue, any_value::Value},
+    metrics::v1::{Exemplar, metric::Data},
+    resource::v1::Resource,
+};
+
+/// Replacement for values matched by the mask pattern.
+const MASK: &str = "****";
+
+pub struct Redactor {
+    delete_keys: Vec<String>,
+    hash_keys: Vec<String>,
+    hash_salt: String,
+    mask: Option<Regex>,
+}
+
+impl Redactor {
+    pub fn new(config: &RedactionConfig) -> Self {
+        Self {
+            delete_keys: config.delete_keys.clone(),
+            hash_keys: config.hash_keys.clone(),
+            hash_salt: config.hash_salt.clone(),
+            mask: config.mask.clone(),
+        }
+    }
+
+    pub fn redact_traces(&self, req: &mut ExportTraceServiceRequest) {
+        for rs in &mut req.resource_spans {
+            self.resource(&mut rs.resource);
+            for ss in &mut rs.scope_spans {
+                self.scope(&mut ss.scope);
+                for span in &mut ss.spans {
+                    self.attributes(&mut span.attributes);
+                    for event in &mut span.events {
+                        self.attributes(&mut event.attributes);
+                    }
+                    for link in &mut span.links {
+                        self.attributes(&mut link.attributes);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn redact_metrics(&self, req: &mut ExportMetricsServiceRequest) {
+        for rm in &mut req.resource_metrics {
+            self.resource(&mut rm.resource);
+            for sm in &mut rm.scope_metrics {
+                self.scope(&mut sm.scope);
+                for metric in &mut sm.metrics {
+                    match &mut metric.data {
+                        Some(Data::Gauge(g)) => {
+                            for dp in &mut g.data_points {
+                                self.attributes(&mut dp.attributes);
+                                self.exemplars(&mut dp.exemplars);
+                            }
+                        }
+                        Some(Data::Sum(s)) => {
+                            for dp in &mut s.data_points {
+                                self.attributes(&mut dp.attributes);
+                                self.exemplars(&mut dp.exemplars);
+                            }
+                        }
+                        Some(Data::Histogram(h)) => {
+                            for dp in &mut h.data_points {
+                                self.attributes(&mut dp.attributes);
+                                self.exemplars(&mut dp.exemplars);
+                            }
+                        }
+                        Some(Data::ExponentialHistogram(h)) => {
+                            for dp in &mut h.data_points {
+                                self.attributes(&mut dp.attributes);
+                                self.exemplars(&mut dp.exemplars);
+                            }
+                        }
+                        Some(Data::Summary(s)) => {
+                            for dp in &mut s.data_points {
+                                self.attributes(&mut dp.attributes);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn redact_logs(&self, req: &mut ExportLogsServiceRequest) {
+        for rl in &mut req.resource_logs {
+            self.resource(&mut rl.resource);
+            for sl in &mut rl.scope_logs {
+                self.scope(&mut sl.scope);
+                for record in &mut sl.log_records {
+                    self.attributes(&mut record.attributes);
+                    if let Some(body) = &mut record.body {
+                        self.mask_value(body);
+                    }
+                }
+            }
+        }
+    }
+
+    fn resource(&self, resource: &mut Option<Resource>) {
+        if let Some(resource) = resource {
+            self.attributes(&mut resource.attributes);
+        }
+    }
+
+    fn scope(&self, scope: &mut Option<InstrumentationScope>) {
+        if let Some(scope) = scope {
+            self.attributes(&mut scope.attributes);
+        }
+    }
+
+    fn exemplars(&self, exemplars: &mut [Exemplar]) {
+        for exemplar in exemplars {
+            self.attributes(&mut exemplar.filtered_attributes);
+        }
+    }
+
+    fn attributes(&self, attrs: &mut Vec<KeyValue>) {
+        attrs.retain(|kv| !matches_any(&self.delete_keys, &kv.key));
+        for kv in attrs {
+            let Some(value) = &mut kv.value else {
+                continue;
+            };
+            if matches_any(&self.hash_keys, &kv.key) {
+                self.hash_value(value);
+            } else {
+                self.mask_value(value);
+            }
+        }
+    }
+
+    fn mask_value(&self, value: &mut AnyValue) {
+        match &mut value.value {
+            Some(Value::StringValue(s)) => {
+                if let Some(mask) = &self.mask
+                    && mask.is_match(s)
+                {
+                    *s = mask.replace_all(s, MASK).into_owned();
+                }
+            }
+            Some(Value::ArrayValue(array)) => {
+                for v in &mut array.values {
+                    self.mask_value(v);
+                }
+            }
+            Some(Value::KvlistValue(list)) => self.attributes(&mut list.values),
+            _ => {}
+        }
+    }
+
+    /// Replace scalars with their salted hash. Arrays and maps are hashed
+    /// element by element so their shape is preserved.
+    fn hash_value(&self, value: &mut AnyValue) {
+        let hashed = match &mut value.value {
+            Some(Value::StringValue(s)) => self.hash(s.as_bytes()),
+            Some(Value::BytesValue(b)) => self.hash(b),
+            Some(Value::IntValue(i)) => self.hash(i.to_string().as_bytes()),
+            Some(Value::DoubleValue(d)) => self.hash(d.to_string().as_bytes()),
+            Some(Value::BoolValue(b)) => self.hash(b.to_string().as_bytes()),
+            Some(Value::ArrayValue(array)) => {
+                for v in &mut array.values {
+                    self.hash_value(v);
+                }
+                return;
+            }
+            Some(Value::KvlistValue(list)) => {
+                for v in list.values.iter_mut().filter_map(|kv| kv.value.as_mut()) {
+                    self.hash_value(v);
+                }
+                return;
+            }
+            None => return,
+        };
+        value.value = Some(Value::StringValue(hashed));
+    }
+
+    fn hash(&self, data: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.hash_salt.as_bytes());
+        hasher.update(data);
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+fn matches_any(globs: &[String], key: &str) -> bool {
+    globs.iter().any(|glob| glob_match(glob, key))
+}
+
+#[cfg(test)]
+#[path = "redaction_tests.rs"]
+mod tests;
@@ -0,0 +1,240 @@
+use super::*;
+use crate::proto::opentelemetry::proto::{
+    common::v1::KeyValueList,
+    logs::v1::{LogRecord, ResourceLogs, ScopeLogs},
+    metrics::v1::{Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics},
+    trace::v1::{ResourceSpans, ScopeSpans, Span, span::Event},
+};
+
+fn string(s: &str) -> AnyValue {
+    AnyValue {
+        value: Some(Value::StringValue(s.to_owned())),
+    }
+}
+
+fn kv(key: &str, value: AnyValue) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(value),
+    }
+}
+
+fn get<'a>(attrs: &'a [KeyValue], key: &str) -> Option<&'a Value> {
+    attrs
+        .iter()
+        .find(|kv| kv.key == key)
+        .and_then(|kv| kv.value.as_ref())
+        .and_then(|v| v.value.as_ref())
+}
+
+fn redactor(delete: &[&str], hash: &[&str], mask: Option<&str>) -> Redactor {
+    Redactor::new(&RedactionConfig {
+        delete_keys: delete.iter().map(|s| s.to_string()).collect(),
+        hash_keys: hash.iter().map(|s| s.to_string()).collect(),
+        hash_salt: "salt".to_owned(),
+        mask: mask.map(|m| Regex::new(m).unwrap()),
+    })
+}
+
+fn trace_request(span: Span, resource_attrs: Vec<KeyValue>) -> ExportTraceServiceRequest {
+    ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Some(Resource {
+                attributes: resource_attrs,
+                ..Default::default()
+            }),
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans: vec![span],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    }
+}
+
+#[test]
+fn deletes_span_and_resource_attributes() {
+    let span = Span {
+        attributes: vec![
+            kv("http.request.header.authorization", string("Bearer x")),
+            kv("http.method", string("GET")),
+        ],
+        ..Default::default()
+    };
+    let mut req = trace_request(span, vec![kv("host.secret", string("s"))]);
+
+    redactor(&["http.request.header.*", "*.secret"], &[], None).redact_traces(&mut req);
+
+    let rs = &req.resource_spans[0];
+    assert!(rs.resource.as_ref().unwrap().attributes.is_empty());
+    let attrs = &rs.scope_spans[0].spans[0].attributes;
+    assert_eq!(attrs.len(), 1);
+    assert_eq!(attrs[0].key, "http.method");
+}
+
+#[test]
+fn hashes_values_with_salt() {
+    let span = Span {
+        attributes: vec![kv("user.email", string("a@example.com"))],
+        ..Default::default()
+    };
+    let mut req = trace_request(span, vec![]);
+
+    let r = redactor(&[], &["user.*"], None);
+    r.redact_traces(&mut req);
+
+    let Some(Value::StringValue(hashed)) = get(
+        &req.resource_spans[0].scope_spans[0].spans[0].attributes,
+        "user.email",
+    ) else {
+        panic!("expected string value");
+    };
+    assert_eq!(hashed, &r.hash(b"a@example.com"));
+    assert_eq!(hashed.len(), 64);
+    // Salted: differs from the plain hash of the value.
+    let unsalted: String = Sha256::digest(b"a@example.com")
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    assert_ne!(hashed, &unsalted);
+}
+
+#[test]
+fn hashes_non_string_scalars() {
+    let span = Span {
+        attributes: vec![kv(
+            "user.id",
+            AnyValue {
+                value: Some(Value::IntValue(42)),
+            },
+        )],
+        ..Default::default()
+    };
+    let mut req = trace_request(span, vec![]);
+
+    let r = redactor(&[], &["user.id"], None);
+    r.redact_traces(&mut req);
+
+    assert_eq!(
+        get(
+            &req.resource_spans[0].scope_spans[0].spans[0].attributes,
+            "user.id"
+        ),
+        Some(&Value::StringValue(r.hash(b"42")))
+    );
+}
+
+#[test]
+fn masks_span_and_event_attributes() {
+    let span = Span {
+        attributes: vec![kv(
+            "message",
+            string("contact a@example.com or b@example.org"),
+        )],
+        events: vec![Event {
+            name: "exception".to_owned(),
+            attributes: vec![kv(
+                "exception.message",
+                string("no match for c@example.net"),
+            )],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let mut req = trace_request(span, vec![]);
+
+    redactor(&[], &[], Some(r"[\w.]+@[\w.]+")).redact_traces(&mut req);
+
+    let span = &req.resource_spans[0].scope_spans[0].spans[0];
+    assert_eq!(
+        get(&span.attributes, "message"),
+        Some(&Value::StringValue("contact **** or ****".to_owned()))
+    );
+    assert_eq!(
+        get(&span.events[0].attributes, "exception.message"),
+        Some(&Value::StringValue("no match for ****".to_owned()))
+    );
+}
+
+#[test]
+fn redacts_log_bodies() {
+    let mut req = ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs {
+            resource: None,
+            scope_logs: vec![ScopeLogs {
+                scope: None,
+                log_records: vec![
+                    LogRecord {
+                        body: Some(string("token=abc123 accepted")),
+                        ..Default::default()
+                    },
+                    LogRecord {
+                        body: Some(AnyValue {
+                            value: Some(Value::KvlistValue(KeyValueList {
+                                values: vec![
+                                    kv("password", string("hunter2")),
+                                    kv("msg", string("token=def456")),
+                                ],
+                            })),
+                        }),
+                        ..Default::default()
+                    },
+                ],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+
+    redactor(&["password"], &[], Some(r"token=\w+")).redact_logs(&mut req);
+
+    let records = &req.resource_logs[0].scope_logs[0].log_records;
+    assert_eq!(
+        records[0].body.as_ref().unwrap().value,
+        Some(Value::StringValue("**** accepted".to_owned()))
+    );
+    let Some(Value::KvlistValue(body)) = &records[1].body.as_ref().unwrap().value else {
+        panic!("expected kvlist body");
+    };
+    assert_eq!(body.values.len(), 1);
+    assert_eq!(
+        get(&body.values, "msg"),
+        Some(&Value::StringValue("****".to_owned()))
+    );
+}
+
+#[test]
+fn redacts_metric_data_point_attributes() {
+    let mut req = ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: None,
+            scope_metrics: vec![ScopeMetrics {
+                scope: None,
+                metrics: vec![Metric {
+                    name: "requests".to_owned(),
+                    data: Some(Data::Gauge(Gauge {
+                        data_points: vec![NumberDataPoint {
+                            attributes: vec![
+                                kv("user.email", string("a@example.com")),
+                                kv("route", string("/")),
+                            ],
+                            ..Default::default()
+                        }],
+                    })),
+                    ..Default::default()
+                }],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+
+    redactor(&["user.email"], &[], None).redact_metrics(&mut req);
+
+    let Some(Data::Gauge(g)) = &req.resource_metrics[0].scope_metrics[0].metrics[0].data else {
+        panic!("expected gauge");
+    };
+    assert_eq!(g.data_points[0].attributes.len(), 1);
+    assert_eq!(g.data_points[0].attributes[0].key, "route");
+}
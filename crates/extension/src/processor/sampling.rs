@@ -0,0 +1,207 @@
+//! Head-based probabilistic trace sampling.
+//!
+//! Implements the consistent-probability `TraceIdRatioBased` sampler from the
+//! OpenTelemetry [tracestate probability sampling] spec: each trace has a
+//! 56-bit randomness value `R` (the explicit `ot=rv:` sub-key, or the least
+//! significant 56 bits of the trace ID), and a span is kept when `R >= T` for
+//! the rejection threshold `T = (1 - ratio) * 2^56`. Every function in a
+//! trace therefore makes the same decision for the same ratio.
+//!
+//! When a span is kept, its `ot=th:` threshold is raised to the relay's
+//! threshold so downstream consumers can compute the adjusted count. Error
+//! spans kept only because of `keep_errors` get `th:0`, an adjusted count of
+//! one.
+//!
+//! [tracestate probability sampling]: https://opentelemetry.io/docs/specs/otel/trace/tracestate-probability-sampling/
+
+use tracing::debug;
+
+use crate::config::SamplingConfig;
+use crate::proto::opentelemetry::proto::{
+    collector::trace::v1::ExportTraceServiceRequest,
+    trace::v1::{Span, status::StatusCode},
+};
+
+/// `2^56` — thresholds and randomness values are 56-bit integers.
+const MAX_THRESHOLD: u64 = 1 << 56;
+
+/// Number of hex digits in a full-precision threshold or randomness value.
+const HEX_DIGITS: usize = 14;
+
+pub struct TraceSampler {
+    /// Rejection threshold in `[0, 2^56]`. `2^56` rejects everything.
+    threshold: u64,
+    keep_errors: bool,
+}
+
+impl TraceSampler {
+    pub fn new(config: &SamplingConfig) -> Self {
+        Self {
+            threshold: ratio_to_threshold(config.ratio),
+            keep_errors: config.keep_errors,
+        }
+    }
+
+    /// Drop unsampled spans in place, pruning scopes and resources left empty.
+    pub fn sample(&self, req: &mut ExportTraceServiceRequest) {
+        let mut dropped = 0usize;
+        for rs in &mut req.resource_spans {
+            for ss in &mut rs.scope_spans {
+                ss.spans.retain_mut(|span| {
+                    let keep = self.keep(span);
+                    if !keep {
+                        dropped += 1;
+                    }
+                    keep
+                });
+            }
+            rs.scope_spans.retain(|ss| !ss.spans.is_empty());
+        }
+        req.resource_spans.retain(|rs| !rs.scope_spans.is_empty());
+
+        if dropped > 0 {
+            debug!(dropped, "sampler dropped spans");
+        }
+    }
+
+    /// Sampling decision for a single span. Updates `trace_state` when kept.
+    fn keep(&self, span: &mut Span) -> bool {
+        if self.threshold == 0 {
+            return true;
+        }
+
+        let is_error = span
+            .status
+            .as_ref()
+            .is_some_and(|s| s.code == StatusCode::Error as i32);
+
+        let ot = ot_value(&span.trace_state);
+        let randomness = ot
+            .and_then(|ot| ot_sub_key(ot, "rv"))
+            .and_then(parse_randomness)
+            .or_else(|| trace_id_randomness(&span.trace_id));
+
+        let Some(randomness) = randomness else {
+            // Without a valid trace ID there is nothing consistent to decide on.
+            return true;
+        };
+
+        if randomness < self.threshold {
+            if !(self.keep_errors && is_error) {
+                return false;
+            }
+            // Kept regardless of the ratio, so the span stands only for
+            // itself; an inherited upstream threshold would inflate counts.
+            span.trace_state = set_threshold(&span.trace_state, 0);
+            return true;
+        }
+
+        let existing = ot
+            .and_then(|ot| ot_sub_key(ot, "th"))
+            .and_then(parse_threshold);
+        if existing.is_none_or(|th| th < self.threshold) {
+            span.trace_state = set_threshold(&span.trace_state, self.threshold);
+        }
+        true
+    }
+}
+
+fn ratio_to_threshold(ratio: f64) -> u64 {
+    if ratio <= 0.0 {
+        MAX_THRESHOLD
+    } else {
+        // f64 has 53 bits of mantissa; the rounding error is irrelevant at
+        // this scale and the spec allows thresholds to be approximated.
+        ((1.0 - ratio) * MAX_THRESHOLD as f64).round() as u64
+    }
+}
+
+/// Least significant 56 bits of a 16-byte trace ID.
+fn trace_id_randomness(trace_id: &[u8]) -> Option<u64> {
+    let bytes: [u8; 16] = trace_id.try_into().ok()?;
+    if bytes == [0; 16] {
+        return None;
+    }
+    let mut low = [0u8; 8];
+    low[1..].copy_from_slice(&bytes[9..]);
+    Some(u64::from_be_bytes(low))
+}
+
+/// Explicit randomness must be exactly 14 hex digits.
+fn parse_randomness(raw: &str) -> Option<u64> {
+    if raw.len() != HEX_DIGITS {
+        return None;
+    }
+    u64::from_str_radix(raw, 16).ok()
+}
+
+/// Thresholds are 1–14 hex digits with trailing zeros omitted.
+fn parse_threshold(raw: &str) -> Option<u64> {
+    if raw.is_empty() || raw.len() > HEX_DIGITS || !raw.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u64::from_str_radix(raw, 16).ok()?;
+    Some(value << (4 * (HEX_DIGITS - raw.len())))
+}
+
+fn format_threshold(threshold: u64) -> String {
+    let full = format!("{threshold:014x}");
+    let trimmed = full.trim_end_matches('0');
+    if trimmed.is_empty() {
+        "0".to_owned()
+    } else {
+        trimmed.to_owned()
+    }
+}
+
+/// The value of the `ot` list member of a W3C `tracestate` header.
+fn ot_value(trace_state: &str) -> Option<&str> {
+    trace_state
+        .split(',')
+        .filter_map(|member| member.trim().split_once('='))
+        .find(|(key, _)| *key == "ot")
+        .map(|(_, value)| value)
+}
+
+/// A `key:value` sub-key inside the `ot` tracestate value.
+fn ot_sub_key<'a>(ot: &'a str, key: &str) -> Option<&'a str> {
+    ot.split(';')
+        .filter_map(|field| field.split_once(':'))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+/// Rewrite `trace_state` with `ot=th:` set to `threshold`, preserving other
+/// `ot` sub-keys and list members. The modified `ot` member moves to the
+/// front, as required for updated tracestate entries.
+fn set_threshold(trace_state: &str, threshold: u64) -> String {
+    let th = format!("th:{}", format_threshold(threshold));
+
+    let mut ot_fields = vec![th];
+    let mut others = Vec::new();
+    for member in trace_state.split(',').map(str::trim) {
+        if member.is_empty() {
+            continue;
+        }
+        match member.split_once('=') {
+            Some(("ot", value)) => ot_fields.extend(
+                value
+                    .split(';')
+                    .filter(|f| !f.is_empty() && !f.starts_with("th:"))
+                    .map(str::to_owned),
+            ),
+            _ => others.push(member),
+        }
+    }
+
+    let mut out = format!("ot={}", ot_fields.join(";"));
+    for member in others {
+        out.push(',');
+        out.push_str(member);
+    }
+    out
+}
+
+#[cfg(test)]
+#[path = "sampling_tests.rs"]
+mod tests;
@@ -0,0 +1,199 @@
+use super::*;
+use crate::config::SamplingMode;
+use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Status};
+
+/// A trace ID whose low 56 bits are `randomness`.
+fn trace_id(randomness: u64) -> Vec<u8> {
+    let mut id = vec![0xaa; 9];
+    id.extend_from_slice(&randomness.to_be_bytes()[1..]);
+    id
+}
+
+fn span(randomness: u64) -> Span {
+    Span {
+        trace_id: trace_id(randomness),
+        span_id: vec![1; 8],
+        ..Default::default()
+    }
+}
+
+fn error_span(randomness: u64) -> Span {
+    Span {
+        status: Some(Status {
+            message: String::new(),
+            code: StatusCode::Error as i32,
+        }),
+        ..span(randomness)
+    }
+}
+
+fn request(spans: Vec<Span>) -> ExportTraceServiceRequest {
+    ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans,
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    }
+}
+
+fn sampler(ratio: f64, keep_errors: bool) -> TraceSampler {
+    TraceSampler::new(&SamplingConfig {
+        ratio,
+        keep_errors,
+        mode: SamplingMode::Head,
+        latency_threshold: None,
+    })
+}
+
+fn kept(req: &ExportTraceServiceRequest) -> usize {
+    req.resource_spans
+        .iter()
+        .flat_map(|rs| &rs.scope_spans)
+        .map(|ss| ss.spans.len())
+        .sum()
+}
+
+#[test]
+fn ratio_one_keeps_everything_untouched() {
+    let mut req = request(vec![span(0), span(MAX_THRESHOLD - 1)]);
+    sampler(1.0, false).sample(&mut req);
+    assert_eq!(kept(&req), 2);
+    assert!(
+        req.resource_spans[0].scope_spans[0].spans[0]
+            .trace_state
+            .is_empty()
+    );
+}
+
+#[test]
+fn ratio_zero_drops_everything_and_prunes_empty_containers() {
+    let mut req = request(vec![span(0), span(MAX_THRESHOLD - 1)]);
+    sampler(0.0, false).sample(&mut req);
+    assert!(req.resource_spans.is_empty());
+}
+
+#[test]
+fn half_ratio_splits_on_randomness() {
+    // Threshold for 0.5 is 2^55: randomness below is dropped, at or above kept.
+    let mut req = request(vec![span((1 << 55) - 1), span(1 << 55)]);
+    sampler(0.5, false).sample(&mut req);
+    assert_eq!(kept(&req), 1);
+    let survivor = &req.resource_spans[0].scope_spans[0].spans[0];
+    assert_eq!(survivor.trace_id, trace_id(1 << 55));
+    assert_eq!(survivor.trace_state, "ot=th:8");
+}
+
+#[test]
+fn decision_is_consistent_for_all_spans_of_a_trace() {
+    let mut req = request(vec![span(42), span(42), span(1 << 55), span(1 << 55)]);
+    sampler(0.5, false).sample(&mut req);
+    let spans = &req.resource_spans[0].scope_spans[0].spans;
+    assert_eq!(spans.len(), 2);
+    assert!(spans.iter().all(|s| s.trace_id == trace_id(1 << 55)));
+}
+
+#[test]
+fn keep_errors_overrides_drop() {
+    let mut req = request(vec![span(0), error_span(0)]);
+    sampler(0.0, true).sample(&mut req);
+    assert_eq!(kept(&req), 1);
+    assert!(
+        req.resource_spans[0].scope_spans[0].spans[0]
+            .status
+            .is_some()
+    );
+}
+
+#[test]
+fn error_spans_kept_for_keep_errors_count_only_themselves() {
+    let mut upstream = error_span(0);
+    upstream.trace_state = "vendor=x,ot=th:c;rv:00000000000000".to_owned();
+    let mut req = request(vec![upstream, error_span(1)]);
+    sampler(0.5, true).sample(&mut req);
+    let spans = &req.resource_spans[0].scope_spans[0].spans;
+    assert_eq!(spans[0].trace_state, "ot=th:0;rv:00000000000000,vendor=x");
+    assert_eq!(spans[1].trace_state, "ot=th:0");
+}
+
+#[test]
+fn error_spans_dropped_without_keep_errors() {
+    let mut req = request(vec![error_span(0)]);
+    sampler(0.0, false).sample(&mut req);
+    assert_eq!(kept(&req), 0);
+}
+
+#[test]
+fn explicit_randomness_overrides_trace_id() {
+    let mut low = span(0);
+    low.trace_state = "ot=rv:ffffffffffffff".to_owned();
+    let mut high = span(MAX_THRESHOLD - 1);
+    high.trace_state = "ot=rv:00000000000000".to_owned();
+
+    let mut req = request(vec![low, high]);
+    sampler(0.5, false).sample(&mut req);
+    let spans = &req.resource_spans[0].scope_spans[0].spans;
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0].trace_state, "ot=th:8;rv:ffffffffffffff");
+}
+
+#[test]
+fn stricter_upstream_threshold_is_preserved() {
+    // Upstream sampled at 1/4 (th:c); relay samples at 1/2 (th:8).
+    let mut s = span(MAX_THRESHOLD - 1);
+    s.trace_state = "ot=th:c,vendor=x".to_owned();
+    let mut req = request(vec![s]);
+    sampler(0.5, false).sample(&mut req);
+    assert_eq!(
+        req.resource_spans[0].scope_spans[0].spans[0].trace_state,
+        "ot=th:c,vendor=x"
+    );
+}
+
+#[test]
+fn looser_upstream_threshold_is_raised() {
+    let mut s = span(MAX_THRESHOLD - 1);
+    s.trace_state = "vendor=x,ot=th:4;p:8".to_owned();
+    let mut req = request(vec![s]);
+    sampler(0.5, false).sample(&mut req);
+    assert_eq!(
+        req.resource_spans[0].scope_spans[0].spans[0].trace_state,
+        "ot=th:8;p:8,vendor=x"
+    );
+}
+
+#[test]
+fn invalid_trace_id_is_kept() {
+    let mut s = span(0);
+    s.trace_id = vec![1, 2, 3];
+    let mut req = request(vec![s]);
+    sampler(0.0, false).sample(&mut req);
+    assert_eq!(kept(&req), 1);
+}
+
+#[test]
+fn threshold_encoding_round_trips() {
+    for (raw, value) in [
+        ("0", 0),
+        ("8", 1 << 55),
+        ("c", 0xc << 52),
+        ("fffffffffffff", 0xfffffffffffff0),
+    ] {
+        assert_eq!(parse_threshold(raw), Some(value), "parse {raw}");
+        assert_eq!(format_threshold(value), raw, "format {raw}");
+    }
+    assert_eq!(parse_threshold(""), None);
+    assert_eq!(parse_threshold("123456789abcdef"), None);
+    assert_eq!(parse_threshold("xyz"), None);
+}
+
+#[test]
+fn ratio_to_threshold_bounds() {
+    assert_eq!(ratio_to_threshold(1.0), 0);
+    assert_eq!(ratio_to_threshold(0.5), 1 << 55);
+    assert_eq!(ratio_to_threshold(0.0), MAX_THRESHOLD);
+}
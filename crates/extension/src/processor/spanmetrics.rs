@@ -0,0 +1,217 @@
+//! Span metrics connector.
+//!
+//! Derives request rate, error and duration metrics from spans, like the
+//! collector's `spanmetrics` connector, so they are available without running
+//! a collector. For every combination of span name, kind, status code and
+//! configured dimensions it produces:
+//!
+//! - `traces.span.metrics.calls`, a delta sum of spans (errors are the calls
+//!   with `status.code = STATUS_CODE_ERROR`);
+//! - `traces.span.metrics.duration`, a delta histogram of span durations in
+//!   milliseconds.
+//!
+//! Dimensions are looked up on the span and then on its resource. The
+//! metrics are reported under the span's resource, so `service.name` carries
+//! over. The connector runs after resource attributes, transforms and filters
+//! but before sampling, head or tail, so the metrics count every span the
+//! function produced that the filters keep.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use super::attributes_key;
+use crate::config::SpanMetricsConfig;
+use crate::proto::opentelemetry::proto::{
+    collector::{metrics::v1::ExportMetricsServiceRequest, trace::v1::ExportTraceServiceRequest},
+    common::v1::{AnyValue, InstrumentationScope, KeyValue, any_value::Value},
+    metrics::v1::{
+        AggregationTemporality, Histogram, HistogramDataPoint, Metric, NumberDataPoint,
+        ResourceMetrics, ScopeMetrics, Sum, metric::Data, number_data_point,
+    },
+    trace::v1::{Span, span::SpanKind, status::StatusCode},
+};
+
+pub const SCOPE_NAME: &str = "lambda-otel-relay/spanmetrics";
+
+pub struct SpanMetrics {
+    config: SpanMetricsConfig,
+}
+
+/// Running totals for one combination of dimensions.
+struct Stream {
+    attributes: Vec<KeyValue>,
+    start: u64,
+    end: u64,
+    calls: u64,
+    sum_ms: f64,
+    min_ms: f64,
+    max_ms: f64,
+    bucket_counts: Vec<u64>,
+}
+
+impl SpanMetrics {
+    pub fn new(config: &SpanMetricsConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Metrics for the spans in `req`. Empty if there are no spans.
+    pub fn derive(&self, req: &ExportTraceServiceRequest) -> ExportMetricsServiceRequest {
+        let resource_metrics = req
+            .resource_spans
+            .iter()
+            .filter_map(|rs| {
+                let resource = rs.resource.as_ref().map_or(&[][..], |r| &r.attributes[..]);
+                let spans = rs.scope_spans.iter().flat_map(|ss| &ss.spans);
+                let streams = self.streams(spans, resource);
+                if streams.is_empty() {
+                    return None;
+                }
+                Some(ResourceMetrics {
+                    resource: rs.resource.clone(),
+                    scope_metrics: vec![ScopeMetrics {
+                        scope: Some(InstrumentationScope {
+                            name: SCOPE_NAME.to_owned(),
+                            ..Default::default()
+                        }),
+                        metrics: self.metrics(streams),
+                        schema_url: String::new(),
+                    }],
+                    schema_url: rs.schema_url.clone(),
+                })
+            })
+            .collect();
+        ExportMetricsServiceRequest { resource_metrics }
+    }
+
+    fn streams<'a>(
+        &self,
+        spans: impl Iterator<Item = &'a Span>,
+        resource: &[KeyValue],
+    ) -> Vec<Stream> {
+        let mut streams: Vec<Stream> = Vec::new();
+        let mut index: HashMap<Vec<u8>, usize> = HashMap::new();
+        for span in spans {
+            let attributes = self.dimensions(span, resource);
+            let i = match index.entry(attributes_key(&attributes)) {
+                Entry::Occupied(e) => *e.get(),
+                Entry::Vacant(e) => {
+                    e.insert(streams.len());
+                    streams.push(Stream {
+                        attributes,
+                        start: span.start_time_unix_nano,
+                        end: span.end_time_unix_nano,
+                        calls: 0,
+                        sum_ms: 0.0,
+                        min_ms: f64::INFINITY,
+                        max_ms: f64::NEG_INFINITY,
+                        bucket_counts: vec![0; self.config.buckets.len() + 1],
+                    });
+                    streams.len() - 1
+                }
+            };
+            let stream = &mut streams[i];
+            let ms = span
+                .end_time_unix_nano
+                .saturating_sub(span.start_time_unix_nano) as f64
+                / 1_000_000.0;
+            stream.start = stream.start.min(span.start_time_unix_nano);
+            stream.end = stream.end.max(span.end_time_unix_nano);
+            stream.calls += 1;
+            stream.sum_ms += ms;
+            stream.min_ms = stream.min_ms.min(ms);
+            stream.max_ms = stream.max_ms.max(ms);
+            // Bucket `i` holds values in `(buckets[i - 1], buckets[i]]`.
+            stream.bucket_counts[self.config.buckets.partition_point(|&b| b < ms)] += 1;
+        }
+        streams
+    }
+
+    fn dimensions(&self, span: &Span, resource: &[KeyValue]) -> Vec<KeyValue> {
+        let kind = SpanKind::try_from(span.kind).unwrap_or(SpanKind::Unspecified);
+        let status = span
+            .status
+            .as_ref()
+            .and_then(|s| StatusCode::try_from(s.code).ok())
+            .unwrap_or(StatusCode::Unset);
+        let mut attributes = vec![
+            string("span.name", &span.name),
+            string("span.kind", kind.as_str_name()),
+            string("status.code", status.as_str_name()),
+        ];
+        for key in &self.config.dimensions {
+            let found = span
+                .attributes
+                .iter()
+                .chain(resource)
+                .find(|kv| &kv.key == key);
+            if let Some(kv) = found {
+                attributes.push(kv.clone());
+            }
+        }
+        attributes
+    }
+
+    fn metrics(&self, streams: Vec<Stream>) -> Vec<Metric> {
+        let delta = AggregationTemporality::Delta as i32;
+        let calls = streams
+            .iter()
+            .map(|s| NumberDataPoint {
+                attributes: s.attributes.clone(),
+                start_time_unix_nano: s.start,
+                time_unix_nano: s.end,
+                value: Some(number_data_point::Value::AsInt(s.calls as i64)),
+                ..Default::default()
+            })
+            .collect();
+        let durations = streams
+            .into_iter()
+            .map(|s| HistogramDataPoint {
+                attributes: s.attributes,
+                start_time_unix_nano: s.start,
+                time_unix_nano: s.end,
+                count: s.calls,
+                sum: Some(s.sum_ms),
+                bucket_counts: s.bucket_counts,
+                explicit_bounds: self.config.buckets.clone(),
+                min: Some(s.min_ms),
+                max: Some(s.max_ms),
+                ..Default::default()
+            })
+            .collect();
+        vec![
+            Metric {
+                name: "traces.span.metrics.calls".to_owned(),
+                data: Some(Data::Sum(Sum {
+                    data_points: calls,
+                    aggregation_temporality: delta,
+                    is_monotonic: true,
+                })),
+                ..Default::default()
+            },
+            Metric {
+                name: "traces.span.metrics.duration".to_owned(),
+                unit: "ms".to_owned(),
+                data: Some(Data::Histogram(Histogram {
+                    data_points: durations,
+                    aggregation_temporality: delta,
+                })),
+                ..Default::default()
+            },
+        ]
+    }
+}
+
+fn string(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value.to_owned())),
+        }),
+    }
+}
+
+#[cfg(test)]
+#[path = "spanmetrics_tests.rs"]
+mod tests;
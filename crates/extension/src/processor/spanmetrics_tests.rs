@@ -0,0 +1,155 @@
+use super::*;
+use crate::proto::opentelemetry::proto::{
+    resource::v1::Resource,
+    trace::v1::{ResourceSpans, ScopeSpans, Status},
+};
+
+const MS: u64 = 1_000_000;
+
+fn span(name: &str, kind: SpanKind, error: bool, start_ms: u64, duration_ms: u64) -> Span {
+    Span {
+        name: name.to_owned(),
+        kind: kind as i32,
+        start_time_unix_nano: start_ms * MS,
+        end_time_unix_nano: (start_ms + duration_ms) * MS,
+        status: error.then(|| Status {
+            code: StatusCode::Error as i32,
+            ..Default::default()
+        }),
+        attributes: vec![string("http.route", "/orders")],
+        ..Default::default()
+    }
+}
+
+fn request(spans: Vec<Span>) -> ExportTraceServiceRequest {
+    ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Some(Resource {
+                attributes: vec![
+                    string("service.name", "checkout"),
+                    string("cloud.region", "eu-west-1"),
+                ],
+                ..Default::default()
+            }),
+            scope_spans: vec![ScopeSpans {
+                spans,
+                ..Default::default()
+            }],
+            schema_url: String::new(),
+        }],
+    }
+}
+
+fn connector(dimensions: &[&str]) -> SpanMetrics {
+    SpanMetrics::new(&SpanMetricsConfig {
+        dimensions: dimensions.iter().map(|d| d.to_string()).collect(),
+        buckets: vec![10.0, 100.0],
+    })
+}
+
+/// Attribute values of a data point, in order.
+fn values(attributes: &[KeyValue]) -> Vec<String> {
+    attributes
+        .iter()
+        .map(
+            |kv| match kv.value.as_ref().and_then(|v| v.value.as_ref()) {
+                Some(Value::StringValue(s)) => s.clone(),
+                other => panic!("unexpected value {other:?}"),
+            },
+        )
+        .collect()
+}
+
+#[test]
+fn counts_calls_and_durations_per_name_kind_and_status() {
+    let req = request(vec![
+        span("GET /orders", SpanKind::Server, false, 0, 5),
+        span("GET /orders", SpanKind::Server, false, 10, 10),
+        span("GET /orders", SpanKind::Server, true, 20, 150),
+        span("db.query", SpanKind::Client, false, 1, 2),
+    ]);
+
+    let derived = connector(&[]).derive(&req);
+
+    let rm = &derived.resource_metrics[0];
+    assert_eq!(rm.resource, req.resource_spans[0].resource);
+    let scope = &rm.scope_metrics[0];
+    assert_eq!(scope.scope.as_ref().unwrap().name, SCOPE_NAME);
+
+    let Some(Data::Sum(calls)) = &scope.metrics[0].data else {
+        panic!("expected a sum");
+    };
+    assert_eq!(scope.metrics[0].name, "traces.span.metrics.calls");
+    let calls: Vec<_> = calls
+        .data_points
+        .iter()
+        .map(|p| (values(&p.attributes), p.value))
+        .collect();
+    let int = |v| Some(number_data_point::Value::AsInt(v));
+    assert_eq!(
+        calls,
+        [
+            (
+                vec!["GET /orders", "SPAN_KIND_SERVER", "STATUS_CODE_UNSET"],
+                int(2)
+            ),
+            (
+                vec!["GET /orders", "SPAN_KIND_SERVER", "STATUS_CODE_ERROR"],
+                int(1)
+            ),
+            (
+                vec!["db.query", "SPAN_KIND_CLIENT", "STATUS_CODE_UNSET"],
+                int(1)
+            ),
+        ]
+        .map(|(attrs, v)| (attrs.into_iter().map(str::to_owned).collect(), v))
+    );
+
+    let Some(Data::Histogram(durations)) = &scope.metrics[1].data else {
+        panic!("expected a histogram");
+    };
+    assert_eq!(scope.metrics[1].unit, "ms");
+    let ok = &durations.data_points[0];
+    assert_eq!(ok.count, 2);
+    assert_eq!(ok.sum, Some(15.0));
+    assert_eq!(
+        ok.bucket_counts,
+        [2, 0, 0],
+        "10ms falls in the first bucket"
+    );
+    assert_eq!((ok.min, ok.max), (Some(5.0), Some(10.0)));
+    assert_eq!((ok.start_time_unix_nano, ok.time_unix_nano), (0, 20 * MS));
+    assert_eq!(durations.data_points[1].bucket_counts, [0, 0, 1]);
+}
+
+#[test]
+fn dimensions_come_from_the_span_then_the_resource() {
+    let req = request(vec![span("GET /orders", SpanKind::Server, false, 0, 5)]);
+
+    let derived = connector(&["http.route", "cloud.region", "missing"]).derive(&req);
+
+    let Some(Data::Sum(calls)) = &derived.resource_metrics[0].scope_metrics[0].metrics[0].data
+    else {
+        panic!("expected a sum");
+    };
+    assert_eq!(
+        values(&calls.data_points[0].attributes),
+        [
+            "GET /orders",
+            "SPAN_KIND_SERVER",
+            "STATUS_CODE_UNSET",
+            "/orders",
+            "eu-west-1"
+        ]
+    );
+}
+
+#[test]
+fn no_spans_no_metrics() {
+    assert!(
+        connector(&[])
+            .derive(&request(vec![]))
+            .resource_metrics
+            .is_empty()
+    );
+}
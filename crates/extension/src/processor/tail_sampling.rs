@@ -0,0 +1,191 @@
+//! Invocation-scoped tail sampling.
+//!
+//! Trace payloads that arrive while an invocation is running are held back
+//! until the platform reports `platform.runtimeDone` for it. The relay then
+//! knows how the invocation ended and can decide for all of its spans at once:
+//! failed, timed out and slow invocations are kept whole, and the rest go
+//! through the consistent-probability [`TraceSampler`] at the configured ratio.
+//!
+//! Attribution is by arrival: with `SimpleSpanProcessor` every span is
+//! exported before the handler returns, so everything received between the
+//! INVOKE event and `runtimeDone` belongs to that invocation. Payloads that
+//! arrive outside an invocation (init, or environments without INVOKE events
+//! such as Lambda Managed Instances) are passed through unsampled.
+
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use prost::Message;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use super::sampling::TraceSampler;
+use crate::config::SamplingConfig;
+use crate::merge;
+
+pub struct TailSampler {
+    sampler: TraceSampler,
+    latency_threshold: Option<Duration>,
+    /// Upper bound on held bytes across all invocations. Once exceeded, the
+    /// invocation receiving spans is released unsampled rather than growing
+    /// without limit.
+    max_held_bytes: Option<usize>,
+    /// The invocation currently receiving spans.
+    current: Option<Invocation>,
+    /// The invocation before it, still waiting for its `runtimeDone` event.
+    /// Telemetry API events are delivered asynchronously and regularly show
+    /// up after the next INVOKE.
+    previous: Option<Invocation>,
+}
+
+struct Invocation {
+    request_id: String,
+    started: Instant,
+    /// Wall-clock start, for [`TailSampler::reset`].
+    started_at: SystemTime,
+    payloads: Vec<Bytes>,
+    held_bytes: usize,
+    /// Set when the hold limit was hit; later payloads pass straight through.
+    overflowed: bool,
+}
+
+impl TailSampler {
+    pub fn new(config: &SamplingConfig, max_held_bytes: Option<usize>) -> Self {
+        Self {
+            sampler: TraceSampler::new(config),
+            latency_threshold: config.latency_threshold,
+            max_held_bytes,
+            current: None,
+            previous: None,
+        }
+    }
+
+    /// An invocation started. Returns the spans of any invocation that has
+    /// now been waiting for its `runtimeDone` for two INVOKEs — kept, since
+    /// its outcome is unknown.
+    pub fn start(&mut self, request_id: &str) -> Vec<Bytes> {
+        let stale = std::mem::replace(&mut self.previous, self.current.take());
+        self.current = Some(Invocation {
+            request_id: request_id.to_owned(),
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+            payloads: Vec::new(),
+            held_bytes: 0,
+            overflowed: false,
+        });
+
+        let released = stale.map(|inv| inv.payloads).unwrap_or_default();
+        if !released.is_empty() {
+            debug!("releasing held spans for invocation without runtimeDone");
+        }
+        released
+    }
+
+    /// Offer a trace payload. Returns the payloads to buffer now: empty if
+    /// the payload is being held, otherwise the payload itself (plus anything
+    /// released because the hold limit was reached).
+    pub fn hold(&mut self, payload: Bytes) -> Vec<Bytes> {
+        let previous_bytes = self.previous.as_ref().map_or(0, |inv| inv.held_bytes);
+        let Some(inv) = self.current.as_mut().filter(|inv| !inv.overflowed) else {
+            return vec![payload];
+        };
+
+        inv.held_bytes += payload.len();
+        inv.payloads.push(payload);
+
+        if self
+            .max_held_bytes
+            .is_some_and(|max| inv.held_bytes + previous_bytes > max)
+        {
+            warn!(
+                request_id = inv.request_id,
+                bytes = inv.held_bytes,
+                "tail sampling hold limit exceeded, releasing invocation spans unsampled"
+            );
+            inv.overflowed = true;
+            inv.held_bytes = 0;
+            return std::mem::take(&mut inv.payloads);
+        }
+        Vec::new()
+    }
+
+    /// The platform reported the outcome of an invocation. Returns the
+    /// payloads to buffer: everything if the invocation is interesting,
+    /// otherwise a single sampled payload (or nothing if all spans were
+    /// dropped).
+    pub fn finish(
+        &mut self,
+        request_id: &str,
+        status: &str,
+        duration_ms: Option<f64>,
+    ) -> Vec<Bytes> {
+        let slot = [&mut self.current, &mut self.previous]
+            .into_iter()
+            .find(|slot| {
+                slot.as_ref()
+                    .is_some_and(|inv| inv.request_id == request_id)
+            });
+        let Some(inv) = slot.and_then(Option::take) else {
+            return Vec::new();
+        };
+        if inv.payloads.is_empty() {
+            return Vec::new();
+        }
+
+        let duration = duration_ms
+            .map(|ms| Duration::from_secs_f64(ms.max(0.0) / 1000.0))
+            .unwrap_or_else(|| inv.started.elapsed());
+        let slow = self
+            .latency_threshold
+            .is_some_and(|threshold| duration >= threshold);
+
+        if status != "success" || slow {
+            debug!(
+                request_id,
+                status,
+                duration_ms = duration.as_millis() as u64,
+                "tail sampling kept invocation"
+            );
+            return inv.payloads;
+        }
+
+        let mut req = merge::merge_traces(&inv.payloads.into());
+        self.sampler.sample(&mut req);
+        if req.encoded_len() == 0 {
+            return Vec::new();
+        }
+        vec![Bytes::from(req.encode_to_vec())]
+    }
+
+    /// Whether spans are held for an invocation that has not finished.
+    pub fn is_holding(&self) -> bool {
+        [&self.previous, &self.current]
+            .into_iter()
+            .flatten()
+            .any(|inv| !inv.payloads.is_empty())
+    }
+
+    /// Forget the invocations that started before `cutoff` (SnapStart
+    /// restore) and return everything held for them.
+    pub fn reset(&mut self, cutoff: SystemTime) -> Vec<Bytes> {
+        [&mut self.previous, &mut self.current]
+            .into_iter()
+            .filter(|slot| slot.as_ref().is_some_and(|inv| inv.started_at < cutoff))
+            .filter_map(Option::take)
+            .flat_map(|inv| inv.payloads)
+            .collect()
+    }
+
+    /// Release everything held without sampling (shutdown).
+    pub fn release(&mut self) -> Vec<Bytes> {
+        [self.previous.take(), self.current.take()]
+            .into_iter()
+            .flatten()
+            .flat_map(|inv| inv.payloads)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[path = "tail_sampling_tests.rs"]
+mod tests;
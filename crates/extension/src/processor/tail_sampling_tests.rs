@@ -0,0 +1,174 @@
+use super::*;
+use crate::config::SamplingMode;
+use crate::proto::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Span};
+
+/// Randomness low enough to be dropped at any ratio below one.
+const DROPPED: u64 = 0;
+
+fn payload(randomness: u64) -> Bytes {
+    let mut trace_id = vec![0xaa; 9];
+    trace_id.extend_from_slice(&randomness.to_be_bytes()[1..]);
+    let req = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans: vec![Span {
+                    trace_id,
+                    span_id: vec![1; 8],
+                    ..Default::default()
+                }],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+    Bytes::from(req.encode_to_vec())
+}
+
+fn sampler(latency_threshold: Option<Duration>, max_held_bytes: Option<usize>) -> TailSampler {
+    TailSampler::new(
+        &SamplingConfig {
+            ratio: 0.5,
+            keep_errors: false,
+            mode: SamplingMode::Tail,
+            latency_threshold,
+        },
+        max_held_bytes,
+    )
+}
+
+#[test]
+fn payload_outside_invocation_passes_through() {
+    let mut tail = sampler(None, None);
+    let p = payload(DROPPED);
+    assert_eq!(tail.hold(p.clone()), vec![p]);
+}
+
+#[test]
+fn successful_invocation_is_sampled() {
+    let mut tail = sampler(None, None);
+    tail.start("req-1");
+    assert!(tail.hold(payload(DROPPED)).is_empty());
+    assert!(tail.hold(payload(DROPPED)).is_empty());
+
+    assert!(tail.finish("req-1", "success", Some(10.0)).is_empty());
+}
+
+#[test]
+fn successful_invocation_kept_spans_are_merged() {
+    let mut tail = sampler(None, None);
+    tail.start("req-1");
+    tail.hold(payload((1 << 56) - 1));
+    tail.hold(payload((1 << 56) - 2));
+
+    let out = tail.finish("req-1", "success", Some(10.0));
+    assert_eq!(out.len(), 1);
+    let req = ExportTraceServiceRequest::decode(out[0].clone()).unwrap();
+    let spans: usize = req
+        .resource_spans
+        .iter()
+        .flat_map(|rs| &rs.scope_spans)
+        .map(|ss| ss.spans.len())
+        .sum();
+    assert_eq!(spans, 2);
+}
+
+#[test]
+fn failed_invocation_is_kept_whole() {
+    for status in ["error", "failure", "timeout"] {
+        let mut tail = sampler(None, None);
+        tail.start("req-1");
+        tail.hold(payload(DROPPED));
+        tail.hold(payload(DROPPED));
+
+        let out = tail.finish("req-1", status, Some(10.0));
+        assert_eq!(out.len(), 2, "status {status}");
+    }
+}
+
+#[test]
+fn slow_invocation_is_kept_whole() {
+    let mut tail = sampler(Some(Duration::from_millis(500)), None);
+    tail.start("req-1");
+    tail.hold(payload(DROPPED));
+
+    assert_eq!(tail.finish("req-1", "success", Some(500.0)).len(), 1);
+}
+
+#[test]
+fn fast_invocation_below_threshold_is_sampled() {
+    let mut tail = sampler(Some(Duration::from_millis(500)), None);
+    tail.start("req-1");
+    tail.hold(payload(DROPPED));
+
+    assert!(tail.finish("req-1", "success", Some(499.0)).is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn local_elapsed_time_used_without_duration_metric() {
+    let mut tail = sampler(Some(Duration::from_millis(500)), None);
+    tail.start("req-1");
+    tail.hold(payload(DROPPED));
+    tokio::time::advance(Duration::from_secs(1)).await;
+
+    assert_eq!(tail.finish("req-1", "success", None).len(), 1);
+}
+
+#[test]
+fn late_runtime_done_for_previous_invocation() {
+    let mut tail = sampler(None, None);
+    tail.start("req-1");
+    tail.hold(payload(DROPPED));
+    assert!(tail.start("req-2").is_empty());
+    tail.hold(payload(DROPPED));
+
+    assert_eq!(tail.finish("req-1", "error", None).len(), 1);
+    assert_eq!(tail.finish("req-2", "error", None).len(), 1);
+}
+
+#[test]
+fn invocation_without_runtime_done_is_released_after_two_invokes() {
+    let mut tail = sampler(None, None);
+    tail.start("req-1");
+    tail.hold(payload(DROPPED));
+    assert!(tail.start("req-2").is_empty());
+
+    assert_eq!(tail.start("req-3").len(), 1);
+}
+
+#[test]
+fn unknown_request_id_is_ignored() {
+    let mut tail = sampler(None, None);
+    tail.start("req-1");
+    tail.hold(payload(DROPPED));
+
+    assert!(tail.finish("other", "error", None).is_empty());
+    assert_eq!(tail.release().len(), 1);
+}
+
+#[test]
+fn hold_limit_releases_invocation_unsampled() {
+    let p = payload(DROPPED);
+    let mut tail = sampler(None, Some(p.len() + 1));
+    tail.start("req-1");
+    assert!(tail.hold(p.clone()).is_empty());
+
+    assert_eq!(tail.hold(p.clone()).len(), 2);
+    // Later payloads for the same invocation pass straight through.
+    assert_eq!(tail.hold(p.clone()), vec![p]);
+    assert!(tail.finish("req-1", "success", Some(1.0)).is_empty());
+}
+
+#[test]
+fn release_returns_all_held_payloads() {
+    let mut tail = sampler(None, None);
+    tail.start("req-1");
+    tail.hold(payload(DROPPED));
+    tail.start("req-2");
+    tail.hold(payload(DROPPED));
+
+    assert_eq!(tail.release().len(), 2);
+    assert!(tail.release().is_empty());
+}
@@ -0,0 +1,351 @@
+//! Delta ↔ cumulative temporality conversion.
+//!
+//! Sums and explicit-bucket histograms not already in the configured
+//! temporality are converted point by point. Each stream, identified by its
+//! resource, scope, metric name and data point attributes, keeps its last
+//! point across flushes for the lifetime of the environment:
+//!
+//! - to cumulative, the running total that the next delta is added to;
+//! - to delta, the last cumulative point that the next one is diffed against.
+//!
+//! A cumulative stream whose start time changes, or whose monotonic value
+//! goes down, was restarted by its producer; the next point is then handled
+//! as the stream's first, according to [`InitialValue`]. Points older than
+//! the stream's last point are dropped as duplicates or out of order.
+//!
+//! Streams without a point for `max_staleness` are forgotten, and at most
+//! `max_streams` are tracked: points of further streams are dropped, as
+//! there is nothing to diff them against when converting to delta, and
+//! nothing to add them to when converting to cumulative.
+//!
+//! Gauges, summaries and exponential histograms are left as they are.
+
+use std::collections::HashMap;
+use std::time::{Instant, SystemTime};
+
+use tracing::{debug, warn};
+
+use super::{attributes_key, has_no_value, resource_key, scope_key};
+use crate::config::{InitialValue, Temporality, TemporalityConfig};
+use crate::proto::opentelemetry::proto::{
+    collector::metrics::v1::ExportMetricsServiceRequest,
+    metrics::v1::{
+        AggregationTemporality, HistogramDataPoint, NumberDataPoint, metric::Data,
+        number_data_point::Value,
+    },
+};
+
+/// Identity of one metric stream.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct StreamKey {
+    resource: Vec<u8>,
+    scope: Vec<u8>,
+    metric: String,
+    attributes: Vec<u8>,
+}
+
+/// Last point of a stream: the running total when converting to cumulative,
+/// the last cumulative point seen when converting to delta.
+enum Last {
+    Number(NumberDataPoint),
+    Histogram(HistogramDataPoint),
+}
+
+struct Stream {
+    last: Last,
+    /// When the stream last had a point.
+    seen: Instant,
+}
+
+pub struct TemporalityConverter {
+    config: TemporalityConfig,
+    /// When the relay started, the environment was last restored, or stale
+    /// streams were last forgotten. Streams starting earlier are not trusted
+    /// under `InitialValue::Auto`: a forgotten stream that comes back would
+    /// otherwise export its whole running total as one delta.
+    started: u64,
+    streams: HashMap<StreamKey, Stream>,
+    /// Set once `max_streams` was first reached, so it's only logged once.
+    overflowed: bool,
+}
+
+impl TemporalityConverter {
+    pub fn new(config: &TemporalityConfig) -> Self {
+        Self {
+            config: *config,
+            started: now_unix_nanos(),
+            streams: HashMap::new(),
+            overflowed: false,
+        }
+    }
+
+    /// Forget every stream, e.g. after a SnapStart restore: the state was
+    /// taken over from the snapshot and is shared by every restored
+    /// environment.
+    pub fn reset(&mut self) {
+        self.started = now_unix_nanos();
+        self.streams.clear();
+        self.overflowed = false;
+    }
+
+    /// Forget streams that had no point for `max_staleness` as of `now`.
+    fn expire(&mut self, now: Instant) {
+        let Some(max_staleness) = self.config.max_staleness else {
+            return;
+        };
+        let before = self.streams.len();
+        self.streams
+            .retain(|_, stream| now.saturating_duration_since(stream.seen) <= max_staleness);
+        let expired = before - self.streams.len();
+        if expired > 0 {
+            debug!(expired, "forgetting stale metric streams");
+            self.started = now_unix_nanos();
+        }
+    }
+
+    /// Store the last point of a stream. Returns `false` if the stream is
+    /// new and `max_streams` are already tracked.
+    fn remember(&mut self, key: &StreamKey, last: Last, now: Instant) -> bool {
+        if let Some(stream) = self.streams.get_mut(key) {
+            *stream = Stream { last, seen: now };
+            return true;
+        }
+        if let Some(max) = self
+            .config
+            .max_streams
+            .filter(|&max| self.streams.len() >= max)
+        {
+            if !self.overflowed {
+                self.overflowed = true;
+                warn!(
+                    max_streams = max,
+                    "too many metric streams for temporality conversion, \
+                     points of new streams are dropped"
+                );
+            }
+            return false;
+        }
+        self.streams.insert(key.clone(), Stream { last, seen: now });
+        true
+    }
+
+    pub fn convert(&mut self, req: &mut ExportMetricsServiceRequest) {
+        let now = Instant::now();
+        self.expire(now);
+        let target = match self.config.target {
+            Temporality::Delta => AggregationTemporality::Delta,
+            Temporality::Cumulative => AggregationTemporality::Cumulative,
+        } as i32;
+        let source = match self.config.target {
+            Temporality::Delta => AggregationTemporality::Cumulative,
+            Temporality::Cumulative => AggregationTemporality::Delta,
+        } as i32;
+
+        for rm in &mut req.resource_metrics {
+            let resource = resource_key(rm.resource.as_ref(), &rm.schema_url);
+            for sm in &mut rm.scope_metrics {
+                let scope = scope_key(sm.scope.as_ref());
+                for metric in &mut sm.metrics {
+                    let mut key = StreamKey {
+                        resource: resource.clone(),
+                        scope: scope.clone(),
+                        metric: metric.name.clone(),
+                        attributes: Vec::new(),
+                    };
+                    match &mut metric.data {
+                        Some(Data::Sum(sum)) if sum.aggregation_temporality == source => {
+                            let monotonic = sum.is_monotonic;
+                            sum.data_points.retain_mut(|point| {
+                                key.attributes = attributes_key(&point.attributes);
+                                self.convert_number(&key, point, monotonic, now)
+                            });
+                            sum.aggregation_temporality = target;
+                        }
+                        Some(Data::Histogram(histogram))
+                            if histogram.aggregation_temporality == source =>
+                        {
+                            histogram.data_points.retain_mut(|point| {
+                                key.attributes = attributes_key(&point.attributes);
+                                self.convert_histogram(&key, point, now)
+                            });
+                            histogram.aggregation_temporality = target;
+                        }
+                        _ => {}
+                    }
+                }
+                sm.metrics.retain(|m| !is_empty(&m.data));
+            }
+            rm.scope_metrics.retain(|sm| !sm.metrics.is_empty());
+        }
+        req.resource_metrics
+            .retain(|rm| !rm.scope_metrics.is_empty());
+    }
+
+    /// Convert one sum point in place. Returns `false` if it must be dropped.
+    fn convert_number(
+        &mut self,
+        key: &StreamKey,
+        point: &mut NumberDataPoint,
+        monotonic: bool,
+        now: Instant,
+    ) -> bool {
+        if has_no_value(point.flags) {
+            return true;
+        }
+        let last = match self.streams.get(key).map(|stream| &stream.last) {
+            Some(Last::Number(last)) => Some(last),
+            _ => None,
+        };
+        if last.is_some_and(|last| point.time_unix_nano <= last.time_unix_nano) {
+            debug!(metric = key.metric, "dropping out-of-order data point");
+            return false;
+        }
+        match self.config.target {
+            Temporality::Cumulative => {
+                if let Some(last) = last {
+                    point.start_time_unix_nano = last.start_time_unix_nano;
+                    point.value = add(&last.value, &point.value);
+                }
+                self.remember(key, Last::Number(point.clone()), now)
+            }
+            Temporality::Delta => {
+                let previous = last.filter(|last| {
+                    last.start_time_unix_nano == point.start_time_unix_nano
+                        && !(monotonic && as_f64(&point.value) < as_f64(&last.value))
+                });
+                let cumulative = point.clone();
+                let keep = match previous {
+                    Some(last) => {
+                        point.start_time_unix_nano = last.time_unix_nano;
+                        point.value = sub(&point.value, &last.value);
+                        true
+                    }
+                    None => self.keep_initial(point.start_time_unix_nano),
+                };
+                self.remember(key, Last::Number(cumulative), now) && keep
+            }
+        }
+    }
+
+    /// Convert one histogram point in place. Returns `false` if it must be
+    /// dropped.
+    fn convert_histogram(
+        &mut self,
+        key: &StreamKey,
+        point: &mut HistogramDataPoint,
+        now: Instant,
+    ) -> bool {
+        if has_no_value(point.flags) {
+            return true;
+        }
+        let last = match self.streams.get(key).map(|stream| &stream.last) {
+            Some(Last::Histogram(last)) => Some(last),
+            _ => None,
+        };
+        if last.is_some_and(|last| point.time_unix_nano <= last.time_unix_nano) {
+            debug!(metric = key.metric, "dropping out-of-order data point");
+            return false;
+        }
+        // Points with different bounds cannot be combined: start over.
+        let last = last.filter(|last| {
+            last.explicit_bounds == point.explicit_bounds
+                && last.bucket_counts.len() == point.bucket_counts.len()
+        });
+        match self.config.target {
+            Temporality::Cumulative => {
+                if let Some(last) = last {
+                    point.start_time_unix_nano = last.start_time_unix_nano;
+                    point.count += last.count;
+                    point.sum = point.sum.zip(last.sum).map(|(a, b)| a + b);
+                    for (count, last) in point.bucket_counts.iter_mut().zip(&last.bucket_counts) {
+                        *count += last;
+                    }
+                    point.min = min_max(point.min, last.min, f64::min);
+                    point.max = min_max(point.max, last.max, f64::max);
+                }
+                self.remember(key, Last::Histogram(point.clone()), now)
+            }
+            Temporality::Delta => {
+                let previous = last.filter(|last| {
+                    last.start_time_unix_nano == point.start_time_unix_nano
+                        && point.count >= last.count
+                });
+                let cumulative = point.clone();
+                let keep = match previous {
+                    Some(last) => {
+                        point.start_time_unix_nano = last.time_unix_nano;
+                        point.count -= last.count;
+                        point.sum = point.sum.zip(last.sum).map(|(a, b)| a - b);
+                        for (count, last) in point.bucket_counts.iter_mut().zip(&last.bucket_counts)
+                        {
+                            *count = count.saturating_sub(*last);
+                        }
+                        // The extremes of the interval alone are unknown.
+                        point.min = None;
+                        point.max = None;
+                        true
+                    }
+                    None => self.keep_initial(point.start_time_unix_nano),
+                };
+                self.remember(key, Last::Histogram(cumulative), now) && keep
+            }
+        }
+    }
+
+    /// Whether the first cumulative point of a stream starting at `start` is
+    /// exported as a delta.
+    fn keep_initial(&self, start: u64) -> bool {
+        match self.config.initial_value {
+            InitialValue::Keep => true,
+            InitialValue::Drop => false,
+            InitialValue::Auto => start != 0 && start >= self.started,
+        }
+    }
+}
+
+fn now_unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+fn is_empty(data: &Option<Data>) -> bool {
+    match data {
+        Some(Data::Sum(sum)) => sum.data_points.is_empty(),
+        Some(Data::Histogram(histogram)) => histogram.data_points.is_empty(),
+        _ => false,
+    }
+}
+
+fn as_f64(value: &Option<Value>) -> f64 {
+    match value {
+        Some(Value::AsInt(v)) => *v as f64,
+        Some(Value::AsDouble(v)) => *v,
+        None => 0.0,
+    }
+}
+
+pub(super) fn add(a: &Option<Value>, b: &Option<Value>) -> Option<Value> {
+    match (a, b) {
+        (Some(Value::AsInt(a)), Some(Value::AsInt(b))) => Some(Value::AsInt(a.wrapping_add(*b))),
+        _ => Some(Value::AsDouble(as_f64(a) + as_f64(b))),
+    }
+}
+
+fn sub(a: &Option<Value>, b: &Option<Value>) -> Option<Value> {
+    match (a, b) {
+        (Some(Value::AsInt(a)), Some(Value::AsInt(b))) => Some(Value::AsInt(a.wrapping_sub(*b))),
+        _ => Some(Value::AsDouble(as_f64(a) - as_f64(b))),
+    }
+}
+
+pub(super) fn min_max(a: Option<f64>, b: Option<f64>, pick: fn(f64, f64) -> f64) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(pick(a, b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+#[path = "temporality_tests.rs"]
+mod tests;
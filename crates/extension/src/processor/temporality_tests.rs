@@ -0,0 +1,423 @@
+use std::time::Duration;
+
+use super::*;
+use crate::proto::opentelemetry::proto::{
+    common::v1::{AnyValue, KeyValue, any_value},
+    metrics::v1::{Gauge, Histogram, Metric, ResourceMetrics, ScopeMetrics, Sum},
+};
+
+const SECOND: u64 = 1_000_000_000;
+
+fn converter(target: Temporality, initial_value: InitialValue) -> TemporalityConverter {
+    TemporalityConverter::new(&TemporalityConfig {
+        target,
+        initial_value,
+        max_staleness: None,
+        max_streams: None,
+    })
+}
+
+fn attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_owned())),
+        }),
+    }
+}
+
+fn point(start: u64, time: u64, value: i64) -> NumberDataPoint {
+    NumberDataPoint {
+        start_time_unix_nano: start,
+        time_unix_nano: time,
+        value: Some(Value::AsInt(value)),
+        ..Default::default()
+    }
+}
+
+fn request(data: Data) -> ExportMetricsServiceRequest {
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            scope_metrics: vec![ScopeMetrics {
+                metrics: vec![Metric {
+                    name: "requests".to_owned(),
+                    data: Some(data),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+}
+
+fn sum(
+    temporality: AggregationTemporality,
+    points: Vec<NumberDataPoint>,
+) -> ExportMetricsServiceRequest {
+    request(Data::Sum(Sum {
+        data_points: points,
+        aggregation_temporality: temporality as i32,
+        is_monotonic: true,
+    }))
+}
+
+fn histogram(
+    temporality: AggregationTemporality,
+    points: Vec<HistogramDataPoint>,
+) -> ExportMetricsServiceRequest {
+    request(Data::Histogram(Histogram {
+        data_points: points,
+        aggregation_temporality: temporality as i32,
+    }))
+}
+
+/// The converted sum points as `(start, time, value)`, and the temporality.
+fn sum_points(req: &ExportMetricsServiceRequest) -> (Vec<(u64, u64, i64)>, i32) {
+    let Some(rm) = req.resource_metrics.first() else {
+        return (vec![], 0);
+    };
+    let Some(Data::Sum(sum)) = &rm.scope_metrics[0].metrics[0].data else {
+        panic!("expected a sum");
+    };
+    let points = sum
+        .data_points
+        .iter()
+        .map(|p| {
+            let Some(Value::AsInt(v)) = p.value else {
+                panic!("expected an int value");
+            };
+            (p.start_time_unix_nano, p.time_unix_nano, v)
+        })
+        .collect();
+    (points, sum.aggregation_temporality)
+}
+
+fn convert_sum(
+    converter: &mut TemporalityConverter,
+    temporality: AggregationTemporality,
+    points: Vec<NumberDataPoint>,
+) -> Vec<(u64, u64, i64)> {
+    let mut req = sum(temporality, points);
+    converter.convert(&mut req);
+    sum_points(&req).0
+}
+
+#[test]
+fn delta_sums_accumulate_across_flushes() {
+    let mut converter = converter(Temporality::Cumulative, InitialValue::Auto);
+    let mut req = sum(
+        AggregationTemporality::Delta,
+        vec![point(0, SECOND, 1), point(SECOND, 2 * SECOND, 2)],
+    );
+    converter.convert(&mut req);
+    assert_eq!(
+        sum_points(&req),
+        (
+            vec![(0, SECOND, 1), (0, 2 * SECOND, 3)],
+            AggregationTemporality::Cumulative as i32
+        )
+    );
+
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Delta,
+        vec![point(2 * SECOND, 3 * SECOND, 3)],
+    );
+    assert_eq!(points, [(0, 3 * SECOND, 6)]);
+}
+
+#[test]
+fn cumulative_sums_are_diffed_against_the_previous_point() {
+    let mut converter = converter(Temporality::Delta, InitialValue::Keep);
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Cumulative,
+        vec![point(SECOND, 2 * SECOND, 10)],
+    );
+    assert_eq!(points, [(SECOND, 2 * SECOND, 10)]);
+
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Cumulative,
+        vec![point(SECOND, 3 * SECOND, 15), point(SECOND, 4 * SECOND, 15)],
+    );
+    assert_eq!(
+        points,
+        [(2 * SECOND, 3 * SECOND, 5), (3 * SECOND, 4 * SECOND, 0)]
+    );
+}
+
+#[test]
+fn initial_value_policy() {
+    let now = now_unix_nanos();
+    let first = |initial_value, start| {
+        let mut converter = converter(Temporality::Delta, initial_value);
+        convert_sum(
+            &mut converter,
+            AggregationTemporality::Cumulative,
+            vec![point(start, start + SECOND, 10)],
+        )
+        .len()
+    };
+    assert_eq!(first(InitialValue::Keep, SECOND), 1);
+    assert_eq!(first(InitialValue::Drop, now + SECOND), 0);
+    assert_eq!(
+        first(InitialValue::Auto, SECOND),
+        0,
+        "started before the relay"
+    );
+    assert_eq!(first(InitialValue::Auto, now + SECOND), 1);
+}
+
+#[test]
+fn producer_restarts_start_a_new_stream() {
+    let mut converter = converter(Temporality::Delta, InitialValue::Drop);
+    convert_sum(
+        &mut converter,
+        AggregationTemporality::Cumulative,
+        vec![point(SECOND, 2 * SECOND, 10)],
+    );
+
+    // New start time, then a lower value with the same start time.
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Cumulative,
+        vec![
+            point(3 * SECOND, 4 * SECOND, 4),
+            point(3 * SECOND, 5 * SECOND, 7),
+            point(3 * SECOND, 6 * SECOND, 2),
+            point(3 * SECOND, 7 * SECOND, 5),
+        ],
+    );
+    assert_eq!(
+        points,
+        [(4 * SECOND, 5 * SECOND, 3), (6 * SECOND, 7 * SECOND, 3)]
+    );
+}
+
+#[test]
+fn out_of_order_points_are_dropped() {
+    let mut converter = converter(Temporality::Cumulative, InitialValue::Auto);
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Delta,
+        vec![
+            point(SECOND, 2 * SECOND, 1),
+            point(0, SECOND, 5),
+            point(SECOND, 2 * SECOND, 5),
+        ],
+    );
+    assert_eq!(points, [(SECOND, 2 * SECOND, 1)]);
+}
+
+#[test]
+fn streams_are_keyed_by_attributes_in_any_order() {
+    let mut converter = converter(Temporality::Cumulative, InitialValue::Auto);
+    let mut a = point(0, SECOND, 1);
+    a.attributes = vec![attribute("route", "/a"), attribute("method", "GET")];
+    let mut b = point(0, SECOND, 10);
+    b.attributes = vec![attribute("route", "/b"), attribute("method", "GET")];
+    convert_sum(&mut converter, AggregationTemporality::Delta, vec![a, b]);
+
+    let mut a = point(SECOND, 2 * SECOND, 1);
+    a.attributes = vec![attribute("method", "GET"), attribute("route", "/a")];
+    let points = convert_sum(&mut converter, AggregationTemporality::Delta, vec![a]);
+    assert_eq!(points, [(0, 2 * SECOND, 2)]);
+}
+
+#[test]
+fn reset_forgets_streams() {
+    let mut converter = converter(Temporality::Cumulative, InitialValue::Auto);
+    convert_sum(
+        &mut converter,
+        AggregationTemporality::Delta,
+        vec![point(0, SECOND, 1)],
+    );
+    converter.reset();
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Delta,
+        vec![point(SECOND, 2 * SECOND, 1)],
+    );
+    assert_eq!(points, [(SECOND, 2 * SECOND, 1)]);
+}
+
+#[test]
+fn stale_streams_are_forgotten() {
+    let mut converter = TemporalityConverter::new(&TemporalityConfig {
+        target: Temporality::Cumulative,
+        initial_value: InitialValue::Auto,
+        max_staleness: Some(Duration::from_secs(60)),
+        max_streams: None,
+    });
+    convert_sum(
+        &mut converter,
+        AggregationTemporality::Delta,
+        vec![point(0, SECOND, 1)],
+    );
+    converter.expire(Instant::now() + Duration::from_secs(30));
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Delta,
+        vec![point(SECOND, 2 * SECOND, 2)],
+    );
+    assert_eq!(points, [(0, 2 * SECOND, 3)]);
+
+    // Not seen for longer than the staleness limit: the running total
+    // starts over.
+    converter.expire(Instant::now() + Duration::from_secs(90));
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Delta,
+        vec![point(2 * SECOND, 3 * SECOND, 4)],
+    );
+    assert_eq!(points, [(2 * SECOND, 3 * SECOND, 4)]);
+}
+
+#[test]
+fn streams_beyond_the_limit_are_not_converted() {
+    let mut converter = TemporalityConverter::new(&TemporalityConfig {
+        target: Temporality::Delta,
+        initial_value: InitialValue::Keep,
+        max_staleness: None,
+        max_streams: Some(1),
+    });
+    let labelled = |route: &str, time, value| NumberDataPoint {
+        attributes: vec![attribute("route", route)],
+        ..point(0, time, value)
+    };
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Cumulative,
+        vec![labelled("/a", SECOND, 1), labelled("/b", SECOND, 5)],
+    );
+    assert_eq!(points, [(0, SECOND, 1)]);
+
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Cumulative,
+        vec![labelled("/a", 2 * SECOND, 3), labelled("/b", 2 * SECOND, 8)],
+    );
+    assert_eq!(points, [(SECOND, 2 * SECOND, 2)]);
+}
+
+#[test]
+fn streams_beyond_the_limit_are_dropped_when_converting_to_cumulative() {
+    let mut converter = TemporalityConverter::new(&TemporalityConfig {
+        target: Temporality::Cumulative,
+        initial_value: InitialValue::Auto,
+        max_staleness: None,
+        max_streams: Some(1),
+    });
+    let labelled = |route: &str, time, value| NumberDataPoint {
+        attributes: vec![attribute("route", route)],
+        ..point(time - SECOND, time, value)
+    };
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Delta,
+        vec![labelled("/a", SECOND, 1), labelled("/b", SECOND, 5)],
+    );
+    assert_eq!(points, [(0, SECOND, 1)]);
+
+    let points = convert_sum(
+        &mut converter,
+        AggregationTemporality::Delta,
+        vec![labelled("/a", 2 * SECOND, 3), labelled("/b", 2 * SECOND, 8)],
+    );
+    assert_eq!(points, [(0, 2 * SECOND, 4)]);
+
+    let histogram_point = |route: &str| HistogramDataPoint {
+        attributes: vec![attribute("route", route)],
+        start_time_unix_nano: 2 * SECOND,
+        time_unix_nano: 3 * SECOND,
+        count: 1,
+        bucket_counts: vec![1],
+        ..Default::default()
+    };
+    let mut req = histogram(AggregationTemporality::Delta, vec![histogram_point("/c")]);
+    converter.convert(&mut req);
+    assert!(req.resource_metrics.is_empty());
+}
+
+#[test]
+fn histograms_convert_both_ways() {
+    let histogram_point = |start, time, count, sum, buckets: Vec<u64>| HistogramDataPoint {
+        start_time_unix_nano: start,
+        time_unix_nano: time,
+        count,
+        sum: Some(sum),
+        bucket_counts: buckets,
+        explicit_bounds: vec![1.0],
+        min: Some(0.5),
+        max: Some(2.0),
+        ..Default::default()
+    };
+    let converted = |req: &ExportMetricsServiceRequest| {
+        let Some(Data::Histogram(h)) = &req.resource_metrics[0].scope_metrics[0].metrics[0].data
+        else {
+            panic!("expected a histogram");
+        };
+        h.data_points
+            .iter()
+            .map(|p| {
+                (
+                    p.start_time_unix_nano,
+                    p.count,
+                    p.sum,
+                    p.bucket_counts.clone(),
+                    p.max,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut to_cumulative = converter(Temporality::Cumulative, InitialValue::Auto);
+    let mut req = histogram(
+        AggregationTemporality::Delta,
+        vec![
+            histogram_point(0, SECOND, 2, 1.5, vec![1, 1]),
+            histogram_point(SECOND, 2 * SECOND, 3, 4.0, vec![1, 2]),
+        ],
+    );
+    to_cumulative.convert(&mut req);
+    assert_eq!(
+        converted(&req),
+        [
+            (0, 2, Some(1.5), vec![1, 1], Some(2.0)),
+            (0, 5, Some(5.5), vec![2, 3], Some(2.0)),
+        ]
+    );
+
+    let mut to_delta = converter(Temporality::Delta, InitialValue::Keep);
+    let mut req = histogram(
+        AggregationTemporality::Cumulative,
+        vec![
+            histogram_point(0, SECOND, 2, 1.5, vec![1, 1]),
+            histogram_point(0, 2 * SECOND, 5, 5.5, vec![2, 3]),
+        ],
+    );
+    to_delta.convert(&mut req);
+    assert_eq!(
+        converted(&req),
+        [
+            (0, 2, Some(1.5), vec![1, 1], Some(2.0)),
+            (SECOND, 3, Some(4.0), vec![1, 2], None),
+        ]
+    );
+}
+
+#[test]
+fn other_metrics_are_left_alone() {
+    let mut converter = converter(Temporality::Delta, InitialValue::Drop);
+    let gauge = request(Data::Gauge(Gauge {
+        data_points: vec![point(0, SECOND, 1)],
+    }));
+    let delta = sum(AggregationTemporality::Delta, vec![point(0, SECOND, 1)]);
+    for req in [gauge, delta] {
+        let mut converted = req.clone();
+        converter.convert(&mut converted);
+        assert_eq!(converted, req);
+    }
+}
@@ -0,0 +1,408 @@
+use bytes::Bytes;
+use prost::Message;
+
+use super::*;
+use crate::buffers::Signal;
+use crate::config::{
+    EmfConfig, FilterConfig, RedactionConfig, ResourceAction, ResourceAttribute, SamplingConfig,
+    SamplingMode, SpanMetricsConfig,
+};
+use crate::proto::opentelemetry::proto::common::v1::{AnyValue, any_value::Value};
+use crate::proto::opentelemetry::proto::metrics::v1::{
+    Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, metric, number_data_point,
+};
+use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Span};
+
+const MAX_RANDOMNESS: u64 = (1 << 56) - 1;
+
+fn trace_payload(randomness: u64) -> Bytes {
+    let mut trace_id = vec![0xaa; 9];
+    trace_id.extend_from_slice(&randomness.to_be_bytes()[1..]);
+    let req = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans: vec![Span {
+                    trace_id,
+                    span_id: vec![1; 8],
+                    ..Default::default()
+                }],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+    Bytes::from(req.encode_to_vec())
+}
+
+fn sampling_pipeline(ratio: f64) -> Pipeline {
+    Pipeline {
+        enricher: None,
+        transformer: None,
+        filter: None,
+        spanmetrics: None,
+        drop_traces: false,
+        sampler: Some(TraceSampler::new(&SamplingConfig {
+            ratio,
+            keep_errors: false,
+            mode: SamplingMode::Head,
+            latency_threshold: None,
+        })),
+        redactor: None,
+        limits: None,
+        cardinality: None,
+        aggregate_metrics: false,
+        temporality: None,
+        emf: None,
+        metrics: Arc::default(),
+    }
+}
+
+#[test]
+fn empty_pipeline_leaves_payloads_untouched() {
+    let mut data = BufferData::new();
+    data.push(Signal::Traces, Bytes::from("not protobuf"));
+    data.push(Signal::Traces, Bytes::from("also not protobuf"));
+
+    Pipeline::default().process(&mut data);
+
+    assert_eq!(data.traces.queue.len(), 2);
+    assert_eq!(data.traces.processed, 0);
+}
+
+#[test]
+fn processed_payloads_are_merged_and_marked() {
+    let mut data = BufferData::new();
+    data.push(Signal::Traces, trace_payload(1 << 55));
+    data.push(Signal::Traces, trace_payload(1 << 55));
+
+    sampling_pipeline(1.0).process(&mut data);
+
+    assert_eq!(data.traces.queue.len(), 1);
+    assert_eq!(data.traces.processed, 1);
+    assert_eq!(data.traces.size_bytes, data.traces.queue[0].len());
+    let req = ExportTraceServiceRequest::decode(data.traces.queue[0].as_ref()).unwrap();
+    assert_eq!(req.resource_spans[0].scope_spans.len(), 2);
+}
+
+#[test]
+fn fully_dropped_signal_leaves_queue_empty() {
+    let mut data = BufferData::new();
+    data.push(Signal::Traces, trace_payload(0));
+
+    sampling_pipeline(0.5).process(&mut data);
+
+    assert!(data.traces.is_empty());
+    assert_eq!(data.traces.size_bytes, 0);
+}
+
+#[test]
+fn already_processed_prefix_is_skipped() {
+    let mut failed = BufferData::new();
+    failed.push(Signal::Traces, trace_payload(0));
+    failed.traces.processed = 1;
+
+    let mut data = BufferData::new();
+    data.push(Signal::Traces, trace_payload(0));
+    data.push(Signal::Traces, trace_payload(MAX_RANDOMNESS));
+    data.prepend(failed);
+
+    sampling_pipeline(0.5).process(&mut data);
+
+    // The prepended payload is exported as-is even though the sampler would
+    // drop it; the two fresh payloads are sampled down to one.
+    assert_eq!(data.traces.queue.len(), 2);
+    assert_eq!(data.traces.processed, 2);
+    assert_eq!(data.traces.queue[0], trace_payload(0));
+}
+
+#[test]
+fn redaction_processes_every_signal() {
+    let mut pipeline = Pipeline {
+        enricher: None,
+        transformer: None,
+        filter: None,
+        spanmetrics: None,
+        drop_traces: false,
+        sampler: None,
+        redactor: Some(Redactor::new(&RedactionConfig {
+            delete_keys: vec!["secret".to_owned()],
+            hash_keys: vec![],
+            hash_salt: String::new(),
+            mask: None,
+        })),
+        limits: None,
+        cardinality: None,
+        aggregate_metrics: false,
+        temporality: None,
+        emf: None,
+        metrics: Arc::default(),
+    };
+    let mut data = BufferData::new();
+    data.push(Signal::Traces, trace_payload(0));
+    data.push(
+        Signal::Metrics,
+        Bytes::from(ExportMetricsServiceRequest::default().encode_to_vec()),
+    );
+    data.push(
+        Signal::Logs,
+        Bytes::from(ExportLogsServiceRequest::default().encode_to_vec()),
+    );
+
+    pipeline.process(&mut data);
+
+    assert_eq!(data.traces.processed, 1);
+    // Empty requests stay empty and are not pushed back.
+    assert!(data.metrics.is_empty());
+    assert!(data.logs.is_empty());
+}
+
+#[test]
+fn emf_standalone_drops_metrics_after_writing_them() {
+    let metrics = || {
+        let req = ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                scope_metrics: vec![ScopeMetrics {
+                    metrics: vec![Metric {
+                        name: "latency".to_owned(),
+                        data: Some(metric::Data::Gauge(Gauge {
+                            data_points: vec![NumberDataPoint {
+                                value: Some(number_data_point::Value::AsInt(1)),
+                                ..Default::default()
+                            }],
+                        })),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+        Bytes::from(req.encode_to_vec())
+    };
+    let pipeline = |mode| Pipeline {
+        emf: Some(EmfSink::with_writer(
+            &EmfConfig {
+                namespace: "test".to_owned(),
+                dimensions: vec![],
+                mode,
+            },
+            Box::new(std::io::sink()),
+        )),
+        ..Default::default()
+    };
+
+    let mut data = BufferData::new();
+    data.push(Signal::Metrics, metrics());
+    pipeline(EmfMode::Standalone).process(&mut data);
+    assert!(data.metrics.is_empty());
+
+    let mut data = BufferData::new();
+    data.push(Signal::Metrics, metrics());
+    pipeline(EmfMode::Alongside).process(&mut data);
+    assert_eq!(data.metrics.processed, 1);
+}
+
+#[test]
+fn span_metrics_count_sampled_and_dropped_spans() {
+    let spanmetrics = || {
+        Some(SpanMetrics::new(&SpanMetricsConfig {
+            dimensions: vec![],
+            buckets: vec![10.0],
+        }))
+    };
+    let mut pipeline = Pipeline {
+        spanmetrics: spanmetrics(),
+        ..sampling_pipeline(0.0)
+    };
+    let mut data = BufferData::new();
+    data.push(Signal::Traces, trace_payload(0));
+    pipeline.process(&mut data);
+    assert!(data.traces.is_empty(), "sampled out");
+    assert_eq!(data.metrics.queue.len(), 1);
+
+    let mut pipeline = Pipeline {
+        spanmetrics: spanmetrics(),
+        drop_traces: true,
+        ..Default::default()
+    };
+    let mut data = BufferData::new();
+    data.push(Signal::Traces, trace_payload(MAX_RANDOMNESS));
+    pipeline.process(&mut data);
+    assert!(data.traces.is_empty(), "traces are not exported");
+    assert_eq!(data.metrics.queue.len(), 1);
+}
+
+#[test]
+fn enriched_resources_are_merged_again() {
+    let environment = |value: &str| KeyValue {
+        key: "deployment.environment".to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value.to_owned())),
+        }),
+    };
+    let mut tagged = ExportTraceServiceRequest::decode(trace_payload(0)).unwrap();
+    tagged.resource_spans[0].resource = Some(Resource {
+        attributes: vec![environment("dev")],
+        ..Default::default()
+    });
+    let mut pipeline = Pipeline {
+        enricher: Some(ResourceEnricher::new(&[ResourceAttribute {
+            key: "deployment.environment".to_owned(),
+            action: ResourceAction::Upsert("prod".to_owned()),
+        }])),
+        ..Default::default()
+    };
+    let mut data = BufferData::new();
+    data.push(Signal::Traces, trace_payload(0));
+    data.push(Signal::Traces, Bytes::from(tagged.encode_to_vec()));
+
+    pipeline.process(&mut data);
+
+    let req = ExportTraceServiceRequest::decode(data.traces.queue[0].clone()).unwrap();
+    assert_eq!(req.resource_spans.len(), 1);
+    let rs = &req.resource_spans[0];
+    assert_eq!(
+        rs.resource.as_ref().unwrap().attributes,
+        [environment("prod")]
+    );
+    assert_eq!(rs.scope_spans.len(), 2);
+}
+
+#[test]
+fn filtered_records_are_counted_per_signal() {
+    let mut pipeline = Pipeline {
+        filter: Some(Filter::new(&FilterConfig {
+            span_names: vec!["*".to_owned()],
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    let metrics = Arc::new(RelayMetrics::default());
+    pipeline.set_metrics(Arc::clone(&metrics));
+    let mut data = BufferData::new();
+    data.push(Signal::Traces, trace_payload(0));
+    data.push(Signal::Traces, trace_payload(1));
+
+    pipeline.process(&mut data);
+
+    assert!(data.traces.is_empty());
+    assert_eq!(metrics.filtered(Signal::Traces), 2);
+    assert_eq!(metrics.filtered(Signal::Logs), 0);
+}
+
+#[test]
+fn glob_matching() {
+    assert!(glob_match("user.email", "user.email"));
+    assert!(!glob_match("user.email", "user.email2"));
+    assert!(glob_match(
+        "http.request.header.*",
+        "http.request.header.authorization"
+    ));
+    assert!(glob_match("*.password", "db.password"));
+    assert!(!glob_match("*.password", "password"));
+    assert!(glob_match("*token*", "x-auth-token-id"));
+    assert!(glob_match("a*b*c", "abc"));
+    assert!(!glob_match("a*b*c", "acb"));
+    assert!(!glob_match("ab*ba", "aba"));
+    assert!(glob_match("*", ""));
+}
+
+/// Under tail sampling span metrics are derived in the event loop, ahead of
+/// the pipeline, but from spans that went through the same stages first.
+#[tokio::test]
+async fn span_metrics_match_in_both_sampling_modes() {
+    use crate::config::{
+        AttributeLevel, AttributeRef, TransformOp, TransformRule, TransformSource,
+    };
+
+    let attribute = |key: &str, value: &str| KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value.to_owned())),
+        }),
+    };
+    let span = |name: &str| Span {
+        name: name.to_owned(),
+        trace_id: vec![1; 16],
+        span_id: vec![2; 8],
+        attributes: vec![attribute("http.method", "GET")],
+        ..Default::default()
+    };
+    let payload = Bytes::from(
+        ExportTraceServiceRequest {
+            resource_spans: vec![ResourceSpans {
+                scope_spans: vec![ScopeSpans {
+                    spans: vec![span("GET /orders"), span("GET /health")],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+        .encode_to_vec(),
+    );
+
+    let mut config = crate::testing::dummy_config().await;
+    config.spanmetrics = Some(SpanMetricsConfig {
+        dimensions: vec!["http.request.method".to_owned()],
+        buckets: vec![10.0],
+    });
+    config.transform = vec![TransformRule {
+        op: TransformOp::Rename,
+        from: TransformSource::Attribute(AttributeRef {
+            level: AttributeLevel::Record,
+            key: "http.method".to_owned(),
+        }),
+        to: AttributeRef {
+            level: AttributeLevel::Record,
+            key: "http.request.method".to_owned(),
+        },
+    }];
+    config.filter = Some(FilterConfig {
+        span_names: vec!["GET /health".to_owned()],
+        ..Default::default()
+    });
+
+    let mut data = BufferData::new();
+    data.push(Signal::Traces, payload.clone());
+    Pipeline::new(&config).process(&mut data);
+    let pipeline = ExportMetricsServiceRequest::decode(data.metrics.queue[0].clone()).unwrap();
+
+    config.sampling = Some(SamplingConfig {
+        ratio: 1.0,
+        keep_errors: false,
+        mode: SamplingMode::Tail,
+        latency_threshold: None,
+    });
+    assert!(Pipeline::new(&config).spanmetrics.is_none());
+    let derived = Pipeline::tail_spanmetrics(&config)
+        .unwrap()
+        .derive_span_metrics(&payload)
+        .unwrap();
+    let tail = ExportMetricsServiceRequest::decode(derived).unwrap();
+
+    assert_eq!(pipeline, tail);
+    let calls = &tail.resource_metrics[0].scope_metrics[0].metrics[0];
+    let Some(metric::Data::Sum(sum)) = &calls.data else {
+        panic!("expected the calls sum");
+    };
+    assert_eq!(sum.data_points.len(), 1, "the filtered span is not counted");
+    assert!(
+        sum.data_points[0]
+            .attributes
+            .contains(&attribute("http.request.method", "GET"))
+    );
+}
+
+#[test]
+fn malformed_payloads_derive_no_span_metrics() {
+    let mut pipeline = Pipeline {
+        spanmetrics: Some(SpanMetrics::new(&SpanMetricsConfig {
+            dimensions: vec![],
+            buckets: vec![10.0],
+        })),
+        ..Default::default()
+    };
+    assert_eq!(pipeline.derive_span_metrics(b"not protobuf"), None);
+}
@@ -0,0 +1,461 @@
+//! Attribute transform rules.
+//!
+//! Applies the `LAMBDA_OTEL_RELAY_TRANSFORM` rules, in order, to every span,
+//! metric data point and log record together with its scope and resource,
+//! e.g. to migrate between semantic convention versions.
+//!
+//! Rules that only touch one level are applied in place. Once a rule moves
+//! attributes between levels, every record gets its own copy of its scope
+//! and resource to apply the rules to, and records are grouped again under
+//! the scope and resource they end up with. Promoting an attribute whose
+//! value differs between the spans of a resource therefore splits that
+//! resource. Scopes and resources left without records are dropped.
+
+use std::collections::HashMap;
+use std::mem;
+
+use super::aggregation::append_points;
+use super::{resource_key, scope_key};
+use crate::config::{AttributeLevel, TransformOp, TransformRule, TransformSource};
+use crate::proto::opentelemetry::proto::{
+    collector::{
+        logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest,
+        trace::v1::ExportTraceServiceRequest,
+    },
+    common::v1::{AnyValue, InstrumentationScope, KeyValue, any_value::Value},
+    logs::v1::{ResourceLogs, ScopeLogs},
+    metrics::v1::{
+        ExponentialHistogram, Gauge, Histogram, Metric, ResourceMetrics, ScopeMetrics, Sum,
+        Summary, metric::Data,
+    },
+    resource::v1::Resource,
+    trace::v1::{ResourceSpans, ScopeSpans},
+};
+
+pub struct Transformer {
+    rules: Vec<TransformRule>,
+    /// Whether any rule moves attributes between levels.
+    crosses_levels: bool,
+}
+
+/// The attribute lists a rule can read from and write to.
+struct Levels<'a> {
+    resource: &'a mut Vec<KeyValue>,
+    scope: &'a mut Vec<KeyValue>,
+    record: &'a mut Vec<KeyValue>,
+}
+
+/// Records grouped by the resource and scope they ended up with.
+struct Regrouped<T> {
+    resources: Vec<ResourceGroup<T>>,
+    index: HashMap<Vec<u8>, usize>,
+}
+
+struct ResourceGroup<T> {
+    resource: Option<Resource>,
+    schema_url: String,
+    scopes: Vec<ScopeGroup<T>>,
+    index: HashMap<Vec<u8>, usize>,
+}
+
+struct ScopeGroup<T> {
+    scope: Option<InstrumentationScope>,
+    schema_url: String,
+    records: Vec<T>,
+}
+
+impl Transformer {
+    pub fn new(rules: &[TransformRule]) -> Self {
+        let crosses_levels = rules.iter().any(|rule| match &rule.from {
+            TransformSource::Attribute(from) => from.level != rule.to.level,
+            TransformSource::Literal(_) => false,
+        });
+        Self {
+            rules: rules.to_vec(),
+            crosses_levels,
+        }
+    }
+
+    pub fn transform_traces(&self, req: &mut ExportTraceServiceRequest) {
+        if !self.crosses_levels {
+            for rs in &mut req.resource_spans {
+                self.resource(&mut rs.resource);
+                for ss in &mut rs.scope_spans {
+                    self.scope(&mut ss.scope);
+                    for span in &mut ss.spans {
+                        self.record(&mut span.attributes);
+                    }
+                }
+            }
+            return;
+        }
+        let mut regrouped = Regrouped::default();
+        for rs in mem::take(&mut req.resource_spans) {
+            for ss in rs.scope_spans {
+                for mut span in ss.spans {
+                    let (resource, scope) =
+                        self.parents(&rs.resource, &ss.scope, &mut span.attributes);
+                    regrouped
+                        .records(resource, &rs.schema_url, scope, &ss.schema_url)
+                        .push(span);
+                }
+            }
+        }
+        req.resource_spans = regrouped
+            .resources
+            .into_iter()
+            .map(|r| ResourceSpans {
+                resource: r.resource,
+                scope_spans: r
+                    .scopes
+                    .into_iter()
+                    .map(|s| ScopeSpans {
+                        scope: s.scope,
+                        spans: s.records,
+                        schema_url: s.schema_url,
+                    })
+                    .collect(),
+                schema_url: r.schema_url,
+            })
+            .collect();
+    }
+
+    pub fn transform_metrics(&self, req: &mut ExportMetricsServiceRequest) {
+        if !self.crosses_levels {
+            for rm in &mut req.resource_metrics {
+                self.resource(&mut rm.resource);
+                for sm in &mut rm.scope_metrics {
+                    self.scope(&mut sm.scope);
+                    for metric in &mut sm.metrics {
+                        for_each_point(metric, |attrs| self.record(attrs));
+                    }
+                }
+            }
+            return;
+        }
+        // Every metric is split into one metric per data point, then points of
+        // the same metric that end up in the same scope are put back together.
+        let mut regrouped: Regrouped<(usize, Metric)> = Regrouped::default();
+        let mut origin = 0usize;
+        for rm in mem::take(&mut req.resource_metrics) {
+            for sm in rm.scope_metrics {
+                for metric in sm.metrics {
+                    origin += 1;
+                    for mut point in split_points(metric) {
+                        let mut parents = None;
+                        for_each_point(&mut point, |attrs| {
+                            parents = Some(self.parents(&rm.resource, &sm.scope, attrs));
+                        });
+                        let Some((resource, scope)) = parents else {
+                            continue;
+                        };
+                        let metrics =
+                            regrouped.records(resource, &rm.schema_url, scope, &sm.schema_url);
+                        match metrics.last_mut() {
+                            Some((last, metric)) if *last == origin => {
+                                append_points(&mut metric.data, point.data);
+                            }
+                            _ => metrics.push((origin, point)),
+                        }
+                    }
+                }
+            }
+        }
+        req.resource_metrics = regrouped
+            .resources
+            .into_iter()
+            .map(|r| ResourceMetrics {
+                resource: r.resource,
+                scope_metrics: r
+                    .scopes
+                    .into_iter()
+                    .map(|s| ScopeMetrics {
+                        scope: s.scope,
+                        metrics: s.records.into_iter().map(|(_, m)| m).collect(),
+                        schema_url: s.schema_url,
+                    })
+                    .collect(),
+                schema_url: r.schema_url,
+            })
+            .collect();
+    }
+
+    pub fn transform_logs(&self, req: &mut ExportLogsServiceRequest) {
+        if !self.crosses_levels {
+            for rl in &mut req.resource_logs {
+                self.resource(&mut rl.resource);
+                for sl in &mut rl.scope_logs {
+                    self.scope(&mut sl.scope);
+                    for record in &mut sl.log_records {
+                        self.record(&mut record.attributes);
+                    }
+                }
+            }
+            return;
+        }
+        let mut regrouped = Regrouped::default();
+        for rl in mem::take(&mut req.resource_logs) {
+            for sl in rl.scope_logs {
+                for mut record in sl.log_records {
+                    let (resource, scope) =
+                        self.parents(&rl.resource, &sl.scope, &mut record.attributes);
+                    regrouped
+                        .records(resource, &rl.schema_url, scope, &sl.schema_url)
+                        .push(record);
+                }
+            }
+        }
+        req.resource_logs = regrouped
+            .resources
+            .into_iter()
+            .map(|r| ResourceLogs {
+                resource: r.resource,
+                scope_logs: r
+                    .scopes
+                    .into_iter()
+                    .map(|s| ScopeLogs {
+                        scope: s.scope,
+                        log_records: s.records,
+                        schema_url: s.schema_url,
+                    })
+                    .collect(),
+                schema_url: r.schema_url,
+            })
+            .collect();
+    }
+
+    fn resource(&self, resource: &mut Option<Resource>) {
+        if let Some(resource) = resource {
+            self.apply_at(AttributeLevel::Resource, &mut resource.attributes);
+        }
+    }
+
+    fn scope(&self, scope: &mut Option<InstrumentationScope>) {
+        if let Some(scope) = scope {
+            self.apply_at(AttributeLevel::Scope, &mut scope.attributes);
+        }
+    }
+
+    fn record(&self, attrs: &mut Vec<KeyValue>) {
+        self.apply_at(AttributeLevel::Record, attrs);
+    }
+
+    /// Apply rules that stay on one level to the attributes of that level.
+    fn apply_at(&self, level: AttributeLevel, attrs: &mut Vec<KeyValue>) {
+        let mut others = (Vec::new(), Vec::new());
+        let mut levels = match level {
+            AttributeLevel::Resource => Levels {
+                resource: attrs,
+                scope: &mut others.0,
+                record: &mut others.1,
+            },
+            AttributeLevel::Scope => Levels {
+                resource: &mut others.0,
+                scope: attrs,
+                record: &mut others.1,
+            },
+            AttributeLevel::Record => Levels {
+                resource: &mut others.0,
+                scope: &mut others.1,
+                record: attrs,
+            },
+        };
+        for rule in self.rules.iter().filter(|rule| rule.to.level == level) {
+            apply(rule, &mut levels);
+        }
+    }
+
+    /// Apply every rule to one record and copies of its scope and resource,
+    /// returning the copies.
+    fn parents(
+        &self,
+        resource: &Option<Resource>,
+        scope: &Option<InstrumentationScope>,
+        record: &mut Vec<KeyValue>,
+    ) -> (Option<Resource>, Option<InstrumentationScope>) {
+        let mut resource = resource.clone();
+        let mut scope = scope.clone();
+        let mut resource_attrs = resource
+            .as_mut()
+            .map(|r| mem::take(&mut r.attributes))
+            .unwrap_or_default();
+        let mut scope_attrs = scope
+            .as_mut()
+            .map(|s| mem::take(&mut s.attributes))
+            .unwrap_or_default();
+        let mut levels = Levels {
+            resource: &mut resource_attrs,
+            scope: &mut scope_attrs,
+            record,
+        };
+        for rule in &self.rules {
+            apply(rule, &mut levels);
+        }
+        // Only materialize a missing resource or scope if it gained attributes.
+        if resource.is_some() || !resource_attrs.is_empty() {
+            resource.get_or_insert_with(Resource::default).attributes = resource_attrs;
+        }
+        if scope.is_some() || !scope_attrs.is_empty() {
+            scope
+                .get_or_insert_with(InstrumentationScope::default)
+                .attributes = scope_attrs;
+        }
+        (resource, scope)
+    }
+}
+
+impl Levels<'_> {
+    fn get(&mut self, level: AttributeLevel) -> &mut Vec<KeyValue> {
+        match level {
+            AttributeLevel::Resource => &mut *self.resource,
+            AttributeLevel::Scope => &mut *self.scope,
+            AttributeLevel::Record => &mut *self.record,
+        }
+    }
+}
+
+fn apply(rule: &TransformRule, levels: &mut Levels) {
+    let value = match &rule.from {
+        TransformSource::Attribute(from) => levels
+            .get(from.level)
+            .iter()
+            .find(|kv| kv.key == from.key)
+            .and_then(|kv| kv.value.clone()),
+        TransformSource::Literal(literal) => Some(AnyValue {
+            value: Some(Value::StringValue(literal.clone())),
+        }),
+    };
+    let Some(value) = value else {
+        return;
+    };
+    let to = levels.get(rule.to.level);
+    match to.iter_mut().find(|kv| kv.key == rule.to.key) {
+        Some(_) if rule.op == TransformOp::SetIfAbsent => return,
+        Some(existing) => existing.value = Some(value),
+        None => to.push(KeyValue {
+            key: rule.to.key.clone(),
+            value: Some(value),
+        }),
+    }
+    if let (TransformOp::Rename, TransformSource::Attribute(from)) = (rule.op, &rule.from)
+        && *from != rule.to
+    {
+        levels.get(from.level).retain(|kv| kv.key != from.key);
+    }
+}
+
+impl<T> Default for Regrouped<T> {
+    fn default() -> Self {
+        Self {
+            resources: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Regrouped<T> {
+    /// The records of the group for this resource and scope, in the order
+    /// groups were first seen.
+    fn records(
+        &mut self,
+        resource: Option<Resource>,
+        resource_schema_url: &str,
+        scope: Option<InstrumentationScope>,
+        scope_schema_url: &str,
+    ) -> &mut Vec<T> {
+        let key = resource_key(resource.as_ref(), resource_schema_url);
+        let r = *self.index.entry(key).or_insert_with(|| {
+            self.resources.push(ResourceGroup {
+                resource,
+                schema_url: resource_schema_url.to_owned(),
+                scopes: Vec::new(),
+                index: HashMap::new(),
+            });
+            self.resources.len() - 1
+        });
+        let group = &mut self.resources[r];
+        let mut key = scope_key(scope.as_ref());
+        key.extend_from_slice(scope_schema_url.as_bytes());
+        let s = *group.index.entry(key).or_insert_with(|| {
+            group.scopes.push(ScopeGroup {
+                scope,
+                schema_url: scope_schema_url.to_owned(),
+                records: Vec::new(),
+            });
+            group.scopes.len() - 1
+        });
+        &mut group.scopes[s].records
+    }
+}
+
+fn for_each_point(metric: &mut Metric, mut f: impl FnMut(&mut Vec<KeyValue>)) {
+    match &mut metric.data {
+        Some(Data::Gauge(g)) => g.data_points.iter_mut().for_each(|p| f(&mut p.attributes)),
+        Some(Data::Sum(s)) => s.data_points.iter_mut().for_each(|p| f(&mut p.attributes)),
+        Some(Data::Histogram(h)) => h.data_points.iter_mut().for_each(|p| f(&mut p.attributes)),
+        Some(Data::ExponentialHistogram(h)) => {
+            h.data_points.iter_mut().for_each(|p| f(&mut p.attributes))
+        }
+        Some(Data::Summary(s)) => s.data_points.iter_mut().for_each(|p| f(&mut p.attributes)),
+        None => {}
+    }
+}
+
+/// One copy of `metric` per data point, holding only that point.
+fn split_points(mut metric: Metric) -> Vec<Metric> {
+    let data = metric.data.take();
+    let with = |data| Metric {
+        data: Some(data),
+        ..metric.clone()
+    };
+    match data {
+        Some(Data::Gauge(mut g)) => mem::take(&mut g.data_points)
+            .into_iter()
+            .map(|p| {
+                with(Data::Gauge(Gauge {
+                    data_points: vec![p],
+                }))
+            })
+            .collect(),
+        Some(Data::Sum(mut s)) => mem::take(&mut s.data_points)
+            .into_iter()
+            .map(|p| {
+                with(Data::Sum(Sum {
+                    data_points: vec![p],
+                    ..s.clone()
+                }))
+            })
+            .collect(),
+        Some(Data::Histogram(mut h)) => mem::take(&mut h.data_points)
+            .into_iter()
+            .map(|p| {
+                with(Data::Histogram(Histogram {
+                    data_points: vec![p],
+                    ..h.clone()
+                }))
+            })
+            .collect(),
+        Some(Data::ExponentialHistogram(mut h)) => mem::take(&mut h.data_points)
+            .into_iter()
+            .map(|p| {
+                with(Data::ExponentialHistogram(ExponentialHistogram {
+                    data_points: vec![p],
+                    ..h.clone()
+                }))
+            })
+            .collect(),
+        Some(Data::Summary(mut s)) => mem::take(&mut s.data_points)
+            .into_iter()
+            .map(|p| {
+                with(Data::Summary(Summary {
+                    data_points: vec![p],
+                }))
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+#[path = "transform_tests.rs"]
+mod tests;
@@ -0,0 +1,266 @@
+use super::*;
+use crate::config::AttributeRef;
+use crate::proto::opentelemetry::proto::{
+    logs::v1::LogRecord,
+    metrics::v1::{NumberDataPoint, number_data_point},
+    trace::v1::Span,
+};
+
+fn string(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value.to_owned())),
+        }),
+    }
+}
+
+fn attribute(reference: &str) -> AttributeRef {
+    let (level, key) = match reference.split_once(':') {
+        Some(("resource", key)) => (AttributeLevel::Resource, key),
+        Some(("scope", key)) => (AttributeLevel::Scope, key),
+        _ => (AttributeLevel::Record, reference),
+    };
+    AttributeRef {
+        level,
+        key: key.to_owned(),
+    }
+}
+
+fn rule(op: TransformOp, from: &str, to: &str) -> TransformRule {
+    TransformRule {
+        op,
+        from: TransformSource::Attribute(attribute(from)),
+        to: attribute(to),
+    }
+}
+
+fn resource(attributes: Vec<KeyValue>) -> Option<Resource> {
+    Some(Resource {
+        attributes,
+        ..Default::default()
+    })
+}
+
+fn span(name: &str, attributes: Vec<KeyValue>) -> Span {
+    Span {
+        name: name.to_owned(),
+        attributes,
+        ..Default::default()
+    }
+}
+
+fn traces(spans: Vec<Span>) -> ExportTraceServiceRequest {
+    ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: resource(vec![string("service.name", "checkout")]),
+            scope_spans: vec![ScopeSpans {
+                scope: Some(InstrumentationScope {
+                    name: "http".to_owned(),
+                    ..Default::default()
+                }),
+                spans,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+}
+
+#[test]
+fn renames_copies_and_sets_on_spans() {
+    let mut req = traces(vec![
+        span(
+            "old",
+            vec![string("http.method", "GET"), string("env", "dev")],
+        ),
+        span("new", vec![string("http.request.method", "POST")]),
+    ]);
+    let transformer = Transformer::new(&[
+        rule(TransformOp::Rename, "http.method", "http.request.method"),
+        rule(TransformOp::Copy, "http.request.method", "method"),
+        TransformRule {
+            op: TransformOp::SetIfAbsent,
+            from: TransformSource::Literal("prod".to_owned()),
+            to: attribute("env"),
+        },
+    ]);
+
+    transformer.transform_traces(&mut req);
+
+    let spans = &req.resource_spans[0].scope_spans[0].spans;
+    assert_eq!(
+        spans[0].attributes,
+        [
+            string("env", "dev"),
+            string("http.request.method", "GET"),
+            string("method", "GET"),
+        ]
+    );
+    assert_eq!(
+        spans[1].attributes,
+        [
+            string("http.request.method", "POST"),
+            string("method", "POST"),
+            string("env", "prod"),
+        ]
+    );
+}
+
+#[test]
+fn promoting_span_attributes_splits_the_resource() {
+    let mut req = traces(vec![
+        span("a", vec![string("tenant", "acme")]),
+        span("b", vec![string("tenant", "globex")]),
+        span("c", vec![string("tenant", "acme")]),
+        span("d", vec![]),
+    ]);
+    let transformer = Transformer::new(&[
+        rule(TransformOp::Rename, "tenant", "resource:tenant"),
+        rule(TransformOp::Rename, "resource:service.name", "service"),
+    ]);
+
+    transformer.transform_traces(&mut req);
+
+    let groups: Vec<_> = req
+        .resource_spans
+        .iter()
+        .map(|rs| {
+            let spans = &rs.scope_spans[0].spans;
+            assert!(
+                spans
+                    .iter()
+                    .all(|s| s.attributes == [string("service", "checkout")])
+            );
+            assert_eq!(rs.scope_spans[0].scope.as_ref().unwrap().name, "http");
+            (
+                rs.resource.as_ref().unwrap().attributes.clone(),
+                spans.iter().map(|s| &s.name[..]).collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        groups,
+        [
+            (vec![string("tenant", "acme")], vec!["a", "c"]),
+            (vec![string("tenant", "globex")], vec!["b"]),
+            (vec![], vec!["d"]),
+        ]
+    );
+}
+
+#[test]
+fn promoting_data_point_attributes_regroups_metric_points() {
+    let point = |region: &str, value: i64| NumberDataPoint {
+        attributes: vec![string("cloud.region", region)],
+        value: Some(number_data_point::Value::AsInt(value)),
+        ..Default::default()
+    };
+    let mut req = ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            scope_metrics: vec![ScopeMetrics {
+                metrics: vec![Metric {
+                    name: "requests".to_owned(),
+                    data: Some(Data::Sum(Sum {
+                        data_points: vec![
+                            point("eu-west-1", 1),
+                            point("us-east-1", 2),
+                            point("eu-west-1", 3),
+                        ],
+                        aggregation_temporality: 1,
+                        is_monotonic: true,
+                    })),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    };
+    let transformer = Transformer::new(&[rule(
+        TransformOp::Rename,
+        "cloud.region",
+        "resource:cloud.region",
+    )]);
+
+    transformer.transform_metrics(&mut req);
+
+    let groups: Vec<_> = req
+        .resource_metrics
+        .iter()
+        .map(|rm| {
+            let metrics = &rm.scope_metrics[0].metrics;
+            assert_eq!(metrics.len(), 1);
+            let Some(Data::Sum(sum)) = &metrics[0].data else {
+                panic!("expected a sum");
+            };
+            assert!(sum.is_monotonic);
+            let values: Vec<_> = sum
+                .data_points
+                .iter()
+                .map(|p| {
+                    assert!(p.attributes.is_empty());
+                    p.value
+                })
+                .collect();
+            (rm.resource.as_ref().unwrap().attributes.clone(), values)
+        })
+        .collect();
+    let int = |v| Some(number_data_point::Value::AsInt(v));
+    assert_eq!(
+        groups,
+        [
+            (
+                vec![string("cloud.region", "eu-west-1")],
+                vec![int(1), int(3)]
+            ),
+            (vec![string("cloud.region", "us-east-1")], vec![int(2)]),
+        ]
+    );
+}
+
+#[test]
+fn demotes_scope_attributes_to_log_records() {
+    let mut req = ExportLogsServiceRequest {
+        resource_logs: vec![ResourceLogs {
+            scope_logs: vec![ScopeLogs {
+                scope: Some(InstrumentationScope {
+                    attributes: vec![string("logger", "app")],
+                    ..Default::default()
+                }),
+                log_records: vec![
+                    LogRecord::default(),
+                    LogRecord {
+                        attributes: vec![string("logger.name", "audit")],
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    };
+    let transformer = Transformer::new(&[rule(
+        TransformOp::SetIfAbsent,
+        "scope:logger",
+        "logger.name",
+    )]);
+
+    transformer.transform_logs(&mut req);
+
+    assert_eq!(req.resource_logs.len(), 1);
+    let sl = &req.resource_logs[0].scope_logs[0];
+    assert_eq!(
+        sl.scope.as_ref().unwrap().attributes,
+        [string("logger", "app")]
+    );
+    let records: Vec<_> = sl.log_records.iter().map(|r| &r.attributes).collect();
+    assert_eq!(
+        records,
+        [
+            &vec![string("logger.name", "app")],
+            &vec![string("logger.name", "audit")],
+        ]
+    );
+    assert!(req.resource_logs[0].resource.is_none());
+}
@@ -0,0 +1,503 @@
+//! Conversion of OTLP metrics into Prometheus remote-write requests.
+//!
+//! Follows the [Prometheus compatibility] part of the OpenTelemetry spec:
+//!
+//! - metric names are sanitized and get their unit (`_seconds`, `_bytes`,
+//!   `_ratio` for unit `1` gauges) and, for monotonic sums, `_total` appended
+//! - attribute keys are sanitized into label names
+//! - `job` and `instance` come from `service.namespace`/`service.name` and
+//!   `service.instance.id`; the other resource attributes go on `target_info`
+//! - histograms become cumulative `_bucket` series plus `_sum` and `_count`,
+//!   summaries `quantile` series plus `_sum` and `_count`
+//!
+//! Prometheus only understands cumulative temporality, so delta sums and
+//! histograms are dropped, as are exponential histograms, which remote-write
+//! v1 cannot carry.
+//!
+//! [Prometheus compatibility]: https://opentelemetry.io/docs/specs/otel/compatibility/prometheus_and_openmetrics/
+
+pub mod snappy;
+
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use tracing::debug;
+
+use crate::proto::opentelemetry::proto::{
+    collector::metrics::v1::ExportMetricsServiceRequest,
+    common::v1::{InstrumentationScope, KeyValue, any_value::Value},
+    metrics::v1::{
+        AggregationTemporality, DataPointFlags, Metric, metric::Data, number_data_point,
+    },
+};
+
+/// The `NaN` Prometheus uses to mark a series as stale.
+const STALE_NAN: u64 = 0x7ff0_0000_0000_0002;
+
+/// `prometheus.WriteRequest` from the remote-write v1 protocol.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub timeseries: Vec<TimeSeries>,
+    #[prost(message, repeated, tag = "3")]
+    pub metadata: Vec<MetricMetadata>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TimeSeries {
+    /// Sorted by name.
+    #[prost(message, repeated, tag = "1")]
+    pub labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Label {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Sample {
+    #[prost(double, tag = "1")]
+    pub value: f64,
+    /// Milliseconds since the epoch.
+    #[prost(int64, tag = "2")]
+    pub timestamp: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MetricMetadata {
+    #[prost(enumeration = "MetricType", tag = "1")]
+    pub r#type: i32,
+    #[prost(string, tag = "2")]
+    pub metric_family_name: String,
+    #[prost(string, tag = "4")]
+    pub help: String,
+    #[prost(string, tag = "5")]
+    pub unit: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum MetricType {
+    Unknown = 0,
+    Counter = 1,
+    Gauge = 2,
+    Histogram = 3,
+    GaugeHistogram = 4,
+    Summary = 5,
+    Info = 6,
+    Stateset = 7,
+}
+
+/// Convert `req` into a remote-write request.
+pub fn write_request(req: &ExportMetricsServiceRequest) -> WriteRequest {
+    let now_ms = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64);
+    let mut out = WriteRequest::default();
+    for rm in &req.resource_metrics {
+        let resource = rm.resource.as_ref().map_or(&[][..], |r| &r.attributes[..]);
+        let target = Target::new(resource);
+        if let Some(info) = target.info(resource, now_ms) {
+            out.timeseries.push(info);
+        }
+        for sm in &rm.scope_metrics {
+            for metric in &sm.metrics {
+                convert_metric(&mut out, metric, &target, sm.scope.as_ref(), now_ms);
+            }
+        }
+    }
+    out
+}
+
+/// The `job` and `instance` labels of a resource.
+struct Target {
+    labels: Vec<(String, String)>,
+}
+
+impl Target {
+    fn new(resource: &[KeyValue]) -> Self {
+        let namespace = string_attribute(resource, "service.namespace");
+        let job = match (namespace, string_attribute(resource, "service.name")) {
+            (Some(namespace), Some(name)) => Some(format!("{namespace}/{name}")),
+            (None, Some(name)) => Some(name.to_owned()),
+            _ => None,
+        };
+        let instance = string_attribute(resource, "service.instance.id").map(str::to_owned);
+        let labels = [("job", job), ("instance", instance)]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.to_owned(), value?)))
+            .collect();
+        Self { labels }
+    }
+
+    /// `target_info` carrying the resource attributes that are not already
+    /// `job` or `instance`.
+    fn info(&self, resource: &[KeyValue], now_ms: i64) -> Option<TimeSeries> {
+        const IDENTIFYING: [&str; 3] = ["service.name", "service.namespace", "service.instance.id"];
+        let other: Vec<&KeyValue> = resource
+            .iter()
+            .filter(|kv| !IDENTIFYING.contains(&kv.key.as_str()))
+            .collect();
+        if other.is_empty() {
+            return None;
+        }
+        let mut labels = Labels::default();
+        for kv in other {
+            labels.attribute(kv);
+        }
+        labels.extend(&self.labels);
+        Some(labels.series("target_info", 1.0, now_ms))
+    }
+}
+
+/// Label set under construction. Attribute keys that sanitize to the same
+/// label name have their values joined with `;`.
+#[derive(Default, Clone)]
+struct Labels(BTreeMap<String, String>);
+
+impl Labels {
+    fn attribute(&mut self, kv: &KeyValue) {
+        let Some(value) = kv
+            .value
+            .as_ref()
+            .and_then(|v| label_value(v.value.as_ref()?))
+        else {
+            return;
+        };
+        self.0
+            .entry(label_name(&kv.key))
+            .and_modify(|existing| {
+                existing.push(';');
+                existing.push_str(&value);
+            })
+            .or_insert(value);
+    }
+
+    fn set(&mut self, name: &str, value: impl Into<String>) {
+        self.0.insert(name.to_owned(), value.into());
+    }
+
+    fn extend(&mut self, labels: &[(String, String)]) {
+        for (name, value) in labels {
+            self.set(name, value.clone());
+        }
+    }
+
+    fn series(mut self, name: &str, value: f64, timestamp: i64) -> TimeSeries {
+        self.set("__name__", name);
+        TimeSeries {
+            labels: self
+                .0
+                .into_iter()
+                .map(|(name, value)| Label { name, value })
+                .collect(),
+            samples: vec![Sample { value, timestamp }],
+        }
+    }
+}
+
+fn convert_metric(
+    out: &mut WriteRequest,
+    metric: &Metric,
+    target: &Target,
+    scope: Option<&InstrumentationScope>,
+    now_ms: i64,
+) {
+    let labels = |attributes: &[KeyValue]| {
+        let mut labels = Labels::default();
+        for kv in attributes {
+            labels.attribute(kv);
+        }
+        if let Some(scope) = scope {
+            if !scope.name.is_empty() {
+                labels.set("otel_scope_name", scope.name.clone());
+            }
+            if !scope.version.is_empty() {
+                labels.set("otel_scope_version", scope.version.clone());
+            }
+        }
+        labels.extend(&target.labels);
+        labels
+    };
+    let timestamp = |time_unix_nano: u64| match time_unix_nano {
+        0 => now_ms,
+        nanos => (nanos / 1_000_000) as i64,
+    };
+    let value = |flags: u32, value: f64| {
+        if flags & DataPointFlags::NoRecordedValueMask as u32 != 0 {
+            f64::from_bits(STALE_NAN)
+        } else {
+            value
+        }
+    };
+    let delta = |temporality: i32| temporality == AggregationTemporality::Delta as i32;
+
+    let metric_type = match &metric.data {
+        Some(Data::Gauge(gauge)) => {
+            let name = metric_name(metric, MetricType::Gauge);
+            for point in &gauge.data_points {
+                let Some(number) = number(point.value) else {
+                    continue;
+                };
+                out.timeseries.push(labels(&point.attributes).series(
+                    &name,
+                    value(point.flags, number),
+                    timestamp(point.time_unix_nano),
+                ));
+            }
+            MetricType::Gauge
+        }
+        Some(Data::Sum(sum)) => {
+            if delta(sum.aggregation_temporality) {
+                debug!(metric = %metric.name, "dropping delta sum for Prometheus remote-write");
+                return;
+            }
+            let metric_type = if sum.is_monotonic {
+                MetricType::Counter
+            } else {
+                MetricType::Gauge
+            };
+            let name = metric_name(metric, metric_type);
+            for point in &sum.data_points {
+                let Some(number) = number(point.value) else {
+                    continue;
+                };
+                out.timeseries.push(labels(&point.attributes).series(
+                    &name,
+                    value(point.flags, number),
+                    timestamp(point.time_unix_nano),
+                ));
+            }
+            metric_type
+        }
+        Some(Data::Histogram(histogram)) => {
+            if delta(histogram.aggregation_temporality) {
+                debug!(metric = %metric.name, "dropping delta histogram for Prometheus remote-write");
+                return;
+            }
+            let name = metric_name(metric, MetricType::Histogram);
+            for point in &histogram.data_points {
+                let ts = timestamp(point.time_unix_nano);
+                let base = labels(&point.attributes);
+                let mut cumulative = 0;
+                for (i, count) in point.bucket_counts.iter().enumerate() {
+                    cumulative += count;
+                    let le = point
+                        .explicit_bounds
+                        .get(i)
+                        .map_or_else(|| "+Inf".to_owned(), |bound| format_float(*bound));
+                    let mut bucket = base.clone();
+                    bucket.set("le", le);
+                    out.timeseries.push(bucket.series(
+                        &format!("{name}_bucket"),
+                        value(point.flags, cumulative as f64),
+                        ts,
+                    ));
+                }
+                if let Some(sum) = point.sum {
+                    out.timeseries.push(base.clone().series(
+                        &format!("{name}_sum"),
+                        value(point.flags, sum),
+                        ts,
+                    ));
+                }
+                out.timeseries.push(base.series(
+                    &format!("{name}_count"),
+                    value(point.flags, point.count as f64),
+                    ts,
+                ));
+            }
+            MetricType::Histogram
+        }
+        Some(Data::Summary(summary)) => {
+            let name = metric_name(metric, MetricType::Summary);
+            for point in &summary.data_points {
+                let ts = timestamp(point.time_unix_nano);
+                let base = labels(&point.attributes);
+                for quantile in &point.quantile_values {
+                    let mut series = base.clone();
+                    series.set("quantile", format_float(quantile.quantile));
+                    out.timeseries.push(series.series(
+                        &name,
+                        value(point.flags, quantile.value),
+                        ts,
+                    ));
+                }
+                out.timeseries.push(base.clone().series(
+                    &format!("{name}_sum"),
+                    value(point.flags, point.sum),
+                    ts,
+                ));
+                out.timeseries.push(base.series(
+                    &format!("{name}_count"),
+                    value(point.flags, point.count as f64),
+                    ts,
+                ));
+            }
+            MetricType::Summary
+        }
+        Some(Data::ExponentialHistogram(_)) => {
+            debug!(metric = %metric.name, "dropping exponential histogram for Prometheus remote-write");
+            return;
+        }
+        None => return,
+    };
+    out.metadata.push(MetricMetadata {
+        r#type: metric_type as i32,
+        metric_family_name: metric_name(metric, metric_type),
+        help: metric.description.clone(),
+        unit: unit_suffix(&metric.unit, metric_type).unwrap_or_default(),
+    });
+}
+
+fn number(value: Option<number_data_point::Value>) -> Option<f64> {
+    match value? {
+        number_data_point::Value::AsDouble(v) => Some(v),
+        number_data_point::Value::AsInt(v) => Some(v as f64),
+    }
+}
+
+/// The Prometheus name of `metric` when exported as `metric_type`.
+pub fn metric_name(metric: &Metric, metric_type: MetricType) -> String {
+    let mut name = sanitize(&metric.name, false);
+    if let Some(unit) = unit_suffix(&metric.unit, metric_type)
+        && !name.split('_').any(|word| word == unit)
+    {
+        name.push('_');
+        name.push_str(&unit);
+    }
+    if metric_type == MetricType::Counter && !name.ends_with("_total") {
+        name.push_str("_total");
+    }
+    name
+}
+
+/// The name suffix for a UCUM unit: `ms` is `milliseconds`, `By/s` is
+/// `bytes_per_second`, `1` is `ratio` for gauges. Annotations in braces are
+/// dropped.
+fn unit_suffix(unit: &str, metric_type: MetricType) -> Option<String> {
+    if unit == "1" {
+        return (metric_type == MetricType::Gauge).then(|| "ratio".to_owned());
+    }
+    let (main, per) = match unit.split_once('/') {
+        Some((main, per)) => (main, Some(per)),
+        None => (unit, None),
+    };
+    let main = unit_word(main, false);
+    let per = per.and_then(|per| unit_word(per, true));
+    let suffix = match (main, per) {
+        (Some(main), Some(per)) => format!("{main}_per_{per}"),
+        (Some(main), None) => main,
+        (None, Some(per)) => format!("per_{per}"),
+        (None, None) => return None,
+    };
+    Some(suffix)
+}
+
+fn unit_word(unit: &str, singular: bool) -> Option<String> {
+    let unit = unit.trim();
+    if unit.is_empty() || (unit.starts_with('{') && unit.ends_with('}')) {
+        return None;
+    }
+    let word = match (unit, singular) {
+        ("d", false) => "days",
+        ("h", false) => "hours",
+        ("min", false) => "minutes",
+        ("s", false) => "seconds",
+        ("ms", false) => "milliseconds",
+        ("us", false) => "microseconds",
+        ("ns", false) => "nanoseconds",
+        ("By", false) => "bytes",
+        ("KiBy", false) => "kibibytes",
+        ("MiBy", false) => "mebibytes",
+        ("GiBy", false) => "gibibytes",
+        ("KBy", false) | ("kBy", false) => "kilobytes",
+        ("MBy", false) => "megabytes",
+        ("GBy", false) => "gigabytes",
+        ("m", false) => "meters",
+        ("V", false) => "volts",
+        ("A", false) => "amperes",
+        ("J", false) => "joules",
+        ("W", false) => "watts",
+        ("g", false) => "grams",
+        ("Cel", false) => "celsius",
+        ("Hz", false) => "hertz",
+        ("%", false) => "percent",
+        ("s", true) => "second",
+        ("m", true) => "minute",
+        ("h", true) => "hour",
+        ("d", true) => "day",
+        ("w", true) => "week",
+        ("mo", true) => "month",
+        ("y", true) => "year",
+        _ => return Some(sanitize(unit, true)).filter(|word| !word.is_empty()),
+    };
+    Some(word.to_owned())
+}
+
+/// Replace characters that are not valid in a metric (or label) name with
+/// `_`, collapsing runs of them, and prefix names that start with a digit.
+fn sanitize(name: &str, label: bool) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        let valid = c.is_ascii_alphanumeric() || c == '_' || (c == ':' && !label);
+        if valid {
+            out.push(c);
+        } else if !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn label_name(key: &str) -> String {
+    let name = sanitize(key, true);
+    if name.starts_with('_') && !name.starts_with("__") {
+        format!("key{name}")
+    } else {
+        name
+    }
+}
+
+fn label_value(value: &Value) -> Option<String> {
+    match value {
+        Value::StringValue(s) => Some(s.clone()),
+        Value::BoolValue(b) => Some(b.to_string()),
+        Value::IntValue(i) => Some(i.to_string()),
+        Value::DoubleValue(d) => Some(format_float(*d)),
+        _ => None,
+    }
+}
+
+/// Floats as Prometheus writes them: `+Inf`, `-Inf`, `NaN`, otherwise the
+/// shortest representation.
+fn format_float(v: f64) -> String {
+    if v.is_nan() {
+        "NaN".to_owned()
+    } else if v.is_infinite() {
+        if v > 0.0 { "+Inf" } else { "-Inf" }.to_owned()
+    } else {
+        v.to_string()
+    }
+}
+
+fn string_attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a str> {
+    attributes.iter().find(|kv| kv.key == key).and_then(|kv| {
+        match kv.value.as_ref()?.value.as_ref()? {
+            Value::StringValue(s) => Some(s.as_str()),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,166 @@
+//! Snappy block-format compression, as remote-write requires.
+//!
+//! A port of the reference encoder, as golang/snappy (used by Prometheus)
+//! implements it: the input is split into 64 KiB blocks, 4-byte matches are
+//! found through a hash table sized to the block, and lookups skip ahead
+//! faster the longer nothing matches. It produces the same bytes as the
+//! reference implementation.
+//!
+//! See the [format description].
+//!
+//! [format description]: https://github.com/google/snappy/blob/main/format_description.txt
+
+/// Blocks are compressed independently, so copies never reach further back
+/// than this and offsets fit in two bytes.
+const MAX_BLOCK_SIZE: usize = 1 << 16;
+
+const MAX_TABLE_SIZE: usize = 1 << 14;
+
+/// Bytes at the end of a block where no match starts.
+const INPUT_MARGIN: usize = 16 - 1;
+
+/// Blocks shorter than this are written as a single literal.
+const MIN_NON_LITERAL_BLOCK_SIZE: usize = 1 + 1 + INPUT_MARGIN;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    write_varint(&mut out, input.len() as u64);
+    for block in input.chunks(MAX_BLOCK_SIZE) {
+        if block.len() < MIN_NON_LITERAL_BLOCK_SIZE {
+            write_literal(&mut out, block);
+        } else {
+            compress_block(&mut out, block);
+        }
+    }
+    out
+}
+
+fn compress_block(out: &mut Vec<u8>, src: &[u8]) {
+    let mut shift = 32 - 8;
+    let mut table_size = 1 << 8;
+    while table_size < MAX_TABLE_SIZE && table_size < src.len() {
+        table_size *= 2;
+        shift -= 1;
+    }
+    let mut table = vec![0u16; table_size];
+    let limit = src.len() - INPUT_MARGIN;
+    let mut next_emit = 0;
+    let mut s = 1;
+    let mut next_hash = hash(load32(src, s), shift);
+    let mut candidate;
+
+    'blocks: loop {
+        // Look for a match, checking every byte at first and then skipping
+        // further ahead the longer the search goes on.
+        let mut skip = 32;
+        let mut next_s = s;
+        loop {
+            s = next_s;
+            let step = skip >> 5;
+            next_s = s + step;
+            skip += step;
+            if next_s > limit {
+                break 'blocks;
+            }
+            candidate = usize::from(table[next_hash]);
+            table[next_hash] = s as u16;
+            next_hash = hash(load32(src, next_s), shift);
+            if load32(src, s) == load32(src, candidate) {
+                break;
+            }
+        }
+        write_literal(out, &src[next_emit..s]);
+
+        // Emit copies for as long as the next bytes match too.
+        loop {
+            let base = s;
+            s += 4;
+            let mut i = candidate + 4;
+            while s < src.len() && src[i] == src[s] {
+                i += 1;
+                s += 1;
+            }
+            write_copy(out, base - candidate, s - base);
+            next_emit = s;
+            if s >= limit {
+                break 'blocks;
+            }
+            let x = load64(src, s - 1);
+            table[hash(x as u32, shift)] = (s - 1) as u16;
+            let current = hash((x >> 8) as u32, shift);
+            candidate = usize::from(table[current]);
+            table[current] = s as u16;
+            if (x >> 8) as u32 != load32(src, candidate) {
+                next_hash = hash((x >> 16) as u32, shift);
+                s += 1;
+                break;
+            }
+        }
+    }
+    write_literal(out, &src[next_emit..]);
+}
+
+fn load32(src: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([src[i], src[i + 1], src[i + 2], src[i + 3]])
+}
+
+fn load64(src: &[u8], i: usize) -> u64 {
+    u64::from_le_bytes(src[i..i + 8].try_into().unwrap())
+}
+
+fn hash(word: u32, shift: u32) -> usize {
+    (word.wrapping_mul(0x1e35_a7bd) >> shift) as usize
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn write_literal(out: &mut Vec<u8>, literal: &[u8]) {
+    if literal.is_empty() {
+        return;
+    }
+    let n = literal.len() - 1;
+    if n < 60 {
+        out.push((n as u8) << 2);
+    } else {
+        // Tags 60..=63 say how many little-endian length bytes follow.
+        let bytes = (n.ilog2() / 8 + 1) as u8;
+        out.push((59 + bytes) << 2);
+        out.extend_from_slice(&n.to_le_bytes()[..bytes as usize]);
+    }
+    out.extend_from_slice(literal);
+}
+
+/// Copies of 4 to 11 bytes less than 2 KiB back take a 1-byte offset, others
+/// a 2-byte offset and at most 64 bytes each. Long copies are split so that
+/// the last one is at least 4 bytes.
+fn write_copy(out: &mut Vec<u8>, offset: usize, mut len: usize) {
+    while len >= 68 {
+        write_copy2(out, offset, 64);
+        len -= 64;
+    }
+    if len > 64 {
+        write_copy2(out, offset, 60);
+        len -= 60;
+    }
+    if len >= 12 || offset >= 2048 {
+        write_copy2(out, offset, len);
+    } else {
+        out.push((((offset >> 8) as u8) << 5) | (((len - 4) as u8) << 2) | 0b01);
+        out.push(offset as u8);
+    }
+}
+
+fn write_copy2(out: &mut Vec<u8>, offset: usize, len: usize) {
+    out.push((((len - 1) as u8) << 2) | 0b10);
+    out.extend_from_slice(&(offset as u16).to_le_bytes());
+}
+
+#[cfg(test)]
+#[path = "snappy_tests.rs"]
+mod tests;
@@ -0,0 +1,176 @@
+use super::*;
+
+/// Reference decoder for the subset of the format `compress` writes, plus
+/// 1- and 4-byte-offset copies.
+fn decompress(input: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let mut len = 0u64;
+    let mut shift = 0;
+    loop {
+        let b = input[pos];
+        pos += 1;
+        len |= u64::from(b & 0x7f) << shift;
+        shift += 7;
+        if b < 0x80 {
+            break;
+        }
+    }
+    let mut out = Vec::with_capacity(len as usize);
+    while pos < input.len() {
+        let tag = input[pos];
+        pos += 1;
+        let (offset, n) = match tag & 0b11 {
+            0 => {
+                let mut n = usize::from(tag >> 2);
+                if n >= 60 {
+                    let bytes = n - 59;
+                    let mut le = [0u8; 8];
+                    le[..bytes].copy_from_slice(&input[pos..pos + bytes]);
+                    n = usize::from_le_bytes(le);
+                    pos += bytes;
+                }
+                out.extend_from_slice(&input[pos..pos + n + 1]);
+                pos += n + 1;
+                continue;
+            }
+            1 => {
+                let offset = (usize::from(tag >> 5) << 8) | usize::from(input[pos]);
+                pos += 1;
+                (offset, usize::from((tag >> 2) & 0b111) + 4)
+            }
+            2 => {
+                let offset = usize::from(u16::from_le_bytes([input[pos], input[pos + 1]]));
+                pos += 2;
+                (offset, usize::from(tag >> 2) + 1)
+            }
+            _ => {
+                let offset = u32::from_le_bytes(input[pos..pos + 4].try_into().unwrap()) as usize;
+                pos += 4;
+                (offset, usize::from(tag >> 2) + 1)
+            }
+        };
+        for _ in 0..n {
+            out.push(out[out.len() - offset]);
+        }
+    }
+    assert_eq!(out.len() as u64, len);
+    out
+}
+
+#[test]
+fn round_trips() {
+    let inputs: Vec<Vec<u8>> = vec![
+        vec![],
+        b"short".to_vec(),
+        b"abcabcabcabcabcabcabcabcabcabcabc".to_vec(),
+        vec![7; 1000],
+        (0..200_000u32).map(|i| (i % 251) as u8).collect(),
+        (0..100_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect(),
+    ];
+    for input in inputs {
+        assert_eq!(decompress(&compress(&input)), input);
+    }
+}
+
+#[test]
+fn compresses_repetitive_input() {
+    let input = b"http_server_duration_seconds_bucket".repeat(100);
+    assert!(compress(&input).len() < input.len() / 10);
+}
+
+#[test]
+fn writes_long_literal_lengths() {
+    let input: Vec<u8> = (0..300u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+        .collect();
+    let compressed = compress(&input);
+    assert_eq!(decompress(&compressed), input);
+}
+
+// The vectors below come from the reference implementation (golang/snappy).
+
+#[test]
+fn literal_headers_match_the_reference() {
+    let cases: &[(usize, &[u8])] = &[
+        (1, b"\x00"),
+        (2, b"\x04"),
+        (59, b"\xe8"),
+        (60, b"\xec"),
+        (61, b"\xf0\x3c"),
+        (62, b"\xf0\x3d"),
+        (254, b"\xf0\xfd"),
+        (255, b"\xf0\xfe"),
+        (256, b"\xf0\xff"),
+        (257, b"\xf4\x00\x01"),
+        (65534, b"\xf4\xfd\xff"),
+        (65535, b"\xf4\xfe\xff"),
+        (65536, b"\xf4\xff\xff"),
+    ];
+    for &(len, header) in cases {
+        let literal = vec![0x99; len];
+        let mut out = Vec::new();
+        write_literal(&mut out, &literal);
+        assert_eq!(&out[..header.len()], header, "length {len}");
+        assert_eq!(&out[header.len()..], literal.as_slice(), "length {len}");
+    }
+}
+
+#[test]
+fn copies_match_the_reference() {
+    let cases: &[(usize, usize, &[u8])] = &[
+        (8, 4, b"\x01\x08"),
+        (8, 11, b"\x1d\x08"),
+        (8, 12, b"\x2e\x08\x00"),
+        (8, 13, b"\x32\x08\x00"),
+        (8, 59, b"\xea\x08\x00"),
+        (8, 60, b"\xee\x08\x00"),
+        (8, 64, b"\xfe\x08\x00"),
+        (8, 65, b"\xee\x08\x00\x05\x08"),
+        (8, 67, b"\xee\x08\x00\x0d\x08"),
+        (8, 68, b"\xfe\x08\x00\x01\x08"),
+        (8, 69, b"\xfe\x08\x00\x05\x08"),
+        (8, 80, b"\xfe\x08\x00\x3e\x08\x00"),
+        (256, 4, b"\x21\x00"),
+        (256, 11, b"\x3d\x00"),
+        (256, 12, b"\x2e\x00\x01"),
+        (256, 65, b"\xee\x00\x01\x25\x00"),
+        (256, 68, b"\xfe\x00\x01\x21\x00"),
+        (256, 80, b"\xfe\x00\x01\x3e\x00\x01"),
+        (2048, 4, b"\x0e\x00\x08"),
+        (2048, 11, b"\x2a\x00\x08"),
+        (2048, 12, b"\x2e\x00\x08"),
+    ];
+    for &(offset, len, want) in cases {
+        let mut out = Vec::new();
+        write_copy(&mut out, offset, len);
+        assert_eq!(out, want, "offset {offset}, length {len}");
+    }
+}
+
+#[test]
+fn blocks_match_the_reference() {
+    let digits = b"0123456789".repeat(20);
+    let cases: &[(&[u8], &[u8])] = &[
+        (b"", b"\x00"),
+        (b"abcdabcd", b"\x08\x1cabcdabcd"),
+        (&[b'a'; 20], b"\x14\x00aJ\x01\x00"),
+        (
+            &digits,
+            b"\xc8\x01\x240123456789\xfe\x0a\x00\xfe\x0a\x00\xf6\x0a\x00",
+        ),
+        (
+            b"http_requests_total{method=\"GET\"} http_requests_total{method=\"POST\"}",
+            b"D\x88http_requests_total{method=\"GET\"} hj\x22\x00\x14POST\"}",
+        ),
+    ];
+    for &(input, want) in cases {
+        assert_eq!(
+            compress(input),
+            want,
+            "{:?}",
+            String::from_utf8_lossy(input)
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use super::*;
+use crate::proto::opentelemetry::proto::{
+    common::v1::AnyValue,
+    metrics::v1::{
+        Gauge, Histogram, HistogramDataPoint, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum,
+    },
+    resource::v1::Resource,
+};
+
+const TIME: u64 = 1_700_000_000_000_000_000;
+
+fn string(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value.to_owned())),
+        }),
+    }
+}
+
+fn metric(name: &str, unit: &str, data: Data) -> Metric {
+    Metric {
+        name: name.to_owned(),
+        unit: unit.to_owned(),
+        data: Some(data),
+        ..Default::default()
+    }
+}
+
+fn point(value: f64, attributes: Vec<KeyValue>) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes,
+        time_unix_nano: TIME,
+        value: Some(number_data_point::Value::AsDouble(value)),
+        ..Default::default()
+    }
+}
+
+fn request(resource: Vec<KeyValue>, metrics: Vec<Metric>) -> ExportMetricsServiceRequest {
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Some(Resource {
+                attributes: resource,
+                ..Default::default()
+            }),
+            scope_metrics: vec![ScopeMetrics {
+                scope: Some(InstrumentationScope {
+                    name: "app".to_owned(),
+                    ..Default::default()
+                }),
+                metrics,
+                ..Default::default()
+            }],
+            schema_url: String::new(),
+        }],
+    }
+}
+
+fn cumulative_sum(monotonic: bool, points: Vec<NumberDataPoint>) -> Data {
+    Data::Sum(Sum {
+        data_points: points,
+        aggregation_temporality: AggregationTemporality::Cumulative as i32,
+        is_monotonic: monotonic,
+    })
+}
+
+/// Labels of a series as `name=value` pairs.
+fn labels(series: &TimeSeries) -> Vec<String> {
+    series
+        .labels
+        .iter()
+        .map(|l| format!("{}={}", l.name, l.value))
+        .collect()
+}
+
+#[test]
+fn names_follow_otel_conventions() {
+    let name = |name: &str, unit: &str, metric_type| {
+        metric_name(
+            &Metric {
+                name: name.to_owned(),
+                unit: unit.to_owned(),
+                ..Default::default()
+            },
+            metric_type,
+        )
+    };
+    assert_eq!(
+        name("http.server.duration", "ms", MetricType::Histogram),
+        "http_server_duration_milliseconds"
+    );
+    assert_eq!(
+        name("http.server.requests", "{request}", MetricType::Counter),
+        "http_server_requests_total"
+    );
+    assert_eq!(
+        name("requests_total", "1", MetricType::Counter),
+        "requests_total"
+    );
+    assert_eq!(
+        name("cpu.utilization", "1", MetricType::Gauge),
+        "cpu_utilization_ratio"
+    );
+    assert_eq!(
+        name("network.io", "By/s", MetricType::Gauge),
+        "network_io_bytes_per_second"
+    );
+    assert_eq!(
+        name("memory_bytes", "By", MetricType::Gauge),
+        "memory_bytes"
+    );
+    assert_eq!(name("2xx..count", "", MetricType::Gauge), "_2xx_count");
+}
+
+#[test]
+fn converts_gauges_and_counters() {
+    let req = request(
+        vec![
+            string("service.namespace", "shop"),
+            string("service.name", "cart"),
+            string("service.instance.id", "i-1"),
+        ],
+        vec![
+            metric(
+                "queue.depth",
+                "",
+                Data::Gauge(Gauge {
+                    data_points: vec![point(3.0, vec![string("queue.name", "orders")])],
+                }),
+            ),
+            metric(
+                "requests",
+                "",
+                cumulative_sum(true, vec![point(10.0, vec![])]),
+            ),
+        ],
+    );
+    let write = write_request(&req);
+
+    assert_eq!(
+        write.timeseries.len(),
+        2,
+        "no target_info without extra attributes"
+    );
+    assert_eq!(
+        labels(&write.timeseries[0]),
+        [
+            "__name__=queue_depth",
+            "instance=i-1",
+            "job=shop/cart",
+            "otel_scope_name=app",
+            "queue_name=orders",
+        ]
+    );
+    assert_eq!(
+        write.timeseries[0].samples,
+        [Sample {
+            value: 3.0,
+            timestamp: 1_700_000_000_000
+        }]
+    );
+    assert_eq!(
+        write.timeseries[1].labels[0].value, "requests_total",
+        "monotonic sums are counters"
+    );
+    assert_eq!(write.metadata[1].r#type, MetricType::Counter as i32);
+}
+
+#[test]
+fn resource_attributes_go_on_target_info() {
+    let req = request(
+        vec![
+            string("service.name", "cart"),
+            string("cloud.region", "eu-west-1"),
+        ],
+        vec![],
+    );
+    let write = write_request(&req);
+    assert_eq!(
+        labels(&write.timeseries[0]),
+        ["__name__=target_info", "cloud_region=eu-west-1", "job=cart"]
+    );
+    assert_eq!(write.timeseries[0].samples[0].value, 1.0);
+}
+
+#[test]
+fn histograms_become_cumulative_buckets() {
+    let req = request(
+        vec![],
+        vec![metric(
+            "latency",
+            "s",
+            Data::Histogram(Histogram {
+                data_points: vec![HistogramDataPoint {
+                    time_unix_nano: TIME,
+                    count: 6,
+                    sum: Some(4.5),
+                    bucket_counts: vec![1, 3, 2],
+                    explicit_bounds: vec![0.5, 1.0],
+                    ..Default::default()
+                }],
+                aggregation_temporality: AggregationTemporality::Cumulative as i32,
+            }),
+        )],
+    );
+    let write = write_request(&req);
+    let series: Vec<(String, f64)> = write
+        .timeseries
+        .iter()
+        .map(|ts| {
+            let le = ts.labels.iter().find(|l| l.name == "le");
+            let name = &ts.labels[0].value;
+            let key = match le {
+                Some(le) => format!("{name}{{le={}}}", le.value),
+                None => name.clone(),
+            };
+            (key, ts.samples[0].value)
+        })
+        .collect();
+    assert_eq!(
+        series,
+        [
+            ("latency_seconds_bucket{le=0.5}".to_owned(), 1.0),
+            ("latency_seconds_bucket{le=1}".to_owned(), 4.0),
+            ("latency_seconds_bucket{le=+Inf}".to_owned(), 6.0),
+            ("latency_seconds_sum".to_owned(), 4.5),
+            ("latency_seconds_count".to_owned(), 6.0),
+        ]
+    );
+}
+
+#[test]
+fn drops_delta_temporality() {
+    let delta = Data::Sum(Sum {
+        data_points: vec![point(1.0, vec![])],
+        aggregation_temporality: AggregationTemporality::Delta as i32,
+        is_monotonic: true,
+    });
+    let write = write_request(&request(vec![], vec![metric("requests", "", delta)]));
+    assert!(write.timeseries.is_empty());
+    assert!(write.metadata.is_empty());
+}
+
+#[test]
+fn colliding_labels_are_joined_and_missing_values_marked_stale() {
+    let mut missing = point(
+        0.0,
+        vec![string("http.method", "GET"), string("http_method", "get")],
+    );
+    missing.flags = DataPointFlags::NoRecordedValueMask as u32;
+    let write = write_request(&request(
+        vec![],
+        vec![metric("active", "", cumulative_sum(false, vec![missing]))],
+    ));
+    assert_eq!(
+        labels(&write.timeseries[0]),
+        [
+            "__name__=active",
+            "http_method=GET;get",
+            "otel_scope_name=app"
+        ]
+    );
+    assert_eq!(write.timeseries[0].samples[0].value.to_bits(), STALE_NAN);
+}
@@ -0,0 +1,360 @@
+//! Self-observability metrics.
+//!
+//! [`RelayMetrics`] is a set of lock-free counters updated on the hot paths
+//! (OTLP listener, buffer, flush). When `LAMBDA_OTEL_RELAY_SELF_METRICS` is
+//! enabled, the event loop periodically snapshots them into an OTLP metrics
+//! payload under the `lambda-otel-relay` scope and pushes it into the metrics
+//! buffer, so they are exported alongside the function's own telemetry.
+//!
+//! All sums are cumulative since the relay started.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use prost::Message;
+
+use crate::buffers::Signal;
+use crate::proto::opentelemetry::proto::{
+    collector::metrics::v1::ExportMetricsServiceRequest,
+    common::v1::{AnyValue, InstrumentationScope, KeyValue, any_value::Value},
+    metrics::v1::{
+        AggregationTemporality, Gauge, Histogram, HistogramDataPoint, Metric, NumberDataPoint,
+        ResourceMetrics, ScopeMetrics, Sum, metric::Data, number_data_point,
+    },
+    resource::v1::Resource,
+};
+
+pub const SCOPE_NAME: &str = "lambda-otel-relay";
+
+/// Upper bounds (ms) of the flush duration histogram buckets.
+const FLUSH_DURATION_BOUNDS_MS: [f64; 10] = [
+    5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0,
+];
+
+/// Per-signal counter, indexed by `Signal as usize`.
+#[derive(Default)]
+struct SignalCounter([AtomicU64; 3]);
+
+impl SignalCounter {
+    fn add(&self, signal: Signal, n: u64) {
+        self.0[signal as usize].fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn max(&self, signal: Signal, n: u64) {
+        self.0[signal as usize].fetch_max(n, Ordering::Relaxed);
+    }
+
+    fn get(&self, signal: Signal) -> u64 {
+        self.0[signal as usize].load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default)]
+struct DurationHistogram {
+    /// One bucket per bound plus the overflow bucket.
+    buckets: [AtomicU64; FLUSH_DURATION_BOUNDS_MS.len() + 1],
+    count: AtomicU64,
+    /// Sum in microseconds, so that sub-millisecond flushes still add up.
+    sum_us: AtomicU64,
+}
+
+impl DurationHistogram {
+    fn record(&self, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+        let bucket = FLUSH_DURATION_BOUNDS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(FLUSH_DURATION_BOUNDS_MS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_us
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+pub struct RelayMetrics {
+    start_time_unix_nano: u64,
+    received_payloads: SignalCounter,
+    received_bytes: SignalCounter,
+    rejected_payloads: SignalCounter,
+    evicted_bytes: SignalCounter,
+    evicted_records: SignalCounter,
+    filter_dropped: SignalCounter,
+    buffer_high_water_bytes: SignalCounter,
+    flush_success: AtomicU64,
+    flush_failure: AtomicU64,
+    flush_duration: DurationHistogram,
+}
+
+impl Default for RelayMetrics {
+    fn default() -> Self {
+        Self {
+            start_time_unix_nano: unix_nanos(SystemTime::now()),
+            received_payloads: SignalCounter::default(),
+            received_bytes: SignalCounter::default(),
+            rejected_payloads: SignalCounter::default(),
+            evicted_bytes: SignalCounter::default(),
+            evicted_records: SignalCounter::default(),
+            filter_dropped: SignalCounter::default(),
+            buffer_high_water_bytes: SignalCounter::default(),
+            flush_success: AtomicU64::new(0),
+            flush_failure: AtomicU64::new(0),
+            flush_duration: DurationHistogram::default(),
+        }
+    }
+}
+
+impl RelayMetrics {
+    pub fn record_received(&self, signal: Signal, bytes: usize) {
+        self.received_payloads.add(signal, 1);
+        self.received_bytes.add(signal, bytes as u64);
+    }
+
+    /// A payload was turned away with 503 because the channel was full or the
+    /// signal's quota was exhausted.
+    pub fn record_rejected(&self, signal: Signal) {
+        self.rejected_payloads.add(signal, 1);
+    }
+
+    pub fn record_evicted(&self, signal: Signal, bytes: usize, records: usize) {
+        self.evicted_bytes.add(signal, bytes as u64);
+        self.evicted_records.add(signal, records as u64);
+    }
+
+    /// Spans, metrics or log records removed by the filter rules.
+    pub fn record_filtered(&self, signal: Signal, records: u64) {
+        self.filter_dropped.add(signal, records);
+    }
+
+    pub fn filtered(&self, signal: Signal) -> u64 {
+        self.filter_dropped.get(signal)
+    }
+
+    /// Raise the high-water mark for `signal` if `size_bytes` exceeds it.
+    pub fn record_buffer_size(&self, signal: Signal, size_bytes: usize) {
+        self.buffer_high_water_bytes.max(signal, size_bytes as u64);
+    }
+
+    pub fn record_flush(&self, success: bool, duration: Duration) {
+        if success {
+            self.flush_success.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.flush_failure.fetch_add(1, Ordering::Relaxed);
+        }
+        self.flush_duration.record(duration);
+    }
+
+    #[cfg(test)]
+    pub fn received(&self, signal: Signal) -> (u64, u64) {
+        (
+            self.received_payloads.get(signal),
+            self.received_bytes.get(signal),
+        )
+    }
+
+    #[cfg(test)]
+    pub fn rejected(&self, signal: Signal) -> u64 {
+        self.rejected_payloads.get(signal)
+    }
+
+    #[cfg(test)]
+    pub fn evicted(&self, signal: Signal) -> u64 {
+        self.evicted_bytes.get(signal)
+    }
+
+    #[cfg(test)]
+    pub fn high_water(&self, signal: Signal) -> u64 {
+        self.buffer_high_water_bytes.get(signal)
+    }
+
+    #[cfg(test)]
+    pub fn flushes(&self) -> (u64, u64) {
+        (
+            self.flush_success.load(Ordering::Relaxed),
+            self.flush_failure.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Encode the current values as an OTLP metrics payload.
+    pub fn encode(&self) -> Bytes {
+        Bytes::from(self.snapshot(SystemTime::now()).encode_to_vec())
+    }
+
+    fn snapshot(&self, now: SystemTime) -> ExportMetricsServiceRequest {
+        let start = self.start_time_unix_nano;
+        let time = unix_nanos(now);
+
+        let per_signal = |counter: &SignalCounter| -> Vec<NumberDataPoint> {
+            Signal::ALL
+                .iter()
+                .map(|signal| {
+                    int_point(
+                        start,
+                        time,
+                        vec![attr("signal", signal.name())],
+                        counter.get(*signal),
+                    )
+                })
+                .collect()
+        };
+        let flushes = [
+            ("success", &self.flush_success),
+            ("failure", &self.flush_failure),
+        ]
+        .into_iter()
+        .map(|(outcome, counter)| {
+            int_point(
+                start,
+                time,
+                vec![attr("outcome", outcome)],
+                counter.load(Ordering::Relaxed),
+            )
+        })
+        .collect();
+
+        let metrics = vec![
+            counter(
+                "relay.received.payloads",
+                "{payload}",
+                "OTLP payloads accepted by the local listener.",
+                per_signal(&self.received_payloads),
+            ),
+            counter(
+                "relay.received.bytes",
+                "By",
+                "Bytes of OTLP payloads accepted by the local listener.",
+                per_signal(&self.received_bytes),
+            ),
+            counter(
+                "relay.rejected.payloads",
+                "{payload}",
+                "OTLP payloads rejected with 503 because the relay was saturated.",
+                per_signal(&self.rejected_payloads),
+            ),
+            counter(
+                "relay.evicted.bytes",
+                "By",
+                "Bytes evicted from the buffer to stay under the size limit.",
+                per_signal(&self.evicted_bytes),
+            ),
+            counter(
+                "relay.evicted.records",
+                "{record}",
+                "Spans, metric data points and log records evicted from the buffer.",
+                per_signal(&self.evicted_records),
+            ),
+            counter(
+                "relay.filter.dropped",
+                "{record}",
+                "Spans, metrics and log records dropped by the filter rules.",
+                per_signal(&self.filter_dropped),
+            ),
+            counter(
+                "relay.flushes",
+                "{flush}",
+                "Completed flushes by outcome.",
+                flushes,
+            ),
+            self.flush_duration_metric(start, time),
+            Metric {
+                name: "relay.buffer.high_water_mark".to_owned(),
+                unit: "By".to_owned(),
+                description: "Largest buffered size observed per signal.".to_owned(),
+                data: Some(Data::Gauge(Gauge {
+                    data_points: per_signal(&self.buffer_high_water_bytes),
+                })),
+                ..Default::default()
+            },
+        ];
+
+        ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(Resource {
+                    attributes: vec![
+                        attr("service.name", SCOPE_NAME),
+                        attr("service.version", env!("CARGO_PKG_VERSION")),
+                    ],
+                    ..Default::default()
+                }),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: SCOPE_NAME.to_owned(),
+                        version: env!("CARGO_PKG_VERSION").to_owned(),
+                        ..Default::default()
+                    }),
+                    metrics,
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        }
+    }
+
+    fn flush_duration_metric(&self, start: u64, time: u64) -> Metric {
+        let h = &self.flush_duration;
+        Metric {
+            name: "relay.flush.duration".to_owned(),
+            unit: "ms".to_owned(),
+            description: "Time taken to export a flush.".to_owned(),
+            data: Some(Data::Histogram(Histogram {
+                data_points: vec![HistogramDataPoint {
+                    start_time_unix_nano: start,
+                    time_unix_nano: time,
+                    count: h.count.load(Ordering::Relaxed),
+                    sum: Some(h.sum_us.load(Ordering::Relaxed) as f64 / 1000.0),
+                    bucket_counts: h
+                        .buckets
+                        .iter()
+                        .map(|b| b.load(Ordering::Relaxed))
+                        .collect(),
+                    explicit_bounds: FLUSH_DURATION_BOUNDS_MS.to_vec(),
+                    ..Default::default()
+                }],
+                aggregation_temporality: AggregationTemporality::Cumulative as i32,
+            })),
+            ..Default::default()
+        }
+    }
+}
+
+fn counter(name: &str, unit: &str, description: &str, points: Vec<NumberDataPoint>) -> Metric {
+    Metric {
+        name: name.to_owned(),
+        unit: unit.to_owned(),
+        description: description.to_owned(),
+        data: Some(Data::Sum(Sum {
+            data_points: points,
+            aggregation_temporality: AggregationTemporality::Cumulative as i32,
+            is_monotonic: true,
+        })),
+        ..Default::default()
+    }
+}
+
+fn int_point(start: u64, time: u64, attributes: Vec<KeyValue>, value: u64) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes,
+        start_time_unix_nano: start,
+        time_unix_nano: time,
+        value: Some(number_data_point::Value::AsInt(value as i64)),
+        ..Default::default()
+    }
+}
+
+fn attr(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value.to_owned())),
+        }),
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests;
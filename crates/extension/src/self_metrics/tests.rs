@@ -0,0 +1,127 @@
+use super::*;
+
+fn metric<'a>(req: &'a ExportMetricsServiceRequest, name: &str) -> &'a Metric {
+    req.resource_metrics[0].scope_metrics[0]
+        .metrics
+        .iter()
+        .find(|m| m.name == name)
+        .unwrap_or_else(|| panic!("missing metric {name}"))
+}
+
+fn point_value(points: &[NumberDataPoint], key: &str, value: &str) -> i64 {
+    let point = points
+        .iter()
+        .find(|p| {
+            p.attributes
+                .iter()
+                .any(|kv| kv.key == key && kv.value == Some(attr(key, value).value.unwrap()))
+        })
+        .unwrap_or_else(|| panic!("missing point {key}={value}"));
+    match point.value {
+        Some(number_data_point::Value::AsInt(v)) => v,
+        _ => panic!("expected int value"),
+    }
+}
+
+fn sum_points<'a>(req: &'a ExportMetricsServiceRequest, name: &str) -> &'a [NumberDataPoint] {
+    match &metric(req, name).data {
+        Some(Data::Sum(sum)) => {
+            assert!(sum.is_monotonic);
+            assert_eq!(
+                sum.aggregation_temporality,
+                AggregationTemporality::Cumulative as i32
+            );
+            &sum.data_points
+        }
+        other => panic!("expected sum, got {other:?}"),
+    }
+}
+
+#[test]
+fn snapshot_uses_relay_scope() {
+    let req = RelayMetrics::default().snapshot(SystemTime::now());
+    let scope = req.resource_metrics[0].scope_metrics[0]
+        .scope
+        .as_ref()
+        .unwrap();
+    assert_eq!(scope.name, "lambda-otel-relay");
+    assert_eq!(scope.version, env!("CARGO_PKG_VERSION"));
+}
+
+#[test]
+fn per_signal_counters() {
+    let metrics = RelayMetrics::default();
+    metrics.record_received(Signal::Traces, 100);
+    metrics.record_received(Signal::Traces, 50);
+    metrics.record_received(Signal::Logs, 10);
+    metrics.record_rejected(Signal::Metrics);
+    metrics.record_evicted(Signal::Logs, 7, 3);
+    metrics.record_filtered(Signal::Traces, 4);
+
+    let req = metrics.snapshot(SystemTime::now());
+
+    let payloads = sum_points(&req, "relay.received.payloads");
+    assert_eq!(point_value(payloads, "signal", "traces"), 2);
+    assert_eq!(point_value(payloads, "signal", "logs"), 1);
+    assert_eq!(point_value(payloads, "signal", "metrics"), 0);
+    let bytes = sum_points(&req, "relay.received.bytes");
+    assert_eq!(point_value(bytes, "signal", "traces"), 150);
+    let rejected = sum_points(&req, "relay.rejected.payloads");
+    assert_eq!(point_value(rejected, "signal", "metrics"), 1);
+    let evicted = sum_points(&req, "relay.evicted.bytes");
+    assert_eq!(point_value(evicted, "signal", "logs"), 7);
+    let records = sum_points(&req, "relay.evicted.records");
+    assert_eq!(point_value(records, "signal", "logs"), 3);
+    let filtered = sum_points(&req, "relay.filter.dropped");
+    assert_eq!(point_value(filtered, "signal", "traces"), 4);
+}
+
+#[test]
+fn high_water_mark_keeps_maximum() {
+    let metrics = RelayMetrics::default();
+    metrics.record_buffer_size(Signal::Traces, 500);
+    metrics.record_buffer_size(Signal::Traces, 200);
+
+    let req = metrics.snapshot(SystemTime::now());
+    let Some(Data::Gauge(gauge)) = &metric(&req, "relay.buffer.high_water_mark").data else {
+        panic!("expected gauge");
+    };
+    assert_eq!(point_value(&gauge.data_points, "signal", "traces"), 500);
+}
+
+#[test]
+fn flush_outcomes_and_duration_histogram() {
+    let metrics = RelayMetrics::default();
+    metrics.record_flush(true, Duration::from_millis(3));
+    metrics.record_flush(true, Duration::from_millis(40));
+    metrics.record_flush(false, Duration::from_secs(30));
+
+    let req = metrics.snapshot(SystemTime::now());
+
+    let flushes = sum_points(&req, "relay.flushes");
+    assert_eq!(point_value(flushes, "outcome", "success"), 2);
+    assert_eq!(point_value(flushes, "outcome", "failure"), 1);
+
+    let Some(Data::Histogram(histogram)) = &metric(&req, "relay.flush.duration").data else {
+        panic!("expected histogram");
+    };
+    let point = &histogram.data_points[0];
+    assert_eq!(point.count, 3);
+    assert_eq!(point.sum, Some(30_043.0));
+    assert_eq!(point.explicit_bounds.len() + 1, point.bucket_counts.len());
+    // 3ms -> first bucket, 40ms -> (25, 50], 30s -> overflow.
+    assert_eq!(point.bucket_counts[0], 1);
+    assert_eq!(point.bucket_counts[3], 1);
+    assert_eq!(*point.bucket_counts.last().unwrap(), 1);
+}
+
+#[test]
+fn encode_round_trips() {
+    let metrics = RelayMetrics::default();
+    metrics.record_received(Signal::Metrics, 1);
+
+    let decoded = ExportMetricsServiceRequest::decode(metrics.encode()).unwrap();
+    let point = &sum_points(&decoded, "relay.received.payloads")[0];
+    assert!(point.time_unix_nano >= point.start_time_unix_nano);
+    assert!(point.start_time_unix_nano > 0);
+}
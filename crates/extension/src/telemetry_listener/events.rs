@@ -1,13 +1,18 @@
 #![allow(clippy::question_mark)] // nanoserde DeJson derive
 
+use std::time::SystemTime;
+
 use nanoserde::DeJson;
 
+use crate::exporter::parse_rfc3339;
+
 /// Raw JSON shape for a single element in the Telemetry API batch.
 /// https://docs.aws.amazon.com/lambda/latest/dg/telemetry-schema-reference.html
 #[derive(DeJson)]
 struct RawTelemetryEvent {
     #[nserde(rename = "type")]
     event_type: String,
+    time: Option<String>,
     record: RawRecord,
 }
 
@@ -17,6 +22,7 @@ struct RawRecord {
     request_id: Option<String>,
     status: Option<String>,
     tracing: Option<RawTracing>,
+    metrics: Option<RawMetrics>,
 }
 
 #[derive(DeJson)]
@@ -24,20 +30,40 @@ struct RawTracing {
     value: Option<String>,
 }
 
+#[derive(DeJson)]
+struct RawMetrics {
+    #[nserde(rename = "durationMs")]
+    duration_ms: Option<f64>,
+}
+
 /// A platform event delivered by the Lambda Telemetry API.
 /// https://docs.aws.amazon.com/lambda/latest/dg/telemetry-api.html
 #[derive(Debug, PartialEq)]
 pub enum TelemetryEvent {
     /// `platform.runtimeDone` — reports the outcome of an invocation.
     /// status is one of: success, failure, error, timeout.
+    /// `duration_ms` is the runtime's reported handler duration, if present.
     /// https://docs.aws.amazon.com/lambda/latest/dg/telemetry-schema-reference.html#platform-runtimeDone
-    RuntimeDone { request_id: String, status: String },
+    RuntimeDone {
+        request_id: String,
+        status: String,
+        duration_ms: Option<f64>,
+    },
     /// `platform.start` — carries X-Ray trace context when active tracing is enabled.
     /// https://docs.aws.amazon.com/lambda/latest/dg/telemetry-schema-reference.html#platform-start
     Start {
         request_id: String,
         tracing_value: Option<String>,
     },
+    /// `platform.restoreStart` — the environment was restored from a
+    /// SnapStart snapshot. `time` is when the restore started, if the event
+    /// carried a valid timestamp.
+    /// https://docs.aws.amazon.com/lambda/latest/dg/telemetry-schema-reference.html#platform-restoreStart
+    RestoreStart { time: Option<SystemTime> },
+    /// `platform.restoreRuntimeDone` — the runtime's restore hooks finished.
+    /// status is one of: success, failure, error, timeout.
+    /// https://docs.aws.amazon.com/lambda/latest/dg/telemetry-schema-reference.html#platform-restoreRuntimeDone
+    RestoreRuntimeDone { status: String },
 }
 
 impl TelemetryEvent {
@@ -57,6 +83,7 @@ impl TelemetryEvent {
                     events.push(TelemetryEvent::RuntimeDone {
                         request_id: item.record.request_id.unwrap_or_default(),
                         status: item.record.status.unwrap_or_default(),
+                        duration_ms: item.record.metrics.and_then(|m| m.duration_ms),
                     });
                 }
                 "platform.start" => {
@@ -65,6 +92,14 @@ impl TelemetryEvent {
                         tracing_value: item.record.tracing.and_then(|t| t.value),
                     });
                 }
+                "platform.restoreStart" => events.push(TelemetryEvent::RestoreStart {
+                    time: item.time.as_deref().and_then(parse_rfc3339),
+                }),
+                "platform.restoreRuntimeDone" => {
+                    events.push(TelemetryEvent::RestoreRuntimeDone {
+                        status: item.record.status.unwrap_or_default(),
+                    });
+                }
                 _ => {
                     // Ignore event types we don't care about (e.g. platform.initStart)
                 }
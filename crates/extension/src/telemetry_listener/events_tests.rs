@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use super::*;
 
 #[test]
@@ -10,6 +12,7 @@ fn parses_runtime_done_event() {
         vec![TelemetryEvent::RuntimeDone {
             request_id: "req-1".into(),
             status: "success".into(),
+            duration_ms: None,
         }]
     );
 }
@@ -24,6 +27,7 @@ fn parses_runtime_done_timeout() {
         vec![TelemetryEvent::RuntimeDone {
             request_id: "req-2".into(),
             status: "timeout".into(),
+            duration_ms: None,
         }]
     );
 }
@@ -82,7 +86,8 @@ fn parses_mixed_batch() {
             },
             TelemetryEvent::RuntimeDone {
                 request_id: "req-5".into(),
-                status: "success".into()
+                status: "success".into(),
+                duration_ms: None,
             },
         ]
     );
@@ -124,6 +129,25 @@ fn parses_real_aws_runtime_done_with_extra_fields() {
         vec![TelemetryEvent::RuntimeDone {
             request_id: "6d68ca91-49c9-448d-89b8-7ca3e6dc66aa".into(),
             status: "success".into(),
+            duration_ms: Some(140.0),
         }]
     );
 }
+
+#[test]
+fn parses_restore_events() {
+    let events = TelemetryEvent::parse_batch(
+        r#"[{"type":"platform.restoreStart","time":"2024-01-01T00:00:00Z","record":{"runtimeVersion":"python:3.13"}},{"type":"platform.restoreRuntimeDone","time":"2024-01-01T00:00:01Z","record":{"status":"success"}}]"#,
+    );
+    assert_eq!(
+        events,
+        vec![
+            TelemetryEvent::RestoreStart {
+                time: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200)),
+            },
+            TelemetryEvent::RestoreRuntimeDone {
+                status: "success".into(),
+            },
+        ]
+    );
+}
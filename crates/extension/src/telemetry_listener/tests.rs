@@ -34,6 +34,7 @@ async fn accepts_post_and_forwards_events() {
         TelemetryEvent::RuntimeDone {
             request_id: "req-6".into(),
             status: "failure".into(),
+            duration_ms: None,
         }
     );
 }
@@ -64,6 +65,7 @@ async fn logs_when_channel_full() {
     tx.try_send(TelemetryEvent::RuntimeDone {
         request_id: "fill".into(),
         status: "success".into(),
+        duration_ms: None,
     })
     .unwrap();
 
@@ -121,6 +121,10 @@ pub async fn dummy_config() -> crate::config::Config {
         compression: crate::config::Compression::None,
         export_headers: vec![],
         buffer_max_bytes: Some(4_194_304),
+        flush_thresholds: Default::default(),
+        buffer_quotas: [None; 3],
+        eviction_policy: crate::config::EvictionPolicy::DropOldest,
+        restore_policy: crate::config::RestorePolicy::Discard,
         flush_strategy: crate::flush_strategy::FlushStrategy::Default,
         tls_ca: None,
         tls_client_cert: None,
@@ -128,5 +132,22 @@ pub async fn dummy_config() -> crate::config::Config {
         sigv4: None,
         enabled_signals: crate::buffers::EnabledSignals::all(),
         protocol: crate::config::ExportProtocol::default(),
+        sampling: None,
+        redaction: None,
+        filter: None,
+        limits: None,
+        resource_attributes: Vec::new(),
+        transform: Vec::new(),
+        self_metrics_interval: None,
+        concurrency: None,
+        xray_parent: false,
+        xray_annotations: vec![],
+        xray_skip_timestamp_validation: false,
+        metrics_cardinality_limit: None,
+        aggregate_metrics: false,
+        temporality: None,
+        emf: None,
+        spanmetrics: None,
+        remote_write: None,
     }
 }
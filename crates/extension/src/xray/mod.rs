@@ -0,0 +1,160 @@
+//! Bridging AWS X-Ray trace context to W3C trace context.
+//!
+//! Lambda reports the X-Ray trace header of each invocation in
+//! `platform.start` (`Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1`).
+//! An X-Ray trace ID is the W3C trace ID with a version prefix and a dash
+//! after the epoch, and an X-Ray segment ID is a W3C span ID, so the header
+//! converts directly.
+//!
+//! With `LAMBDA_OTEL_RELAY_XRAY_PARENT=true`, root spans whose trace ID is the
+//! invocation's X-Ray trace ID get the X-Ray parent segment as their parent,
+//! connecting them to the upstream API Gateway or X-Ray segments.
+//!
+//! [`segment`] converts spans the other way, into X-Ray segment documents,
+//! for `LAMBDA_OTEL_RELAY_PROTOCOL=xray`.
+
+pub mod segment;
+
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+use prost::Message;
+
+use crate::proto::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+use crate::proto::opentelemetry::proto::trace::v1::SpanFlags;
+
+/// Contexts of this many recent invocations are kept: spans keep arriving
+/// after `platform.runtimeDone`, and Managed Instances run invocations
+/// concurrently.
+const RECENT_INVOCATIONS: usize = 16;
+
+/// The parts of an X-Ray trace header that map to W3C trace context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XrayContext {
+    pub trace_id: [u8; 16],
+    /// Segment ID of the caller, absent at the start of a trace.
+    pub parent_id: Option<[u8; 8]>,
+    pub sampled: bool,
+}
+
+impl XrayContext {
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut trace_id = None;
+        let mut parent_id = None;
+        let mut sampled = false;
+        for field in header.split(';') {
+            match field.trim().split_once('=') {
+                Some(("Root", root)) => trace_id = parse_root(root),
+                Some(("Parent", parent)) => parent_id = parse_hex(parent),
+                Some(("Sampled", flag)) => sampled = flag == "1",
+                _ => {}
+            }
+        }
+        Some(Self {
+            trace_id: trace_id?,
+            parent_id,
+            sampled,
+        })
+    }
+}
+
+/// Parents root spans on the X-Ray segment of the invocation they belong to.
+#[derive(Default)]
+pub struct XrayBridge {
+    recent: VecDeque<XrayContext>,
+}
+
+impl XrayBridge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember the context of an invocation that just started. Contexts
+    /// without a parent, or whose parent X-Ray did not record, have nothing
+    /// to link to.
+    pub fn record(&mut self, context: XrayContext) {
+        if context.parent_id.is_none() || !context.sampled {
+            return;
+        }
+        if self.recent.len() == RECENT_INVOCATIONS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(context);
+    }
+
+    /// Forget all invocation contexts (SnapStart restore).
+    pub fn reset(&mut self) {
+        self.recent.clear();
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.recent.is_empty()
+    }
+
+    /// Fill the parent of root spans in a trace payload whose trace ID
+    /// belongs to a recent invocation. The parent is marked remote. Payloads
+    /// that do not decode or have nothing to fill are returned unchanged.
+    pub fn link(&self, payload: Bytes) -> Bytes {
+        if self.recent.is_empty() {
+            return payload;
+        }
+        let Ok(mut req) = ExportTraceServiceRequest::decode(&payload[..]) else {
+            return payload;
+        };
+        let mut changed = false;
+        for span in req
+            .resource_spans
+            .iter_mut()
+            .flat_map(|rs| &mut rs.scope_spans)
+            .flat_map(|ss| &mut ss.spans)
+            .filter(|span| span.parent_span_id.is_empty())
+        {
+            let parent = self
+                .recent
+                .iter()
+                .rev()
+                .find(|context| context.trace_id[..] == span.trace_id[..])
+                .and_then(|context| context.parent_id);
+            if let Some(parent) = parent {
+                span.parent_span_id = parent.to_vec();
+                span.flags |= SpanFlags::ContextHasIsRemoteMask as u32
+                    | SpanFlags::ContextIsRemoteMask as u32;
+                changed = true;
+            }
+        }
+        if changed {
+            Bytes::from(req.encode_to_vec())
+        } else {
+            payload
+        }
+    }
+}
+
+/// `1-5759e988-bd862e3fe1be46a994272793` as a W3C trace ID.
+fn parse_root(root: &str) -> Option<[u8; 16]> {
+    let mut parts = root.split('-');
+    let (Some("1"), Some(epoch), Some(unique), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    if epoch.len() != 8 {
+        return None;
+    }
+    parse_hex(&format!("{epoch}{unique}"))
+}
+
+fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != 2 * N {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests;
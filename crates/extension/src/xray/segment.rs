@@ -0,0 +1,406 @@
+//! Conversion of OTLP spans into X-Ray segment documents.
+//!
+//! Every span becomes one document. Server and consumer spans, and spans
+//! without a parent, become segments named after the resource's
+//! `service.name`; all other spans become independent subsegments that point
+//! at their parent through `parent_id`. Attributes are split into the
+//! documents' `http` and `aws` objects, annotations (for the keys listed in
+//! `LAMBDA_OTEL_RELAY_XRAY_ANNOTATIONS`) and `metadata`.
+//!
+//! X-Ray trace IDs start with the trace's start time in seconds, and X-Ray
+//! rejects traces that started more than 30 days ago or more than 5 minutes
+//! from now. Like the ADOT collector's exporter, spans with such trace IDs
+//! are dropped unless timestamp validation is off.
+//!
+//! See the [segment document schema].
+//!
+//! [segment document schema]: https://docs.aws.amazon.com/xray/latest/devguide/xray-api-segmentdocuments.html
+
+use std::fmt::Write;
+
+use crate::otlp_json::{Object, write_f64, write_str};
+use crate::proto::opentelemetry::proto::{
+    collector::trace::v1::ExportTraceServiceRequest,
+    common::v1::{AnyValue, KeyValue, any_value::Value},
+    trace::v1::{Span, span::SpanKind, status::StatusCode},
+};
+
+/// X-Ray rejects segment and subsegment names longer than this.
+const MAX_NAME_CHARS: usize = 200;
+
+/// Oldest trace ID epoch X-Ray accepts, relative to now.
+const MAX_TRACE_AGE_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Allowed clock skew for trace ID epochs in the future.
+const MAX_TRACE_SKEW_SECS: u64 = 5 * 60;
+
+const HTTP_METHOD: [&str; 2] = ["http.request.method", "http.method"];
+const HTTP_URL: [&str; 2] = ["url.full", "http.url"];
+const HTTP_USER_AGENT: [&str; 2] = ["user_agent.original", "http.user_agent"];
+const HTTP_CLIENT_IP: [&str; 2] = ["client.address", "http.client_ip"];
+const HTTP_STATUS: [&str; 2] = ["http.response.status_code", "http.status_code"];
+const HTTP_CONTENT_LENGTH: [&str; 2] = ["http.response.body.size", "http.response_content_length"];
+
+/// The segment documents of a request.
+#[derive(Debug, Default)]
+pub struct Documents {
+    /// Each document with the index of its span, counting the spans of the
+    /// request in order.
+    pub documents: Vec<(usize, String)>,
+    /// Spans dropped because their trace ID epoch is out of range.
+    pub invalid_epoch: usize,
+}
+
+/// Convert every span of `req` into a segment document. Spans with malformed
+/// IDs are skipped. With `now` (Unix seconds), so are spans whose trace ID
+/// epoch X-Ray would reject.
+pub fn documents(
+    req: &ExportTraceServiceRequest,
+    annotations: &[String],
+    now: Option<u64>,
+) -> Documents {
+    let mut docs = Documents::default();
+    let spans = req.resource_spans.iter().flat_map(|rs| {
+        let service = rs
+            .resource
+            .as_ref()
+            .and_then(|r| string_attribute(&r.attributes, "service.name"));
+        rs.scope_spans
+            .iter()
+            .flat_map(|ss| &ss.spans)
+            .map(move |span| (span, service))
+    });
+    for (index, (span, service)) in spans.enumerate() {
+        let Some(trace_id) = trace_id(&span.trace_id, now) else {
+            if span.trace_id.len() == 16 {
+                docs.invalid_epoch += 1;
+            }
+            continue;
+        };
+        if let Some(doc) = document(span, &trace_id, service, annotations) {
+            docs.documents.push((index, doc));
+        }
+    }
+    docs
+}
+
+/// `1-5759e988-bd862e3fe1be46a994272793` for a 16-byte trace ID. With `now`
+/// (Unix seconds), `None` if the epoch in its first 4 bytes is older than 30
+/// days or more than 5 minutes ahead.
+pub fn trace_id(id: &[u8], now: Option<u64>) -> Option<String> {
+    if id.len() != 16 {
+        return None;
+    }
+    if let Some(now) = now {
+        let epoch = u64::from(u32::from_be_bytes([id[0], id[1], id[2], id[3]]));
+        if epoch + MAX_TRACE_AGE_SECS < now || epoch > now + MAX_TRACE_SKEW_SECS {
+            return None;
+        }
+    }
+    let hex = hex(id);
+    Some(format!("1-{}-{}", &hex[..8], &hex[8..]))
+}
+
+fn document(
+    span: &Span,
+    trace_id: &str,
+    service: Option<&str>,
+    annotations: &[String],
+) -> Option<String> {
+    if span.span_id.len() != 8 || !matches!(span.parent_span_id.len(), 0 | 8) {
+        return None;
+    }
+    let kind = span.kind();
+    let is_segment =
+        matches!(kind, SpanKind::Server | SpanKind::Consumer) || span.parent_span_id.is_empty();
+    let fields = Fields::collect(&span.attributes, annotations);
+
+    let mut out = String::with_capacity(256);
+    let mut o = Object::new(&mut out);
+    o.str("trace_id", trace_id);
+    o.str("id", &hex(&span.span_id));
+    o.str("parent_id", &hex(&span.parent_span_id));
+    let name = match service {
+        Some(service) if is_segment => service,
+        _ => &span.name,
+    };
+    o.str("name", &sanitize_name(name));
+    write_seconds(o.key("start_time"), span.start_time_unix_nano);
+    write_seconds(o.key("end_time"), span.end_time_unix_nano);
+    if !is_segment {
+        o.str("type", "subsegment");
+        if fields.aws_api {
+            o.str("namespace", "aws");
+        } else if matches!(kind, SpanKind::Client | SpanKind::Producer) {
+            o.str("namespace", "remote");
+        }
+    }
+
+    let failed = span
+        .status
+        .as_ref()
+        .is_some_and(|status| status.code() == StatusCode::Error);
+    let (fault, error, throttle) = match fields.status {
+        Some(429) => (false, true, true),
+        Some(400..=499) => (false, true, false),
+        Some(500..=599) => (true, false, false),
+        _ => (failed, false, false),
+    };
+    for (name, set) in [("fault", fault), ("error", error), ("throttle", throttle)] {
+        if set {
+            o.key(name).push_str("true");
+        }
+    }
+
+    fields.write_http(&mut o);
+    if !fields.aws.is_empty() {
+        let mut aws = Object::new(o.key("aws"));
+        for (key, value) in &fields.aws {
+            write_value(aws.key(key), value);
+        }
+        aws.finish();
+    }
+    if !fields.annotations.is_empty() {
+        let mut annotations = Object::new(o.key("annotations"));
+        for (key, value) in &fields.annotations {
+            write_value(annotations.key(&sanitize_annotation_key(key)), value);
+        }
+        annotations.finish();
+    }
+    if !fields.metadata.is_empty() {
+        let mut metadata = Object::new(o.key("metadata"));
+        let mut default = Object::new(metadata.key("default"));
+        for (key, value) in &fields.metadata {
+            write_value(default.key(key), value);
+        }
+        default.finish();
+        metadata.finish();
+    }
+    write_cause(&mut o, span);
+    o.finish();
+    Some(out)
+}
+
+/// Span attributes sorted into the parts of a segment document.
+#[derive(Default)]
+struct Fields<'a> {
+    method: Option<&'a AnyValue>,
+    url: Option<&'a AnyValue>,
+    user_agent: Option<&'a AnyValue>,
+    client_ip: Option<&'a AnyValue>,
+    status: Option<i64>,
+    content_length: Option<&'a AnyValue>,
+    aws_api: bool,
+    aws: Vec<(&'a str, &'a AnyValue)>,
+    annotations: Vec<(&'a str, &'a AnyValue)>,
+    metadata: Vec<(&'a str, &'a AnyValue)>,
+}
+
+impl<'a> Fields<'a> {
+    fn collect(attributes: &'a [KeyValue], annotations: &[String]) -> Self {
+        let mut fields = Self::default();
+        for kv in attributes {
+            let Some(value) = kv.value.as_ref() else {
+                continue;
+            };
+            let key = kv.key.as_str();
+            if key == "rpc.system" {
+                fields.aws_api =
+                    matches!(&value.value, Some(Value::StringValue(s)) if s == "aws-api");
+            }
+            if annotations.iter().any(|a| a == key) && is_scalar(value) {
+                fields.annotations.push((key, value));
+            } else if HTTP_METHOD.contains(&key) {
+                fields.method = Some(value);
+            } else if HTTP_URL.contains(&key) {
+                fields.url = Some(value);
+            } else if HTTP_USER_AGENT.contains(&key) {
+                fields.user_agent = Some(value);
+            } else if HTTP_CLIENT_IP.contains(&key) {
+                fields.client_ip = Some(value);
+            } else if HTTP_CONTENT_LENGTH.contains(&key) {
+                fields.content_length = Some(value);
+            } else if let (true, Some(Value::IntValue(status))) =
+                (HTTP_STATUS.contains(&key), &value.value)
+            {
+                fields.status = Some(*status);
+            } else if let Some(aws_key) = key.strip_prefix("aws.") {
+                fields.aws.push((aws_key, value));
+            } else {
+                fields.metadata.push((key, value));
+            }
+        }
+        fields
+    }
+
+    fn write_http(&self, o: &mut Object) {
+        let has_request = self.method.is_some()
+            || self.url.is_some()
+            || self.user_agent.is_some()
+            || self.client_ip.is_some();
+        let has_response = self.status.is_some() || self.content_length.is_some();
+        if !has_request && !has_response {
+            return;
+        }
+        let mut http = Object::new(o.key("http"));
+        if has_request {
+            let mut request = Object::new(http.key("request"));
+            let request_fields = [
+                ("method", self.method),
+                ("url", self.url),
+                ("user_agent", self.user_agent),
+                ("client_ip", self.client_ip),
+            ];
+            for (name, value) in request_fields {
+                if let Some(value) = value {
+                    write_value(request.key(name), value);
+                }
+            }
+            request.finish();
+        }
+        if has_response {
+            let mut response = Object::new(http.key("response"));
+            if let Some(status) = self.status {
+                let _ = write!(response.key("status"), "{status}");
+            }
+            if let Some(value) = self.content_length {
+                write_value(response.key("content_length"), value);
+            }
+            response.finish();
+        }
+        http.finish();
+    }
+}
+
+/// `exception` events become the exceptions of the document's `cause`.
+fn write_cause(o: &mut Object, span: &Span) {
+    let exceptions: Vec<_> = span
+        .events
+        .iter()
+        .filter(|event| event.name == "exception")
+        .collect();
+    if exceptions.is_empty() {
+        return;
+    }
+    let mut cause = Object::new(o.key("cause"));
+    let out = cause.key("exceptions");
+    out.push('[');
+    let span_id = u64::from_be_bytes(span.span_id[..].try_into().unwrap_or_default());
+    for (i, event) in exceptions.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let mut exception = Object::new(out);
+        // Exception IDs only need to be unique within the trace.
+        let id = span_id.wrapping_add(i as u64 + 1);
+        exception.str("id", &format!("{id:016x}"));
+        for (name, key) in [("type", "exception.type"), ("message", "exception.message")] {
+            if let Some(value) = string_attribute(&event.attributes, key) {
+                exception.str(name, value);
+            }
+        }
+        exception.finish();
+    }
+    out.push(']');
+    cause.finish();
+}
+
+fn string_attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a str> {
+    attributes.iter().find(|kv| kv.key == key).and_then(|kv| {
+        match kv.value.as_ref()?.value.as_ref()? {
+            Value::StringValue(s) => Some(s.as_str()),
+            _ => None,
+        }
+    })
+}
+
+/// Annotations only hold strings, numbers and booleans.
+fn is_scalar(value: &AnyValue) -> bool {
+    matches!(
+        value.value,
+        Some(
+            Value::StringValue(_)
+                | Value::BoolValue(_)
+                | Value::IntValue(_)
+                | Value::DoubleValue(_)
+        )
+    )
+}
+
+fn write_value(out: &mut String, value: &AnyValue) {
+    match &value.value {
+        Some(Value::StringValue(s)) => write_str(out, s),
+        Some(Value::BoolValue(b)) => {
+            let _ = write!(out, "{b}");
+        }
+        Some(Value::IntValue(i)) => {
+            let _ = write!(out, "{i}");
+        }
+        Some(Value::DoubleValue(d)) => write_f64(out, *d),
+        Some(Value::BytesValue(bytes)) => write_str(out, &hex(bytes)),
+        Some(Value::ArrayValue(array)) => {
+            out.push('[');
+            for (i, value) in array.values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, value);
+            }
+            out.push(']');
+        }
+        Some(Value::KvlistValue(list)) => {
+            let mut o = Object::new(out);
+            for kv in &list.values {
+                match &kv.value {
+                    Some(value) => write_value(o.key(&kv.key), value),
+                    None => o.key(&kv.key).push_str("null"),
+                }
+            }
+            o.finish();
+        }
+        None => out.push_str("null"),
+    }
+}
+
+/// Epoch seconds with microsecond precision.
+fn write_seconds(out: &mut String, unix_nano: u64) {
+    let _ = write!(
+        out,
+        "{}.{:06}",
+        unix_nano / 1_000_000_000,
+        unix_nano % 1_000_000_000 / 1_000
+    );
+}
+
+/// Names may contain Unicode letters, numbers, whitespace and `_.:/%&#=+\-@`.
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c.is_whitespace() || "_.:/%&#=+\\-@".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(MAX_NAME_CHARS)
+        .collect()
+}
+
+/// Annotation keys may only contain alphanumerics and underscores.
+fn sanitize_annotation_key(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(2 * bytes.len());
+    for b in bytes {
+        let _ = write!(out, "{b:02x}");
+    }
+    out
+}
+
+#[cfg(test)]
+#[path = "segment_tests.rs"]
+mod tests;
@@ -0,0 +1,204 @@
+use super::*;
+use crate::proto::opentelemetry::proto::{
+    resource::v1::Resource,
+    trace::v1::{ResourceSpans, ScopeSpans, Status, span::Event},
+};
+
+const TRACE_ID: [u8; 16] = [
+    0x57, 0x59, 0xe9, 0x88, 0xbd, 0x86, 0x2e, 0x3f, 0xe1, 0xbe, 0x46, 0xa9, 0x94, 0x27, 0x27, 0x93,
+];
+
+fn kv(key: &str, value: Value) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: Some(AnyValue { value: Some(value) }),
+    }
+}
+
+fn string(key: &str, value: &str) -> KeyValue {
+    kv(key, Value::StringValue(value.to_owned()))
+}
+
+fn span(parent: &[u8], kind: SpanKind, attributes: Vec<KeyValue>) -> Span {
+    Span {
+        trace_id: TRACE_ID.to_vec(),
+        span_id: vec![0x53, 0x99, 0x5c, 0x3f, 0x42, 0xcd, 0x8a, 0xd8],
+        parent_span_id: parent.to_vec(),
+        name: "GET /items".to_owned(),
+        kind: kind as i32,
+        start_time_unix_nano: 1_700_000_000_123_456_789,
+        end_time_unix_nano: 1_700_000_001_000_000_000,
+        attributes,
+        ..Default::default()
+    }
+}
+
+fn convert(spans: Vec<Span>, annotations: &[&str]) -> Vec<String> {
+    let req = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Some(Resource {
+                attributes: vec![string("service.name", "checkout")],
+                ..Default::default()
+            }),
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans,
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+    let annotations: Vec<String> = annotations.iter().map(|s| (*s).to_owned()).collect();
+    documents(&req, &annotations, None)
+        .documents
+        .into_iter()
+        .map(|(_, document)| document)
+        .collect()
+}
+
+#[test]
+fn converts_trace_ids() {
+    assert_eq!(
+        trace_id(&TRACE_ID, None).as_deref(),
+        Some("1-5759e988-bd862e3fe1be46a994272793")
+    );
+    assert_eq!(trace_id(&[0; 8], None), None);
+}
+
+#[test]
+fn validates_trace_id_epochs() {
+    const EPOCH: u64 = 0x5759e988;
+    const DAY: u64 = 24 * 60 * 60;
+    assert!(trace_id(&TRACE_ID, Some(EPOCH + 29 * DAY)).is_some());
+    assert!(trace_id(&TRACE_ID, Some(EPOCH - 60)).is_some());
+    assert_eq!(trace_id(&TRACE_ID, Some(EPOCH + 31 * DAY)), None);
+    assert_eq!(trace_id(&TRACE_ID, Some(EPOCH - 10 * 60)), None);
+
+    let mut stale = span(&[], SpanKind::Server, vec![]);
+    stale.trace_id[..4].copy_from_slice(&0u32.to_be_bytes());
+    let req = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans: vec![stale, span(&[], SpanKind::Server, vec![])],
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+    let docs = documents(&req, &[], Some(EPOCH));
+    assert_eq!(docs.invalid_epoch, 1);
+    assert_eq!(docs.documents.len(), 1);
+    assert_eq!(docs.documents[0].0, 1);
+}
+
+#[test]
+fn root_spans_become_segments() {
+    let docs = convert(vec![span(&[], SpanKind::Internal, vec![])], &[]);
+    assert_eq!(
+        docs,
+        [concat!(
+            r#"{"trace_id":"1-5759e988-bd862e3fe1be46a994272793","id":"53995c3f42cd8ad8","#,
+            r#""name":"checkout","start_time":1700000000.123456,"end_time":1700000001.000000}"#
+        )]
+    );
+}
+
+#[test]
+fn child_spans_become_subsegments() {
+    let parent = [1, 2, 3, 4, 5, 6, 7, 8];
+    let docs = convert(
+        vec![
+            span(&parent, SpanKind::Client, vec![]),
+            span(
+                &parent,
+                SpanKind::Client,
+                vec![string("rpc.system", "aws-api")],
+            ),
+            span(&parent, SpanKind::Internal, vec![]),
+            span(&parent, SpanKind::Server, vec![]),
+        ],
+        &[],
+    );
+    assert!(docs[0].contains(r#""parent_id":"0102030405060708","name":"GET /items""#));
+    assert!(docs[0].contains(r#""type":"subsegment","namespace":"remote""#));
+    assert!(docs[1].contains(r#""namespace":"aws""#));
+    assert!(docs[2].contains(r#""type":"subsegment""#) && !docs[2].contains("namespace"));
+    assert!(!docs[3].contains("subsegment") && docs[3].contains(r#""name":"checkout""#));
+}
+
+#[test]
+fn maps_http_aws_annotations_and_metadata() {
+    let docs = convert(
+        vec![span(
+            &[],
+            SpanKind::Server,
+            vec![
+                string("http.request.method", "GET"),
+                string("url.full", "https://example.com/items"),
+                kv("http.response.status_code", Value::IntValue(200)),
+                string("aws.request_id", "abc"),
+                string("tenant.id", "t-1"),
+                kv("cache.hit", Value::BoolValue(true)),
+            ],
+        )],
+        &["tenant.id"],
+    );
+    let doc = &docs[0];
+    assert!(doc.contains(concat!(
+        r#""http":{"request":{"method":"GET","url":"https://example.com/items"},"#,
+        r#""response":{"status":200}}"#
+    )));
+    assert!(doc.contains(r#""aws":{"request_id":"abc"}"#));
+    assert!(doc.contains(r#""annotations":{"tenant_id":"t-1"}"#));
+    assert!(doc.contains(r#""metadata":{"default":{"cache.hit":true}}"#));
+}
+
+#[test]
+fn maps_failures() {
+    let mut failed = span(&[], SpanKind::Server, vec![]);
+    failed.status = Some(Status {
+        code: StatusCode::Error as i32,
+        ..Default::default()
+    });
+    failed.events.push(Event {
+        name: "exception".to_owned(),
+        attributes: vec![
+            string("exception.type", "ValueError"),
+            string("exception.message", "bad input"),
+        ],
+        ..Default::default()
+    });
+    let status = |code| {
+        span(
+            &[],
+            SpanKind::Server,
+            vec![kv("http.response.status_code", Value::IntValue(code))],
+        )
+    };
+    let docs = convert(vec![failed, status(404), status(429), status(503)], &[]);
+
+    assert!(docs[0].contains(r#""fault":true"#));
+    assert!(docs[0].contains(concat!(
+        r#""cause":{"exceptions":[{"id":"53995c3f42cd8ad9","#,
+        r#""type":"ValueError","message":"bad input"}]}"#
+    )));
+    assert!(docs[1].contains(r#""error":true"#) && !docs[1].contains("throttle"));
+    assert!(docs[2].contains(r#""error":true,"throttle":true"#));
+    assert!(docs[3].contains(r#""fault":true"#));
+}
+
+#[test]
+fn sanitizes_names_and_annotation_keys() {
+    assert_eq!(sanitize_name("GET /items?id=1"), "GET /items_id=1");
+    assert_eq!(sanitize_name(&"a".repeat(300)).len(), MAX_NAME_CHARS);
+    assert_eq!(sanitize_annotation_key("user.tier-name"), "user_tier_name");
+}
+
+#[test]
+fn skips_spans_with_malformed_ids() {
+    let mut bad = span(&[], SpanKind::Server, vec![]);
+    bad.span_id = vec![1; 4];
+    assert!(convert(vec![bad], &[]).is_empty());
+}
@@ -0,0 +1,126 @@
+use super::*;
+use crate::proto::opentelemetry::proto::trace::v1::{ResourceSpans, ScopeSpans, Span};
+
+const HEADER: &str = "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1";
+const TRACE_ID: [u8; 16] = [
+    0x57, 0x59, 0xe9, 0x88, 0xbd, 0x86, 0x2e, 0x3f, 0xe1, 0xbe, 0x46, 0xa9, 0x94, 0x27, 0x27, 0x93,
+];
+const PARENT_ID: [u8; 8] = [0x53, 0x99, 0x5c, 0x3f, 0x42, 0xcd, 0x8a, 0xd8];
+
+fn span(trace_id: &[u8], parent: &[u8]) -> Span {
+    Span {
+        trace_id: trace_id.to_vec(),
+        span_id: vec![1; 8],
+        parent_span_id: parent.to_vec(),
+        ..Default::default()
+    }
+}
+
+fn request(spans: Vec<Span>) -> Bytes {
+    let req = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: None,
+            scope_spans: vec![ScopeSpans {
+                scope: None,
+                spans,
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    };
+    Bytes::from(req.encode_to_vec())
+}
+
+fn spans(payload: &[u8]) -> Vec<Span> {
+    ExportTraceServiceRequest::decode(payload)
+        .unwrap()
+        .resource_spans[0]
+        .scope_spans[0]
+        .spans
+        .clone()
+}
+
+fn bridge() -> XrayBridge {
+    let mut bridge = XrayBridge::new();
+    bridge.record(XrayContext::parse(HEADER).unwrap());
+    bridge
+}
+
+#[test]
+fn parses_header() {
+    assert_eq!(
+        XrayContext::parse(HEADER),
+        Some(XrayContext {
+            trace_id: TRACE_ID,
+            parent_id: Some(PARENT_ID),
+            sampled: true,
+        })
+    );
+}
+
+#[test]
+fn parses_header_without_parent() {
+    let context = XrayContext::parse("Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=0").unwrap();
+    assert_eq!(context.parent_id, None);
+    assert!(!context.sampled);
+}
+
+#[test]
+fn rejects_malformed_root() {
+    assert_eq!(XrayContext::parse("Parent=53995c3f42cd8ad8"), None);
+    assert_eq!(XrayContext::parse("Root=1-5759e988-short"), None);
+    assert_eq!(
+        XrayContext::parse("Root=2-5759e988-bd862e3fe1be46a994272793"),
+        None
+    );
+}
+
+#[test]
+fn fills_parent_of_root_span_in_xray_trace() {
+    let payload = bridge().link(request(vec![
+        span(&TRACE_ID, &[]),
+        span(&TRACE_ID, &[2; 8]),
+        span(&[7; 16], &[]),
+    ]));
+
+    let spans = spans(&payload);
+    assert_eq!(spans[0].parent_span_id, PARENT_ID);
+    assert_eq!(spans[0].flags, 0x300);
+    assert_eq!(spans[1].parent_span_id, [2; 8]);
+    assert!(spans[2].parent_span_id.is_empty());
+}
+
+#[test]
+fn unsampled_or_parentless_contexts_are_ignored() {
+    let mut bridge = XrayBridge::new();
+    bridge.record(
+        XrayContext::parse(
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=0",
+        )
+        .unwrap(),
+    );
+    bridge
+        .record(XrayContext::parse("Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1").unwrap());
+    let payload = request(vec![span(&TRACE_ID, &[])]);
+    assert_eq!(bridge.link(payload.clone()), payload);
+}
+
+#[test]
+fn keeps_recent_invocations_only() {
+    let mut bridge = bridge();
+    for i in 0..RECENT_INVOCATIONS {
+        bridge.record(XrayContext {
+            trace_id: [i as u8; 16],
+            parent_id: Some([1; 8]),
+            sampled: true,
+        });
+    }
+    let payload = request(vec![span(&TRACE_ID, &[])]);
+    assert_eq!(bridge.link(payload.clone()), payload);
+}
+
+#[test]
+fn undecodable_payload_passes_through() {
+    let garbage = Bytes::from_static(b"\xff\xff");
+    assert_eq!(bridge().link(garbage.clone()), garbage);
+}